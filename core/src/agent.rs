@@ -0,0 +1,42 @@
+use once_cell::sync::Lazy;
+use regex::Regex;
+
+// Browsers name the engines they are compatible with too, so the more specific ones go first. The first capture group
+// is the version.
+static BROWSERS: Lazy<Vec<(&str, Regex)>> = Lazy::new(|| {
+    [
+        ("Edge", r"\bEdg(?:e|A|iOS)?/(\d+)"),
+        ("Opera", r"\bOPR/(\d+)"),
+        ("Samsung Internet", r"\bSamsungBrowser/(\d+)"),
+        ("Firefox", r"\bFirefox/(\d+)"),
+        ("Chrome", r"\b(?:Chrome|CriOS)/(\d+)"),
+        ("Safari", r"\bVersion/(\d+)(?:\.\d+)*.*\bSafari/"),
+        ("IE", r"\b(?:MSIE |Trident/.*rv:)(\d+)"),
+    ]
+    .into_iter()
+    .map(|(family, re)| (family, Regex::new(re).unwrap()))
+    .collect()
+});
+
+// Libraries and applications name themselves first, like `curl/8.4.0` or `qnode-cli/1.4.2 (linux)`.
+static PRODUCT_RE: Lazy<Regex> = Lazy::new(|| Regex::new(r"^([A-Za-z][\w.-]*)/v?(\d+(?:\.\d+)?)").unwrap());
+
+/// Client family and version of a user agent, like `("Chrome", "120")` or `("qnode-cli", "1.4")`. Browsers are
+/// reported with the major version, other clients with the major and minor ones.
+pub(crate) fn parse_user_agent(user_agent: &str) -> (String, String) {
+    let user_agent = user_agent.trim();
+    if user_agent.starts_with("Mozilla/") || user_agent.starts_with("Opera/") {
+        for (family, re) in BROWSERS.iter() {
+            if let Some(caps) = re.captures(user_agent) {
+                return (family.to_string(), caps[1].to_string());
+            }
+        }
+        if user_agent.contains("bot") || user_agent.contains("Bot") {
+            return ("Bot".to_string(), String::new());
+        }
+    }
+    else if let Some(caps) = PRODUCT_RE.captures(user_agent) {
+        return (caps[1].to_string(), caps[2].to_string());
+    }
+    ("Other".to_string(), String::new())
+}
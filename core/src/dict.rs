@@ -0,0 +1,62 @@
+use std::collections::HashMap;
+
+use xxhash_rust::xxh3::xxh3_64;
+
+/// Maps strings to integer IDs and back, so records don't need to keep copies of repeating strings. With `HASHED` the
+/// IDs are hashes of the strings, stable between runs and between instances, rather than sequential numbers.
+#[derive(Default)]
+pub(crate) struct Dictionary<const HASHED: bool = false> {
+    /// Map an ID to its string
+    items: HashMap<u64, String>,
+    /// Map a string to its ID
+    idx:   HashMap<String, u64>,
+    /// IDs in the order the strings were encountered
+    order: Vec<u64>,
+}
+
+impl<const HASHED: bool> Dictionary<HASHED> {
+    pub(crate) fn id(&mut self, item: &str) -> u64 {
+        if let Some(id) = self.idx.get(item) {
+            return *id;
+        }
+
+        let mut new_id = if HASHED {
+            hashed_id(item)
+        }
+        else {
+            self.order.len() as u64
+        };
+        // A hash collision moves the later string to the next free ID.
+        while self.items.contains_key(&new_id) {
+            new_id = new_id.wrapping_add(1);
+        }
+        self.items.insert(new_id, item.to_owned());
+        self.idx.insert(item.to_owned(), new_id);
+        self.order.push(new_id);
+
+        new_id
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        self.order.len()
+    }
+
+    /// All strings with their IDs, in the order they were encountered.
+    pub(crate) fn iter(&self) -> impl Iterator<Item = (u64, &str)> {
+        self.order.iter().map(|id| (*id, self.items[id].as_str()))
+    }
+
+    /// ID of the string if it has been encountered.
+    pub(crate) fn lookup(&self, item: &str) -> Option<u64> {
+        self.idx.get(item).copied()
+    }
+
+    pub(crate) fn get(&self, id: u64) -> Option<&str> {
+        self.items.get(&id).map(|s| s.as_str())
+    }
+}
+
+/// ID of the string in a hashed dictionary, unless it collides with another one.
+pub(crate) fn hashed_id(item: &str) -> u64 {
+    xxh3_64(item.as_bytes())
+}
@@ -0,0 +1,92 @@
+use regex::Regex;
+
+use crate::agent::parse_user_agent;
+
+/// Patterns taking request details out of messages. A window applies its own to every record it is given; a copy can
+/// do the same ahead of time on another thread, leaving the window only the counting.
+#[derive(Debug, Clone, Default)]
+pub struct Extractor {
+    endpoint:   Option<Regex>,
+    status:     Option<Regex>,
+    user_agent: Option<Regex>,
+    trace:      Option<Regex>,
+    sequence:   Option<Regex>,
+}
+
+/// Request details of a message, as taken by an `Extractor`.
+#[derive(Debug, Clone, Default)]
+pub struct Extracted {
+    pub(crate) endpoint: Option<String>,
+    pub(crate) status:   Option<u16>,
+    /// Client as `family/version`
+    pub(crate) client:   Option<String>,
+    pub(crate) trace:    Option<String>,
+    pub(crate) sequence: Option<u64>,
+}
+
+impl Extractor {
+    pub(crate) fn set_endpoint(&mut self, pattern: Regex) {
+        self.endpoint = Some(pattern);
+    }
+
+    pub(crate) fn set_status(&mut self, pattern: Regex) {
+        self.status = Some(pattern);
+    }
+
+    pub(crate) fn set_user_agent(&mut self, pattern: Regex) {
+        self.user_agent = Some(pattern);
+    }
+
+    pub(crate) fn set_trace(&mut self, pattern: Regex) {
+        self.trace = Some(pattern);
+    }
+
+    pub(crate) fn set_sequence(&mut self, pattern: Regex) {
+        self.sequence = Some(pattern);
+    }
+
+    pub fn extract(&self, message: &str) -> Extracted {
+        Extracted {
+            endpoint: self.endpoint.as_ref().and_then(|re| capture(re, message)).map(str::to_string),
+            status:   self
+                .status
+                .as_ref()
+                .and_then(|re| capture(re, message)?.parse::<u16>().ok())
+                .filter(|status| (100..600).contains(status)),
+            client:   self.user_agent.as_ref().and_then(|re| {
+                let (family, version) = parse_user_agent(capture(re, message)?);
+                Some(format!("{}/{}", family, version))
+            }),
+            trace:    self.trace.as_ref().and_then(|re| capture(re, message)).map(str::to_string),
+            sequence: self.sequence.as_ref().and_then(|re| capture(re, message)?.parse().ok()),
+        }
+    }
+}
+
+impl Extracted {
+    pub fn endpoint(&self) -> Option<&str> {
+        self.endpoint.as_deref()
+    }
+
+    pub fn status(&self) -> Option<u16> {
+        self.status
+    }
+
+    pub fn client(&self) -> Option<&str> {
+        self.client.as_deref()
+    }
+
+    pub fn trace(&self) -> Option<&str> {
+        self.trace.as_deref()
+    }
+
+    pub fn sequence(&self) -> Option<u64> {
+        self.sequence
+    }
+}
+
+// The first capture group which participates in the match, or the whole match.
+pub(crate) fn capture<'a>(re: &Regex, text: &'a str) -> Option<&'a str> {
+    let caps = re.captures(text)?;
+    caps.iter().skip(1).flatten().next().or(caps.get(0)).map(|m| m.as_str())
+}
@@ -0,0 +1,100 @@
+use std::{
+    fmt,
+    net::{IpAddr, Ipv4Addr, Ipv6Addr},
+    str::FromStr,
+};
+
+use anyhow::{anyhow, Result};
+use regex::Regex;
+
+use crate::extract::{capture, Extracted};
+
+/// A dimension the records of a window can be grouped by, each making a table of records and errors per value.
+#[derive(Debug, Clone)]
+pub enum GroupBy {
+    Source,
+    /// Networks of the IPs, by the prefix length
+    Ip(u8),
+    Endpoint,
+    /// HTTP status code
+    Status,
+    /// A value taken from messages by the first capture group of the pattern which participates in the match, or the
+    /// whole match
+    Message { name: String, pattern: Regex },
+}
+
+impl GroupBy {
+    /// Value of the dimension for a record, if it has one.
+    pub(crate) fn key(&self, ip: &str, source: &str, message: &str, extracted: &Extracted) -> Option<String> {
+        match self {
+            Self::Source => Some(source.to_string()),
+            Self::Ip(prefix) => ip.parse::<IpAddr>().ok().map(|ip| network(ip, *prefix)),
+            Self::Endpoint => extracted.endpoint.clone(),
+            Self::Status => extracted.status.map(|status| status.to_string()),
+            Self::Message { pattern, .. } => capture(pattern, message).map(str::to_string),
+        }
+    }
+}
+
+impl fmt::Display for GroupBy {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Source => write!(f, "source"),
+            Self::Ip(prefix) => write!(f, "ip/{}", prefix),
+            Self::Endpoint => write!(f, "endpoint"),
+            Self::Status => write!(f, "status"),
+            Self::Message { name, .. } => write!(f, "{}", name),
+        }
+    }
+}
+
+/// Parses `source`, `ip/PREFIX`, `endpoint`, `status` or `NAME~REGEX`.
+impl FromStr for GroupBy {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        if let Some((name, pattern)) = s.split_once('~') {
+            let name = name.trim();
+            if name.is_empty() {
+                return Err(anyhow!("Group name is missing in '{}'", s));
+            }
+            let pattern = Regex::new(pattern).map_err(|e| anyhow!("Bad regex '{}': {}", pattern, e))?;
+            return Ok(Self::Message {
+                name: name.to_string(),
+                pattern,
+            });
+        }
+        if let Some(prefix) = s.strip_prefix("ip/") {
+            let prefix = prefix
+                .parse()
+                .ok()
+                .filter(|prefix| *prefix <= 128)
+                .ok_or_else(|| anyhow!("Bad prefix length in '{}'", s))?;
+            return Ok(Self::Ip(prefix));
+        }
+        match s {
+            "source" => Ok(Self::Source),
+            "endpoint" => Ok(Self::Endpoint),
+            "status" => Ok(Self::Status),
+            _ => Err(anyhow!(
+                "Expected source, ip/PREFIX, endpoint, status or NAME~REGEX to group by, got '{}'",
+                s
+            )),
+        }
+    }
+}
+
+// The network of the address with the prefix, which is capped by the address length, like `10.1.2.0/24`.
+fn network(ip: IpAddr, prefix: u8) -> String {
+    match ip {
+        IpAddr::V4(ip) => {
+            let prefix = prefix.min(32);
+            let mask = u32::MAX.checked_shl(32 - prefix as u32).unwrap_or(0);
+            format!("{}/{}", Ipv4Addr::from(u32::from(ip) & mask), prefix)
+        }
+        IpAddr::V6(ip) => {
+            let mask = u128::MAX.checked_shl(128 - prefix as u32).unwrap_or(0);
+            format!("{}/{}", Ipv6Addr::from(u128::from(ip) & mask), prefix)
+        }
+    }
+}
@@ -0,0 +1,28 @@
+//! Parser and sliding window statistics of qnode-logproc. The crate has no async runtime or terminal dependencies, so
+//! it builds for `wasm32-unknown-unknown`; with the `wasm` feature it provides JavaScript bindings.
+
+mod agent;
+mod dict;
+mod extract;
+mod group;
+mod merge;
+mod parser;
+mod sequence;
+mod snapshot;
+mod types;
+#[cfg(feature = "wasm")]
+mod wasm;
+mod window;
+
+pub use extract::{Extracted, Extractor};
+pub use group::GroupBy;
+pub use parser::{parse_line, split_line, try_parse_line, try_parse_line_mapped, LevelMap, LineFields};
+pub use snapshot::{
+    Attack, AttackKind, ClientCount, Context, DictionaryEntry, EndpointCount, Example, FacilityCount, Failure,
+    FileProgress, GroupCount, GroupTable, Heartbeat, HourlyRate, MessageCount, MessageRate, Provenance, RateRange,
+    RequestCount, RunInfo, SequenceStats, SloCount, SloStatus, SnapshotView, SuspiciousIp, WindowRow,
+};
+pub use types::{facility_by_name, ErrorType, Level, ParsedLine, FACILITIES, SEVERITIES};
+#[cfg(feature = "wasm")]
+pub use wasm::Analyzer;
+pub use window::{Clock, PartialStats, StatsWindow};
@@ -0,0 +1,270 @@
+use std::{
+    cmp::Ordering,
+    collections::{BTreeMap, HashMap},
+    hash::Hash,
+};
+
+use crate::snapshot::{
+    ClientCount, EndpointCount, GroupCount, MessageCount, MessageRate, RateRange, RequestCount, SnapshotView,
+};
+
+impl SnapshotView {
+    /// Combine snapshots taken on several hosts, or of several time ranges, into one. Counts and rates add up, as
+    /// for inputs analyzed together; top lists are ranked again from the entries they have, so an entry which didn't
+    /// make the top list of any snapshot is missing. `distinct_ips` adds up too, it is an upper bound since hosts may
+    /// share clients. Burstiness can't be combined from percentiles and is left unknown.
+    pub fn merge(views: impl IntoIterator<Item = SnapshotView>) -> SnapshotView {
+        let mut merged = SnapshotView::default();
+        let mut lag_sum = 0.0;
+        let mut skews = BTreeMap::<String, (f64, i64)>::new();
+        // The longest lists of the snapshots, and the most examples of a message
+        let mut top_len = [0; 5];
+        let mut example_budget = 0;
+        let mut group_len = 0;
+        for view in views {
+            let records = view.errors + view.infos + view.debugs;
+            lag_sum += view.avg_lag * records as f64;
+            for (source, skew) in view.source_skew.iter() {
+                let count = view.sources.get(source).copied().unwrap_or(1);
+                let entry = skews.entry(source.clone()).or_default();
+                entry.0 += skew * count as f64;
+                entry.1 += count;
+            }
+            for (len, list_len) in top_len.iter_mut().zip([
+                view.top_errors.len(),
+                view.top_endpoints.len(),
+                view.top_requests.len(),
+                view.top_clients.len(),
+                view.trending.len(),
+            ]) {
+                *len = (*len).max(list_len);
+            }
+            example_budget = view.examples.values().map(Vec::len).fold(example_budget, usize::max);
+
+            merged.timestamp = merged.timestamp.max(view.timestamp);
+            merged.entries += view.entries;
+            merged.collected_interval_ms = merged.collected_interval_ms.max(view.collected_interval_ms);
+            merged.window_ms = merged.window_ms.max(view.window_ms);
+            merged.current_rate += view.current_rate;
+            merged.bucket_ms = view.bucket_ms;
+            merged.rate += view.rate;
+            merged.peak_rate += view.peak_rate;
+            merged.errors += view.errors;
+            merged.infos += view.infos;
+            merged.debugs += view.debugs;
+            merged.malformed += view.malformed;
+            merged.bad_timestamps += view.bad_timestamps;
+            merged.unknown_levels += view.unknown_levels;
+            merged.oversized += view.oversized;
+            merged.decode_errors += view.decode_errors;
+            merged.late_arrivals += view.late_arrivals;
+            merged.distinct_ips += view.distinct_ips;
+            merged.muted += view.muted;
+            merged.sampled_out += view.sampled_out;
+            merged.below_min_level += view.below_min_level;
+            merged.backlog += view.backlog;
+            merged.drop_rate += view.drop_rate;
+            add_counts(&mut merged.throttled, view.throttled);
+            add_counts(&mut merged.truncated_datagrams, view.truncated_datagrams);
+            add_counts(&mut merged.escalated, view.escalated);
+            merged.maintenance |= view.maintenance;
+            merged.input_errors.extend(view.input_errors);
+            merged.failures.extend(view.failures);
+            add_counts(&mut merged.output_drops, view.output_drops);
+            merged.files.extend(view.files);
+            add_counts(&mut merged.sources, view.sources);
+            merged.top_errors.extend(view.top_errors);
+            merged.top_endpoints.extend(view.top_endpoints);
+            merged.affected_requests += view.affected_requests;
+            merged.top_requests.extend(view.top_requests);
+            merged.top_clients.extend(view.top_clients);
+            for table in view.groups {
+                group_len = group_len.max(table.rows.len());
+                match merged.groups.iter_mut().find(|merged_table| merged_table.name == table.name) {
+                    Some(merged_table) => merged_table.rows.extend(table.rows),
+                    None => merged.groups.push(table),
+                }
+            }
+            for (source, seq) in view.sequences {
+                let merged_seq = merged.sequences.entry(source).or_default();
+                merged_seq.received += seq.received;
+                merged_seq.missing += seq.missing;
+                merged_seq.duplicates += seq.duplicates;
+                merged_seq.restarts += seq.restarts;
+            }
+            merged.heartbeats.extend(view.heartbeats);
+            merged.suspicious.extend(view.suspicious);
+            merged.attacks.extend(view.attacks);
+            // Rates and typical ranges of the same hour add up, as of inputs analyzed together.
+            for hourly in view.hourly {
+                match merged.hourly.iter_mut().find(|merged| merged.hour == hourly.hour) {
+                    Some(merged) => {
+                        merged.entries += hourly.entries;
+                        merged.errors += hourly.errors;
+                        merged.rate += hourly.rate;
+                        merged.typical = match (merged.typical, hourly.typical) {
+                            (Some(a), Some(b)) => Some(RateRange {
+                                low:  a.low + b.low,
+                                high: a.high + b.high,
+                                days: a.days.min(b.days),
+                            }),
+                            _ => None,
+                        };
+                    }
+                    None => merged.hourly.push(hourly),
+                }
+            }
+            // The objective and the period of the first snapshot stand for all of them.
+            merged.slo = match (merged.slo.take(), view.slo) {
+                (Some(mut slo), Some(other)) => {
+                    slo.total.entries += other.total.entries;
+                    slo.total.errors += other.total.errors;
+                    for (name, count) in other.windows {
+                        let merged_count = slo.windows.entry(name).or_default();
+                        merged_count.entries += count.entries;
+                        merged_count.errors += count.errors;
+                    }
+                    Some(slo)
+                }
+                (slo, other) => slo.or(other),
+            };
+            add_counts(&mut merged.statuses, view.statuses);
+            for (class, series) in view.status_series {
+                let merged_series = merged.status_series.entry(class).or_default();
+                merged_series.resize(merged_series.len().max(series.len()), 0);
+                for (merged_count, count) in merged_series.iter_mut().zip(series) {
+                    *merged_count += count;
+                }
+            }
+            for (facility, count) in view.facilities {
+                let merged_count = merged.facilities.entry(facility).or_default();
+                merged_count.errors += count.errors;
+                merged_count.infos += count.infos;
+                merged_count.debugs += count.debugs;
+            }
+            merged.trending.extend(view.trending);
+            for (message, examples) in view.examples {
+                merged.examples.entry(message).or_default().extend(examples);
+            }
+            merged.contexts.extend(view.contexts);
+        }
+
+        let records = merged.errors + merged.infos + merged.debugs;
+        if records > 0 {
+            merged.avg_lag = lag_sum / records as f64;
+        }
+        if merged.entries > 0 {
+            merged.error_rate = merged.errors as f32 / merged.entries as f32;
+        }
+        merged.source_skew = skews
+            .into_iter()
+            .map(|(source, (sum, count))| (source, sum / count.max(1) as f64))
+            .collect();
+
+        let [errors_len, endpoints_len, requests_len, clients_len, trending_len] = top_len;
+        merged.top_errors = ranked(
+            merged.top_errors,
+            |top: &MessageCount| top.message.clone(),
+            |top, other| top.count += other.count,
+            |a, b| b.count.cmp(&a.count),
+            errors_len,
+        );
+        merged.top_endpoints = ranked(
+            merged.top_endpoints,
+            |top: &EndpointCount| top.endpoint.clone(),
+            |top, other| {
+                top.requests += other.requests;
+                top.errors += other.errors;
+            },
+            |a, b| b.errors.cmp(&a.errors).then(b.requests.cmp(&a.requests)),
+            endpoints_len,
+        );
+        merged.top_requests = ranked(
+            merged.top_requests,
+            |top: &RequestCount| top.request.clone(),
+            |top, other| top.errors += other.errors,
+            |a, b| b.errors.cmp(&a.errors),
+            requests_len,
+        );
+        merged.top_clients = ranked(
+            merged.top_clients,
+            |top: &ClientCount| (top.family.clone(), top.version.clone()),
+            |top, other| {
+                top.requests += other.requests;
+                top.errors += other.errors;
+            },
+            |a, b| b.errors.cmp(&a.errors).then(b.requests.cmp(&a.requests)),
+            clients_len,
+        );
+        for table in merged.groups.iter_mut() {
+            table.rows = ranked(
+                std::mem::take(&mut table.rows),
+                |row: &GroupCount| row.key.clone(),
+                |row, other| {
+                    row.records += other.records;
+                    row.errors += other.errors;
+                },
+                |a, b| b.errors.cmp(&a.errors).then(b.records.cmp(&a.records)),
+                group_len,
+            );
+        }
+        // A message trending anywhere is trending.
+        merged.trending = ranked(
+            merged.trending,
+            |top: &MessageRate| top.message.clone(),
+            |top, other| top.rate = top.rate.max(other.rate),
+            |a, b| b.rate.partial_cmp(&a.rate).unwrap_or(Ordering::Equal),
+            trending_len,
+        );
+
+        // Keep as many of the latest examples as a snapshot has, of the messages still listed.
+        let listed = merged
+            .top_errors
+            .iter()
+            .map(|top| &top.message)
+            .chain(merged.trending.iter().map(|trending| &trending.message))
+            .cloned()
+            .collect::<Vec<_>>();
+        merged.examples.retain(|message, _| listed.contains(message));
+        for examples in merged.examples.values_mut() {
+            examples.sort_by_key(|example| example.timestamp);
+            examples.drain(..examples.len().saturating_sub(example_budget));
+        }
+        merged.contexts.sort_by_key(|context| context.timestamp);
+        merged.failures.sort_by_key(|failure| failure.since);
+        merged.attacks.sort_by(|a, b| b.attempts.cmp(&a.attempts).then_with(|| a.subject.cmp(&b.subject)));
+        merged.hourly.sort_by_key(|hourly| hourly.hour);
+        merged.suspicious.sort_by(|a, b| b.errors.cmp(&a.errors).then_with(|| a.ip.cmp(&b.ip)));
+
+        merged
+    }
+}
+
+fn add_counts<K: Ord, N: Copy + std::ops::AddAssign + Default>(into: &mut BTreeMap<K, N>, from: BTreeMap<K, N>) {
+    for (key, count) in from {
+        *into.entry(key).or_default() += count;
+    }
+}
+
+// Combine the entries with the same key and keep the first `len` of them in the order, ties by the key.
+fn ranked<T, K: Ord + Hash>(
+    entries: Vec<T>,
+    key: impl Fn(&T) -> K,
+    combine: impl Fn(&mut T, T),
+    order: impl Fn(&T, &T) -> Ordering,
+    len: usize,
+) -> Vec<T> {
+    let mut combined = HashMap::<K, T>::new();
+    for entry in entries {
+        match combined.get_mut(&key(&entry)) {
+            Some(existing) => combine(existing, entry),
+            None => {
+                combined.insert(key(&entry), entry);
+            }
+        }
+    }
+    let mut entries = combined.into_values().collect::<Vec<_>>();
+    entries.sort_by(|a, b| order(a, b).then_with(|| key(a).cmp(&key(b))));
+    entries.truncate(len);
+    entries
+}
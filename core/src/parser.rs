@@ -0,0 +1,116 @@
+use std::collections::HashMap;
+
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use once_cell::sync::Lazy;
+use regex::Regex;
+
+use crate::types::{ErrorType, Level, ParsedLine};
+
+// Timestamps and levels are matched loosely, so that a bad one can be told from a line of a different format. Lines
+// relayed by syslog may start with the `<PRI>` priority.
+static LINE_RE: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(concat!(
+        r"^(?:<(?<pri>\d{1,3})>)?",
+        r"\[(?<dt>[^\]]*)\]\s+(?<level>\pL+)\s+-\s+IP:(?<ip>\S+)\s+(?:Error \d+ -\s+)?(?<msg>.*)$"
+    ))
+    .unwrap()
+});
+
+// Priorities above `local7.debug` are invalid.
+const MAX_PRIORITY: u8 = 191;
+
+/// Level names of other conventions, like `WARNING` or `ERR`, mapped to the levels records are counted by. Names are
+/// matched exactly; the names of the levels themselves keep their meaning unless mapped too.
+#[derive(Debug, Clone, Default)]
+pub struct LevelMap {
+    names: HashMap<String, Level>,
+}
+
+impl LevelMap {
+    pub fn insert(&mut self, name: &str, level: Level) {
+        self.names.insert(name.to_string(), level);
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.names.is_empty()
+    }
+
+    /// The level a name written in a line stands for.
+    pub fn level(&self, name: &str) -> Option<Level> {
+        self.names.get(name).copied().or_else(|| name.parse().ok())
+    }
+}
+
+impl FromIterator<(String, Level)> for LevelMap {
+    fn from_iter<I: IntoIterator<Item = (String, Level)>>(iter: I) -> Self {
+        Self {
+            names: iter.into_iter().collect(),
+        }
+    }
+}
+
+/// Parse a text log line. Returns `None` if the line doesn't make a record.
+pub fn parse_line(line: &str) -> Result<Option<ParsedLine>> {
+    Ok(try_parse_line(line).ok())
+}
+
+/// Fields of a text log line as they are written, before the timestamp and the level are checked.
+#[derive(Debug, Clone)]
+pub struct LineFields<'a> {
+    /// The syslog priority the line starts with, if any
+    pub priority:  Option<&'a str>,
+    pub timestamp: &'a str,
+    pub level:     &'a str,
+    pub ip:        &'a str,
+    pub message:   &'a str,
+}
+
+/// Split a text log line into its fields. Returns `None` if the line isn't in the log format at all.
+pub fn split_line(line: &str) -> Option<LineFields<'_>> {
+    let captures = LINE_RE.captures(line)?;
+    Some(LineFields {
+        priority:  captures.name("pri").map(|pri| pri.as_str()),
+        timestamp: captures.name("dt").unwrap().as_str(),
+        level:     captures.name("level").unwrap().as_str(),
+        ip:        captures.name("ip").unwrap().as_str(),
+        message:   captures.name("msg").unwrap().as_str(),
+    })
+}
+
+/// Parse a text log line, telling why it doesn't make a record if it doesn't. Lines with invalid UTF-8 replaced by
+/// the replacement character are decode errors.
+pub fn try_parse_line(line: &str) -> std::result::Result<ParsedLine, ErrorType> {
+    try_parse_line_mapped(line, &LevelMap::default())
+}
+
+/// Parse a text log line like `try_parse_line` does, with the level names mapped.
+pub fn try_parse_line_mapped(line: &str, levels: &LevelMap) -> std::result::Result<ParsedLine, ErrorType> {
+    if line.contains(char::REPLACEMENT_CHARACTER) {
+        return Err(ErrorType::DecodeError);
+    }
+    let fields = split_line(line).ok_or(ErrorType::Malformed)?;
+    let priority = match fields.priority {
+        Some(pri) => Some(pri.parse().ok().filter(|pri| *pri <= MAX_PRIORITY).ok_or(ErrorType::Malformed)?),
+        None => None,
+    };
+    let dt = fields.timestamp;
+    // Only the exact form like 2024-01-01T12:00:00Z is accepted, optionally with a fraction of a second like
+    // 2024-01-01T12:00:00.250Z.
+    let fraction = dt.get(19..dt.len().saturating_sub(1)).unwrap_or_default();
+    let digits = fraction.strip_prefix('.').unwrap_or(fraction);
+    let fraction_ok = fraction.is_empty()
+        || (fraction.starts_with('.') && (1..=9).contains(&digits.len()) && digits.bytes().all(|b| b.is_ascii_digit()));
+    if dt.len() < 20 || !dt.ends_with('Z') || !fraction_ok {
+        return Err(ErrorType::BadTimestamp);
+    }
+    let dt: DateTime<Utc> = dt.parse().map_err(|_| ErrorType::BadTimestamp)?;
+
+    Ok(ParsedLine {
+        logged_millis: dt.timestamp_millis(),
+        level:         levels.level(fields.level).ok_or(ErrorType::UnknownLevel)?,
+        ip:            fields.ip.to_string(),
+        message:       fields.message.to_string(),
+        priority,
+    })
+}
@@ -0,0 +1,60 @@
+use std::collections::BTreeSet;
+
+use crate::snapshot::SequenceStats;
+
+/// How far behind the highest sequence number a line may arrive and still fill its gap. Numbers further behind
+/// are taken for a restart of the numbering.
+const REORDER_WINDOW: u64 = 4096;
+
+/// Follows the sequence numbers of the lines of a source to tell lost and duplicate lines.
+#[derive(Default)]
+pub(crate) struct SequenceTracker {
+    highest:    Option<u64>,
+    /// Missing numbers recent enough to still arrive
+    gaps:       BTreeSet<u64>,
+    received:   u64,
+    missing:    u64,
+    duplicates: u64,
+    restarts:   u64,
+}
+
+impl SequenceTracker {
+    pub(crate) fn push(&mut self, seq: u64) {
+        self.received += 1;
+        let Some(highest) = self.highest
+        else {
+            self.highest = Some(seq);
+            return;
+        };
+
+        if seq > highest {
+            let skipped = seq - highest - 1;
+            self.missing += skipped;
+            self.gaps.extend((highest + 1).max(seq.saturating_sub(REORDER_WINDOW))..seq);
+            self.highest = Some(seq);
+            // Gaps which fell out of the window are lost for good.
+            self.gaps = self.gaps.split_off(&seq.saturating_sub(REORDER_WINDOW));
+        }
+        else if self.gaps.remove(&seq) {
+            // A late line, it isn't lost after all.
+            self.missing -= 1;
+        }
+        else if highest - seq <= REORDER_WINDOW {
+            self.duplicates += 1;
+        }
+        else {
+            self.restarts += 1;
+            self.gaps.clear();
+            self.highest = Some(seq);
+        }
+    }
+
+    pub(crate) fn stats(&self) -> SequenceStats {
+        SequenceStats {
+            received:   self.received,
+            missing:    self.missing,
+            duplicates: self.duplicates,
+            restarts:   self.restarts,
+        }
+    }
+}
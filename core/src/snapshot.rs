@@ -0,0 +1,513 @@
+use std::collections::BTreeMap;
+
+use chrono::{DateTime, Local};
+use serde::{Deserialize, Serialize};
+
+use crate::types::ErrorType;
+
+/// Statistics at a moment, with all dictionary IDs resolved. This is what leaves the analyzer: outputs, APIs and
+/// persisted state are all built from it.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct SnapshotView {
+    pub timestamp:             DateTime<Local>,
+    /// Number of entries in the window
+    pub entries:               i64,
+    /// Time span between the oldest and the newest entries in the window
+    pub collected_interval_ms: i64,
+    pub window_ms:             usize,
+    /// Entries received during the last second
+    pub current_rate:          usize,
+    /// Length of the buckets records are counted in over time, like for `current_rate` and `status_series`, in
+    /// milliseconds
+    #[serde(default = "default_bucket_ms")]
+    pub bucket_ms:             i64,
+    /// Entries of the busiest buckets of the window, at the 99th percentile
+    #[serde(default)]
+    pub bucket_p99:            i64,
+    /// Mean entries of the buckets of the window, empty ones included
+    #[serde(default)]
+    pub bucket_mean:           f64,
+    /// Average entries per second over the window
+    pub rate:                  f64,
+    pub peak_rate:             f64,
+    /// Average difference between receive and log times, in seconds
+    pub avg_lag:               f64,
+    /// Share of ERROR entries in the window
+    pub error_rate:            f32,
+    pub errors:                i64,
+    pub infos:                 i64,
+    pub debugs:                i64,
+    pub malformed:             i64,
+    #[serde(default)]
+    pub bad_timestamps:        i64,
+    #[serde(default)]
+    pub unknown_levels:        i64,
+    /// Lines longer than allowed
+    #[serde(default)]
+    pub oversized:             i64,
+    #[serde(default)]
+    pub decode_errors:         i64,
+    /// Records received too long after they were logged
+    #[serde(default)]
+    pub late_arrivals:         i64,
+    pub distinct_ips:          usize,
+    /// ERROR entries with acknowledged messages, left out of the top lists and alerting
+    #[serde(default)]
+    pub muted:                 i64,
+    /// Lines skipped by sampling under overload since the start
+    pub sampled_out:           u64,
+    /// Records less severe than `--min-level` dropped since the start
+    #[serde(default)]
+    pub below_min_level:       u64,
+    /// Lines waiting for the parser
+    #[serde(default)]
+    pub backlog:               u64,
+    /// Lines sampled out or throttled per second since the previous snapshot
+    #[serde(default)]
+    pub drop_rate:             f64,
+    /// Lines dropped by the rate limiter since the start, per source
+    pub throttled:             BTreeMap<String, u64>,
+    /// Datagrams longer than `--listen-max-size` since the start, cut to it, per source
+    #[serde(default)]
+    pub truncated_datagrams:   BTreeMap<String, u64>,
+    /// Records counted as ERROR by the escalation rules since the start, per rule
+    #[serde(default)]
+    pub escalated:             BTreeMap<String, u64>,
+    /// Taken during a maintenance window, with alerting suppressed
+    #[serde(default)]
+    pub maintenance:           bool,
+    /// Inputs which currently fail to be read, with their errors
+    #[serde(default)]
+    pub input_errors:          BTreeMap<String, String>,
+    /// Inputs, outputs and tasks which keep failing, the longest failing first
+    #[serde(default)]
+    pub failures:              Vec<Failure>,
+    /// Items the outputs dropped since the start as they could not keep up, per output
+    #[serde(default)]
+    pub output_drops:          BTreeMap<String, u64>,
+    /// Reading progress of file inputs, per source
+    #[serde(default)]
+    pub files:                 BTreeMap<String, FileProgress>,
+    /// Entries in the window per source
+    pub sources:               BTreeMap<String, i64>,
+    /// Average difference between log and receive times per source, in seconds; positive if the source clock is
+    /// ahead
+    #[serde(default)]
+    pub source_skew:           BTreeMap<String, f64>,
+    pub top_errors:            Vec<MessageCount>,
+    /// Endpoints with the most errors
+    #[serde(default)]
+    pub top_endpoints:         Vec<EndpointCount>,
+    /// Distinct requests, by trace or request ID, with errors
+    #[serde(default)]
+    pub affected_requests:     usize,
+    /// Requests with the most errors
+    #[serde(default)]
+    pub top_requests:          Vec<RequestCount>,
+    /// Clients with the most errors
+    #[serde(default)]
+    pub top_clients:           Vec<ClientCount>,
+    /// Tables of the `group_by` dimensions, in their order
+    #[serde(default)]
+    pub groups:                Vec<GroupTable>,
+    /// Sequence numbering of the sources whose lines carry sequence numbers, since the start
+    #[serde(default)]
+    pub sequences:             BTreeMap<String, SequenceStats>,
+    /// Expected heartbeats of the sources with when they were seen last
+    #[serde(default)]
+    pub heartbeats:            Vec<Heartbeat>,
+    /// IPs whose errors jumped well above their own baseline, the most erroring first
+    #[serde(default)]
+    pub suspicious:            Vec<SuspiciousIp>,
+    /// Brute-force attempts and scans seen in the failed authentications, the most attempts first
+    #[serde(default)]
+    pub attacks:               Vec<Attack>,
+    /// Error budget of the service level objective, if one is tracked
+    #[serde(default)]
+    pub slo:                   Option<SloStatus>,
+    /// Rates of the latest hours kept by the baseline, the oldest first
+    #[serde(default)]
+    pub hourly:                Vec<HourlyRate>,
+    /// Records per HTTP status class, like `5xx`
+    #[serde(default)]
+    pub statuses:              BTreeMap<String, i64>,
+    /// Records per HTTP status class in equal periods of the window, the oldest first
+    #[serde(default)]
+    pub status_series:         BTreeMap<String, Vec<i64>>,
+    /// Records per syslog facility, like `daemon`, of the lines which came with a priority
+    #[serde(default)]
+    pub facilities:            BTreeMap<String, FacilityCount>,
+    pub trending:              Vec<MessageRate>,
+    /// Latest occurrences of the top and trending messages
+    #[serde(default)]
+    pub examples:              BTreeMap<String, Vec<Example>>,
+    /// Lines around the latest occurrences of the messages selected for context capture, the newest last
+    #[serde(default)]
+    pub contexts:              Vec<Context>,
+    /// The run which made the snapshot
+    #[serde(default)]
+    pub run:                   RunInfo,
+}
+
+// Snapshots of older versions counted in whole seconds.
+fn default_bucket_ms() -> i64 {
+    1000
+}
+
+impl SnapshotView {
+    /// How spiky the stream is: the 99th percentile of the entries per bucket against their mean. 1 for an even
+    /// stream, 0 if unknown.
+    pub fn burstiness(&self) -> f64 {
+        if self.bucket_mean > 0.0 {
+            self.bucket_p99 as f64 / self.bucket_mean
+        }
+        else {
+            0.0
+        }
+    }
+
+    /// Number of lines in the window which didn't make a record for the reason.
+    pub fn rejected(&self, err_type: ErrorType) -> i64 {
+        match err_type {
+            ErrorType::Malformed => self.malformed,
+            ErrorType::BadTimestamp => self.bad_timestamps,
+            ErrorType::UnknownLevel => self.unknown_levels,
+            ErrorType::Oversized => self.oversized,
+            ErrorType::DecodeError => self.decode_errors,
+            ErrorType::LateArrival => self.late_arrivals,
+        }
+    }
+}
+
+/// A message with the number of its entries in the window.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MessageCount {
+    pub message: String,
+    pub count:   i64,
+}
+
+/// An endpoint of request logs with its records in the window.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct EndpointCount {
+    pub endpoint: String,
+    pub requests: i64,
+    pub errors:   i64,
+}
+
+/// A client family and version, taken from user agents, with its records in the window.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ClientCount {
+    pub family:   String,
+    pub version:  String,
+    pub requests: i64,
+    pub errors:   i64,
+}
+
+/// Records of the window grouped by a dimension, the values with the most errors first.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct GroupTable {
+    /// The dimension, like `ip/24`
+    pub name: String,
+    pub rows: Vec<GroupCount>,
+}
+
+/// A value of a dimension with its records in the window.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct GroupCount {
+    pub key:     String,
+    pub records: i64,
+    pub errors:  i64,
+}
+
+/// Records of a syslog facility in the window, per level.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct FacilityCount {
+    pub errors: i64,
+    pub infos:  i64,
+    pub debugs: i64,
+}
+
+/// A request, by its trace or request ID, with its errors in the window.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RequestCount {
+    pub request: String,
+    pub errors:  i64,
+}
+
+/// Lines of a source counted by their sequence numbers.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct SequenceStats {
+    /// Lines with sequence numbers
+    pub received:   u64,
+    /// Numbers skipped, less those which arrived late
+    pub missing:    u64,
+    /// Numbers seen before
+    pub duplicates: u64,
+    /// Times the numbering started over, like after a restart of the source
+    pub restarts:   u64,
+}
+
+impl SequenceStats {
+    /// Share of the lines sent which were not received.
+    pub fn loss(&self) -> f64 {
+        let sent = self.received - self.duplicates + self.missing;
+        if sent > 0 {
+            self.missing as f64 / sent as f64
+        }
+        else {
+            0.0
+        }
+    }
+}
+
+/// A heartbeat message a source is expected to log regularly.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Heartbeat {
+    pub source:        String,
+    /// The rule as given
+    pub rule:          String,
+    pub interval_secs: f64,
+    /// Never seen if not set
+    pub last_seen:     Option<DateTime<Local>>,
+    /// Seconds since the last heartbeat, or since the source was first expected to send one
+    pub age_secs:      f64,
+}
+
+/// An IP whose errors per minute jumped well above its own baseline.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SuspiciousIp {
+    pub ip:       String,
+    /// Errors within the last minute
+    pub errors:   u64,
+    /// Errors per minute the IP usually has
+    pub baseline: f64,
+    /// No errors of the IP were seen before it was flagged
+    pub new:      bool,
+    /// When it was flagged
+    pub since:    DateTime<Local>,
+}
+
+/// What a pattern of failed authentications looks like.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AttackKind {
+    /// One IP with many distinct messages, like for different user names
+    BruteForce,
+    /// One message from many IPs
+    Scan,
+}
+
+impl AttackKind {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::BruteForce => "brute_force",
+            Self::Scan => "scan",
+        }
+    }
+}
+
+/// A brute-force attempt or a scan seen within the interval of detection.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Attack {
+    pub kind:     AttackKind,
+    /// The IP of a brute-force attempt, the message of a scan
+    pub subject:  String,
+    /// Distinct messages of the IP, or IPs with the message
+    pub distinct: usize,
+    /// Failed authentications of the subject
+    pub attempts: u64,
+}
+
+/// Entries and errors within a period of the service level objective.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct SloCount {
+    pub entries: u64,
+    pub errors:  u64,
+}
+
+impl SloCount {
+    pub fn error_ratio(&self) -> f64 {
+        if self.entries > 0 {
+            self.errors as f64 / self.entries as f64
+        }
+        else {
+            0.0
+        }
+    }
+}
+
+/// How much of the error budget of a service level objective is spent, and how fast.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SloStatus {
+    /// Share of entries which must not be errors, like 0.999
+    pub objective:   f64,
+    pub period_secs: i64,
+    /// Over the whole period
+    pub total:       SloCount,
+    /// Over the windows the burn rate is measured in, by their names like `1h`
+    pub windows:     BTreeMap<String, SloCount>,
+}
+
+impl SloStatus {
+    /// Errors allowed per entry.
+    pub fn budget(&self) -> f64 {
+        1.0 - self.objective
+    }
+
+    /// Share of the budget of the period left, negative once it is overspent.
+    pub fn budget_remaining(&self) -> f64 {
+        1.0 - self.total.error_ratio() / self.budget()
+    }
+
+    /// How fast the errors within the window spend the budget: at 1 it is spent exactly by the end of the period.
+    pub fn burn_rate(&self, window: &str) -> f64 {
+        self.windows.get(window).map_or(0.0, |count| count.error_ratio() / self.budget())
+    }
+}
+
+/// Range the rate of an hour usually falls in, from the same time of day on earlier days.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct RateRange {
+    pub low:  f64,
+    pub high: f64,
+    /// Earlier days the range is taken from
+    pub days: usize,
+}
+
+/// Entries per second within an hour, with what is typical of the hour.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct HourlyRate {
+    /// Start of the hour
+    pub hour:    DateTime<Local>,
+    pub entries: u64,
+    pub errors:  u64,
+    /// Over the part of the hour the entries were counted in
+    pub rate:    f64,
+    /// Unknown before enough days are seen
+    pub typical: Option<RateRange>,
+}
+
+impl Heartbeat {
+    /// Whether the heartbeat is overdue.
+    pub fn is_silent(&self) -> bool {
+        self.age_secs > self.interval_secs
+    }
+}
+
+/// A message with its trending rate: how much more frequent it became recently.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MessageRate {
+    pub message: String,
+    pub rate:    f64,
+}
+
+/// An occurrence of a message.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Example {
+    /// When the message was logged
+    pub timestamp:  DateTime<Local>,
+    pub ip:         String,
+    pub source:     String,
+    /// The record as a log line
+    pub line:       String,
+    #[serde(default)]
+    pub provenance: Provenance,
+}
+
+/// Where exactly a record came from.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Provenance {
+    /// Place of the line in its source, like `PATH:OFFSET` for files
+    pub origin: Option<String>,
+    /// Number of the line among all lines received, from 1; 0 if unknown
+    pub seq:    u64,
+}
+
+/// Where and how a snapshot was made, so archived ones can be told apart.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct RunInfo {
+    /// Version of qnode-logproc
+    pub version:     String,
+    pub started:     DateTime<Local>,
+    pub host:        String,
+    /// Inputs read, like file paths or `stdin`
+    pub inputs:      Vec<String>,
+    /// Hash of the options, the same for runs with the same configuration
+    pub config_hash: String,
+}
+
+/// A record of the window with its fields resolved, for queries.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct WindowRow {
+    pub logged_millis:   i64,
+    pub received_millis: i64,
+    /// Level of a record, `None` for a rejected line
+    pub level:           Option<String>,
+    /// Why the line was rejected, like `bad_timestamp`
+    pub rejected:        Option<String>,
+    pub msg_id:          Option<u64>,
+    pub message:         Option<String>,
+    /// The message with the words containing digits replaced by `<*>`
+    pub template:        Option<String>,
+    pub ip:              Option<String>,
+    pub source:          String,
+    pub endpoint:        Option<String>,
+    pub status:          Option<u16>,
+    /// Client family and version, like `curl/8.5`
+    pub client:          Option<String>,
+    /// Values of the `group_by` dimensions and the syslog facility, by name
+    pub tags:            Vec<(String, String)>,
+}
+
+/// A message of the dictionary with its totals since the start.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DictionaryEntry {
+    pub id:         u64,
+    pub message:    String,
+    /// Records with the message, including those which have left the window
+    pub count:      u64,
+    /// Log time of the earliest record with the message
+    pub first_seen: DateTime<Local>,
+    /// Log time of the latest record with the message
+    pub last_seen:  DateTime<Local>,
+}
+
+/// Lines of a source around an occurrence of a selected message.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Context {
+    pub source:    String,
+    /// When the occurrence was received
+    pub timestamp: DateTime<Local>,
+    pub line:      String,
+    pub before:    Vec<String>,
+    /// Lines which followed so far
+    pub after:     Vec<String>,
+}
+
+/// Something which keeps failing, like an input or an output retrying its work.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Failure {
+    /// What fails, like `Redis output` or a source name
+    pub subject: String,
+    /// The last error
+    pub error:   String,
+    /// Number of failures in a row
+    pub count:   u64,
+    pub since:   DateTime<Local>,
+    pub last:    DateTime<Local>,
+}
+
+/// How far a file input is read.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct FileProgress {
+    /// Bytes read so far
+    pub offset:        u64,
+    /// Current size of the file
+    pub size:          u64,
+    pub lines_per_sec: f64,
+    /// When the last line was read
+    pub last_read:     Option<DateTime<Local>>,
+    /// Where the history of the file ends while it is being backfilled
+    #[serde(default)]
+    pub backfill_end:  Option<u64>,
+}
@@ -0,0 +1,117 @@
+use chrono::DateTime;
+use strum_macros::EnumString;
+
+/// Log level of a record.
+#[allow(clippy::upper_case_acronyms)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, EnumString)]
+pub enum Level {
+    INFO,
+    ERROR,
+    DEBUG,
+}
+
+impl Level {
+    /// Rank of the level, the higher the more severe.
+    pub fn severity(&self) -> u8 {
+        match self {
+            Self::DEBUG => 0,
+            Self::INFO => 1,
+            Self::ERROR => 2,
+        }
+    }
+}
+
+/// Names of the syslog facilities, by number.
+pub const FACILITIES: [&str; 24] = [
+    "kern", "user", "mail", "daemon", "auth", "syslog", "lpr", "news", "uucp", "cron", "authpriv", "ftp", "ntp",
+    "security", "console", "solaris-cron", "local0", "local1", "local2", "local3", "local4", "local5", "local6",
+    "local7",
+];
+
+/// Names of the syslog severities, by number.
+pub const SEVERITIES: [&str; 8] = ["emerg", "alert", "crit", "err", "warning", "notice", "info", "debug"];
+
+/// Syslog facility by its name, like `daemon`, or number.
+pub fn facility_by_name(name: &str) -> Option<u8> {
+    match FACILITIES.iter().position(|facility| facility.eq_ignore_ascii_case(name)) {
+        Some(facility) => Some(facility as u8),
+        None => name.parse().ok().filter(|facility| (*facility as usize) < FACILITIES.len()),
+    }
+}
+
+/// Why an input line didn't make a record.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorType {
+    /// The line doesn't have the expected format
+    Malformed,
+    /// The timestamp is not a valid date and time
+    BadTimestamp,
+    /// The level is none of the known ones
+    UnknownLevel,
+    /// The line is longer than allowed
+    Oversized,
+    /// The input couldn't be decoded, like invalid UTF-8 or a broken protobuf payload
+    DecodeError,
+    /// The record was received too long after it was logged
+    LateArrival,
+}
+
+impl ErrorType {
+    pub const ALL: [ErrorType; 6] = [
+        Self::Malformed,
+        Self::BadTimestamp,
+        Self::UnknownLevel,
+        Self::Oversized,
+        Self::DecodeError,
+        Self::LateArrival,
+    ];
+
+    /// Name used in exports, like `bad_timestamp`.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Malformed => "malformed",
+            Self::BadTimestamp => "bad_timestamp",
+            Self::UnknownLevel => "unknown_level",
+            Self::Oversized => "oversized",
+            Self::DecodeError => "decode_error",
+            Self::LateArrival => "late_arrival",
+        }
+    }
+}
+
+/// Fields of a log line, either parsed from text or received as a structured record.
+#[derive(Debug, Clone)]
+pub struct ParsedLine {
+    /// When the message was generated, in milliseconds
+    pub logged_millis: i64,
+    pub level:         Level,
+    pub ip:            String,
+    pub message:       String,
+    /// Syslog priority, the facility times 8 plus the severity, of a line which came with one
+    pub priority:      Option<u8>,
+}
+
+impl ParsedLine {
+    /// The syslog facility of the line, if it came with a priority.
+    pub fn facility(&self) -> Option<u8> {
+        self.priority.map(|priority| priority >> 3)
+    }
+
+    /// The syslog severity of the line, from 0 for `emerg` to 7 for `debug`, if it came with a priority.
+    pub fn severity(&self) -> Option<u8> {
+        self.priority.map(|priority| priority & 7)
+    }
+
+    /// The record in the text log format.
+    pub fn to_line(&self) -> String {
+        let logged = DateTime::from_timestamp_millis(self.logged_millis).unwrap_or_default();
+        format!(
+            "{}[{}] {:?} - IP:{} {}",
+            self.priority.map(|priority| format!("<{}>", priority)).unwrap_or_default(),
+            logged.format("%Y-%m-%dT%H:%M:%SZ"),
+            self.level,
+            self.ip,
+            self.message
+        )
+    }
+}
@@ -0,0 +1,36 @@
+use wasm_bindgen::prelude::*;
+
+use crate::{parser::try_parse_line, window::StatsWindow};
+
+/// Name of the source lines pushed from JavaScript are attributed to.
+const SOURCE: &str = "browser";
+
+/// The statistics core for JavaScript: lines go in, snapshots come out as JSON.
+#[wasm_bindgen]
+#[derive(Default)]
+pub struct Analyzer {
+    window: StatsWindow,
+}
+
+#[wasm_bindgen]
+impl Analyzer {
+    #[wasm_bindgen(constructor)]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Parse a line and add it to the window. `received_millis` is when the line was received, like `Date.now()`.
+    #[wasm_bindgen(js_name = pushLine)]
+    pub fn push_line(&mut self, line: &str, received_millis: f64) {
+        match try_parse_line(line) {
+            Ok(record) => self.window.push_ok(received_millis as i64, &record, SOURCE),
+            Err(err_type) => self.window.push_error(received_millis as i64, err_type, SOURCE),
+        }
+    }
+
+    /// The current statistics as a JSON-encoded `SnapshotView`.
+    pub fn snapshot(&mut self) -> Result<String, JsError> {
+        self.window.cleanup_and_adjust();
+        Ok(serde_json::to_string(&self.window.view(chrono::Local::now()))?)
+    }
+}
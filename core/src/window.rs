@@ -0,0 +1,1448 @@
+use core::f64;
+use std::{
+    cmp::Ordering,
+    collections::{BTreeMap, HashMap, HashSet, VecDeque},
+};
+
+use chrono::{DateTime, Local, Utc};
+use fieldx::fxstruct;
+use regex::Regex;
+use xxhash_rust::xxh3::xxh3_64;
+
+mod blocks;
+mod partial;
+
+use blocks::RecordBlocks;
+pub use partial::PartialStats;
+
+use crate::{
+    dict::{hashed_id, Dictionary},
+    extract::{Extracted, Extractor},
+    group::GroupBy,
+    sequence::SequenceTracker,
+    snapshot::{
+        ClientCount, DictionaryEntry, EndpointCount, Example, FacilityCount, MessageCount, MessageRate, Provenance,
+        RequestCount, GroupCount, GroupTable, RunInfo, SnapshotView, WindowRow,
+    },
+    types::{ErrorType, Level, ParsedLine, FACILITIES},
+};
+
+/// Values of the `group_by` dimensions of a record are joined by it into one key.
+const GROUP_KEY_SEPARATOR: &str = "\x1f";
+/// Rows of every group table in views.
+const GROUP_ROWS: usize = 10;
+
+// In seconds
+const MSG_ERROR_WINDOW: i64 = 15000;
+// Window bounds in seconds
+/// Classes of HTTP status codes, from 1xx to 5xx.
+const STATUS_CLASSES: [&str; 5] = ["1xx", "2xx", "3xx", "4xx", "5xx"];
+/// Number of periods the window is split into for the HTTP status series.
+const STATUS_SERIES_LEN: usize = 20;
+
+const MIN_WINDOW: usize = 30;
+const MAX_WINDOW: usize = 120;
+/// The fewest buckets the window must span for the percentiles of the buckets to mean anything.
+const MIN_BURST_BUCKETS: usize = 10;
+
+/// Which time drives window expiry and rates.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Clock {
+    /// Wall-clock time records are received at. Suits live streams.
+    #[default]
+    Receive,
+    /// Log time of the records, the newest one seen being the current time. Suits replaying old logs.
+    Log,
+}
+
+#[derive(Clone, Copy)]
+enum Act {
+    Inc = 1,
+    Dec = -1,
+}
+
+#[fxstruct(no_new, default)]
+struct StatsSnapshot {
+    entries:            i64,
+    // In milliseconds
+    collected_interval: i64,
+    rate:               f64,
+    peak_rate:          f64,
+    error_rate:         f32,
+
+    errors:    i64,
+    infos:     i64,
+    debugs:    i64,
+    malformed: i64,
+
+    bad_timestamps: i64,
+    unknown_levels: i64,
+    oversized:      i64,
+    decode_errors:  i64,
+    late_arrivals:  i64,
+
+    /// Sum of differences between receive and log times of the well-formed records in the window, in milliseconds
+    lag_sum: i64,
+
+    /// Map a message ID to the number of times it has been seen
+    error_msg_counts: HashMap<u64, i64>,
+
+    /// For each bucket of log time, map a message ID to the number of times it has been seen in that bucket
+    error_msg_per_bucket: HashMap<i64, HashMap<u64, i64>>,
+
+    /// Map a message ID to its weight
+    error_msg_rates: HashMap<u64, f64>,
+
+    /// Map an IP ID to the number of records from that IP
+    ip_counts: HashMap<u64, i64>,
+
+    /// Map a source ID to the number of records from that source
+    source_counts: HashMap<u64, i64>,
+
+    /// Map an endpoint ID to the number of records and errors of that endpoint
+    endpoint_counts: HashMap<u64, (i64, i64)>,
+
+    /// Map a hash of a trace ID to the number of errors of that request
+    trace_errors: HashMap<u64, i64>,
+
+    /// Map a client ID to the number of records and errors of that client
+    client_counts: HashMap<u64, (i64, i64)>,
+
+    /// Map a group key ID to the number of records and errors with those values of the `group_by` dimensions
+    group_counts: HashMap<u64, (i64, i64)>,
+
+    /// Number of records per HTTP status class, indexed like `STATUS_CLASSES`
+    status_counts: [i64; 5],
+
+    /// Map a syslog facility to the number of records of that facility per level: ERROR, INFO, DEBUG
+    facility_counts: HashMap<u8, [i64; 3]>,
+
+    /// For each bucket of the window clock, the number of records
+    entries_per_bucket: HashMap<i64, i64>,
+
+    /// For each bucket of log time, the number of records per HTTP status class
+    status_per_bucket: HashMap<i64, [i64; 5]>,
+
+    /// Map a source ID to the sum of lags of its well-formed records, in milliseconds, and their number
+    source_lags: HashMap<u64, (i64, i64)>,
+
+    /// Measure in milliseconds
+    #[fieldx(default(60000))]
+    window: usize,
+
+    #[fieldx(default(60000))]
+    previous_window: usize,
+
+    /// Length of the buckets records are counted in over time, in milliseconds
+    #[fieldx(default(1000))]
+    bucket_ms: i64,
+
+    clock: Clock,
+
+    /// List of timestamps in milliseconds of messages received in the last bucket
+    last_bucket_received: VecDeque<i64>,
+}
+
+impl StatsSnapshot {
+    fn count_inner_rec(&mut self, rec: InnerRecord, act: Act) -> InnerRecord {
+        let source_id = rec.source_id();
+        let cnt = *self
+            .source_counts
+            .entry(source_id)
+            .and_modify(|count| *count += act as i64)
+            .or_insert(act as i64);
+        if cnt == 0 {
+            self.source_counts.remove(&source_id);
+        }
+
+        let bucket = rec.timestamp_millis(self.clock).div_euclid(self.bucket_ms);
+        let cnt = *self
+            .entries_per_bucket
+            .entry(bucket)
+            .and_modify(|count| *count += act as i64)
+            .or_insert(act as i64);
+        if cnt == 0 {
+            self.entries_per_bucket.remove(&bucket);
+        }
+
+        if let InnerRecord::OK(ok) = &rec {
+            let lag = ok.received_millis - ok.logged_millis;
+            self.lag_sum += lag * act as i64;
+
+            let source_lag = self.source_lags.entry(source_id).or_default();
+            source_lag.0 += lag * act as i64;
+            source_lag.1 += act as i64;
+            if source_lag.1 == 0 {
+                self.source_lags.remove(&source_id);
+            }
+
+            let cnt = *self
+                .ip_counts
+                .entry(ok.ip_id)
+                .and_modify(|count| *count += act as i64)
+                .or_insert(act as i64);
+            if cnt == 0 {
+                self.ip_counts.remove(&ok.ip_id);
+            }
+
+            if let Some(endpoint_id) = ok.endpoint_id {
+                let counts = self.endpoint_counts.entry(endpoint_id).or_default();
+                counts.0 += act as i64;
+                if ok.level == Level::ERROR {
+                    counts.1 += act as i64;
+                }
+                if counts.0 == 0 {
+                    self.endpoint_counts.remove(&endpoint_id);
+                }
+            }
+
+            if let (Some(trace_id), Level::ERROR) = (ok.trace_id, ok.level) {
+                let cnt = *self
+                    .trace_errors
+                    .entry(trace_id)
+                    .and_modify(|count| *count += act as i64)
+                    .or_insert(act as i64);
+                if cnt == 0 {
+                    self.trace_errors.remove(&trace_id);
+                }
+            }
+
+            if let Some(client_id) = ok.client_id {
+                let counts = self.client_counts.entry(client_id).or_default();
+                counts.0 += act as i64;
+                if ok.level == Level::ERROR {
+                    counts.1 += act as i64;
+                }
+                if counts.0 == 0 {
+                    self.client_counts.remove(&client_id);
+                }
+            }
+
+            if let Some(group_id) = ok.group_id {
+                let counts = self.group_counts.entry(group_id).or_default();
+                counts.0 += act as i64;
+                if ok.level == Level::ERROR {
+                    counts.1 += act as i64;
+                }
+                if counts.0 == 0 {
+                    self.group_counts.remove(&group_id);
+                }
+            }
+
+            if let Some(facility) = ok.facility {
+                let column = match ok.level {
+                    Level::ERROR => 0,
+                    Level::INFO => 1,
+                    Level::DEBUG => 2,
+                };
+                let counts = self.facility_counts.entry(facility).or_default();
+                counts[column] += act as i64;
+                if counts.iter().all(|count| *count == 0) {
+                    self.facility_counts.remove(&facility);
+                }
+            }
+
+            if let Some(status) = ok.status {
+                let class = status as usize / 100 - 1;
+                self.status_counts[class] += act as i64;
+                let bucket = rec.log_bucket(self.bucket_ms);
+                let counts = self.status_per_bucket.entry(bucket).or_default();
+                counts[class] += act as i64;
+                if counts.iter().all(|count| *count == 0) {
+                    self.status_per_bucket.remove(&bucket);
+                }
+            }
+        }
+
+        match &rec {
+            InnerRecord::OK(ok) => match ok.level {
+                Level::ERROR => {
+                    self.errors += act as i64;
+                    self.error_msg_counts
+                        .entry(ok.msg_id)
+                        .and_modify(|count| *count += act as i64)
+                        .or_insert(act as i64);
+
+                    let bucket = rec.log_bucket(self.bucket_ms);
+                    let cnt = *self
+                        .error_msg_per_bucket
+                        .entry(bucket)
+                        .or_default()
+                        .entry(ok.msg_id)
+                        .and_modify(|count| *count += act as i64)
+                        .or_insert(act as i64);
+                    // Clean up empty entries.
+                    // Since log times are not guaranteed to be monotonically increasing and can fall out
+                    // of the current time window, we need to check if the count is zero here for better performance.
+                    // Otherwise, it would be necessary to iterate over all entries in the cleanup_and_adjust body.
+                    if cnt == 0 {
+                        self.error_msg_per_bucket.get_mut(&bucket).unwrap().remove(&ok.msg_id);
+                        if self.error_msg_per_bucket[&bucket].is_empty() {
+                            self.error_msg_per_bucket.remove(&bucket);
+                        }
+                    }
+                }
+                Level::INFO => {
+                    self.infos += act as i64;
+                }
+                Level::DEBUG => {
+                    self.debugs += act as i64;
+                }
+            },
+            InnerRecord::Err(err) => {
+                let count = match err.err_type {
+                    ErrorType::Malformed => &mut self.malformed,
+                    ErrorType::BadTimestamp => &mut self.bad_timestamps,
+                    ErrorType::UnknownLevel => &mut self.unknown_levels,
+                    ErrorType::Oversized => &mut self.oversized,
+                    ErrorType::DecodeError => &mut self.decode_errors,
+                    ErrorType::LateArrival => &mut self.late_arrivals,
+                };
+                *count += act as i64;
+            }
+        }
+
+        rec
+    }
+}
+
+#[derive(Clone)]
+enum InnerRecord {
+    OK(InnerOKRecord),
+    Err(InnerErrRecord),
+}
+
+impl InnerRecord {
+    fn recv_timestamp_millis(&self) -> i64 {
+        match self {
+            Self::OK(ok) => ok.received_millis,
+            Self::Err(err) => err.received_millis,
+        }
+    }
+
+    #[allow(dead_code)]
+    fn recv_timestamp(&self) -> i64 {
+        self.recv_timestamp_millis() / 1000
+    }
+
+    fn log_timestamp_millis(&self) -> i64 {
+        match self {
+            Self::OK(ok) => ok.logged_millis,
+            Self::Err(err) => err.logged_millis,
+        }
+    }
+
+    fn timestamp_millis(&self, clock: Clock) -> i64 {
+        match clock {
+            Clock::Receive => self.recv_timestamp_millis(),
+            Clock::Log => self.log_timestamp_millis(),
+        }
+    }
+
+    /// Number of the bucket of log time the record falls into.
+    fn log_bucket(&self, bucket_ms: i64) -> i64 {
+        self.log_timestamp_millis().div_euclid(bucket_ms)
+    }
+
+    fn source_id(&self) -> u64 {
+        match self {
+            Self::OK(ok) => ok.source_id,
+            Self::Err(err) => err.source_id,
+        }
+    }
+}
+
+#[derive(Clone)]
+struct InnerOKRecord {
+    received_millis: i64,
+    logged_millis:   i64,
+    level:           Level,
+    msg_id:          u64,
+    ip_id:           u64,
+    source_id:       u64,
+    endpoint_id:     Option<u64>,
+    /// HTTP status code, 100 to 599
+    status:          Option<u16>,
+    client_id:       Option<u64>,
+    /// Hash of the trace or request ID
+    trace_id:        Option<u64>,
+    /// Values of the `group_by` dimensions, joined into one key
+    group_id:        Option<u64>,
+    /// Syslog facility
+    facility:        Option<u8>,
+}
+
+/// An occurrence of a message kept as an example.
+#[derive(Clone)]
+struct InnerExample {
+    logged_millis: i64,
+    level:         Level,
+    ip_id:         u64,
+    source_id:     u64,
+    provenance:    Provenance,
+}
+
+/// Totals of a message since the start.
+#[derive(Clone, Copy)]
+struct InnerMessageTotals {
+    count:        u64,
+    first_millis: i64,
+    last_millis:  i64,
+}
+
+impl InnerMessageTotals {
+    /// Totals of a single occurrence.
+    fn once(logged_millis: i64) -> Self {
+        Self {
+            count:        1,
+            first_millis: logged_millis,
+            last_millis:  logged_millis,
+        }
+    }
+
+    fn add_to(self, totals: &mut HashMap<u64, InnerMessageTotals>, msg_id: u64) {
+        let totals = totals.entry(msg_id).or_insert(Self {
+            count: 0,
+            ..self
+        });
+        totals.count += self.count;
+        totals.first_millis = totals.first_millis.min(self.first_millis);
+        totals.last_millis = totals.last_millis.max(self.last_millis);
+    }
+}
+
+#[derive(Clone)]
+struct InnerErrRecord {
+    received_millis: i64,
+    /// Malformed lines have no log time of their own. It is the receive time, or the replay clock at the moment the
+    /// line was received when windowing by log time.
+    logged_millis:   i64,
+    err_type:        ErrorType,
+    source_id:       u64,
+}
+
+impl StatsSnapshot {
+    /// Average lag between log and receive times, in seconds.
+    fn avg_lag(&self) -> f64 {
+        let ok_entries = self.errors + self.infos + self.debugs;
+        if ok_entries > 0 {
+            self.lag_sum as f64 / ok_entries as f64 / 1000.0
+        }
+        else {
+            0.0
+        }
+    }
+
+    fn refresh_last_bucket(&mut self, ts: Option<i64>, now: i64) {
+        if let Some(ts) = ts {
+            self.last_bucket_received.push_front(ts);
+        }
+        while let Some(last) = self.last_bucket_received.back() {
+            if now - last > self.bucket_ms {
+                self.last_bucket_received.pop_back();
+            }
+            else {
+                break;
+            }
+        }
+    }
+
+    /// Rate of the records received in the last bucket, per second.
+    fn current_rate(&self) -> usize {
+        (self.last_bucket_received.len() as i64 * 1000 / self.bucket_ms) as usize
+    }
+}
+
+/// Records within the sliding window and the statistics over them. Message, IP and source strings are kept in
+/// dictionaries, records refer to them by IDs.
+#[derive(Default)]
+pub struct StatsWindow {
+    records: RecordBlocks,
+
+    /// All distinct log messages encountered. The IDs are content hashes, so they can be joined on between runs.
+    msgs: Dictionary<true>,
+
+    /// Totals of every message, by message ID
+    msg_totals: HashMap<u64, InnerMessageTotals>,
+
+    /// All distinct IPs encountered
+    ips: Dictionary,
+
+    /// All distinct input sources encountered
+    sources: Dictionary,
+
+    /// All distinct endpoints encountered
+    endpoints: Dictionary,
+
+    /// All distinct clients encountered, as `family/version`
+    clients: Dictionary,
+
+    /// Dimensions to group records by
+    group_by: Vec<GroupBy>,
+
+    /// All distinct combinations of the `group_by` values encountered, joined by `GROUP_KEY_SEPARATOR`
+    groups: Dictionary,
+
+    /// Trace IDs of the errors by their hashes. Unlike the dictionaries it forgets the IDs which have left the window,
+    /// since every request has its own.
+    trace_ids: HashMap<u64, String>,
+
+    /// Takes endpoints, statuses, clients, trace IDs and sequence numbers from messages
+    extractor: Extractor,
+
+    /// Sequence numbering of the sources, by source ID
+    sequences: HashMap<u64, SequenceTracker>,
+
+    stat: StatsSnapshot,
+
+    clock: Clock,
+
+    /// Number of occurrences kept per message as examples
+    example_budget: usize,
+
+    /// The latest occurrences of every message, by message ID
+    examples: HashMap<u64, VecDeque<InnerExample>>,
+
+    /// The newest log time seen, in milliseconds. The current time when windowing by log time.
+    log_now: i64,
+
+    /// Patterns of the messages acknowledged as known
+    mute_patterns: Vec<Regex>,
+
+    /// IDs of the messages matching `mute_patterns`
+    muted: HashSet<u64>,
+}
+
+impl StatsWindow {
+    pub fn with_clock(clock: Clock) -> Self {
+        let mut window = Self {
+            clock,
+            ..Self::default()
+        };
+        window.stat.clock = clock;
+        window
+    }
+
+    /// Count records over time in buckets of `millis` instead of whole seconds, which shows bursts shorter than a
+    /// second. Must be set before any record is added.
+    pub fn bucket_millis(mut self, millis: i64) -> Self {
+        self.stat.bucket_ms = millis.max(1);
+        self
+    }
+
+    /// Keep the latest `budget` occurrences of every message as examples.
+    pub fn keep_examples(self, budget: usize) -> Self {
+        Self {
+            example_budget: budget,
+            ..self
+        }
+    }
+
+    /// Group records by the dimensions, each making a table in the views.
+    pub fn group_by(self, dimensions: Vec<GroupBy>) -> Self {
+        Self {
+            group_by: dimensions,
+            ..self
+        }
+    }
+
+    /// Count records per endpoint, taken from messages by the first capture group of the pattern which participates
+    /// in the match, or the whole match.
+    pub fn extract_endpoints(mut self, pattern: Regex) -> Self {
+        self.extractor.set_endpoint(pattern);
+        self
+    }
+
+    /// Count records per HTTP status class, the code taken from messages the same way as endpoints are.
+    pub fn extract_statuses(mut self, pattern: Regex) -> Self {
+        self.extractor.set_status(pattern);
+        self
+    }
+
+    /// Count records per client family and version, parsed from user agents taken from messages the same way as
+    /// endpoints are.
+    pub fn extract_user_agents(mut self, pattern: Regex) -> Self {
+        self.extractor.set_user_agent(pattern);
+        self
+    }
+
+    /// Count errors per request, by trace or request IDs taken from messages the same way as endpoints are.
+    pub fn extract_trace_ids(mut self, pattern: Regex) -> Self {
+        self.extractor.set_trace(pattern);
+        self
+    }
+
+    /// Follow sequence numbers of the lines per source, taken from messages the same way as endpoints are, to tell
+    /// lost and duplicate lines.
+    pub fn extract_sequences(mut self, pattern: Regex) -> Self {
+        self.extractor.set_sequence(pattern);
+        self
+    }
+
+    /// Acknowledge the messages matching the pattern: they are left out of the top lists and counted as muted.
+    pub fn mute(&mut self, pattern: Regex) {
+        self.muted
+            .extend(self.msgs.iter().filter(|(_, msg)| pattern.is_match(msg)).map(|(id, _)| id));
+        self.mute_patterns.push(pattern);
+    }
+
+    /// Stop acknowledging the messages matching the pattern, unless another pattern matches them too.
+    pub fn unmute(&mut self, pattern: &Regex) {
+        self.mute_patterns.retain(|muted| muted.as_str() != pattern.as_str());
+        self.muted = self
+            .msgs
+            .iter()
+            .filter(|(_, msg)| self.mute_patterns.iter().any(|pattern| pattern.is_match(msg)))
+            .map(|(id, _)| id)
+            .collect();
+    }
+
+    /// Acknowledge the exact message.
+    pub fn mute_message(&mut self, message: &str) {
+        self.mute(Regex::new(&format!("^{}$", regex::escape(message))).unwrap());
+    }
+
+    /// ID the message is known by in dictionary exports, unless it collides with another message.
+    pub fn message_id(message: &str) -> u64 {
+        hashed_id(message)
+    }
+
+    /// The patterns the request details are taken from messages with.
+    pub fn extractor(&self) -> &Extractor {
+        &self.extractor
+    }
+
+    pub fn is_muted(&self, message: &str) -> bool {
+        self.msgs.lookup(message).is_some_and(|id| self.muted.contains(&id))
+    }
+
+    /// Current time of the replay clock if windowing by log time and any record has been seen yet.
+    pub fn log_time(&self) -> Option<DateTime<Local>> {
+        if self.clock == Clock::Log && self.log_now > 0 {
+            DateTime::from_timestamp_millis(self.log_now).map(|t| t.with_timezone(&Local))
+        }
+        else {
+            None
+        }
+    }
+
+    fn now_millis(&self) -> i64 {
+        match self.clock {
+            Clock::Receive => Utc::now().timestamp_millis(),
+            Clock::Log => self.log_now,
+        }
+    }
+    pub fn is_empty(&self) -> bool {
+        self.records.is_empty()
+    }
+
+    /// Add a well-formed record. `received_millis` is when the record was received.
+    pub fn push_ok(&mut self, received_millis: i64, record: &ParsedLine, source: &str) {
+        let extracted = self.extractor.extract(&record.message);
+        self.push_ok_extracted(received_millis, record, source, extracted, Provenance::default());
+    }
+
+    /// Same as `push_ok`, with the request details already taken from the message by a copy of `extractor()` and
+    /// where the record came from, for its examples.
+    pub fn push_ok_extracted(
+        &mut self,
+        received_millis: i64,
+        record: &ParsedLine,
+        source: &str,
+        extracted: Extracted,
+        provenance: Provenance,
+    ) {
+        self.log_now = self.log_now.max(record.logged_millis);
+        // Refresh the last bucket list so we know the current rate
+        let ts = match self.clock {
+            Clock::Receive => received_millis,
+            Clock::Log => record.logged_millis,
+        };
+        let now = self.now_millis();
+        self.stat.refresh_last_bucket(Some(ts), now);
+        self.push_ok_uncounted_extracted(received_millis, record, source, extracted, provenance);
+    }
+
+    /// Same as `push_ok`, but the record is not counted in the current rate, like one read from a backlog.
+    pub fn push_ok_uncounted(&mut self, received_millis: i64, record: &ParsedLine, source: &str) {
+        let extracted = self.extractor.extract(&record.message);
+        self.push_ok_uncounted_extracted(received_millis, record, source, extracted, Provenance::default());
+    }
+
+    /// Same as `push_ok_uncounted`, with the request details already taken from the message.
+    pub fn push_ok_uncounted_extracted(
+        &mut self,
+        received_millis: i64,
+        record: &ParsedLine,
+        source: &str,
+        extracted: Extracted,
+        provenance: Provenance,
+    ) {
+        self.log_now = self.log_now.max(record.logged_millis);
+        let known_msgs = self.msgs.len();
+        // Records are counted per combination of values, the tables are made of the combinations in views.
+        let group_id = (!self.group_by.is_empty())
+            .then(|| self.groups.id(&group_key(&self.group_by, record, source, &extracted)));
+        let trace_id = extracted.trace.map(|trace| {
+            let hash = xxh3_64(trace.as_bytes());
+            if record.level == Level::ERROR {
+                self.trace_ids.entry(hash).or_insert(trace);
+            }
+            hash
+        });
+        let inner_rec = InnerOKRecord {
+            received_millis,
+            logged_millis: record.logged_millis,
+            level: record.level,
+            msg_id: self.msgs.id(&record.message),
+            ip_id: self.ips.id(&record.ip),
+            source_id: self.sources.id(source),
+            endpoint_id: extracted.endpoint.map(|endpoint| self.endpoints.id(&endpoint)),
+            status: extracted.status,
+            client_id: extracted.client.map(|client| self.clients.id(&client)),
+            trace_id,
+            group_id,
+            facility: record.facility(),
+        };
+        if let Some(seq) = extracted.sequence {
+            self.sequences.entry(inner_rec.source_id).or_default().push(seq);
+        }
+
+        // Only a message seen for the first time needs to be checked.
+        if self.msgs.len() > known_msgs && self.mute_patterns.iter().any(|re| re.is_match(&record.message)) {
+            self.muted.insert(inner_rec.msg_id);
+        }
+        InnerMessageTotals::once(record.logged_millis).add_to(&mut self.msg_totals, inner_rec.msg_id);
+
+        if self.example_budget > 0 {
+            keep_example(
+                self.examples.entry(inner_rec.msg_id).or_default(),
+                self.example_budget,
+                InnerExample {
+                    logged_millis: inner_rec.logged_millis,
+                    level:         inner_rec.level,
+                    ip_id:         inner_rec.ip_id,
+                    source_id:     inner_rec.source_id,
+                    provenance,
+                },
+            );
+        }
+
+        self.records.push_front(self.stat.count_inner_rec(InnerRecord::OK(inner_rec), Act::Inc));
+
+        self.cleanup_and_adjust();
+    }
+
+    /// Account an input line which didn't make a record.
+    pub fn push_error(&mut self, received_millis: i64, err_type: ErrorType, source: &str) {
+        let inner_err = InnerErrRecord {
+            received_millis,
+            logged_millis: match self.clock {
+                Clock::Receive => received_millis,
+                Clock::Log => self.log_now,
+            },
+            err_type,
+            source_id: self.sources.id(source),
+        };
+
+        self.records.push_front(self.stat.count_inner_rec(InnerRecord::Err(inner_err), Act::Inc));
+    }
+
+    /// Drop records which fell out of the window, then update the rates and adjust the window size.
+    pub fn cleanup_and_adjust(&mut self) {
+        let now = self.now_millis();
+        let clock = self.clock;
+        let oldest = now - self.stat.window as i64;
+
+        let mut recalc = true;
+
+        while recalc {
+            recalc = false;
+
+            let stat_snapshot = &mut self.stat;
+
+            while let Some(rec) = self.records.back() {
+                if rec.timestamp_millis(clock) < oldest {
+                    stat_snapshot.count_inner_rec(self.records.pop_back().unwrap(), Act::Dec);
+                }
+                else {
+                    break;
+                }
+            }
+            stat_snapshot.entries = self.records.len() as i64;
+
+            stat_snapshot.collected_interval = self.records.front().map_or(0, |r| r.timestamp_millis(clock))
+                - self.records.back().map_or(0, |r| r.timestamp_millis(clock));
+
+            // We need per second, not per millisecond
+            if stat_snapshot.collected_interval > 100 {
+                stat_snapshot.rate = stat_snapshot.entries as f64 / (stat_snapshot.collected_interval as f64 / 1000.0);
+            }
+            if stat_snapshot.collected_interval >= 1000 {
+                // Let the stats stabilize first
+                stat_snapshot.peak_rate = stat_snapshot.rate.max(stat_snapshot.peak_rate);
+            }
+            stat_snapshot.error_rate = stat_snapshot.errors as f32 / stat_snapshot.entries as f32;
+
+            self.recalc_weights(now);
+
+            if self.trace_ids.len() > 2 * self.stat.trace_errors.len() {
+                let trace_errors = &self.stat.trace_errors;
+                self.trace_ids.retain(|hash, _| trace_errors.contains_key(hash));
+            }
+
+            let stat_snapshot = &mut self.stat;
+            // Adjust window if necessary. The technical spec requires, say, 30 secs window for 2,500 entries/sec.
+            // Let's make it weighted dynamic decision. So, 2500*30 = 75,000 entries per window. Rust can do much better,
+            // let's round it to 100k and try keeping the records queue size around that.
+            if stat_snapshot.rate > 0.0 {
+                // Calculate expected buffer size
+                let expected_buffer_size = stat_snapshot.rate * (stat_snapshot.window as f64 / 1000.0);
+                if !(75_000.0..=100_000.0).contains(&expected_buffer_size) {
+                    let new_window = ((100_000.0 / stat_snapshot.rate) as usize)
+                        .min(MIN_WINDOW)
+                        .max(MAX_WINDOW)
+                        * 1000;
+                    if new_window != stat_snapshot.window {
+                        stat_snapshot.previous_window = stat_snapshot.window;
+                        stat_snapshot.window = new_window;
+                        recalc = true;
+                        eprintln!("NEED RECALC");
+                    }
+                }
+            }
+        }
+
+        #[cfg(feature = "invariants")]
+        for violation in self.invariant_violations(oldest) {
+            eprintln!("Stats invariant violated: {}", violation);
+        }
+    }
+
+    /// Recount the statistics from the records in the window and report where the maintained counters disagree.
+    /// `oldest` is the expiry bound of the last cleanup. It is slow, only meant to catch bugs in the incremental
+    /// counting.
+    #[cfg(feature = "invariants")]
+    pub fn invariant_violations(&self, oldest: i64) -> Vec<String> {
+        let stat = &self.stat;
+        let mut violations = Vec::new();
+        let mut check = |ok: bool, what: &dyn Fn() -> String| {
+            if !ok {
+                violations.push(what());
+            }
+        };
+
+        let mut expected = StatsSnapshot {
+            bucket_ms: stat.bucket_ms,
+            clock: stat.clock,
+            ..StatsSnapshot::default()
+        };
+        for rec in self.records.iter() {
+            expected.count_inner_rec(rec, Act::Inc);
+        }
+
+        check(stat.entries == self.records.len() as i64, &|| {
+            format!("{} entries counted, {} records in the window", stat.entries, self.records.len())
+        });
+        for (name, actual, expected) in [
+            ("errors", stat.errors, expected.errors),
+            ("infos", stat.infos, expected.infos),
+            ("debugs", stat.debugs, expected.debugs),
+            ("malformed", stat.malformed, expected.malformed),
+            ("bad timestamps", stat.bad_timestamps, expected.bad_timestamps),
+            ("unknown levels", stat.unknown_levels, expected.unknown_levels),
+            ("oversized", stat.oversized, expected.oversized),
+            ("decode errors", stat.decode_errors, expected.decode_errors),
+            ("late arrivals", stat.late_arrivals, expected.late_arrivals),
+        ] {
+            check(actual >= 0, &|| format!("{} went negative: {}", name, actual));
+            check(actual == expected, &|| format!("{} is {}, the records make {}", name, actual, expected));
+        }
+        check(stat.lag_sum == expected.lag_sum, &|| {
+            format!("lag sum is {}, the records make {}", stat.lag_sum, expected.lag_sum)
+        });
+
+        for (name, actual, expected) in [
+            ("error message", &stat.error_msg_counts, &expected.error_msg_counts),
+            ("IP", &stat.ip_counts, &expected.ip_counts),
+            ("source", &stat.source_counts, &expected.source_counts),
+        ] {
+            // Counts which dropped to zero may be left in place.
+            let nonzero = actual.iter().filter(|(_, count)| **count != 0).map(|(id, count)| (*id, *count));
+            check(actual.values().all(|count| *count >= 0), &|| format!("{} counts went negative", name));
+            check(nonzero.collect::<HashMap<_, _>>() == *expected, &|| {
+                format!("{} counts differ from the records", name)
+            });
+        }
+
+        let per_bucket_sum = stat.error_msg_per_bucket.values().flat_map(HashMap::values).sum::<i64>();
+        check(per_bucket_sum == stat.errors, &|| {
+            format!("per-bucket error messages sum to {}, errors are {}", per_bucket_sum, stat.errors)
+        });
+        check(stat.error_msg_per_bucket == expected.error_msg_per_bucket, &|| {
+            "per-bucket error messages differ from the records".to_string()
+        });
+        let ok_records = stat.errors + stat.infos + stat.debugs;
+        let ip_sum = stat.ip_counts.values().sum::<i64>();
+        check(ip_sum == ok_records, &|| {
+            format!("IP counts sum to {}, there are {} well-formed records", ip_sum, ok_records)
+        });
+        check(stat.source_lags == expected.source_lags, &|| {
+            "per-source lags differ from the records".to_string()
+        });
+        check(stat.endpoint_counts == expected.endpoint_counts, &|| {
+            "per-endpoint counts differ from the records".to_string()
+        });
+        check(stat.trace_errors == expected.trace_errors, &|| {
+            "per-request errors differ from the records".to_string()
+        });
+        check(stat.client_counts == expected.client_counts, &|| {
+            "per-client counts differ from the records".to_string()
+        });
+        check(stat.group_counts == expected.group_counts, &|| {
+            "per-group counts differ from the records".to_string()
+        });
+        check(stat.status_counts == expected.status_counts, &|| {
+            "HTTP status counts differ from the records".to_string()
+        });
+        check(stat.facility_counts == expected.facility_counts, &|| {
+            "per-facility counts differ from the records".to_string()
+        });
+        check(stat.entries_per_bucket == expected.entries_per_bucket, &|| {
+            "per-bucket entries differ from the records".to_string()
+        });
+        check(stat.status_per_bucket == expected.status_per_bucket, &|| {
+            "per-bucket HTTP status counts differ from the records".to_string()
+        });
+
+        if let Some(rec) = self.records.back() {
+            let ts = rec.timestamp_millis(self.clock);
+            check(ts >= oldest, &|| format!("the oldest record at {} is past the expiry bound {}", ts, oldest));
+        }
+
+        violations
+    }
+
+    /// Resolve the statistics into a self-contained view. Data maintained outside of the window, like `sampled_out`,
+    /// `below_min_level`, `backlog`, `drop_rate`, `throttled`, `truncated_datagrams`, `escalated`, `maintenance`,
+    /// `input_errors`, `failures`, `output_drops`, `files`, `heartbeats`, `suspicious`, `attacks`, `slo`, `hourly`,
+    /// `contexts` and `run`, is left empty.
+    pub fn view(&self, now: DateTime<Local>) -> SnapshotView {
+        let stat_snapshot = &self.stat;
+        let top_errors = self
+            .top_errors(3)
+            .into_iter()
+            .map(|(message, count)| MessageCount { message, count })
+            .collect::<Vec<_>>();
+        let trending = self
+            .trending_errors(3)
+            .into_iter()
+            .map(|(message, rate)| MessageRate { message, rate })
+            .collect::<Vec<_>>();
+        let examples = top_errors
+            .iter()
+            .map(|top| &top.message)
+            .chain(trending.iter().map(|trending| &trending.message))
+            .map(|message| (message.clone(), self.examples(message)))
+            .filter(|(_, examples)| !examples.is_empty())
+            .collect();
+        let (bucket_p99, bucket_mean) = self.bucket_percentile(0.99);
+        SnapshotView {
+            timestamp:             now,
+            entries:               stat_snapshot.entries,
+            collected_interval_ms: stat_snapshot.collected_interval,
+            window_ms:             stat_snapshot.window,
+            current_rate:          stat_snapshot.current_rate(),
+            bucket_ms:             stat_snapshot.bucket_ms,
+            rate:                  stat_snapshot.rate,
+            peak_rate:             stat_snapshot.peak_rate,
+            avg_lag:               stat_snapshot.avg_lag(),
+            // It is NaN for an empty window, which doesn't survive a JSON round trip.
+            error_rate:            if stat_snapshot.entries > 0 {
+                stat_snapshot.error_rate
+            }
+            else {
+                0.0
+            },
+            errors:                stat_snapshot.errors,
+            infos:                 stat_snapshot.infos,
+            debugs:                stat_snapshot.debugs,
+            malformed:             stat_snapshot.malformed,
+            bad_timestamps:        stat_snapshot.bad_timestamps,
+            unknown_levels:        stat_snapshot.unknown_levels,
+            oversized:             stat_snapshot.oversized,
+            decode_errors:         stat_snapshot.decode_errors,
+            late_arrivals:         stat_snapshot.late_arrivals,
+            distinct_ips:          stat_snapshot.ip_counts.len(),
+            muted:                 self.muted_errors(),
+            sampled_out:           0,
+            below_min_level:       0,
+            backlog:               0,
+            drop_rate:             0.0,
+            throttled:             BTreeMap::new(),
+            truncated_datagrams:   BTreeMap::new(),
+            escalated:             BTreeMap::new(),
+            maintenance:           false,
+            input_errors:          BTreeMap::new(),
+            failures:              Vec::new(),
+            output_drops:          BTreeMap::new(),
+            files:                 BTreeMap::new(),
+            sources:               self.top_sources(usize::MAX).into_iter().collect(),
+            source_skew:           self.source_skew(),
+            top_errors,
+            top_endpoints:         self.top_endpoints(5),
+            affected_requests:     self.stat.trace_errors.len(),
+            top_requests:          self.top_requests(5),
+            top_clients:           self.top_clients(5),
+            groups:                self.group_tables(GROUP_ROWS),
+            sequences:             self
+                .sequences
+                .iter()
+                .map(|(source_id, tracker)| (self.source_by_id(*source_id), tracker.stats()))
+                .collect(),
+            heartbeats:            Vec::new(),
+            suspicious:            Vec::new(),
+            attacks:               Vec::new(),
+            slo:                   None,
+            hourly:                Vec::new(),
+            bucket_p99,
+            bucket_mean,
+            statuses:              self.statuses(),
+            status_series:         self.status_series(STATUS_SERIES_LEN),
+            facilities:            self.facilities(),
+            trending,
+            examples,
+            contexts:              Vec::new(),
+            run:                   RunInfo::default(),
+        }
+    }
+
+    /// Latest occurrences of the message, the newest last.
+    pub fn examples(&self, message: &str) -> Vec<Example> {
+        let Some(examples) = self.msgs.lookup(message).and_then(|id| self.examples.get(&id))
+        else {
+            return Vec::new();
+        };
+        examples
+            .iter()
+            .filter_map(|example| {
+                let logged = DateTime::from_timestamp_millis(example.logged_millis)?;
+                let ip = self.ips.get(example.ip_id).unwrap_or("N/A").to_string();
+                Some(Example {
+                    timestamp: logged.with_timezone(&Local),
+                    line: format!(
+                        "[{}] {:?} - IP:{} {}",
+                        logged.format("%Y-%m-%dT%H:%M:%SZ"),
+                        example.level,
+                        ip,
+                        message
+                    ),
+                    ip,
+                    source: self.source_by_id(example.source_id),
+                    provenance: example.provenance.clone(),
+                })
+            })
+            .collect()
+    }
+
+    /// Every message encountered since the start with its ID and totals, in the order they were first seen.
+    pub fn dictionary(&self) -> Vec<DictionaryEntry> {
+        let time = |millis| DateTime::from_timestamp_millis(millis).unwrap_or_default().with_timezone(&Local);
+        self.msgs
+            .iter()
+            .filter_map(|(id, message)| {
+                let totals = self.msg_totals.get(&id)?;
+                Some(DictionaryEntry {
+                    id,
+                    message: message.to_string(),
+                    count: totals.count,
+                    first_seen: time(totals.first_millis),
+                    last_seen: time(totals.last_millis),
+                })
+            })
+            .collect()
+    }
+
+    /// Every record in the window with its fields resolved, the oldest first. Decodes all the records, it is costly.
+    pub fn rows(&self) -> Vec<WindowRow> {
+        let resolve = |dict: &Dictionary, id: u64| dict.get(id).map(str::to_string);
+        let mut rows = self
+            .records
+            .iter()
+            .map(|rec| match rec {
+                InnerRecord::OK(ok) => {
+                    let message = self.msgs.get(ok.msg_id);
+                    let mut tags = Vec::new();
+                    if let Some(key) = ok.group_id.and_then(|id| self.groups.get(id)) {
+                        tags.extend(
+                            self.group_by
+                                .iter()
+                                .zip(key.split(GROUP_KEY_SEPARATOR))
+                                .filter(|(_, value)| !value.is_empty())
+                                .map(|(group, value)| (group.to_string(), value.to_string())),
+                        );
+                    }
+                    if let Some(facility) = ok.facility {
+                        let name = FACILITIES.get(facility as usize).map_or(facility.to_string(), |n| n.to_string());
+                        tags.push(("facility".to_string(), name));
+                    }
+                    WindowRow {
+                        logged_millis:   ok.logged_millis,
+                        received_millis: ok.received_millis,
+                        level:           Some(format!("{:?}", ok.level)),
+                        rejected:        None,
+                        msg_id:          Some(ok.msg_id),
+                        message:         message.map(str::to_string),
+                        template:        message.map(message_template),
+                        ip:              resolve(&self.ips, ok.ip_id),
+                        source:          resolve(&self.sources, ok.source_id).unwrap_or_default(),
+                        endpoint:        ok.endpoint_id.and_then(|id| resolve(&self.endpoints, id)),
+                        status:          ok.status,
+                        client:          ok.client_id.and_then(|id| resolve(&self.clients, id)),
+                        tags,
+                    }
+                }
+                InnerRecord::Err(err) => WindowRow {
+                    logged_millis:   err.logged_millis,
+                    received_millis: err.received_millis,
+                    level:           None,
+                    rejected:        Some(err.err_type.name().to_string()),
+                    msg_id:          None,
+                    message:         None,
+                    template:        None,
+                    ip:              None,
+                    source:          resolve(&self.sources, err.source_id).unwrap_or_default(),
+                    endpoint:        None,
+                    status:          None,
+                    client:          None,
+                    tags:            Vec::new(),
+                },
+            })
+            .collect::<Vec<_>>();
+        rows.reverse();
+        rows
+    }
+
+    pub fn top_errors(&self, count: usize) -> Vec<(String, i64)> {
+        let mut msgs = self
+            .stat
+            .error_msg_counts
+            .iter()
+            .filter(|(id, _)| !self.muted.contains(id))
+            .collect::<Vec<_>>();
+        msgs.sort_by(|a, b| b.1.cmp(a.1));
+        msgs.iter()
+            .take(count)
+            .map(|(id, cnt)| (self.msg_by_id(**id), **cnt))
+            .collect()
+    }
+
+    /// Endpoints with errors in the window, the most failing first.
+    pub fn top_endpoints(&self, count: usize) -> Vec<EndpointCount> {
+        let mut endpoints = self
+            .stat
+            .endpoint_counts
+            .iter()
+            .filter(|(_, (_, errors))| *errors > 0)
+            .map(|(id, (requests, errors))| EndpointCount {
+                endpoint: self.endpoints.get(*id).unwrap_or("N/A").to_string(),
+                requests: *requests,
+                errors:   *errors,
+            })
+            .collect::<Vec<_>>();
+        endpoints.sort_by(|a, b| b.errors.cmp(&a.errors).then(b.requests.cmp(&a.requests)));
+        endpoints.truncate(count);
+        endpoints
+    }
+
+    pub fn top_sources(&self, count: usize) -> Vec<(String, i64)> {
+        let mut sources = self.stat.source_counts.iter().collect::<Vec<_>>();
+        sources.sort_by(|a, b| b.1.cmp(a.1));
+        sources
+            .iter()
+            .take(count)
+            .map(|(id, cnt)| (self.source_by_id(**id), **cnt))
+            .collect()
+    }
+
+    pub fn trending_errors(&self, count: usize) -> Vec<(String, f64)> {
+        let mut rates = self
+            .stat
+            .error_msg_rates
+            .iter()
+            .filter(|(id, _)| !self.muted.contains(id))
+            .collect::<Vec<_>>();
+        rates.sort_by(|a, b| b.1.partial_cmp(a.1).unwrap_or(Ordering::Equal));
+        rates
+            .iter()
+            .take(count)
+            .map(|(id, rate)| (self.msg_by_id(**id), **rate))
+            .collect()
+    }
+
+    /// Requests with errors in the window, the most failing first.
+    pub fn top_requests(&self, count: usize) -> Vec<RequestCount> {
+        let mut requests = self
+            .stat
+            .trace_errors
+            .iter()
+            .map(|(hash, errors)| RequestCount {
+                request: self.trace_ids.get(hash).cloned().unwrap_or_else(|| "N/A".to_string()),
+                errors:  *errors,
+            })
+            .collect::<Vec<_>>();
+        requests.sort_by(|a, b| b.errors.cmp(&a.errors).then_with(|| a.request.cmp(&b.request)));
+        requests.truncate(count);
+        requests
+    }
+
+    /// Clients with errors in the window, the most failing first.
+    pub fn top_clients(&self, count: usize) -> Vec<ClientCount> {
+        let mut clients = self
+            .stat
+            .client_counts
+            .iter()
+            .filter(|(_, (_, errors))| *errors > 0)
+            .map(|(id, (requests, errors))| {
+                let client = self.clients.get(*id).and_then(|client| client.rsplit_once('/'));
+                let (family, version) = client.unwrap_or_default();
+                ClientCount {
+                    family:   family.to_string(),
+                    version:  version.to_string(),
+                    requests: *requests,
+                    errors:   *errors,
+                }
+            })
+            .collect::<Vec<_>>();
+        clients.sort_by(|a, b| b.errors.cmp(&a.errors).then(b.requests.cmp(&a.requests)));
+        clients.truncate(count);
+        clients
+    }
+
+    /// Tables of the `group_by` dimensions, with up to `count` values each. Records without a value of a dimension are
+    /// left out of its table.
+    pub fn group_tables(&self, count: usize) -> Vec<GroupTable> {
+        let mut tables = vec![HashMap::<&str, (i64, i64)>::new(); self.group_by.len()];
+        for (id, (records, errors)) in self.stat.group_counts.iter() {
+            let Some(key) = self.groups.get(*id)
+            else {
+                continue;
+            };
+            for (table, value) in tables.iter_mut().zip(key.split(GROUP_KEY_SEPARATOR)) {
+                if !value.is_empty() {
+                    let counts = table.entry(value).or_default();
+                    counts.0 += records;
+                    counts.1 += errors;
+                }
+            }
+        }
+        self.group_by
+            .iter()
+            .zip(tables)
+            .map(|(group, table)| {
+                let mut rows = table
+                    .into_iter()
+                    .map(|(key, (records, errors))| GroupCount {
+                        key: key.to_string(),
+                        records,
+                        errors,
+                    })
+                    .collect::<Vec<_>>();
+                rows.sort_by(|a, b| b.errors.cmp(&a.errors).then(b.records.cmp(&a.records)).then(a.key.cmp(&b.key)));
+                rows.truncate(count);
+                GroupTable {
+                    name: group.to_string(),
+                    rows,
+                }
+            })
+            .collect()
+    }
+
+    /// Number of records per HTTP status class seen in the window, like `5xx`.
+    pub fn statuses(&self) -> BTreeMap<String, i64> {
+        STATUS_CLASSES
+            .iter()
+            .zip(self.stat.status_counts.iter())
+            .filter(|(_, count)| **count > 0)
+            .map(|(class, count)| (class.to_string(), *count))
+            .collect()
+    }
+
+    /// Records per syslog facility and level seen in the window, by facility name like `daemon`.
+    pub fn facilities(&self) -> BTreeMap<String, FacilityCount> {
+        self.stat
+            .facility_counts
+            .iter()
+            .map(|(facility, counts)| {
+                let name = FACILITIES.get(*facility as usize).map_or(facility.to_string(), |name| name.to_string());
+                let count = FacilityCount {
+                    errors: counts[0],
+                    infos:  counts[1],
+                    debugs: counts[2],
+                };
+                (name, count)
+            })
+            .collect()
+    }
+
+    /// Records per HTTP status class in `len` equal periods of the window up to the latest bucket with a status, the
+    /// oldest first.
+    pub fn status_series(&self, len: usize) -> BTreeMap<String, Vec<i64>> {
+        let Some(latest) = self.stat.status_per_bucket.keys().max()
+        else {
+            return BTreeMap::new();
+        };
+        let span = (self.stat.window as i64 / self.stat.bucket_ms).max(len as i64);
+        let mut series = vec![vec![0; len]; STATUS_CLASSES.len()];
+        for (bucket, counts) in self.stat.status_per_bucket.iter() {
+            let age = latest - bucket;
+            if age >= span {
+                continue;
+            }
+            let period = len - 1 - (age * len as i64 / span) as usize;
+            for (class, count) in counts.iter().enumerate() {
+                series[class][period] += count;
+            }
+        }
+        STATUS_CLASSES
+            .iter()
+            .zip(series)
+            .enumerate()
+            .filter(|(idx, _)| self.stat.status_counts[*idx] > 0)
+            .map(|(_, (class, counts))| (class.to_string(), counts))
+            .collect()
+    }
+
+    /// Entries of a bucket at the percentile, from 0 to 1, of the buckets from the oldest to the newest in the window,
+    /// and the mean of the buckets. Buckets without entries count too, so gaps between bursts tell. Both are zero
+    /// while the window spans less than `MIN_BURST_BUCKETS`.
+    pub fn bucket_percentile(&self, percentile: f64) -> (i64, f64) {
+        let buckets = &self.stat.entries_per_bucket;
+        let (Some(oldest), Some(newest)) = (buckets.keys().min(), buckets.keys().max())
+        else {
+            return (0, 0.0);
+        };
+        let span = (newest - oldest + 1) as usize;
+        if span < MIN_BURST_BUCKETS {
+            return (0, 0.0);
+        }
+        let mut counts = buckets.values().copied().collect::<Vec<_>>();
+        counts.resize(span, 0);
+        counts.sort_unstable();
+        let rank = ((percentile * span as f64).ceil() as usize).clamp(1, span);
+        (counts[rank - 1], self.stat.entries as f64 / span as f64)
+    }
+
+    /// Average difference between log and receive times per source, in seconds. Positive when the source clock is
+    /// ahead.
+    pub fn source_skew(&self) -> BTreeMap<String, f64> {
+        self.stat
+            .source_lags
+            .iter()
+            .map(|(id, (lag_sum, count))| (self.source_by_id(*id), -(*lag_sum as f64) / *count as f64 / 1000.0))
+            .collect()
+    }
+
+    /// Number of ERROR entries in the window with acknowledged messages.
+    pub fn muted_errors(&self) -> i64 {
+        self.muted
+            .iter()
+            .filter_map(|id| self.stat.error_msg_counts.get(id))
+            .sum()
+    }
+
+    /// Number of buckets with error messages tracked for trending.
+    pub fn error_buckets(&self) -> usize {
+        self.stat.error_msg_per_bucket.len()
+    }
+
+    fn msg_by_id(&self, id: u64) -> String {
+        self.msgs.get(id).unwrap_or("N/A").to_string()
+    }
+
+    fn source_by_id(&self, id: u64) -> String {
+        self.sources.get(id).unwrap_or("N/A").to_string()
+    }
+
+    fn recalc_weights(&mut self, now: i64) {
+        if self.records.is_empty() {
+            return;
+        }
+        let window_size = (self.records.front().unwrap().log_timestamp_millis()
+            - self.records.back().unwrap().log_timestamp_millis())
+        .max(MSG_ERROR_WINDOW);
+
+        let stat_snapshot = &mut self.stat;
+
+        // We need at least 2 seconds of data to calculate the weights
+        if window_size < 2000 {
+            return;
+        }
+
+        let mut buckets = stat_snapshot.error_msg_per_bucket.keys().copied().collect::<Vec<_>>();
+        buckets.sort_by(|a, b| b.cmp(a));
+
+        // Group by MSG_ERROR_WINDOW seconds from now. We need the last two groups only.
+        // index 0 is for newer, 1 is for older
+        let mut grouped = vec![HashMap::new(); 2];
+        let base_time_millis = [(now - window_size / 2), now];
+
+        for bucket in buckets.iter().copied() {
+            let msec = bucket * stat_snapshot.bucket_ms;
+
+            let group_idx = ((now - msec) * 2 / window_size) as usize;
+
+            // Too old messages are not interesting
+            if group_idx > 1 {
+                break;
+            }
+
+            let counts = stat_snapshot.error_msg_per_bucket.get(&bucket).unwrap();
+
+            for (msg_id, count) in counts.iter() {
+                let weight = grouped[group_idx].entry(*msg_id).or_insert(0.0);
+                *weight += f64::consts::E.powf((base_time_millis[group_idx] - msec) as f64 / window_size as f64)
+                    * (*count as f64);
+            }
+        }
+
+        for msg_id in stat_snapshot.error_msg_counts.keys() {
+            stat_snapshot.error_msg_rates.insert(
+                *msg_id,
+                if let Some(older) = grouped[1].get(msg_id) {
+                    grouped[0].get(msg_id).unwrap_or(&0.0) / *older
+                }
+                else {
+                    0.0
+                },
+            );
+        }
+    }
+}
+
+/// Values of the `group_by` dimensions of the record, joined into one key.
+fn group_key(group_by: &[GroupBy], record: &ParsedLine, source: &str, extracted: &Extracted) -> String {
+    group_by
+        .iter()
+        .map(|group| group.key(&record.ip, source, &record.message, extracted).unwrap_or_default())
+        .collect::<Vec<_>>()
+        .join(GROUP_KEY_SEPARATOR)
+}
+
+/// Keep the example as the latest occurrence of its message, forgetting the oldest ones over the budget.
+fn keep_example(examples: &mut VecDeque<InnerExample>, budget: usize, example: InnerExample) {
+    examples.push_back(example);
+    while examples.len() > budget {
+        examples.pop_front();
+    }
+}
+
+/// The message with the words containing digits, like IDs, numbers and addresses, replaced by `<*>`, so that messages
+/// differing only in those share a template.
+fn message_template(message: &str) -> String {
+    message
+        .split(' ')
+        .map(|word| if word.bytes().any(|b| b.is_ascii_digit()) { "<*>" } else { word })
+        .collect::<Vec<_>>()
+        .join(" ")
+}
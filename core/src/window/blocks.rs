@@ -0,0 +1,313 @@
+use std::collections::VecDeque;
+
+use super::{InnerErrRecord, InnerOKRecord, InnerRecord};
+use crate::types::{ErrorType, Level};
+
+/// Number of records encoded together.
+const BLOCK_LEN: usize = 1024;
+
+const TAG_ERR: u8 = 0x01;
+const TAG_ENDPOINT: u8 = 0x08;
+const TAG_STATUS: u8 = 0x10;
+const TAG_CLIENT: u8 = 0x20;
+const TAG_TRACE: u8 = 0x40;
+const TAG_GROUP: u8 = 0x80;
+
+/// The records of the window, newest first, like a `VecDeque` pushed at the front and expired from the back. Only the
+/// newest records, still being added, and the oldest ones, being expired, are kept as they are. The others are
+/// encoded in blocks: timestamps as deltas, message IDs as indices into a table of the block and the rest of the IDs
+/// as varints, which takes a fraction of the memory of the records. With the `lz4` feature the blocks are compressed
+/// too.
+///
+/// The memory of the blocks is recycled as the window slides: a block which is decoded for expiry passes its buffers
+/// on to the next block to be encoded, and the decoded records go to the same queue every time. Records are never
+/// allocated one by one, so there is no allocator churn at high ingest rates.
+#[derive(Default)]
+pub(super) struct RecordBlocks {
+    /// The newest records, the oldest of them first
+    head:    Vec<InnerRecord>,
+    /// Encoded records, the newest block first
+    cold:    VecDeque<Block>,
+    /// The oldest records, the oldest first. Never empty unless there are no records at all.
+    tail:    VecDeque<InnerRecord>,
+    len:     usize,
+    /// Buffers of the last decoded block, for the next one to be encoded
+    spare:   Option<(Vec<u64>, Vec<u8>)>,
+    scratch: Scratch,
+}
+
+struct Block {
+    /// The newest record of the block, so the newest one of the window is known without decoding
+    newest:  InnerRecord,
+    /// Message IDs referred to by the records
+    msgs:    Vec<u64>,
+    data:    Vec<u8>,
+    /// Size of the data before compression
+    #[cfg(feature = "lz4")]
+    raw_len: usize,
+}
+
+/// Room for the records of a block between encoding and compression.
+#[derive(Default)]
+struct Scratch {
+    #[cfg(feature = "lz4")]
+    raw: Vec<u8>,
+}
+
+impl RecordBlocks {
+    pub(super) fn len(&self) -> usize {
+        self.len
+    }
+
+    pub(super) fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// The newest record.
+    pub(super) fn front(&self) -> Option<&InnerRecord> {
+        self.head
+            .last()
+            .or_else(|| self.cold.front().map(|block| &block.newest))
+            .or_else(|| self.tail.back())
+    }
+
+    /// The oldest record.
+    pub(super) fn back(&self) -> Option<&InnerRecord> {
+        self.tail.front()
+    }
+
+    pub(super) fn push_front(&mut self, rec: InnerRecord) {
+        self.len += 1;
+        if self.tail.is_empty() {
+            self.tail.push_back(rec);
+            return;
+        }
+        self.head.push(rec);
+        if self.head.len() == BLOCK_LEN {
+            let (msgs, data) = self.spare.take().unwrap_or_default();
+            let block = Block::encode(&self.head, msgs, data, &mut self.scratch);
+            self.cold.push_front(block);
+            self.head.clear();
+        }
+    }
+
+    pub(super) fn pop_back(&mut self) -> Option<InnerRecord> {
+        let rec = self.tail.pop_front()?;
+        self.len -= 1;
+        if self.tail.is_empty() {
+            match self.cold.pop_back() {
+                Some(block) => {
+                    block.decode_into(&mut self.tail, &mut self.scratch);
+                    self.spare = Some((block.msgs, block.data));
+                }
+                None => self.tail.extend(self.head.drain(..)),
+            }
+        }
+        Some(rec)
+    }
+
+    /// All records, newest first. Decodes every block, only meant for checks and queries.
+    pub(super) fn iter(&self) -> impl Iterator<Item = InnerRecord> + '_ {
+        self.head
+            .iter()
+            .rev()
+            .cloned()
+            .chain(self.cold.iter().flat_map(|block| {
+                let mut records = VecDeque::new();
+                block.decode_into(&mut records, &mut Scratch::default());
+                records.into_iter().rev()
+            }))
+            .chain(self.tail.iter().rev().cloned())
+    }
+}
+
+impl Block {
+    // `records` are the oldest first. The buffers are reused whatever they contain.
+    #[cfg_attr(not(feature = "lz4"), allow(unused_variables))]
+    fn encode(records: &[InnerRecord], mut msgs: Vec<u64>, mut data: Vec<u8>, scratch: &mut Scratch) -> Self {
+        msgs.clear();
+        #[cfg(feature = "lz4")]
+        let raw = &mut scratch.raw;
+        #[cfg(not(feature = "lz4"))]
+        let raw = &mut data;
+        raw.clear();
+        write_records(records, &mut msgs, raw);
+
+        #[cfg(feature = "lz4")]
+        let raw_len = {
+            data.resize(lz4_flex::block::get_maximum_output_size(raw.len()), 0);
+            let len = lz4_flex::block::compress_into(raw, &mut data).expect("the output is large enough");
+            data.truncate(len);
+            raw.len()
+        };
+
+        Self {
+            newest: records.last().unwrap().clone(),
+            msgs,
+            data,
+            #[cfg(feature = "lz4")]
+            raw_len,
+        }
+    }
+
+    // Append the records, the oldest first, to `records`.
+    #[cfg_attr(not(feature = "lz4"), allow(unused_variables))]
+    fn decode_into(&self, records: &mut VecDeque<InnerRecord>, scratch: &mut Scratch) {
+        #[cfg(feature = "lz4")]
+        let data: &[u8] = {
+            scratch.raw.resize(self.raw_len, 0);
+            lz4_flex::block::decompress_into(&self.data, &mut scratch.raw).expect("a block of the window is corrupted");
+            &scratch.raw
+        };
+        #[cfg(not(feature = "lz4"))]
+        let data: &[u8] = &self.data;
+
+        let mut pos = 0;
+        let mut prev_received = 0;
+        while pos < data.len() {
+            let tag = data[pos];
+            pos += 1;
+            let received_millis = prev_received + unzigzag(read_varint(data, &mut pos));
+            let logged_millis = received_millis - unzigzag(read_varint(data, &mut pos));
+            let source_id = read_varint(data, &mut pos);
+            prev_received = received_millis;
+
+            if tag & TAG_ERR != 0 {
+                records.push_back(InnerRecord::Err(InnerErrRecord {
+                    received_millis,
+                    logged_millis,
+                    err_type: ErrorType::ALL[(tag >> 1) as usize & 0x07],
+                    source_id,
+                }));
+                continue;
+            }
+
+            let level = match (tag >> 1) & 0x03 {
+                0 => Level::INFO,
+                1 => Level::ERROR,
+                _ => Level::DEBUG,
+            };
+            let msg_id = self.msgs[read_varint(data, &mut pos) as usize];
+            let ip_id = read_varint(data, &mut pos);
+            let facility = (ip_id & 1 != 0).then(|| data[pos]);
+            pos += facility.is_some() as usize;
+            let ip_id = ip_id >> 1;
+            let mut optional = |flag: u8| (tag & flag != 0).then(|| read_varint(data, &mut pos));
+            let endpoint_id = optional(TAG_ENDPOINT);
+            let status = optional(TAG_STATUS).map(|status| status as u16);
+            let client_id = optional(TAG_CLIENT);
+            let trace_id = (tag & TAG_TRACE != 0).then(|| {
+                let bytes = data[pos..pos + 8].try_into().unwrap();
+                pos += 8;
+                u64::from_le_bytes(bytes)
+            });
+            let group_id = (tag & TAG_GROUP != 0).then(|| read_varint(data, &mut pos));
+            records.push_back(InnerRecord::OK(InnerOKRecord {
+                received_millis,
+                logged_millis,
+                level,
+                msg_id,
+                ip_id,
+                source_id,
+                endpoint_id,
+                status,
+                client_id,
+                trace_id,
+                group_id,
+                facility,
+            }));
+        }
+    }
+}
+
+// Encode the records, collecting their messages into `msgs`.
+fn write_records(records: &[InnerRecord], msgs: &mut Vec<u64>, data: &mut Vec<u8>) {
+    let mut prev_received = 0;
+    for rec in records {
+        let (received_millis, logged_millis, source_id) = match rec {
+            InnerRecord::OK(ok) => (ok.received_millis, ok.logged_millis, ok.source_id),
+            InnerRecord::Err(err) => (err.received_millis, err.logged_millis, err.source_id),
+        };
+        match rec {
+            InnerRecord::OK(ok) => {
+                let level = match ok.level {
+                    Level::INFO => 0,
+                    Level::ERROR => 1,
+                    Level::DEBUG => 2,
+                };
+                let mut tag = level << 1;
+                for (present, flag) in [
+                    (ok.endpoint_id.is_some(), TAG_ENDPOINT),
+                    (ok.status.is_some(), TAG_STATUS),
+                    (ok.client_id.is_some(), TAG_CLIENT),
+                    (ok.trace_id.is_some(), TAG_TRACE),
+                    (ok.group_id.is_some(), TAG_GROUP),
+                ] {
+                    if present {
+                        tag |= flag;
+                    }
+                }
+                data.push(tag);
+            }
+            InnerRecord::Err(err) => {
+                let err_type = ErrorType::ALL.iter().position(|t| *t == err.err_type).unwrap() as u8;
+                data.push(TAG_ERR | err_type << 1);
+            }
+        }
+        write_varint(data, zigzag(received_millis - prev_received));
+        write_varint(data, zigzag(received_millis - logged_millis));
+        write_varint(data, source_id);
+        prev_received = received_millis;
+
+        if let InnerRecord::OK(ok) = rec {
+            // Blocks have few distinct messages, a linear search is faster than hashing.
+            let msg_idx = msgs.iter().position(|id| *id == ok.msg_id).unwrap_or_else(|| {
+                msgs.push(ok.msg_id);
+                msgs.len() - 1
+            });
+            write_varint(data, msg_idx as u64);
+            // The tag has no bit left, the lowest bit of the IP ID tells whether the facility follows.
+            write_varint(data, ok.ip_id << 1 | ok.facility.is_some() as u64);
+            data.extend(ok.facility);
+            for id in [ok.endpoint_id, ok.status.map(u64::from), ok.client_id].into_iter().flatten() {
+                write_varint(data, id);
+            }
+            if let Some(trace_id) = ok.trace_id {
+                data.extend_from_slice(&trace_id.to_le_bytes());
+            }
+            if let Some(group_id) = ok.group_id {
+                write_varint(data, group_id);
+            }
+        }
+    }
+}
+
+fn zigzag(value: i64) -> u64 {
+    ((value << 1) ^ (value >> 63)) as u64
+}
+
+fn unzigzag(value: u64) -> i64 {
+    (value >> 1) as i64 ^ -((value & 1) as i64)
+}
+
+fn write_varint(data: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        data.push(value as u8 | 0x80);
+        value >>= 7;
+    }
+    data.push(value as u8);
+}
+
+fn read_varint(data: &[u8], pos: &mut usize) -> u64 {
+    let mut value = 0;
+    let mut shift = 0;
+    loop {
+        let byte = data[*pos];
+        *pos += 1;
+        value |= ((byte & 0x7F) as u64) << shift;
+        if byte & 0x80 == 0 {
+            return value;
+        }
+        shift += 7;
+    }
+}
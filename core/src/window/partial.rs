@@ -0,0 +1,348 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    fmt,
+    hash::Hash,
+};
+
+use xxhash_rust::xxh3::xxh3_64;
+
+use super::{
+    group_key, keep_example, Act, Clock, InnerErrRecord, InnerExample, InnerMessageTotals, InnerOKRecord, InnerRecord,
+    StatsSnapshot, StatsWindow,
+};
+use crate::{
+    dict::Dictionary,
+    extract::Extracted,
+    group::GroupBy,
+    snapshot::Provenance,
+    types::{ErrorType, Level, ParsedLine},
+};
+
+/// Records counted apart from the window, like by a worker thread, to be merged into it in one go. The counters are
+/// kept by IDs of dictionaries of its own, which are translated to the ones of the window on merging.
+#[derive(Default)]
+pub struct PartialStats {
+    /// Records in the order they were added
+    records:        Vec<InnerRecord>,
+    stat:           StatsSnapshot,
+    msgs:           Dictionary,
+    ips:            Dictionary,
+    sources:        Dictionary,
+    endpoints:      Dictionary,
+    clients:        Dictionary,
+    groups:         Dictionary,
+    group_by:       Vec<GroupBy>,
+    /// Trace IDs of the errors by their hashes
+    trace_ids:      HashMap<u64, String>,
+    msg_totals:     HashMap<u64, InnerMessageTotals>,
+    example_budget: usize,
+    examples:       HashMap<u64, VecDeque<InnerExample>>,
+    /// Sequence numbers by source ID, in the order they were taken from the messages
+    sequences:      Vec<(u64, u64)>,
+    /// Times of the records counted in the current rate, by the clock of the window
+    rated:          Vec<i64>,
+    clock:          Clock,
+    /// The newest log time seen, kept from one partial to the next
+    log_now:        i64,
+}
+
+/// IDs of the window, indexed by the IDs of a partial they are translated from.
+struct IdMap {
+    msgs:      Vec<u64>,
+    ips:       Vec<u64>,
+    sources:   Vec<u64>,
+    endpoints: Vec<u64>,
+    clients:   Vec<u64>,
+    groups:    Vec<u64>,
+}
+
+/// Counters which add up when a partial is merged.
+trait Mergeable: Default {
+    fn add(&mut self, other: Self);
+}
+
+impl StatsWindow {
+    /// An empty partial to count records in apart from the window, with the same settings.
+    pub fn partial(&self) -> PartialStats {
+        PartialStats::empty(self.stat.bucket_ms, self.clock, self.group_by.clone(), self.example_budget, self.log_now)
+    }
+
+    /// Add the records of the partial with their counters, then drop the records which fell out of the window.
+    pub fn merge(&mut self, partial: PartialStats) {
+        let PartialStats {
+            records,
+            stat,
+            msgs,
+            ips,
+            sources,
+            endpoints,
+            clients,
+            groups,
+            trace_ids,
+            msg_totals,
+            examples,
+            sequences,
+            rated,
+            log_now,
+            ..
+        } = partial;
+        self.log_now = self.log_now.max(log_now);
+
+        let known_msgs = self.msgs.len();
+        let ids = IdMap {
+            msgs:      msgs.iter().map(|(_, msg)| self.msgs.id(msg)).collect(),
+            ips:       ips.iter().map(|(_, ip)| self.ips.id(ip)).collect(),
+            sources:   sources.iter().map(|(_, source)| self.sources.id(source)).collect(),
+            endpoints: endpoints.iter().map(|(_, endpoint)| self.endpoints.id(endpoint)).collect(),
+            clients:   clients.iter().map(|(_, client)| self.clients.id(client)).collect(),
+            groups:    groups.iter().map(|(_, group)| self.groups.id(group)).collect(),
+        };
+        // Only messages seen for the first time need to be checked.
+        if !self.mute_patterns.is_empty() {
+            for (id, msg) in self.msgs.iter().skip(known_msgs) {
+                if self.mute_patterns.iter().any(|re| re.is_match(msg)) {
+                    self.muted.insert(id);
+                }
+            }
+        }
+
+        for (hash, trace) in trace_ids {
+            self.trace_ids.entry(hash).or_insert(trace);
+        }
+        for (msg_id, totals) in msg_totals {
+            totals.add_to(&mut self.msg_totals, ids.msgs[msg_id as usize]);
+        }
+        for (msg_id, msg_examples) in examples {
+            let kept = self.examples.entry(ids.msgs[msg_id as usize]).or_default();
+            for example in msg_examples {
+                let example = InnerExample {
+                    ip_id: ids.ips[example.ip_id as usize],
+                    source_id: ids.sources[example.source_id as usize],
+                    ..example
+                };
+                keep_example(kept, self.example_budget, example);
+            }
+        }
+        for (source_id, seq) in sequences {
+            self.sequences.entry(ids.sources[source_id as usize]).or_default().push(seq);
+        }
+
+        self.stat.merge(stat, &ids);
+        for rec in records {
+            self.records.push_front(ids.record(rec));
+        }
+        let now = self.now_millis();
+        for ts in rated {
+            self.stat.refresh_last_bucket(Some(ts), now);
+        }
+
+        self.cleanup_and_adjust();
+    }
+}
+
+impl PartialStats {
+    fn empty(bucket_ms: i64, clock: Clock, group_by: Vec<GroupBy>, example_budget: usize, log_now: i64) -> Self {
+        Self {
+            stat: StatsSnapshot {
+                bucket_ms,
+                clock,
+                ..StatsSnapshot::default()
+            },
+            group_by,
+            example_budget,
+            clock,
+            log_now,
+            ..Self::default()
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.records.is_empty()
+    }
+
+    /// The records counted so far, leaving an empty partial with the same settings in place.
+    pub fn take(&mut self) -> PartialStats {
+        let (bucket_ms, clock, example_budget, log_now) =
+            (self.stat.bucket_ms, self.clock, self.example_budget, self.log_now);
+        std::mem::replace(self, Self::empty(bucket_ms, clock, self.group_by.clone(), example_budget, log_now))
+    }
+
+    /// Same as `StatsWindow::push_ok_extracted`.
+    pub fn push_ok_extracted(
+        &mut self,
+        received_millis: i64,
+        record: &ParsedLine,
+        source: &str,
+        extracted: Extracted,
+        provenance: Provenance,
+    ) {
+        self.rated.push(match self.clock {
+            Clock::Receive => received_millis,
+            Clock::Log => record.logged_millis,
+        });
+        self.push_ok_uncounted_extracted(received_millis, record, source, extracted, provenance);
+    }
+
+    /// Same as `StatsWindow::push_ok_uncounted_extracted`.
+    pub fn push_ok_uncounted_extracted(
+        &mut self,
+        received_millis: i64,
+        record: &ParsedLine,
+        source: &str,
+        extracted: Extracted,
+        provenance: Provenance,
+    ) {
+        self.log_now = self.log_now.max(record.logged_millis);
+        let group_id = (!self.group_by.is_empty())
+            .then(|| self.groups.id(&group_key(&self.group_by, record, source, &extracted)));
+        let trace_id = extracted.trace.map(|trace| {
+            let hash = xxh3_64(trace.as_bytes());
+            if record.level == Level::ERROR {
+                self.trace_ids.entry(hash).or_insert(trace);
+            }
+            hash
+        });
+        let inner_rec = InnerOKRecord {
+            received_millis,
+            logged_millis: record.logged_millis,
+            level: record.level,
+            msg_id: self.msgs.id(&record.message),
+            ip_id: self.ips.id(&record.ip),
+            source_id: self.sources.id(source),
+            endpoint_id: extracted.endpoint.map(|endpoint| self.endpoints.id(&endpoint)),
+            status: extracted.status,
+            client_id: extracted.client.map(|client| self.clients.id(&client)),
+            trace_id,
+            group_id,
+            facility: record.facility(),
+        };
+        if let Some(seq) = extracted.sequence {
+            self.sequences.push((inner_rec.source_id, seq));
+        }
+
+        InnerMessageTotals::once(record.logged_millis).add_to(&mut self.msg_totals, inner_rec.msg_id);
+
+        if self.example_budget > 0 {
+            keep_example(
+                self.examples.entry(inner_rec.msg_id).or_default(),
+                self.example_budget,
+                InnerExample {
+                    logged_millis: inner_rec.logged_millis,
+                    level:         inner_rec.level,
+                    ip_id:         inner_rec.ip_id,
+                    source_id:     inner_rec.source_id,
+                    provenance,
+                },
+            );
+        }
+
+        self.records.push(self.stat.count_inner_rec(InnerRecord::OK(inner_rec), Act::Inc));
+    }
+
+    /// Same as `StatsWindow::push_error`, the replay clock being the newest log time this partial has seen.
+    pub fn push_error(&mut self, received_millis: i64, err_type: ErrorType, source: &str) {
+        let inner_err = InnerErrRecord {
+            received_millis,
+            logged_millis: match self.clock {
+                Clock::Receive => received_millis,
+                Clock::Log => self.log_now,
+            },
+            err_type,
+            source_id: self.sources.id(source),
+        };
+
+        self.records.push(self.stat.count_inner_rec(InnerRecord::Err(inner_err), Act::Inc));
+    }
+}
+
+impl fmt::Debug for PartialStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("PartialStats")
+            .field("records", &self.records.len())
+            .finish_non_exhaustive()
+    }
+}
+
+impl IdMap {
+    fn record(&self, rec: InnerRecord) -> InnerRecord {
+        match rec {
+            InnerRecord::OK(ok) => InnerRecord::OK(InnerOKRecord {
+                msg_id: self.msgs[ok.msg_id as usize],
+                ip_id: self.ips[ok.ip_id as usize],
+                source_id: self.sources[ok.source_id as usize],
+                endpoint_id: ok.endpoint_id.map(|id| self.endpoints[id as usize]),
+                client_id: ok.client_id.map(|id| self.clients[id as usize]),
+                group_id: ok.group_id.map(|id| self.groups[id as usize]),
+                ..ok
+            }),
+            InnerRecord::Err(err) => InnerRecord::Err(InnerErrRecord {
+                source_id: self.sources[err.source_id as usize],
+                ..err
+            }),
+        }
+    }
+}
+
+impl StatsSnapshot {
+    /// Add the counters of a partial, translating its IDs.
+    fn merge(&mut self, other: StatsSnapshot, ids: &IdMap) {
+        self.errors += other.errors;
+        self.infos += other.infos;
+        self.debugs += other.debugs;
+        self.malformed += other.malformed;
+        self.bad_timestamps += other.bad_timestamps;
+        self.unknown_levels += other.unknown_levels;
+        self.oversized += other.oversized;
+        self.decode_errors += other.decode_errors;
+        self.late_arrivals += other.late_arrivals;
+        self.lag_sum += other.lag_sum;
+        self.status_counts.add(other.status_counts);
+
+        let msg = |id: u64| ids.msgs[id as usize];
+        add_counts(&mut self.error_msg_counts, other.error_msg_counts, msg);
+        for (bucket, counts) in other.error_msg_per_bucket {
+            add_counts(self.error_msg_per_bucket.entry(bucket).or_default(), counts, msg);
+        }
+        add_counts(&mut self.ip_counts, other.ip_counts, |id| ids.ips[id as usize]);
+        add_counts(&mut self.source_counts, other.source_counts, |id| ids.sources[id as usize]);
+        add_counts(&mut self.source_lags, other.source_lags, |id| ids.sources[id as usize]);
+        add_counts(&mut self.endpoint_counts, other.endpoint_counts, |id| ids.endpoints[id as usize]);
+        add_counts(&mut self.client_counts, other.client_counts, |id| ids.clients[id as usize]);
+        add_counts(&mut self.group_counts, other.group_counts, |id| ids.groups[id as usize]);
+        // Trace IDs are hashes already, facilities and buckets are the same everywhere.
+        add_counts(&mut self.trace_errors, other.trace_errors, |hash| hash);
+        add_counts(&mut self.facility_counts, other.facility_counts, |facility| facility);
+        add_counts(&mut self.entries_per_bucket, other.entries_per_bucket, |bucket| bucket);
+        add_counts(&mut self.status_per_bucket, other.status_per_bucket, |bucket| bucket);
+    }
+}
+
+fn add_counts<K: Eq + Hash, V: Mergeable>(counts: &mut HashMap<K, V>, other: HashMap<K, V>, key: impl Fn(K) -> K) {
+    for (k, count) in other {
+        counts.entry(key(k)).or_default().add(count);
+    }
+}
+
+impl Mergeable for i64 {
+    fn add(&mut self, other: Self) {
+        *self += other;
+    }
+}
+
+impl Mergeable for (i64, i64) {
+    fn add(&mut self, other: Self) {
+        self.0 += other.0;
+        self.1 += other.1;
+    }
+}
+
+impl<const N: usize> Mergeable for [i64; N]
+where
+    [i64; N]: Default,
+{
+    fn add(&mut self, other: Self) {
+        for (count, other) in self.iter_mut().zip(other) {
+            *count += other;
+        }
+    }
+}
@@ -0,0 +1,26 @@
+/* C interface of the qnode-logproc stats engine. Build the library with `cargo build --release --features ffi`. */
+#ifndef QNODE_LOGPROC_H
+#define QNODE_LOGPROC_H
+
+#include <stddef.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+typedef struct QlpEngine QlpEngine;
+
+/* argv holds argc command line options, without the program name. Returns NULL if the options are invalid. */
+QlpEngine *qlp_engine_new(const char *const *argv, size_t argc);
+/* Returns 0 on success, -1 on failure. */
+int qlp_engine_push_line(QlpEngine *engine, const char *line);
+/* The latest snapshot as JSON; release it with qlp_string_free. */
+char *qlp_engine_snapshot(QlpEngine *engine);
+void qlp_engine_free(QlpEngine *engine);
+void qlp_string_free(char *s);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif
@@ -0,0 +1,454 @@
+use std::{collections::BTreeMap, str::FromStr, sync::Mutex};
+
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, Local};
+use qnode_logproc_core::{MessageCount, SnapshotView, StatsWindow};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    config::{AlertMetric, AlertSpec},
+    format::{format_duration, NumberFormat},
+};
+
+// State of a single rule.
+#[derive(Debug, Default, Clone)]
+struct RuleState {
+    value:         f64,
+    /// Since when the value is past the threshold of a rule which doesn't fire yet
+    pending_since: Option<DateTime<Local>>,
+    firing_since:  Option<DateTime<Local>>,
+    /// When the last firing started and ended
+    last_fired:    Option<(DateTime<Local>, DateTime<Local>)>,
+    /// The top error when an error rule started firing
+    message:       Option<String>,
+}
+
+/// A rule, or the heartbeat of a source, which has started or stopped firing.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(crate) struct AlertEvent {
+    /// The name of the rule, `heartbeat` for heartbeats
+    pub(crate) rule:      String,
+    /// The source of a heartbeat
+    pub(crate) source:    Option<String>,
+    pub(crate) firing:    bool,
+    /// Like `error_rate > 0.05`
+    pub(crate) condition: String,
+    pub(crate) value:     f64,
+    /// The top error when an error rule started firing
+    pub(crate) message:   Option<String>,
+    /// When the rule started firing
+    pub(crate) since:     DateTime<Local>,
+    pub(crate) timestamp: DateTime<Local>,
+    /// The statistics when the alert fired or resolved
+    pub(crate) context:   AlertContext,
+}
+
+/// What the snapshot an alert came from tells about the stream, for the alert message.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub(crate) struct AlertContext {
+    pub(crate) rate:         f64,
+    pub(crate) errors:       i64,
+    pub(crate) error_rate:   f32,
+    pub(crate) distinct_ips: usize,
+    pub(crate) top_errors:   Vec<MessageCount>,
+}
+
+impl AlertContext {
+    fn of(view: &SnapshotView) -> Self {
+        Self {
+            rate:         view.rate,
+            errors:       view.errors,
+            error_rate:   view.error_rate,
+            distinct_ips: view.distinct_ips,
+            top_errors:   view.top_errors.iter().take(3).cloned().collect(),
+        }
+    }
+
+    /// The top errors in one line, like `"timeout" x12; "refused" x3`.
+    pub(crate) fn top(&self) -> String {
+        self.top_errors
+            .iter()
+            .map(|top| format!("{:?} x{}", top.message, top.count))
+            .collect::<Vec<_>>()
+            .join("; ")
+    }
+}
+
+/// Placeholders of alert templates.
+const PLACEHOLDERS: &[&str] = &[
+    "name",
+    "rule",
+    "source",
+    "state",
+    "condition",
+    "value",
+    "summary",
+    "since",
+    "host",
+    "rate",
+    "errors",
+    "error_rate",
+    "distinct_ips",
+    "top",
+    "dashboard",
+];
+
+#[derive(Debug, Clone)]
+enum Piece {
+    Text(String),
+    Placeholder(&'static str),
+}
+
+/// The message of alert notifications, with placeholders like `{rate}` or `{top}` replaced with what the alert and
+/// its snapshot tell. `{{` and `}}` stand for braces, `\n` for a line break.
+#[derive(Debug, Clone)]
+pub(crate) struct AlertTemplate {
+    pieces: Vec<Piece>,
+}
+
+impl FromStr for AlertTemplate {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let mut pieces = Vec::new();
+        let mut text = String::new();
+        let mut rest = s;
+        while let Some(pos) = rest.find(['{', '}', '\\']) {
+            text.push_str(&rest[..pos]);
+            let tail = &rest[pos..];
+            if let Some(tail) = tail.strip_prefix("{{") {
+                text.push('{');
+                rest = tail;
+            }
+            else if let Some(tail) = tail.strip_prefix("}}") {
+                text.push('}');
+                rest = tail;
+            }
+            else if let Some(tail) = tail.strip_prefix("\\n") {
+                text.push('\n');
+                rest = tail;
+            }
+            else if let Some(tail) = tail.strip_prefix('\\') {
+                text.push('\\');
+                rest = tail;
+            }
+            else if let Some((name, tail)) = tail.strip_prefix('{').and_then(|tail| tail.split_once('}')) {
+                let placeholder = PLACEHOLDERS
+                    .iter()
+                    .find(|placeholder| **placeholder == name)
+                    .ok_or_else(|| anyhow!("Unknown placeholder {{{}}}, known are {}", name, PLACEHOLDERS.join(", ")))?;
+                pieces.push(Piece::Text(std::mem::take(&mut text)));
+                pieces.push(Piece::Placeholder(placeholder));
+                rest = tail;
+            }
+            else {
+                return Err(anyhow!("Unmatched brace in alert template '{}'", s));
+            }
+        }
+        text.push_str(rest);
+        pieces.push(Piece::Text(text));
+        Ok(Self { pieces })
+    }
+}
+
+impl AlertTemplate {
+    pub(crate) fn render(&self, event: &AlertEvent, host: &str, dashboard: Option<&str>) -> String {
+        let mut out = String::new();
+        for piece in self.pieces.iter() {
+            match piece {
+                Piece::Text(text) => out.push_str(text),
+                Piece::Placeholder(name) => out.push_str(&event.placeholder(name, host, dashboard)),
+            }
+        }
+        out
+    }
+}
+
+impl AlertEvent {
+    fn placeholder(&self, name: &str, host: &str, dashboard: Option<&str>) -> String {
+        let round = |value: f64| ((value * 10_000.0).round() / 10_000.0).to_string();
+        match name {
+            "name" => self.name(),
+            "rule" => self.rule.clone(),
+            "source" => self.source.clone().unwrap_or_default(),
+            "state" => if self.firing { "FIRING" } else { "RESOLVED" }.to_string(),
+            "condition" => self.condition.clone(),
+            "value" => round(self.value),
+            "summary" => self.summary(),
+            "since" => self.since.to_rfc3339(),
+            "host" => host.to_string(),
+            "rate" => round(self.context.rate),
+            "errors" => self.context.errors.to_string(),
+            "error_rate" => round(self.context.error_rate as f64),
+            "distinct_ips" => self.context.distinct_ips.to_string(),
+            "top" => self.context.top(),
+            "dashboard" => dashboard.unwrap_or_default().to_string(),
+            _ => String::new(),
+        }
+    }
+
+    /// The name the alert is known as outside, the same when it fires and when it resolves.
+    pub(crate) fn name(&self) -> String {
+        match self.source {
+            Some(ref source) => format!("{} {}", source, self.rule),
+            None => self.rule.clone(),
+        }
+    }
+
+    /// Identifies the incident of the alert at incident management services. The rule firing again for the same top
+    /// error gets the same key, so that flapping doesn't open a new incident every time.
+    #[cfg_attr(not(feature = "paging"), allow(dead_code))]
+    pub(crate) fn dedup_key(&self, host: &str) -> String {
+        let key = format!("{}\n{}\n{}", host, self.name(), self.message.as_deref().unwrap_or_default());
+        format!("qnode-logproc-{:016x}", StatsWindow::message_id(&key))
+    }
+
+    /// One line description, like `error_rate > 0.05 (now 0.0712)`.
+    pub(crate) fn summary(&self) -> String {
+        let summary = format!("{} (now {})", self.condition, (self.value * 10_000.0).round() / 10_000.0);
+        if self.firing {
+            summary
+        }
+        else {
+            let fired = format_duration((self.timestamp - self.since).num_seconds());
+            format!("{}, resolved after {}", summary, fired)
+        }
+    }
+}
+
+impl RuleState {
+    // Returns whether the rule has started, `Some((true, _))`, or stopped, `Some((false, _))`, firing, with when it
+    // started firing.
+    fn advance(&mut self, firing: bool, now: DateTime<Local>) -> Option<(bool, DateTime<Local>)> {
+        match (firing, self.firing_since) {
+            (true, None) => {
+                self.pending_since = None;
+                self.firing_since = Some(now);
+                Some((true, now))
+            }
+            (false, Some(since)) => {
+                self.pending_since = None;
+                self.firing_since = None;
+                self.last_fired = Some((since, now));
+                Some((false, since))
+            }
+            _ => None,
+        }
+    }
+
+    fn status(&self, now: DateTime<Local>) -> String {
+        match (self.firing_since, self.pending_since, self.last_fired) {
+            (Some(since), ..) => format!("FIRING for {}", format_duration((now - since).num_seconds())),
+            (None, Some(since), _) => format!("pending for {}", format_duration((now - since).num_seconds())),
+            (None, None, Some((since, until))) => format!(
+                "resolved {} ago, fired for {}",
+                format_duration((now - until).num_seconds()),
+                format_duration((until - since).num_seconds())
+            ),
+            (None, None, None) => "ok".to_string(),
+        }
+    }
+}
+
+/// Tracks which alert rules are firing and which sources are silent, from one report to the next.
+#[derive(Debug)]
+pub(crate) struct Alerts {
+    /// The rules with their states
+    rules:  Mutex<Vec<(AlertSpec, RuleState)>>,
+    /// Overdue heartbeats, by rule and source
+    silent: Mutex<BTreeMap<(String, String), RuleState>>,
+}
+
+impl Alerts {
+    pub(crate) fn new(rules: Vec<AlertSpec>) -> Self {
+        Self {
+            rules:  Mutex::new(rules.into_iter().map(|rule| (rule, RuleState::default())).collect()),
+            silent: Mutex::new(BTreeMap::new()),
+        }
+    }
+
+    /// Replace the rules with the reloaded ones. A rule keeps its state if one of the same name was there before.
+    pub(crate) fn set_rules(&self, rules: Vec<AlertSpec>) {
+        let mut current = self.rules.lock().unwrap();
+        let mut states = std::mem::take(&mut *current)
+            .into_iter()
+            .map(|(rule, state)| (rule.name, state))
+            .collect::<BTreeMap<_, _>>();
+        *current = rules
+            .into_iter()
+            .map(|rule| {
+                let state = states.remove(&rule.name).unwrap_or_default();
+                (rule, state)
+            })
+            .collect();
+    }
+
+    /// Check the rules and the heartbeats against the view. Returns the ones which have started or stopped firing.
+    /// During maintenance only the values are updated, nothing fires or resolves.
+    pub(crate) fn update(&self, view: &SnapshotView) -> Vec<AlertEvent> {
+        let mut rules = self.rules.lock().unwrap();
+        let mut events = Vec::new();
+        for (rule, state) in rules.iter_mut() {
+            state.value = rule.primary().value(view);
+            if view.maintenance {
+                continue;
+            }
+            // A firing rule holds until the value gets past the clear threshold; one which doesn't fire has to stay
+            // past the threshold for the hold time, and not within the cooldown after it resolved last.
+            let now = view.timestamp;
+            let firing = if state.firing_since.is_some() {
+                !rule.is_cleared(view)
+            }
+            else if rule.is_breached(view) {
+                let pending = *state.pending_since.get_or_insert(now);
+                let cooled = state.last_fired.is_none_or(|(_, resolved)| now - resolved >= rule.cooldown);
+                now - pending >= rule.hold && cooled
+            }
+            else {
+                state.pending_since = None;
+                false
+            };
+            if let Some((firing, since)) = state.advance(firing, now) {
+                let on_errors = rule
+                    .all_conditions()
+                    .any(|condition| matches!(condition.metric, AlertMetric::Errors | AlertMetric::ErrorRate));
+                if firing && on_errors {
+                    state.message = view.top_errors.first().map(|top| top.message.clone());
+                }
+                events.push(AlertEvent {
+                    rule: rule.name.clone(),
+                    source: None,
+                    firing,
+                    condition: rule.condition(),
+                    value: state.value,
+                    message: state.message.clone(),
+                    since,
+                    timestamp: view.timestamp,
+                    context: AlertContext::of(view),
+                });
+            }
+        }
+
+        let mut silent = self.silent.lock().unwrap();
+        for heartbeat in view.heartbeats.iter() {
+            let state = silent.entry((heartbeat.rule.clone(), heartbeat.source.clone())).or_default();
+            state.value = heartbeat.age_secs;
+            if view.maintenance {
+                continue;
+            }
+            if let Some((firing, since)) = state.advance(heartbeat.is_silent(), view.timestamp) {
+                events.push(AlertEvent {
+                    rule: "heartbeat".to_string(),
+                    source: Some(heartbeat.source.clone()),
+                    firing,
+                    condition: format!("heartbeat {} overdue", heartbeat.rule),
+                    value: heartbeat.age_secs,
+                    message: None,
+                    since,
+                    timestamp: view.timestamp,
+                    context: AlertContext::of(view),
+                });
+            }
+        }
+        events
+    }
+
+    pub(crate) fn is_firing(&self) -> bool {
+        self.rules.lock().unwrap().iter().any(|(_, state)| state.firing_since.is_some())
+            || self.silent.lock().unwrap().values().any(|state| state.firing_since.is_some())
+    }
+
+    /// The alerts panel of the report, empty if there are neither rules nor heartbeats.
+    pub(crate) fn lines(&self, view: &SnapshotView, num: &NumberFormat) -> Vec<String> {
+        let rules = self.rules.lock().unwrap();
+        if rules.is_empty() && view.heartbeats.is_empty() {
+            return Vec::new();
+        }
+
+        let now = view.timestamp;
+        let mut lines = vec![if view.maintenance {
+            "Alerts (suppressed for maintenance):".to_string()
+        }
+        else {
+            "Alerts:".to_string()
+        }];
+        for (rule, state) in rules.iter() {
+            let mut condition = rule
+                .conditions
+                .iter()
+                .map(|conditions| {
+                    conditions
+                        .iter()
+                        .map(|condition| {
+                            format!(
+                                "{} {} {} (now {})",
+                                condition.metric.name(),
+                                condition.operator(),
+                                num.float(condition.threshold, 2),
+                                num.float(condition.value(view), 2)
+                            )
+                        })
+                        .collect::<Vec<_>>()
+                        .join(" AND ")
+                })
+                .collect::<Vec<_>>()
+                .join(" OR ");
+            if let Some(clear) = rule.clear {
+                condition.push_str(&format!(" clear {}", num.float(clear, 2)));
+            }
+            for (what, duration) in [("for", rule.hold), ("cooldown", rule.cooldown)] {
+                if duration > chrono::Duration::zero() {
+                    condition.push_str(&format!(" {} {}", what, format_duration(duration.num_seconds())));
+                }
+            }
+            lines.push(format!("  {}: {} {}", rule.name, condition, state.status(now)));
+        }
+
+        // Sources which keep their heartbeats are only counted, there can be many of them.
+        let silent = self.silent.lock().unwrap();
+        let mut beating = 0;
+        for heartbeat in view.heartbeats.iter() {
+            let state = silent.get(&(heartbeat.rule.clone(), heartbeat.source.clone())).cloned().unwrap_or_default();
+            if state.firing_since.is_none() && state.last_fired.is_none() {
+                beating += 1;
+                continue;
+            }
+            let last_seen = match heartbeat.last_seen {
+                Some(_) => format!("last seen {} ago", format_duration(heartbeat.age_secs as i64)),
+                None => "never seen".to_string(),
+            };
+            lines.push(format!(
+                "  {} silent: heartbeat {} {} {}",
+                heartbeat.source, heartbeat.rule, last_seen, state.status(now)
+            ));
+        }
+        if beating > 0 {
+            lines.push(format!("  Heartbeats: {} ok", num.int(beating)));
+        }
+        lines
+    }
+}
+
+pub(crate) fn metric_value(metric: AlertMetric, view: &SnapshotView) -> f64 {
+    match metric {
+        AlertMetric::Rate => view.rate,
+        AlertMetric::CurrentRate => view.current_rate as f64,
+        AlertMetric::PeakRate => view.peak_rate,
+        // Acknowledged errors don't alert.
+        AlertMetric::ErrorRate => (view.errors - view.muted) as f64 / view.entries.max(1) as f64,
+        AlertMetric::Errors => (view.errors - view.muted) as f64,
+        AlertMetric::Malformed => view.malformed as f64,
+        AlertMetric::AvgLag => view.avg_lag,
+        AlertMetric::DistinctIps => view.distinct_ips as f64,
+        // Of the source losing the most.
+        AlertMetric::SequenceLoss => view.sequences.values().map(|seq| seq.loss()).fold(0.0, f64::max),
+        AlertMetric::Burstiness => view.burstiness(),
+        AlertMetric::Backlog => view.backlog as f64,
+        AlertMetric::DropRate => view.drop_rate,
+        // Of all lines in the window, the rejected ones included.
+        AlertMetric::Attacks => view.attacks.len() as f64,
+        AlertMetric::MalformedRatio => view.malformed as f64 / (view.entries + view.malformed).max(1) as f64,
+        AlertMetric::BurnRate(window) => view.slo.as_ref().map_or(0.0, |slo| slo.burn_rate(window)),
+        AlertMetric::ErrorBudget => view.slo.as_ref().map_or(1.0, |slo| slo.budget_remaining()),
+    }
+}
@@ -1,15 +1,24 @@
-use std::sync::Arc;
+use std::{ffi::OsString, sync::Arc};
 
 use anyhow::Result;
+use chrono::Local;
 use console::Term;
 use fieldx::fxstruct;
 use fieldx_plus::{agent_build, fx_plus};
+use qnode_logproc_core::RunInfo;
 use tokio::{
-    sync::mpsc::{UnboundedReceiver, UnboundedSender},
+    signal::unix::{signal, SignalKind},
+    sync::{
+        mpsc::{UnboundedReceiver, UnboundedSender},
+        Notify,
+    },
     task::JoinSet,
 };
 
-use crate::{reader::Reader, stats::Stats, types::LineMessage};
+use crate::{
+    config::Config, daemon, failures::Failures, keyboard, output::Output, reader::Reader, stats::Stats, systemd,
+    types::LineMessage,
+};
 
 #[fxstruct(sync, no_new)]
 pub(crate) struct Channel {
@@ -29,13 +38,13 @@ impl Channel {
     }
 }
 
-#[fx_plus(app, sync, fallible(off, error(anyhow::Error)))]
+#[fx_plus(app, sync, builder(into), fallible(off, error(anyhow::Error)))]
 pub(crate) struct App {
-    #[fieldx(lock, get_mut(private))]
+    #[fieldx(lock, get_mut(private), default(JoinSet::new()))]
     task_set: JoinSet<()>,
 
     #[fieldx(lazy, fallible)]
-    reader: crate::reader::Reader,
+    reader: Arc<crate::reader::Reader>,
 
     #[fieldx(lazy, fallible)]
     parser: crate::parser::Parser,
@@ -43,40 +52,210 @@ pub(crate) struct App {
     #[fieldx(lazy, fallible)]
     stats: Arc<crate::stats::Stats>,
 
+    #[fieldx(lazy, fallible)]
+    output: Output,
+
+    #[fieldx(lazy, fallible)]
+    http: Arc<crate::http::HttpServer>,
+
     #[fieldx(lazy, private)]
     channel: Channel,
 
     #[fieldx(lazy, get)]
     term: console::Term,
+
+    #[fieldx(lazy, get)]
+    config: Config,
+
+    /// Stamped on every snapshot.
+    #[fieldx(lazy, get)]
+    run_info: RunInfo,
+
+    /// Errors of the parts which retry their work.
+    #[fieldx(get(clone), default(Arc::new(Failures::default())))]
+    failures: Arc<Failures>,
+
+    /// Notified when the reader has no more input.
+    #[fieldx(get(clone), default(Arc::new(Notify::new())))]
+    reader_done: Arc<Notify>,
+
+    /// Notified when the statistics have processed all records.
+    #[fieldx(get(clone), default(Arc::new(Notify::new())))]
+    stats_done: Arc<Notify>,
+
+    /// Set when the application is embedded with `StatsEngine` and receives lines from the host instead of an input.
+    #[fieldx(get(copy))]
+    embedded: bool,
 }
 
 impl App {
     pub async fn run() -> Result<()> {
         let app = App::new();
+        crate::panic::install(Arc::downgrade(&app));
+        app.start();
+
+        if let Err(err) = systemd::notify("READY=1") {
+            eprintln!("Failed to notify systemd: {:?}", err);
+        }
 
-        let task_app = app.clone();
+        app.wait_for_signals().await?;
+
+        #[cfg(feature = "simulation")]
+        if app.config().simulate.is_some() && crate::simulation::failed() > 0 {
+            eprintln!("{} simulation checks failed", crate::simulation::failed());
+            std::process::exit(1);
+        }
+
+        Ok(())
+    }
+
+    /// Create the application for `StatsEngine`. `args` are command line options, without the program name.
+    pub(crate) fn new_embedded<I, T>(args: I) -> Result<Arc<Self>>
+    where
+        I: IntoIterator<Item = T>,
+        T: Into<OsString> + Clone,
+    {
+        let mut config = <Config as clap::Parser>::try_parse_from(
+            std::iter::once(OsString::from(env!("CARGO_PKG_NAME"))).chain(args.into_iter().map(Into::into)),
+        )?;
+        config.add_builtin_rules()?;
+        config.add_file_rules()?;
+        config.validate()?;
+        // The host owns the terminal.
+        config.headless = true;
+
+        App::builder()
+            .config(config)
+            .embedded(true)
+            .build()
+            .map_err(|e| anyhow::anyhow!("Failed to build App: {:?}", e))
+    }
+
+    /// Launch the reader, the parser and the statistics tasks.
+    pub(crate) fn start(&self) {
+        // The run starts now, not when the first snapshot is made.
+        let _ = self.run_info();
+        let task_app = self.myself().unwrap();
         tokio::spawn(async move {
             while let Err(err) = task_app.launch().await {
                 task_app.task_set_mut().abort_all();
-                eprintln!("App::launch failed, retrying; the error was: {:?}", err);
+                task_app.failures().fail("Launching the tasks", err);
             }
         });
+    }
 
-        app.ctrl_c().await?;
+    /// Stop all tasks.
+    pub(crate) fn stop(&self) {
+        if let Ok(stats) = self.stats() {
+            stats.shutdown();
+        }
+        self.task_set_mut().abort_all();
+    }
 
-        Ok(())
+    pub(crate) fn channel_tx(&self) -> Arc<UnboundedSender<LineMessage>> {
+        self.channel().tx()
     }
 
-    async fn ctrl_c(&self) -> Result<()> {
-        tokio::signal::ctrl_c().await?;
-        println!("Ctrl-C received, shutting down");
-        self.stats()?.shutdown();
+    // Ctrl-C and SIGTERM stop the application, SIGUSR1 writes an out-of-schedule snapshot to the outputs, the
+    // dictionary file, the SLO state and the baseline, SIGHUP re-opens output files and reloads the `--rules` and the
+    // `--http-auth` files. In `--once` mode the application also stops when all input is processed.
+    async fn wait_for_signals(&self) -> Result<()> {
+        let mut sigterm = signal(SignalKind::terminate())?;
+        let mut sigusr1 = signal(SignalKind::user_defined1())?;
+        let mut sighup = signal(SignalKind::hangup())?;
+        let once = self.config().once;
+        let stats_done = self.stats_done();
+
+        loop {
+            tokio::select! {
+                _ = stats_done.notified(), if once => {
+                    self.stats()?.final_report()?;
+                    break;
+                }
+                res = tokio::signal::ctrl_c() => {
+                    res?;
+                    println!("Ctrl-C received, shutting down");
+                    break;
+                }
+                _ = sigterm.recv() => {
+                    println!("SIGTERM received, shutting down");
+                    break;
+                }
+                _ = sigusr1.recv() => {
+                    if let Err(err) = self.stats()?.dump_snapshot() {
+                        eprintln!("Failed to write snapshot: {:?}", err);
+                    }
+                    if let Err(err) = self.stats()?.dump_dictionary() {
+                        eprintln!("Failed to write dictionary: {:?}", err);
+                    }
+                    if let Err(err) = self.stats()?.save_slo() {
+                        eprintln!("Failed to write SLO state: {:?}", err);
+                    }
+                    if let Err(err) = self.stats()?.save_baseline() {
+                        eprintln!("Failed to write baseline: {:?}", err);
+                    }
+                }
+                _ = sighup.recv() => {
+                    self.output()?.reopen()?;
+                    if self.config().rules.is_some() {
+                        match self.reload_rules() {
+                            Ok(()) => self.failures().recover("Rules file"),
+                            Err(err) => self.failures().fail("Rules file", err),
+                        }
+                    }
+                    if self.config().http_auth.is_some() {
+                        match self.http()?.reload_credentials() {
+                            Ok(()) => self.failures().recover("HTTP credentials"),
+                            Err(err) => self.failures().fail("HTTP credentials", err),
+                        }
+                    }
+                }
+            }
+        }
+
+        self.output()?.close().await;
+        self.stats()?.close_remote_write().await;
+        self.stats()?.close_seconds().await;
+        self.stats()?.close_summaries().await;
+        self.stats()?.close_event_sinks().await;
+        if let Err(err) = self.stats()?.dump_dictionary() {
+            eprintln!("Failed to write dictionary: {:?}", err);
+        }
+        if let Err(err) = self.stats()?.save_slo() {
+            eprintln!("Failed to write SLO state: {:?}", err);
+        }
+        if let Err(err) = self.stats()?.save_baseline() {
+            eprintln!("Failed to write baseline: {:?}", err);
+        }
+        self.shutdown()
+    }
+
+    /// Apply the mutes, escalations and alert rules of the `--rules` file as it is now.
+    fn reload_rules(&self) -> Result<()> {
+        let rules = self.config().load_rules()?;
+        self.stats()?.set_rules(&rules);
+        self.output()?.set_alert_rules(&rules.alerts)
+    }
+
+    fn shutdown(&self) -> Result<()> {
+        // Nothing to do about a failure at this point.
+        let _ = systemd::notify("STOPPING=1");
         eprintln!("Abort all tasks");
-        self.task_set_mut().abort_all();
-        self.term().show_cursor()?;
+        self.stop();
+        if let Some(ref pid_file) = self.config().pid_file {
+            daemon::remove_pid_file(pid_file);
+        }
+        if !self.config().is_headless() {
+            keyboard::restore();
+            if self.term().is_term() {
+                self.term().show_cursor()?;
+            }
+        }
         Ok(())
     }
 
+    // The parser owns the receiving end of the channel for its whole lifetime, hence the lock is held across awaits.
+    #[allow(clippy::await_holding_lock)]
     async fn launch(&self) -> Result<()> {
         // This is a feature of fieldx_plus, produces another copy of Arc-wrapped self.
         let myself = self.myself().unwrap();
@@ -84,20 +263,23 @@ impl App {
             // This would fail only and only if reader builder fails. So, it's dev-time problem.
             let reader = myself.reader().unwrap();
             while let Err(err) = reader.start(myself.channel().tx()).await {
-                eprintln!("Reader::start failed, retrying; the error was: {:?}", err);
+                myself.failures().fail("Reader", err);
                 tokio::time::sleep(tokio::time::Duration::from_millis(10)).await;
             }
+            myself.failures().recover("Reader");
             eprintln!("Reader done.");
+            myself.reader_done().notify_one();
         });
 
         let myself = self.myself().unwrap();
         self.task_set_mut().spawn(async move {
             // This would fail only and only if analyzer builder fails. So, it's dev-time problem.
             let parser = myself.parser().unwrap();
-            while let Err(err) = parser.start(&mut *myself.channel().rx()).await {
-                eprintln!("Parser::start failed, retrying; the error was: {:?}", err);
+            while let Err(err) = parser.start(&mut myself.channel().rx()).await {
+                myself.failures().fail("Parser", err);
                 tokio::time::sleep(tokio::time::Duration::from_millis(10)).await;
             }
+            myself.failures().recover("Parser");
             eprintln!("Parser done.")
         });
 
@@ -106,17 +288,29 @@ impl App {
             // This would fail only and only if analyzer builder fails. So, it's dev-time problem.
             let stats = myself.stats().unwrap();
             while let Err(err) = stats.start().await {
-                eprintln!("Stats::start failed, retrying; the error was: {:?}", err);
+                myself.failures().fail("Stats", err);
                 tokio::time::sleep(tokio::time::Duration::from_millis(10)).await;
             }
+            myself.failures().recover("Stats");
             eprintln!("Stats done.");
         });
 
+        if let Some(address) = self.config().http {
+            let myself = self.myself().unwrap();
+            self.task_set_mut().spawn(async move {
+                let http = myself.http().unwrap();
+                while let Err(err) = http.start(address).await {
+                    myself.failures().fail("HTTP server", err);
+                    tokio::time::sleep(tokio::time::Duration::from_secs(1)).await;
+                }
+            });
+        }
+
         Ok(())
     }
 
     // See the `reader`, its `fieldx` `lazy` parameter above.
-    fn build_reader(&self) -> Result<crate::reader::Reader> {
+    fn build_reader(&self) -> Result<Arc<crate::reader::Reader>> {
         agent_build!(self, Reader).map_err(|e| anyhow::anyhow!("Failed to build Reader: {:?}", e))
     }
 
@@ -125,6 +319,14 @@ impl App {
         agent_build!(self, crate::parser::Parser).map_err(|e| anyhow::anyhow!("Failed to build Parser: {:?}", e))
     }
 
+    fn build_output(&self) -> Result<Output> {
+        agent_build!(self, Output).map_err(|e| anyhow::anyhow!("Failed to build Output: {:?}", e))
+    }
+
+    fn build_http(&self) -> Result<Arc<crate::http::HttpServer>> {
+        agent_build!(self, crate::http::HttpServer).map_err(|e| anyhow::anyhow!("Failed to build HTTP server: {:?}", e))
+    }
+
     fn build_stats(&self) -> Result<Arc<Stats>> {
         agent_build!(self, Stats).map_err(|e| anyhow::anyhow!("Failed to build Stats: {:?}", e))
     }
@@ -137,9 +339,37 @@ impl App {
         Term::buffered_stdout()
     }
 
+    fn build_config(&self) -> Config {
+        let mut config = <Config as clap::Parser>::parse();
+        // The rules are checked by `run()` before the application is built.
+        let _ = config.add_builtin_rules();
+        let _ = config.add_file_rules();
+        config
+    }
+
+    fn build_run_info(&self) -> RunInfo {
+        let config = self.config();
+        RunInfo {
+            version:     env!("CARGO_PKG_VERSION").to_string(),
+            started:     Local::now(),
+            host:        host_name(),
+            inputs:      config.inputs(),
+            config_hash: config.hash(),
+        }
+    }
+
     // This is a universal error message if  app object was destroyed but an agent object remains alive and requesting
     // the app object.
     pub(crate) fn app_is_gone() -> anyhow::Error {
         anyhow::anyhow!("App object is gone while requested")
     }
 }
+
+fn host_name() -> String {
+    let mut buf = [0u8; 256];
+    if unsafe { libc::gethostname(buf.as_mut_ptr().cast(), buf.len()) } != 0 {
+        return String::new();
+    }
+    let len = buf.iter().position(|b| *b == 0).unwrap_or(buf.len());
+    String::from_utf8_lossy(&buf[..len]).into_owned()
+}
@@ -0,0 +1,112 @@
+use std::collections::{HashMap, VecDeque};
+
+use chrono::{DateTime, Local};
+use qnode_logproc_core::{Attack, AttackKind, Clock};
+use regex::Regex;
+
+use crate::types::*;
+
+/// Watches the records matching `--auth-failure` for brute-force attempts, one IP with at least
+/// `--brute-force-messages` distinct messages, and scans, one message from at least `--scan-ips` IPs, within
+/// `--attack-interval`.
+#[derive(Debug)]
+pub(crate) struct AttackDetector {
+    pattern:              Option<Regex>,
+    interval_ms:          i64,
+    brute_force_messages: usize,
+    scan_ips:             usize,
+    /// Whether the interval is measured by log time rather than receive time
+    clock:                Clock,
+    /// Failed authentications within the interval: when, from which IP and with which message
+    recent:               VecDeque<(i64, String, String)>,
+    /// Failed authentications within the interval per IP and message
+    by_ip:                HashMap<String, HashMap<String, u64>>,
+    /// Failed authentications within the interval per message and IP
+    by_message:           HashMap<String, HashMap<String, u64>>,
+}
+
+impl AttackDetector {
+    pub(crate) fn new(
+        pattern: Option<Regex>,
+        interval_ms: i64,
+        brute_force_messages: usize,
+        scan_ips: usize,
+        clock: Clock,
+    ) -> Self {
+        Self {
+            pattern,
+            interval_ms,
+            brute_force_messages,
+            scan_ips,
+            clock,
+            recent: VecDeque::new(),
+            by_ip: HashMap::new(),
+            by_message: HashMap::new(),
+        }
+    }
+
+    pub(crate) fn is_enabled(&self) -> bool {
+        self.pattern.is_some()
+    }
+
+    pub(crate) fn push(&mut self, record: &ParsedLine, received_millis: i64) {
+        if record.ip.is_empty() || !self.pattern.as_ref().is_some_and(|pattern| pattern.is_match(&record.message)) {
+            return;
+        }
+        let now = match self.clock {
+            Clock::Receive => received_millis,
+            Clock::Log => record.logged_millis,
+        };
+        self.expire(now);
+        let (ip, message) = (&record.ip, &record.message);
+        *self.by_ip.entry(ip.clone()).or_default().entry(message.clone()).or_default() += 1;
+        *self.by_message.entry(message.clone()).or_default().entry(ip.clone()).or_default() += 1;
+        self.recent.push_back((now, ip.clone(), message.clone()));
+    }
+
+    /// The attacks within the interval before `now`, the most attempts first.
+    pub(crate) fn attacks(&mut self, now: DateTime<Local>) -> Vec<Attack> {
+        self.expire(now.timestamp_millis());
+        let found = |kind, threshold, counts: &HashMap<String, HashMap<String, u64>>| {
+            counts
+                .iter()
+                .filter(|(_, distinct)| distinct.len() >= threshold)
+                .map(|(subject, distinct)| Attack {
+                    kind,
+                    subject: subject.clone(),
+                    distinct: distinct.len(),
+                    attempts: distinct.values().sum(),
+                })
+                .collect::<Vec<_>>()
+        };
+        let mut attacks = found(AttackKind::BruteForce, self.brute_force_messages, &self.by_ip);
+        attacks.extend(found(AttackKind::Scan, self.scan_ips, &self.by_message));
+        attacks.sort_by(|a, b| b.attempts.cmp(&a.attempts).then_with(|| a.subject.cmp(&b.subject)));
+        attacks
+    }
+
+    fn expire(&mut self, now: i64) {
+        while let Some((_, ip, message)) = self.recent.front().filter(|(time, ..)| now - time >= self.interval_ms) {
+            forget(&mut self.by_ip, ip, message);
+            forget(&mut self.by_message, message, ip);
+            self.recent.pop_front();
+        }
+    }
+}
+
+// Take one attempt off the counts, dropping the ones which reach zero.
+fn forget(counts: &mut HashMap<String, HashMap<String, u64>>, key: &str, other: &str) {
+    let Some(distinct) = counts.get_mut(key)
+    else {
+        return;
+    };
+    if let Some(count) = distinct.get_mut(other) {
+        *count -= 1;
+        if *count == 0 {
+            distinct.remove(other);
+        }
+    }
+    if distinct.is_empty() {
+        counts.remove(key);
+    }
+}
@@ -0,0 +1,193 @@
+use std::{
+    collections::BTreeMap,
+    fs,
+    path::{Path, PathBuf},
+    time::{Duration, Instant},
+};
+
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, Local};
+use qnode_logproc_core::{Clock, HourlyRate, RateRange};
+use serde::{Deserialize, Serialize};
+
+use crate::types::Level;
+
+const HOUR_MS: i64 = 3_600_000;
+/// Latest hours shown, the current one included.
+const HOURS_SHOWN: i64 = 6;
+/// Earlier days with the hour seen it takes to know its typical range.
+const MIN_DAYS: usize = 3;
+/// An hour seen for less than this is too short to tell what is typical.
+const MIN_COVERED_MS: i64 = HOUR_MS / 2;
+/// How often the baseline is written while running.
+const SAVE_INTERVAL: Duration = Duration::from_secs(60);
+
+#[derive(Debug, Default, Clone, Copy)]
+struct HourCount {
+    entries:    u64,
+    errors:     u64,
+    /// Time of the hour the entries were counted in
+    covered_ms: i64,
+}
+
+impl HourCount {
+    fn rate(&self) -> f64 {
+        self.entries as f64 * 1000.0 / self.covered_ms.max(1000) as f64
+    }
+}
+
+/// What `--baseline` keeps.
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct BaselineState {
+    /// Hours since the epoch with their entries, errors and the seconds they were counted in
+    hours: Vec<(i64, u64, u64, i64)>,
+}
+
+/// Counts the entries and errors of every hour across runs, and tells the range the rate of an hour usually falls in
+/// from the same hour of the earlier days.
+#[derive(Debug)]
+pub(crate) struct Baseline {
+    /// Not set if the baseline is not kept or could not be read, so that it is not overwritten
+    path:  Option<PathBuf>,
+    days:  i64,
+    /// Whether the hours are measured by log time rather than receive time
+    clock: Clock,
+    hours: BTreeMap<i64, HourCount>,
+    /// The latest time seen in this run; the time since is counted in the hours covered
+    last:  Option<i64>,
+    saved: Instant,
+}
+
+impl Baseline {
+    pub(crate) fn new(days: u32, clock: Clock) -> Self {
+        Self {
+            path: None,
+            days: days as i64,
+            clock,
+            hours: BTreeMap::new(),
+            last: None,
+            saved: Instant::now(),
+        }
+    }
+
+    /// Read the hours of the earlier runs; a missing file is started afresh.
+    pub(crate) fn load(&mut self, path: &Path) -> Result<()> {
+        if path.exists() {
+            let content = fs::read_to_string(path).map_err(|e| anyhow!("Can't read baseline {:?}: {}", path, e))?;
+            let state = serde_json::from_str::<BaselineState>(&content)
+                .map_err(|e| anyhow!("Bad baseline in {:?}: {}", path, e))?;
+            for (hour, entries, errors, covered_secs) in state.hours {
+                let count = self.hours.entry(hour).or_default();
+                count.entries += entries;
+                count.errors += errors;
+                count.covered_ms += covered_secs * 1000;
+            }
+        }
+        self.path = Some(path.to_path_buf());
+        Ok(())
+    }
+
+    pub(crate) fn is_enabled(&self) -> bool {
+        self.path.is_some()
+    }
+
+    pub(crate) fn push(&mut self, level: Level, logged_millis: i64, received_millis: i64) {
+        let now = match self.clock {
+            Clock::Receive => received_millis,
+            Clock::Log => logged_millis,
+        };
+        self.observe(now);
+        let count = self.hours.entry(now.div_euclid(HOUR_MS)).or_default();
+        count.entries += 1;
+        if level == Level::ERROR {
+            count.errors += 1;
+        }
+    }
+
+    /// Rates of the latest hours as of `now`; the hours older than `--baseline-days` are forgotten.
+    pub(crate) fn hourly(&mut self, now: DateTime<Local>) -> Vec<HourlyRate> {
+        let now = now.timestamp_millis();
+        self.observe(now);
+        let current = now.div_euclid(HOUR_MS);
+        self.hours = self.hours.split_off(&(current - self.days * 24));
+
+        let time = |hour| DateTime::from_timestamp_millis(hour * HOUR_MS).unwrap_or_default().with_timezone(&Local);
+        self.hours
+            .range(current - HOURS_SHOWN + 1..=current)
+            .map(|(hour, count)| HourlyRate {
+                hour:    time(*hour),
+                entries: count.entries,
+                errors:  count.errors,
+                rate:    count.rate(),
+                typical: self.typical(*hour),
+            })
+            .collect()
+    }
+
+    pub(crate) fn is_save_due(&self) -> bool {
+        self.path.is_some() && self.saved.elapsed() >= SAVE_INTERVAL
+    }
+
+    /// Write the hours into the baseline file, if there is one.
+    pub(crate) fn save(&mut self) -> Result<()> {
+        let Some(ref path) = self.path
+        else {
+            return Ok(());
+        };
+        self.saved = Instant::now();
+        let state = BaselineState {
+            hours: self
+                .hours
+                .iter()
+                .map(|(hour, count)| (*hour, count.entries, count.errors, count.covered_ms / 1000))
+                .collect(),
+        };
+        // Written aside first, so that a crash doesn't leave the baseline truncated.
+        let mut tmp_path = path.as_os_str().to_owned();
+        tmp_path.push(".tmp");
+        fs::write(&tmp_path, serde_json::to_string(&state)?)
+            .and_then(|()| fs::rename(&tmp_path, path))
+            .map_err(|e| anyhow!("Can't write baseline {:?}: {}", path, e))
+    }
+
+    // Count the time since the latest one seen in the hours it falls in. Times going back, like of records out of
+    // order by log time, cover nothing; a leap past the kept days, like of a broken timestamp, starts over.
+    fn observe(&mut self, now: i64) {
+        let Some(last) = self.last.filter(|last| now > *last)
+        else {
+            self.last = self.last.or(Some(now));
+            return;
+        };
+        if now - last > self.days * 24 * HOUR_MS {
+            self.last = Some(now);
+            return;
+        }
+        let mut from = last;
+        while from < now {
+            let hour = from.div_euclid(HOUR_MS);
+            let to = now.min((hour + 1) * HOUR_MS);
+            self.hours.entry(hour).or_default().covered_ms += to - from;
+            from = to;
+        }
+        self.last = Some(now);
+    }
+
+    // The 10th to the 90th percentile of the rates of the same hour on the earlier days it was seen long enough.
+    fn typical(&self, hour: i64) -> Option<RateRange> {
+        let mut rates = (1..=self.days)
+            .filter_map(|day| self.hours.get(&(hour - day * 24)))
+            .filter(|count| count.covered_ms >= MIN_COVERED_MS)
+            .map(HourCount::rate)
+            .collect::<Vec<_>>();
+        if rates.len() < MIN_DAYS {
+            return None;
+        }
+        rates.sort_by(f64::total_cmp);
+        let at = |percentile: f64| rates[((rates.len() - 1) as f64 * percentile).round() as usize];
+        Some(RateRange {
+            low:  at(0.1),
+            high: at(0.9),
+            days: rates.len(),
+        })
+    }
+}
@@ -0,0 +1,142 @@
+use std::{
+    fs::{self, OpenOptions},
+    path::Path,
+};
+
+use anyhow::{anyhow, Result};
+use qnode_logproc_core::GroupBy;
+
+use crate::{
+    config::{CheckArgs, Config},
+    explain::explain,
+};
+
+#[cfg(any(feature = "redis", feature = "nats"))]
+const CONNECT_TIMEOUT: tokio::time::Duration = tokio::time::Duration::from_secs(5);
+
+/// Run the `check` command: list the patterns and rules of the configuration, try the outputs and show what the
+/// sample lines are parsed into. Returns the number of problems found.
+pub(crate) async fn run(config: &Config, args: &CheckArgs) -> Result<usize> {
+    let mut problems = 0;
+
+    // Clap has compiled the patterns and parsed the rules by now, invalid ones don't make it here.
+    println!("Options: OK");
+    let patterns = [
+        ("--endpoint", Some(&config.endpoint)),
+        ("--status", Some(&config.status)),
+        ("--trace-id", Some(&config.trace_id)),
+        ("--user-agent", config.user_agent.as_ref()),
+        ("--sequence", config.sequence.as_ref()),
+    ];
+    for (option, pattern) in patterns {
+        match pattern {
+            Some(pattern) if !pattern.as_str().is_empty() => println!("  {} {}", option, pattern),
+            _ => println!("  {} disabled", option),
+        }
+    }
+    for (option, patterns) in [
+        ("--mute", &config.mute),
+        ("--context", &config.context),
+        ("--redact-pattern", &config.redact_pattern),
+        ("--hash-pattern", &config.hash_pattern),
+    ] {
+        for pattern in patterns.iter() {
+            println!("  {} {}", option, pattern);
+        }
+    }
+    for group in config.group_by.iter() {
+        match group {
+            GroupBy::Message { name, pattern } => println!("  --group-by {}~{}", name, pattern),
+            _ => println!("  --group-by {}", group),
+        }
+    }
+    for rule in config.escalations.iter() {
+        println!("  --escalate {}", rule.expr);
+    }
+    for rule in config.alerts.iter() {
+        println!("  --alert {}", rule.name);
+    }
+    for rule in config.heartbeats.iter() {
+        println!("  --heartbeat {}", rule.expr);
+    }
+    for filter in config.compare.iter() {
+        println!("  --compare {}", filter);
+    }
+
+    println!("Outputs:");
+    let mut files = vec![
+        ("--jsonl", config.jsonl.as_deref()),
+        ("--prometheus-file", config.prometheus_file.as_deref()),
+        ("--dictionary", config.dictionary.as_deref()),
+    ];
+    files.extend(config.renderers.iter().map(|spec| ("--renderer", spec.path.as_deref())));
+    for (option, path) in files {
+        let Some(path) = path
+        else {
+            continue;
+        };
+        problems += report(&format!("{} {}", option, path.display()), check_writable(path));
+    }
+    #[cfg(feature = "redis")]
+    if config.redis_output.is_some() || config.redis_stream.is_some() {
+        let tls = crate::tls::ClientTls::from_config(config);
+        let connect = crate::redis_stream::connect(&config.redis_url, &tls);
+        let connected = tokio::time::timeout(CONNECT_TIMEOUT, connect)
+            .await
+            .map_err(|_| anyhow!("Timed out"))
+            .and_then(|conn| conn.map(|_| ()));
+        problems += report(&format!("Redis at {}", config.redis_url), connected);
+    }
+    #[cfg(feature = "nats")]
+    if config.nats_output.is_some() || config.nats_subject.is_some() {
+        let tls = crate::tls::ClientTls::from_config(config);
+        let connect = crate::nats::connect(&config.nats_url, config.nats_creds.as_deref(), &tls, false);
+        let connected = tokio::time::timeout(CONNECT_TIMEOUT, connect)
+            .await
+            .map_err(|_| anyhow!("Timed out"))
+            .and_then(|client| client.map(|_| ()));
+        problems += report(&format!("NATS at {}", config.nats_url), connected);
+    }
+
+    let mut lines = args.lines.iter().map(|line| ("--line".to_string(), line.clone())).collect::<Vec<_>>();
+    if let Some(ref path) = args.sample {
+        let content = fs::read_to_string(path).map_err(|e| anyhow!("Can't read {:?}: {}", path, e))?;
+        let source = path.display().to_string();
+        lines.extend(content.lines().map(|line| (source.clone(), line.to_string())));
+    }
+    if !lines.is_empty() {
+        println!("Sample lines:");
+    }
+    for (source, line) in lines {
+        if !explain(config, &source, &line, "  ")? {
+            problems += 1;
+        }
+    }
+
+    Ok(problems)
+}
+
+fn report(what: &str, result: Result<()>) -> usize {
+    match result {
+        Ok(()) => {
+            println!("  {}: OK", what);
+            0
+        }
+        Err(err) => {
+            println!("  {}: {}", what, err);
+            1
+        }
+    }
+}
+
+// Files are created on demand, so a missing one is fine as long as it can be created.
+fn check_writable(path: &Path) -> Result<()> {
+    if path.exists() {
+        OpenOptions::new().append(true).open(path)?;
+    }
+    else {
+        OpenOptions::new().write(true).create_new(true).open(path)?;
+        fs::remove_file(path)?;
+    }
+    Ok(())
+}
@@ -0,0 +1,101 @@
+use std::collections::HashSet;
+
+use anyhow::Result;
+use aws_sdk_cloudwatchlogs::Client;
+
+/// A log event pulled from CloudWatch Logs.
+pub(crate) struct CloudWatchEvent {
+    pub(crate) message:        String,
+    pub(crate) stream:         String,
+    /// When CloudWatch received the event, in milliseconds
+    pub(crate) ingestion_time: i64,
+}
+
+/// Tails a CloudWatch Logs group by polling `FilterLogEvents`. Region and credentials are resolved by the standard AWS
+/// SDK provider chain.
+pub(crate) struct CloudWatchInput {
+    client:         Client,
+    group:          String,
+    filter_pattern: Option<String>,
+    /// Event time to start the next poll from, in milliseconds
+    next_start:     i64,
+    /// IDs of the events seen with `next_start` timestamp. Since the next poll starts at the same millisecond they'd
+    /// be received again.
+    boundary_ids:   HashSet<String>,
+}
+
+impl CloudWatchInput {
+    pub(crate) async fn new(group: &str, filter_pattern: Option<String>, start_millis: i64) -> Self {
+        let config = aws_config::load_from_env().await;
+        Self {
+            client: Client::new(&config),
+            group: group.to_string(),
+            filter_pattern,
+            next_start: start_millis,
+            boundary_ids: HashSet::new(),
+        }
+    }
+
+    /// Fetch all events which arrived since the previous poll.
+    pub(crate) async fn poll(&mut self) -> Result<Vec<CloudWatchEvent>> {
+        let mut events = Vec::new();
+        let mut next_token = None;
+        let mut max_time = self.next_start;
+        let mut max_time_ids = HashSet::new();
+
+        loop {
+            let output = self
+                .client
+                .filter_log_events()
+                .log_group_name(&self.group)
+                .set_filter_pattern(self.filter_pattern.clone())
+                .start_time(self.next_start)
+                .set_next_token(next_token)
+                .send()
+                .await?;
+
+            for event in output.events() {
+                let id = event.event_id().unwrap_or_default().to_string();
+                let timestamp = event.timestamp().unwrap_or(self.next_start);
+
+                if timestamp == self.next_start && self.boundary_ids.contains(&id) {
+                    continue;
+                }
+
+                if timestamp > max_time {
+                    max_time = timestamp;
+                    max_time_ids.clear();
+                }
+                if timestamp == max_time {
+                    max_time_ids.insert(id);
+                }
+
+                events.push(CloudWatchEvent {
+                    message:        event.message().unwrap_or_default().trim_end().to_string(),
+                    stream:         event.log_stream_name().unwrap_or_default().to_string(),
+                    ingestion_time: event.ingestion_time().unwrap_or(timestamp),
+                });
+            }
+
+            next_token = output.next_token().map(|t| t.to_string());
+            if next_token.is_none() {
+                break;
+            }
+        }
+
+        if max_time > self.next_start {
+            self.next_start = max_time;
+            self.boundary_ids = max_time_ids;
+        }
+        else {
+            self.boundary_ids.extend(max_time_ids);
+        }
+
+        Ok(events)
+    }
+
+    /// What the names of the log group streams as sources start with.
+    pub(crate) fn source_prefix(&self) -> String {
+        format!("cloudwatch/{}", self.group)
+    }
+}
@@ -0,0 +1,1827 @@
+use std::{net::SocketAddr, path::PathBuf, str::FromStr, time::Duration};
+
+use anyhow::{anyhow, Result};
+use clap::Parser;
+use qnode_logproc_core::{facility_by_name, Clock, GroupBy, LevelMap, SnapshotView};
+use regex::Regex;
+use sha2::{Digest, Sha256};
+
+use crate::{
+    alert::{metric_value, AlertEvent, AlertTemplate},
+    filter::Filter,
+    glob::FileGlob,
+    listen::ListenAddress,
+    maintenance::{parse_duration, MaintenanceWindow},
+    notify::Oid,
+    reader::{STDIN_PATH, STDIN_SOURCE},
+    redact::{HashField, RedactKind},
+    rotate::Rotation,
+    slo::BURN_WINDOWS,
+    types::Level,
+};
+
+/// Command line configuration of the application.
+#[derive(Debug, Parser)]
+#[command(version, about = "Sliding window log statistics")]
+pub(crate) struct Config {
+    /// Process all input, render the final report and exit.
+    #[arg(long)]
+    pub(crate) once: bool,
+
+    /// Read the log file instead of standard input. It is followed like `tail -F` does, or read to the end with
+    /// `--once`. A file which can't be read is reported and retried. Wildcards in the file name, like `*.log`, select
+    /// all matching files, including those created later. `-` stands for standard input, read along with the files.
+    /// `--input` and `--glob` are the same. Can be used multiple times.
+    #[arg(
+        long = "file",
+        visible_aliases = ["input", "glob"],
+        value_name = "PATH",
+        conflicts_with_all = ["k8s", "docker"]
+    )]
+    pub(crate) files: Vec<PathBuf>,
+
+    /// Follow the `--file` inputs like `tail -F` does: keep reading as they grow, start over when one is truncated and
+    /// re-open it when it is replaced by a new file on rotation, with the window kept. It is what happens without
+    /// `--once` anyway; the flag makes it explicit and rejects `--once`.
+    #[arg(long, requires = "files", conflicts_with = "once")]
+    pub(crate) follow: bool,
+
+    /// Read what is already in the `--file` inputs before following them, with the progress shown in the report.
+    #[arg(long, requires = "files", conflicts_with = "once")]
+    pub(crate) backfill: bool,
+
+    /// Don't count lines read by `--backfill` in the current rate.
+    #[arg(long, requires = "backfill")]
+    pub(crate) backfill_exclude_rate: bool,
+
+    /// Only count records logged at or after the RFC 3339 time. Log files sorted by time are read starting close to
+    /// it rather than from their beginning.
+    #[arg(long, value_name = "TIME", requires = "once", value_parser = parse_time)]
+    pub(crate) from: Option<i64>,
+
+    /// Only count records logged before the RFC 3339 time. Reading of log files stops soon after it.
+    #[arg(long, value_name = "TIME", requires = "once", value_parser = parse_time)]
+    pub(crate) to: Option<i64>,
+
+    /// Time the sliding window and the rates are computed against: `receive` is the wall-clock time lines are
+    /// received at, `log` is the time in the log records, for replaying old logs.
+    #[arg(long, value_name = "CLOCK", value_enum, default_value_t = WindowClock::Receive)]
+    pub(crate) clock: WindowClock,
+
+    /// Length in milliseconds of the buckets records are counted in over time, from 10 to 1000: the current rate, the
+    /// trending of error messages and the status charts. Shorter buckets show bursts within a second.
+    #[arg(
+        long,
+        value_name = "MILLIS",
+        default_value_t = 1000,
+        value_parser = clap::value_parser!(i64).range(10..=1000)
+    )]
+    pub(crate) bucket_ms: i64,
+
+    /// Run the scenario file through the pipeline over virtual time instead of reading an input, and check the
+    /// snapshots against its expectations. Implies `--clock log`.
+    #[cfg(feature = "simulation")]
+    #[arg(long, value_name = "PATH", requires = "once")]
+    pub(crate) simulate: Option<PathBuf>,
+
+    /// Keep reading standard input after its end, like when the writer of a named pipe disconnects. Lines of the next
+    /// writer are added to the same statistics.
+    #[arg(long, conflicts_with = "once")]
+    pub(crate) keep_stdin: bool,
+
+    /// Kubernetes sidecar mode: follow container logs in the directory instead of reading standard input. Lines are
+    /// expected in CRI format and are tagged with `namespace/pod/container` source names.
+    #[arg(long, value_name = "DIR", num_args = 0..=1, default_missing_value = "/var/log/containers")]
+    pub(crate) k8s: Option<PathBuf>,
+
+    /// Only follow logs of pods whose names match the regular expression.
+    #[arg(long, value_name = "REGEX", requires = "k8s")]
+    pub(crate) k8s_pod: Option<Regex>,
+
+    /// Stream logs of the running container via the Docker API instead of reading standard input. Lines are tagged
+    /// with `docker/<name>` source names. Can be used multiple times.
+    #[arg(long, value_name = "CONTAINER", conflicts_with = "k8s")]
+    pub(crate) docker: Vec<String>,
+
+    /// Docker daemon socket.
+    #[arg(long, value_name = "PATH", default_value = "/var/run/docker.sock")]
+    pub(crate) docker_socket: PathBuf,
+
+    /// Accept lines over the network instead of reading standard input: `tcp://HOST:PORT`, like
+    /// `tcp://0.0.0.0:5140`, takes any number of connections at once; `udp://HOST:PORT`, like `udp://:514`, takes
+    /// syslog datagrams, each as a line. Lines are tagged with `tcp/<peer address>` or `udp/<peer address>` source
+    /// names.
+    #[arg(long, value_name = "URL", conflicts_with_all = ["k8s", "docker", "files", "once"])]
+    pub(crate) listen: Option<ListenAddress>,
+
+    /// Longest datagram in bytes taken by `--listen udp://`; longer ones are cut to it and counted.
+    #[arg(long, value_name = "BYTES", default_value_t = 8192, value_parser = clap::value_parser!(u32).range(1..=65535))]
+    pub(crate) listen_max_size: u32,
+
+    /// Certificate chain in PEM to accept `--listen tcp://` connections with TLS, along with `--listen-tls-key`.
+    #[cfg(feature = "tls")]
+    #[arg(long, value_name = "PATH", requires_all = ["listen", "listen_tls_key"])]
+    pub(crate) listen_tls_cert: Option<PathBuf>,
+
+    /// Private key in PEM of `--listen-tls-cert`.
+    #[cfg(feature = "tls")]
+    #[arg(long, value_name = "PATH", requires = "listen_tls_cert")]
+    pub(crate) listen_tls_key: Option<PathBuf>,
+
+    /// Only accept TLS clients of `--listen` presenting a certificate issued by a CA of the PEM file.
+    #[cfg(feature = "tls")]
+    #[arg(long, value_name = "PATH", requires = "listen_tls_cert")]
+    pub(crate) listen_client_ca: Option<PathBuf>,
+
+    /// Serve `/metrics` in the Prometheus text format, `/stats` with the latest snapshot in JSON and `POST /ingest`
+    /// taking the lines of the request body on the address, like `127.0.0.1:9100`. An address other than a loopback
+    /// one needs `--http-auth`.
+    #[arg(long, value_name = "IP:PORT")]
+    pub(crate) http: Option<SocketAddr>,
+
+    /// File of the credentials `--http` requests must present, one per line: `bearer TOKEN` or
+    /// `basic USER:PASSWORD`. Lines starting with `#` are comments. The file is read again on SIGHUP.
+    #[arg(long, value_name = "PATH", requires = "http")]
+    pub(crate) http_auth: Option<PathBuf>,
+
+    /// Certificate chain in PEM to serve `--http` over TLS, along with `--http-tls-key`.
+    #[cfg(feature = "tls")]
+    #[arg(long, value_name = "PATH", requires_all = ["http", "http_tls_key"])]
+    pub(crate) http_tls_cert: Option<PathBuf>,
+
+    /// Private key in PEM of `--http-tls-cert`.
+    #[cfg(feature = "tls")]
+    #[arg(long, value_name = "PATH", requires = "http_tls_cert")]
+    pub(crate) http_tls_key: Option<PathBuf>,
+
+    /// Read log objects under the location, `s3://bucket/prefix`, instead of standard input. Objects with `.gz`
+    /// suffix are decompressed. Best used with `--once`.
+    #[cfg(feature = "s3")]
+    #[arg(long, value_name = "URL", conflicts_with_all = ["k8s", "docker", "listen"])]
+    pub(crate) s3: Option<crate::s3::S3Location>,
+
+    /// Number of S3 objects downloaded in parallel.
+    #[cfg(feature = "s3")]
+    #[arg(long, value_name = "N", default_value_t = 4, value_parser = clap::value_parser!(u32).range(1..))]
+    pub(crate) s3_parallel: u32,
+
+    /// Tail the CloudWatch Logs group instead of reading standard input. Region and credentials are resolved by the
+    /// standard AWS SDK provider chain. Lines are tagged with `cloudwatch/<group>/<stream>` source names.
+    #[cfg(feature = "cloudwatch")]
+    #[arg(long, value_name = "GROUP", conflicts_with_all = ["k8s", "docker", "listen"])]
+    pub(crate) cloudwatch: Option<String>,
+
+    /// CloudWatch Logs filter pattern to select events.
+    #[cfg(feature = "cloudwatch")]
+    #[arg(long, value_name = "PATTERN", requires = "cloudwatch")]
+    pub(crate) cloudwatch_filter: Option<String>,
+
+    /// Where to start reading the CloudWatch Logs group: an RFC 3339 timestamp or how long ago, like `15m`, `2h`, `1d`.
+    /// Defaults to now.
+    #[cfg(feature = "cloudwatch")]
+    #[arg(long, value_name = "TIME", requires = "cloudwatch", value_parser = parse_start_time)]
+    pub(crate) cloudwatch_start: Option<i64>,
+
+    /// Consume the Google Cloud Pub/Sub subscription, `projects/<project>/subscriptions/<name>`, instead of reading
+    /// standard input. Each message may carry several lines. Lines are tagged with `pubsub/<name>` source names.
+    #[cfg(feature = "pubsub")]
+    #[arg(long, value_name = "SUBSCRIPTION", conflicts_with_all = ["k8s", "docker", "listen"])]
+    pub(crate) pubsub: Option<String>,
+
+    /// Consume the Azure event hub instead of reading standard input, through the Kafka endpoint of its namespace. Each
+    /// event may carry several lines. Lines are tagged with `eventhub/<name>` source names.
+    #[cfg(feature = "eventhubs")]
+    #[arg(
+        long,
+        value_name = "NAME",
+        requires = "eventhub_connection_string",
+        conflicts_with_all = ["k8s", "docker", "listen"]
+    )]
+    pub(crate) eventhub: Option<String>,
+
+    /// Connection string of the Event Hubs namespace or of the hub, as shown by the Azure portal.
+    #[cfg(feature = "eventhubs")]
+    #[arg(long, value_name = "STRING", env = "EVENTHUB_CONNECTION_STRING", hide_env_values = true)]
+    pub(crate) eventhub_connection_string: Option<String>,
+
+    /// Consumer group to read the event hub with.
+    #[cfg(feature = "eventhubs")]
+    #[arg(long, value_name = "NAME", default_value = "$Default", requires = "eventhub")]
+    pub(crate) eventhub_consumer_group: String,
+
+    /// CA certificates in PEM trusted instead of the system ones by TLS connections to servers: Redis with
+    /// `rediss://` URLs, NATS with `tls://` ones, MQTT brokers with `mqtts://` addresses, Event Hubs and the HTTPS
+    /// endpoints of the outputs and inputs.
+    #[cfg(feature = "tls")]
+    #[arg(long, value_name = "PATH")]
+    pub(crate) tls_ca: Option<PathBuf>,
+
+    /// Client certificate chain in PEM presented by TLS connections to servers which ask for one, along with
+    /// `--tls-key`.
+    #[cfg(feature = "tls")]
+    #[arg(long, value_name = "PATH", requires = "tls_key")]
+    pub(crate) tls_cert: Option<PathBuf>,
+
+    /// Private key in PEM of `--tls-cert`.
+    #[cfg(feature = "tls")]
+    #[arg(long, value_name = "PATH", requires = "tls_cert")]
+    pub(crate) tls_key: Option<PathBuf>,
+
+    /// Redis server URL for the Redis Streams input and output; `rediss://` connects with TLS.
+    #[cfg(feature = "redis")]
+    #[arg(long, value_name = "URL", env = "REDIS_URL", default_value = "redis://127.0.0.1/")]
+    pub(crate) redis_url: String,
+
+    /// Read log lines from the Redis Stream instead of standard input, as a member of a consumer group. Lines are
+    /// tagged with `redis/<stream>` source names.
+    #[cfg(feature = "redis")]
+    #[arg(long, value_name = "KEY", conflicts_with_all = ["k8s", "docker", "listen"])]
+    pub(crate) redis_stream: Option<String>,
+
+    /// Consumer group to read the Redis Stream with. Created if missing.
+    #[cfg(feature = "redis")]
+    #[arg(long, value_name = "NAME", default_value = "qnode-logproc", requires = "redis_stream")]
+    pub(crate) redis_group: String,
+
+    /// Consumer name within the group. Instances sharing a group must use different names.
+    #[cfg(feature = "redis")]
+    #[arg(long, value_name = "NAME", default_value = "qnode-logproc", requires = "redis_stream")]
+    pub(crate) redis_consumer: String,
+
+    /// Stream entry field holding the log lines or records.
+    #[cfg(feature = "redis")]
+    #[arg(long, value_name = "FIELD", default_value = "line", requires = "redis_stream")]
+    pub(crate) redis_field: String,
+
+    /// NATS server URL for the NATS input and output; `tls://` connects with TLS.
+    #[cfg(feature = "nats")]
+    #[arg(long, value_name = "URL", env = "NATS_URL", default_value = "nats://127.0.0.1:4222")]
+    pub(crate) nats_url: String,
+
+    /// NATS credentials file.
+    #[cfg(feature = "nats")]
+    #[arg(long, value_name = "PATH", env = "NATS_CREDS")]
+    pub(crate) nats_creds: Option<PathBuf>,
+
+    /// Read log lines from messages published to the NATS subject instead of standard input. Wildcards are allowed.
+    /// Lines are tagged with `nats/<subject>` source names.
+    #[cfg(feature = "nats")]
+    #[arg(long, value_name = "SUBJECT", conflicts_with_all = ["k8s", "docker", "listen"])]
+    pub(crate) nats_subject: Option<String>,
+
+    /// Consume `--nats-subject` from the JetStream stream with a durable consumer instead of a plain subscription.
+    #[cfg(feature = "nats")]
+    #[arg(long, value_name = "STREAM", requires = "nats_subject")]
+    pub(crate) nats_jetstream: Option<String>,
+
+    /// Name of the durable JetStream consumer. Created if missing.
+    #[cfg(feature = "nats")]
+    #[arg(long, value_name = "NAME", default_value = "qnode-logproc", requires = "nats_jetstream")]
+    pub(crate) nats_durable: String,
+
+    /// MQTT broker, `HOST[:PORT]`, to subscribe to `--mqtt-topic` at; `mqtts://HOST[:PORT]` connects with TLS. Lines
+    /// are tagged with `mqtt/<topic>` source names.
+    #[cfg(feature = "mqtt")]
+    #[arg(long, value_name = "ADDRESS", requires = "mqtt_topic", conflicts_with_all = ["k8s", "docker", "listen"])]
+    pub(crate) mqtt: Option<crate::mqtt::MqttBroker>,
+
+    /// MQTT topic filter to subscribe to; `+` and `#` wildcards are allowed. Can be used multiple times.
+    #[cfg(feature = "mqtt")]
+    #[arg(long, value_name = "TOPIC", requires = "mqtt")]
+    pub(crate) mqtt_topic: Vec<String>,
+
+    /// MQTT client ID. Must be unique per broker.
+    #[cfg(feature = "mqtt")]
+    #[arg(long, value_name = "ID", default_value = "qnode-logproc")]
+    pub(crate) mqtt_client_id: String,
+
+    /// QoS level of the MQTT subscriptions.
+    #[cfg(feature = "mqtt")]
+    #[arg(long, value_name = "LEVEL", default_value_t = 1, value_parser = clap::value_parser!(u8).range(0..=2))]
+    pub(crate) mqtt_qos: u8,
+
+    /// MQTT user name.
+    #[cfg(feature = "mqtt")]
+    #[arg(long, value_name = "NAME", env = "MQTT_USERNAME", requires = "mqtt_password")]
+    pub(crate) mqtt_username: Option<String>,
+
+    /// MQTT password.
+    #[cfg(feature = "mqtt")]
+    #[arg(long, value_name = "PASSWORD", env = "MQTT_PASSWORD", hide_env_values = true, requires = "mqtt_username")]
+    pub(crate) mqtt_password: Option<String>,
+
+    /// Format of message bus payloads, like Pub/Sub, Event Hubs, Redis, NATS or MQTT messages: text lines or
+    /// protobuf-encoded `LogRecordBatch` as defined in `proto/record.proto`.
+    #[cfg(feature = "protobuf")]
+    #[arg(long, value_name = "FORMAT", value_enum, default_value_t = InputFormat::Text)]
+    pub(crate) input_format: InputFormat,
+
+    /// Token-bucket ingestion limit in the form `[SOURCE=]LINES_PER_SEC[:BURST]`. A limit without a source name
+    /// applies to every source which doesn't have its own. Can be used multiple times.
+    #[arg(long = "rate-limit", value_name = "SPEC")]
+    pub(crate) rate_limits: Vec<RateLimitSpec>,
+
+    /// Number of lines waiting for the parser above which the pipeline is considered overloaded. Under overload ERROR
+    /// lines are processed first and other levels are sampled.
+    #[arg(long, value_name = "LINES", default_value_t = 10_000)]
+    pub(crate) overload_backlog: usize,
+
+    /// Under overload, only every Nth non-ERROR line is processed.
+    #[arg(long, value_name = "N", default_value_t = 10, value_parser = clap::value_parser!(u64).range(1..))]
+    pub(crate) overload_sample: u64,
+
+    /// Seconds between refreshes of the report and the outputs, from 0.1 to 600. Refreshing backs off while the
+    /// pipeline is overloaded.
+    #[arg(long, value_name = "SECS", default_value_t = 1.0)]
+    pub(crate) refresh: f64,
+
+    /// Number of threads counting the records. With more than one, every thread counts the records of its share of
+    /// the inputs apart, and the counts are merged into the statistics every 100 milliseconds.
+    #[arg(long, value_name = "N", default_value_t = 1, value_parser = clap::value_parser!(u32).range(1..))]
+    pub(crate) stats_shards: u32,
+
+    /// Lines longer than this many bytes are not parsed and are counted as oversized.
+    #[arg(long, value_name = "BYTES", default_value_t = 65_536)]
+    pub(crate) max_line_length: usize,
+
+    /// Records received more than this many seconds after they were logged are counted as late arrivals instead of
+    /// entering the statistics.
+    #[arg(long, value_name = "SECS")]
+    pub(crate) late_after: Option<f64>,
+
+    /// Mask the given kinds of sensitive data in messages before they are stored. Comma-separated.
+    #[arg(long, value_name = "KIND", value_delimiter = ',')]
+    pub(crate) redact: Vec<RedactKind>,
+
+    /// Mask every match of the regular expression in messages with `<redacted>`. Can be used multiple times.
+    #[arg(long, value_name = "REGEX")]
+    pub(crate) redact_pattern: Vec<Regex>,
+
+    /// Replace the given record fields with their salted hashes. Comma-separated.
+    #[arg(long, value_name = "FIELD", value_delimiter = ',')]
+    pub(crate) hash_field: Vec<HashField>,
+
+    /// Replace every match of the regular expression in messages with its salted hash. Can be used multiple times.
+    #[arg(long, value_name = "REGEX")]
+    pub(crate) hash_pattern: Vec<Regex>,
+
+    /// Salt for field hashing. If not set, a random one is generated, making hashes incomparable between runs.
+    #[arg(long, value_name = "SALT", env = "QNODE_LOGPROC_HASH_SALT", hide_env_values = true)]
+    pub(crate) hash_salt: Option<String>,
+
+    /// Detach from the terminal and run in the background. Implies `--headless`.
+    #[arg(long)]
+    pub(crate) daemon: bool,
+
+    /// Write the process ID into the file. It is removed on shutdown.
+    #[arg(long, value_name = "PATH")]
+    pub(crate) pid_file: Option<PathBuf>,
+
+    /// Where to redirect standard output and error in daemon mode. Defaults to `/dev/null`.
+    #[arg(long, value_name = "PATH")]
+    pub(crate) log_file: Option<PathBuf>,
+
+    /// Don't render the report on the terminal.
+    #[arg(long)]
+    pub(crate) headless: bool,
+
+    /// How the report is rendered: `terminal` redraws it in place, `minimal` as a single status line, `plain` prints it
+    /// as text and `json` as JSON lines.
+    /// `plain` and `json` can be written into a file with `=PATH`. Can be used multiple times, but only one renderer
+    /// may write to standard output. Defaults to `terminal`.
+    #[arg(long = "renderer", value_name = "KIND[=PATH]")]
+    pub(crate) renderers: Vec<RendererSpec>,
+
+    /// Render a single status line with the rate, the error share and the top error, updated in place. Same as
+    /// `--renderer minimal`.
+    #[arg(long, conflicts_with = "renderers")]
+    pub(crate) minimal: bool,
+
+    /// Number of past reports the terminal renderer keeps to browse back with the arrow keys. 0 disables browsing.
+    #[arg(long, value_name = "N", default_value_t = 120)]
+    pub(crate) history: usize,
+
+    /// Record the reports the terminal renderer draws, with their timing, into the session file, to replay them later
+    /// with the `playback` command.
+    #[arg(long, value_name = "PATH")]
+    pub(crate) record: Option<PathBuf>,
+
+    /// Upload the snapshot exported with the `s` key of the terminal report to the endpoint with an HTTP POST, and
+    /// show the link it responds with: the `Location` header, the `url` field of a JSON body or the body itself.
+    #[cfg(feature = "share")]
+    #[arg(long, value_name = "URL")]
+    pub(crate) share_url: Option<String>,
+
+    /// Bearer token of the `--share-url` endpoint.
+    #[cfg(feature = "share")]
+    #[arg(long, value_name = "TOKEN", env = "SHARE_TOKEN", hide_env_values = true, requires = "share_url")]
+    pub(crate) share_token: Option<String>,
+
+    /// Show the link to a shared snapshot as a QR code too, to open it on a phone.
+    #[cfg(feature = "share")]
+    #[arg(long, requires = "share_url")]
+    pub(crate) share_qr: bool,
+
+    /// How the report timestamp is displayed.
+    #[arg(long, value_name = "FORMAT", value_enum, default_value_t = TimeFormat::Local)]
+    pub(crate) time_format: TimeFormat,
+
+    /// Locale for digit grouping and decimal separators in the report, like `en_US` or `de_DE`. Defaults to the
+    /// locale of the environment; `C` disables grouping.
+    #[arg(long, value_name = "LOCALE")]
+    pub(crate) locale: Option<String>,
+
+    /// How large numbers are shown in the report: `grouped` like 1,234,567, `compact` like 1.23M, or `raw`.
+    #[arg(long, value_name = "STYLE", value_enum, default_value_t = NumberStyle::Grouped)]
+    pub(crate) number_style: NumberStyle,
+
+    /// Number of latest occurrences kept for every message, shown with the top and trending messages. 0 disables
+    /// examples.
+    #[arg(long, value_name = "N", default_value_t = 3)]
+    pub(crate) examples: usize,
+
+    /// Capture the lines around every occurrence of the messages matching the regular expression, of any level and
+    /// from the same source, to be shown in the report. Can be used multiple times.
+    #[arg(long, value_name = "REGEX")]
+    pub(crate) context: Vec<Regex>,
+
+    /// Number of lines captured before and after an occurrence of a `--context` message.
+    #[arg(long, value_name = "N", default_value_t = 5)]
+    pub(crate) context_lines: usize,
+
+    /// Take the endpoint of request logs from messages by the first capture group of the regular expression, or its
+    /// whole match, and show the endpoints with the most errors. The default finds paths after HTTP methods. An empty
+    /// expression disables it.
+    #[arg(long, value_name = "REGEX", default_value = r"\b(?:GET|POST|PUT|DELETE|PATCH|HEAD|OPTIONS)\s+(/[^\s?#]*)")]
+    pub(crate) endpoint: Regex,
+
+    /// Take the HTTP status code of request logs from messages like `--endpoint` does, and show the distribution of
+    /// the status classes. The default finds codes after `status=`, `code:`, `HTTP/1.1` or a request line. An empty
+    /// expression disables it.
+    #[arg(
+        long,
+        value_name = "REGEX",
+        default_value = concat!(
+            r#"\b(?:status|code)[=:]\s*"?([1-5]\d\d)\b"#,
+            r#"|HTTP/\d(?:\.\d)?"?\s+([1-5]\d\d)\b"#,
+            r"|\b(?:GET|POST|PUT|DELETE|PATCH|HEAD|OPTIONS)\s+/\S*\s+([1-5]\d\d)\b"
+        )
+    )]
+    pub(crate) status: Regex,
+
+    /// Take the trace or request ID from messages like `--endpoint` does, and count the distinct requests affected by
+    /// errors. The default finds values of `trace_id=`, `request-id:`, `req_id=` and similar. An empty expression
+    /// disables it.
+    #[arg(
+        long,
+        value_name = "REGEX",
+        default_value = r#"(?i)\b(?:trace|request|req|correlation)[-_]?id[=:]\s*"?([\w.:-]+)"#
+    )]
+    pub(crate) trace_id: Regex,
+
+    /// Parse user agents taken from messages like `--endpoint` does, and show the client families and versions with
+    /// the most errors. Without the expression, finds `user-agent=` or `UA:` values in quotes and the last quoted
+    /// field of combined access log lines.
+    #[arg(
+        long,
+        value_name = "REGEX",
+        num_args = 0..=1,
+        default_missing_value = r#"(?i)\b(?:user[-_]?agent|ua)[=:]\s*"([^"]*)"|"[^"]*" "([^"]*)"\s*$"#
+    )]
+    pub(crate) user_agent: Option<Regex>,
+
+    /// Group the records by the dimension and show the records and errors of the values with the most errors in a
+    /// section of the report and the outputs: `source`, `ip/PREFIX` for networks, `endpoint`, `status` or
+    /// `NAME~REGEX` for values taken from messages like `--endpoint` does. Can be used multiple times.
+    #[arg(long, value_name = "DIMENSION")]
+    pub(crate) group_by: Vec<GroupBy>,
+
+    /// Take sequence numbers from messages like `--endpoint` does, like `seq=(\d+)`, and report numbers missing or
+    /// repeated per source, which tells lines lost upstream.
+    #[arg(long, value_name = "REGEX")]
+    pub(crate) sequence: Option<Regex>,
+
+    /// Drop records less severe than the level, `DEBUG` < `INFO` < `ERROR`, before they are counted or written to the
+    /// outputs. Dropped records are only tallied.
+    #[arg(long, value_name = "LEVEL", value_parser = parse_level)]
+    pub(crate) min_level: Option<Level>,
+
+    /// Only count the lines relayed by syslog with the facility, by name like `daemon` or `local0`, or by number, to
+    /// keep kernel or system noise out of the statistics of a mixed stream. Lines without a syslog priority are not
+    /// counted either then. Comma-separated, can be used multiple times.
+    #[arg(long, value_name = "FACILITY", value_delimiter = ',', value_parser = parse_facility)]
+    pub(crate) facility: Vec<u8>,
+
+    /// Count lines with the level name, as written, at one of the levels, like `WARNING=INFO,ERR=ERROR`. Lines with
+    /// other names than `INFO`, `ERROR` and `DEBUG` are rejected as of an unknown level otherwise. Comma-separated,
+    /// can be used multiple times.
+    #[arg(long, value_name = "NAME=LEVEL", value_delimiter = ',', value_parser = parse_level_mapping)]
+    pub(crate) level_map: Vec<(String, Level)>,
+
+    /// Acknowledge the messages matching the regular expression as known: their errors are left out of the top lists
+    /// and alerting and counted as muted instead. Can be used multiple times.
+    #[arg(long, value_name = "REGEX")]
+    pub(crate) mute: Vec<Regex>,
+
+    /// Count records of the level with messages matching the regular expression as ERROR while more than COUNT of
+    /// them are seen within the period, like `INFO~retrying>100/min`. Periods are `s`, `min` and `h`. Can be used
+    /// multiple times.
+    #[arg(long = "escalate", value_name = "LEVEL~REGEX>COUNT/PERIOD")]
+    pub(crate) escalations: Vec<EscalationSpec>,
+
+    /// Show statistics of the records matching the filter next to the ones of the records matching the second
+    /// `--compare` filter, or everything else if there is only one. A filter is a comma-separated list of conditions
+    /// which must all match: `ip=ADDRESS[/PREFIX]`, `level=LEVEL`, `source=NAME` or `message~REGEX`.
+    #[arg(long, value_name = "FILTER")]
+    pub(crate) compare: Vec<Filter>,
+
+    /// Alert rule in the form `[NAME=]METRIC>VALUE` or `[NAME=]METRIC<VALUE`, checked on every refresh and listed in
+    /// the alerts panel of the report. Metrics are `rate`, `current_rate`, `peak_rate`, `error_rate`, `errors`,
+    /// `malformed`, `malformed_ratio`, `avg_lag`, `distinct_ips`, `sequence_loss`, `burstiness`, `attacks`, with
+    /// `--slo` `error_budget` and `burn_rate_5m`, `_30m`, `_1h`, `_2h`, `_6h`, `_1d` and `_3d`, and of the analyzer
+    /// itself `backlog` and `drop_rate`; values may be given like `2%` or `500/s`. Conditions can be combined with
+    /// `AND` and `OR`, `AND` binding tighter. The condition may be followed by `for DURATION`, to fire only once it
+    /// has held that long, `clear VALUE`, to resolve only once the value is back past it rather than the threshold,
+    /// and `cooldown DURATION`, to not fire again that soon after resolving, like
+    /// `errors-high=error_rate>0.05 for 30s clear 0.03 cooldown 5m`. Can be used multiple times.
+    #[arg(long = "alert", value_name = "RULE")]
+    pub(crate) alerts: Vec<AlertSpec>,
+
+    /// File with `--mute`, `--escalate` and `--alert` options, one per line like `--mute ^healthcheck`, added to the
+    /// ones given on the command line. Empty lines and lines starting with `#` are skipped. The file is read again on
+    /// SIGHUP; if it can't be loaded then, the rules in use are kept and the failure is reported.
+    #[arg(long, value_name = "PATH")]
+    pub(crate) rules: Option<PathBuf>,
+
+    /// The rules given on the command line, the ones of `--rules` are added to.
+    #[arg(skip)]
+    pub(crate) command_line_rules: Rules,
+
+    /// Expect the source to log a message matching the regular expression at least once per interval, like
+    /// `api.log=health ok/30s`, and alert that the source is silent when it doesn't. Without a source, or with `*`,
+    /// every source which has logged anything is expected to; a regular expression containing `=` needs the source
+    /// given. Intervals are in `s`, `m`, `h` or `d`. Can be used multiple times.
+    #[arg(long = "heartbeat", value_name = "[SOURCE=]REGEX/INTERVAL")]
+    pub(crate) heartbeats: Vec<HeartbeatSpec>,
+
+    /// Flag the IPs whose errors per minute jump to more than `--suspicious-factor` times their own usual rate, like a
+    /// new IP suddenly producing thousands of errors, and list them under "Suspicious sources".
+    #[arg(long)]
+    pub(crate) suspicious_ips: bool,
+
+    /// Errors per minute below which an IP is not flagged, however low its usual rate is.
+    #[arg(long, value_name = "N", default_value_t = 100, requires = "suspicious_ips")]
+    pub(crate) suspicious_min_errors: u64,
+
+    /// How many times its usual errors per minute an IP must have to be flagged.
+    #[arg(long, value_name = "FACTOR", default_value_t = 10.0, requires = "suspicious_ips")]
+    pub(crate) suspicious_factor: f64,
+
+    /// Watch the records with messages matching the regular expression, like `login failed`, for brute-force attempts,
+    /// one IP with many distinct such messages like for different user names, and scans, one such message from many
+    /// IPs, within `--attack-interval`. They are listed under "Attacks" and counted by the `attacks` alert metric.
+    #[arg(long, value_name = "REGEX")]
+    pub(crate) auth_failure: Option<Regex>,
+
+    /// How long back the failed authentications are looked at, like `1m`.
+    #[arg(
+        long,
+        value_name = "DURATION",
+        default_value = "1m",
+        value_parser = parse_interval,
+        requires = "auth_failure"
+    )]
+    pub(crate) attack_interval: Duration,
+
+    /// Distinct failed authentication messages of one IP which make a brute-force attempt.
+    #[arg(long, value_name = "N", default_value_t = 10, requires = "auth_failure")]
+    pub(crate) brute_force_messages: usize,
+
+    /// IPs with the same failed authentication message which make a scan.
+    #[arg(long, value_name = "N", default_value_t = 20, requires = "auth_failure")]
+    pub(crate) scan_ips: usize,
+
+    /// Track a service level objective of the share of entries which are not errors over a period, like `99.9%/30d`,
+    /// 30 days by default, and show the error budget left and how fast it burns. Adds the multiwindow burn-rate alert
+    /// rules `slo-fast` and `slo-slow`; the rule `slo` of `--alert-to` stands for both.
+    #[arg(long, value_name = "OBJECTIVE[/PERIOD]")]
+    pub(crate) slo: Option<SloSpec>,
+
+    /// Keep the entries and errors per minute of the `--slo` period in the file, read on start and written every
+    /// minute, on SIGUSR1 and on exit, so that the budget survives restarts.
+    #[arg(long, value_name = "PATH", requires = "slo")]
+    pub(crate) slo_state: Option<PathBuf>,
+
+    /// Keep the entries and errors of every hour in the file, read on start and written every minute, on SIGUSR1 and
+    /// on exit, and show the rates of the latest hours against the range they usually fall in at the same time of
+    /// day, once the file has seen that hour on at least 3 earlier days.
+    #[arg(long, value_name = "PATH")]
+    pub(crate) baseline: Option<PathBuf>,
+
+    /// Days of hours kept in the `--baseline` file.
+    #[arg(
+        long,
+        value_name = "DAYS",
+        default_value_t = 28,
+        value_parser = clap::value_parser!(u32).range(1..),
+        requires = "baseline"
+    )]
+    pub(crate) baseline_days: u32,
+
+    /// Add alert rules on the health of the analyzer itself: `self-backlog` when more than `--self-backlog` lines wait
+    /// for the parser, `self-drops` when lines are dropped by sampling under overload or by the rate limiter, and
+    /// `self-malformed` when the share of malformed lines in the window is above `--self-malformed-ratio`. The rule
+    /// `self` of `--alert-to` stands for all of them.
+    #[arg(long)]
+    pub(crate) self_monitor: bool,
+
+    /// Lines waiting for the parser above which `self-backlog` fires.
+    #[arg(long, value_name = "LINES", default_value_t = 5_000, requires = "self_monitor")]
+    pub(crate) self_backlog: usize,
+
+    /// Share of malformed lines in the window above which `self-malformed` fires, like `0.1` or `10%`.
+    #[arg(long, value_name = "RATIO", default_value = "10%", value_parser = parse_threshold, requires = "self_monitor")]
+    pub(crate) self_malformed_ratio: f64,
+
+    /// Suppress alerting during the period, either recurring like `mon-fri 02:00-03:00` or `22:00-01:00` for every
+    /// day, in local time, or from the start like `for 30m`. Snapshots taken during the period are flagged with
+    /// `maintenance`. Can be used multiple times.
+    #[arg(long, value_name = "PERIOD")]
+    pub(crate) maintenance: Vec<MaintenanceWindow>,
+
+    /// How the terminal renderer draws attention when an alert fires: `bell` rings the terminal bell, `flash` shows
+    /// the report header in reverse video while any alert is firing. Comma-separated.
+    #[arg(long, value_name = "HOW", value_delimiter = ',')]
+    pub(crate) alert_notify: Vec<AlertNotify>,
+
+    /// Send the alerts of the rule, by its name, to a target as they fire and resolve: an SNMPv2c trap to
+    /// `snmp://[COMMUNITY@]HOST[:PORT]` or a passive check result to the external command file of Nagios or Icinga,
+    /// `nagios:PATH`. With the `paging` feature also a PagerDuty incident, `pagerduty:ROUTING_KEY`, or an Opsgenie
+    /// alert, `opsgenie:API_KEY`; a key given as `$NAME` is taken from the environment variable. The rule `heartbeat`
+    /// stands for all heartbeats, `self` for the `--self-monitor` rules, `slo` for the `--slo` ones, `*` for
+    /// everything. Can be used multiple times.
+    #[arg(long, value_name = "RULE=TARGET")]
+    pub(crate) alert_to: Vec<AlertTarget>,
+
+    /// Message of the alerts sent to the `--alert-to` targets instead of the bare condition, with placeholders for
+    /// the alert: `{name}`, `{rule}`, `{source}`, `{state}`, `{condition}`, `{value}`, `{summary}`, `{since}`,
+    /// `{host}`, and for the snapshot it fired or resolved on: `{rate}`, `{errors}`, `{error_rate}`, `{distinct_ips}`,
+    /// `{top}` the top 3 errors with their counts, `{dashboard}` the `--dashboard-url`. `\n` is a line break.
+    #[arg(long, value_name = "TEMPLATE")]
+    pub(crate) alert_template: Option<AlertTemplate>,
+
+    /// URL of the dashboard the alerts link to, like a Grafana board of the outputs.
+    #[arg(long, value_name = "URL")]
+    pub(crate) dashboard_url: Option<String>,
+
+    /// Host name of the passive check results, the host the analyzer runs on by default. The services are named
+    /// after the rules.
+    #[arg(long, value_name = "NAME")]
+    pub(crate) nagios_host: Option<String>,
+
+    /// OID the SNMP traps are under: the alert firing is `OID.0.1`, resolved `OID.0.2`, and the variables are
+    /// `OID.1.1` the name of the alert, `OID.1.2` its description, `OID.1.3` the value, `OID.1.4` the host and
+    /// `OID.1.5` the top error of an error rule.
+    #[arg(long, value_name = "OID", default_value = "1.3.6.1.4.1.8072.9999.9999")]
+    pub(crate) snmp_trap_oid: Oid,
+
+    /// Base URL of the Opsgenie API, `https://api.eu.opsgenie.com` for accounts in the EU.
+    #[cfg(feature = "paging")]
+    #[arg(long, value_name = "URL", default_value = "https://api.opsgenie.com")]
+    pub(crate) opsgenie_url: String,
+
+    /// Send a summary of every period by `--summary-schedule`, with the counts, the rates and the top errors, whether
+    /// any alert fired or not: as JSON posted to a webhook `URL`, or as a message to a Slack incoming webhook,
+    /// `slack:URL`. Can be used multiple times.
+    #[cfg(feature = "summary")]
+    #[arg(long, value_name = "[slack:]URL")]
+    pub(crate) summary_to: Vec<String>,
+
+    /// When the summaries are sent: every period like `1h`, or daily at a time like `08:00` or `mon-fri 08:00`.
+    #[cfg(feature = "summary")]
+    #[arg(long, value_name = "SCHEDULE", default_value = "1h", requires = "summary_to")]
+    pub(crate) summary_schedule: crate::summary::SummarySchedule,
+
+    /// Flag sources whose log times are on average ahead of or behind receive times by more than this many seconds,
+    /// which means their clocks are off.
+    #[arg(long, value_name = "SECS", default_value_t = 30.0)]
+    pub(crate) skew_threshold: f64,
+
+    /// Append a statistics snapshot as a JSON line to the file on every refresh.
+    #[arg(long, value_name = "PATH")]
+    pub(crate) jsonl: Option<PathBuf>,
+
+    /// Append every record counted as a JSON line to the file, with the level it is counted at.
+    #[arg(long, value_name = "PATH")]
+    pub(crate) events: Option<PathBuf>,
+
+    /// Rotate the `--jsonl` and `--events` files and the files of renderers once they grow to this many bytes.
+    #[arg(long, value_name = "BYTES", value_parser = clap::value_parser!(u64).range(1..))]
+    pub(crate) rotate_size: Option<u64>,
+
+    /// Rotate the `--jsonl` and `--events` files and the files of renderers this long after they are opened, like `1h`
+    /// or `1d`.
+    #[arg(long, value_name = "DURATION", value_parser = parse_interval)]
+    pub(crate) rotate_interval: Option<Duration>,
+
+    /// Number of rotated files to keep for each file, the oldest are removed.
+    #[arg(long, value_name = "N", default_value_t = 10)]
+    pub(crate) rotate_keep: usize,
+
+    /// Compress the rotated files.
+    #[cfg(feature = "compress")]
+    #[arg(long, value_name = "FORMAT")]
+    pub(crate) rotate_compress: Option<Compression>,
+
+    /// Write every message seen with its ID, total count and first and last log times into the file on exit, on
+    /// SIGUSR1 and when `d` is pressed in the terminal report. JSON if the name ends with `.json`, CSV otherwise.
+    #[arg(long, value_name = "PATH")]
+    pub(crate) dictionary: Option<PathBuf>,
+
+    /// Run the SQL query over the records left in the window once all input is processed, and print its result after
+    /// the report. The `records` table has `time`, `received`, `level`, `rejected`, `msg_id`, `message`, `template`,
+    /// `ip`, `source`, `endpoint`, `status`, `client` and `tags` columns. In the live terminal report queries are typed
+    /// at the prompt opened with `:` instead. Can be used multiple times.
+    #[cfg(feature = "sql")]
+    #[arg(long = "query", value_name = "SQL", requires = "once")]
+    pub(crate) queries: Vec<String>,
+
+    /// Write statistics in Prometheus text format into the file on every refresh, suitable for the node_exporter
+    /// textfile collector.
+    #[arg(long, value_name = "PATH")]
+    pub(crate) prometheus_file: Option<PathBuf>,
+
+    /// Write the records per second of log time and level to the Prometheus remote-write endpoint, with their
+    /// original timestamps, to backfill a TSDB from historical logs.
+    #[cfg(feature = "remote-write")]
+    #[arg(long, value_name = "URL")]
+    pub(crate) remote_write: Option<String>,
+
+    /// Label added to the remote-written series, like `job=qnode`. Can be used multiple times.
+    #[cfg(feature = "remote-write")]
+    #[arg(long = "remote-write-label", value_name = "NAME=VALUE", value_parser = crate::remote_write::parse_label)]
+    pub(crate) remote_write_labels: Vec<(String, String)>,
+
+    /// Write the window aggregates as InfluxDB line protocol on every refresh: to the HTTP write endpoint, like
+    /// `http://localhost:8086/api/v2/write?org=ops&bucket=logs&precision=ns`, or as datagrams to `udp://HOST:PORT`.
+    #[cfg(feature = "influx")]
+    #[arg(long, value_name = "URL")]
+    pub(crate) influx: Option<String>,
+
+    /// API token of the InfluxDB HTTP write endpoint.
+    #[cfg(feature = "influx")]
+    #[arg(long, value_name = "TOKEN", env = "INFLUX_TOKEN", hide_env_values = true, requires = "influx")]
+    pub(crate) influx_token: Option<String>,
+
+    /// Measurement of the points written to InfluxDB, the per source and per status class points get `_sources` and
+    /// `_statuses` appended.
+    #[cfg(feature = "influx")]
+    #[arg(long, value_name = "NAME", default_value = "qnode_logproc", requires = "influx")]
+    pub(crate) influx_measurement: String,
+
+    /// Tag added to the points written to InfluxDB, like `host=node1`. Can be used multiple times.
+    #[cfg(feature = "influx")]
+    #[arg(long = "influx-tag", value_name = "NAME=VALUE", value_parser = crate::influx::parse_tag, requires = "influx")]
+    pub(crate) influx_tags: Vec<(String, String)>,
+
+    /// Append the records received per second to the Redis Stream, an entry per second with the entries, their
+    /// levels and the lines which failed to parse.
+    #[cfg(feature = "redis")]
+    #[arg(long, value_name = "KEY")]
+    pub(crate) redis_output: Option<String>,
+
+    /// Approximate number of entries the output Redis Stream is trimmed to.
+    #[cfg(feature = "redis")]
+    #[arg(long, value_name = "N", default_value_t = 10_000, requires = "redis_output")]
+    pub(crate) redis_output_maxlen: usize,
+
+    /// Publish a statistics snapshot as JSON to the NATS subject on every refresh.
+    #[cfg(feature = "nats")]
+    #[arg(long, value_name = "SUBJECT")]
+    pub(crate) nats_output: Option<String>,
+
+    /// Keep what the network outputs fail to deliver in files of the directory, one per output, and deliver it in
+    /// order once the output works again, also after a restart.
+    #[arg(long, value_name = "DIR")]
+    pub(crate) spool_dir: Option<PathBuf>,
+
+    /// Size in bytes a spool file of `--spool-dir` doesn't grow past; what doesn't fit is dropped. Defaults to 100 MiB.
+    #[arg(long, value_name = "BYTES", default_value_t = 100 << 20, value_parser = clap::value_parser!(u64).range(1..))]
+    pub(crate) spool_size: u64,
+
+    #[command(subcommand)]
+    pub(crate) command: Option<Command>,
+}
+
+/// The options of a `--rules` file.
+#[derive(Debug, Clone, Default, Parser)]
+#[command(no_binary_name = true)]
+pub(crate) struct Rules {
+    #[arg(long, value_name = "REGEX")]
+    pub(crate) mute:        Vec<Regex>,
+    #[arg(long = "escalate", value_name = "LEVEL~REGEX>COUNT/PERIOD")]
+    pub(crate) escalations: Vec<EscalationSpec>,
+    #[arg(long = "alert", value_name = "RULE")]
+    pub(crate) alerts:      Vec<AlertSpec>,
+}
+
+#[derive(Debug, clap::Subcommand)]
+pub(crate) enum Command {
+    /// Check the options given before the command and the outputs they name, show what sample lines are parsed into
+    /// and exit. The exit status is 1 if an output can't be written to or a sample line is rejected.
+    Check(CheckArgs),
+    /// Show step by step how the lines are parsed and what the statistics make of them, with the options given
+    /// before the command, and exit. The exit status is 1 if a line is rejected.
+    Explain(ExplainArgs),
+    /// Read two log files to the end and show how the second one differs from the first: rates, the mix of levels,
+    /// rejected lines, and the messages which appeared, disappeared or changed in frequency. Redaction and hashing
+    /// options apply, so messages differing in redacted parts compare alike.
+    Diff(DiffArgs),
+    /// Combine the latest snapshots of `--jsonl` files, like those of several hosts or time ranges, and render the
+    /// combined one with the `--renderer`s given, as text if none or a terminal one is.
+    Merge(MergeArgs),
+    /// Replay a session recorded with `--record` on the terminal, report by report with the timing they were drawn
+    /// with.
+    Playback(PlaybackArgs),
+}
+
+#[derive(Debug, clap::Args)]
+pub(crate) struct CheckArgs {
+    /// Log line to run through the parser. Can be used multiple times.
+    #[arg(long = "line", value_name = "LINE")]
+    pub(crate) lines: Vec<String>,
+
+    /// File with log lines to run through the parser.
+    #[arg(long, value_name = "PATH")]
+    pub(crate) sample: Option<PathBuf>,
+}
+
+#[derive(Debug, clap::Args)]
+pub(crate) struct ExplainArgs {
+    /// Log lines to explain.
+    #[arg(value_name = "LINE", required = true)]
+    pub(crate) lines: Vec<String>,
+
+    /// Source name the lines are taken to come from, for `--compare` filters.
+    #[arg(long, value_name = "NAME", default_value = crate::reader::STDIN_SOURCE)]
+    pub(crate) source: String,
+}
+
+#[derive(Debug, clap::Args)]
+pub(crate) struct DiffArgs {
+    /// Log file to compare against, like one from before a release.
+    #[arg(value_name = "OLD")]
+    pub(crate) old: PathBuf,
+
+    /// Log file to compare.
+    #[arg(value_name = "NEW")]
+    pub(crate) new: PathBuf,
+}
+
+#[derive(Debug, clap::Args)]
+pub(crate) struct PlaybackArgs {
+    /// Session file written with `--record`.
+    #[arg(value_name = "PATH")]
+    pub(crate) file: PathBuf,
+
+    /// How many times faster than recorded to play.
+    #[arg(long, value_name = "FACTOR", default_value_t = 1.0)]
+    pub(crate) speed: f64,
+
+    /// Longest pause between two reports, in seconds of playback. Longer ones, like while the input was quiet, are
+    /// cut short.
+    #[arg(long, value_name = "SECS", default_value_t = 10.0)]
+    pub(crate) max_gap: f64,
+}
+
+#[derive(Debug, clap::Args)]
+pub(crate) struct MergeArgs {
+    /// Files of snapshots written with `--jsonl`.
+    #[arg(value_name = "PATH", required = true)]
+    pub(crate) files: Vec<PathBuf>,
+}
+
+impl Config {
+    /// Whether records of the facility, or without one, are counted.
+    pub(crate) fn counts_facility(&self, facility: Option<u8>) -> bool {
+        self.facility.is_empty() || facility.is_some_and(|facility| self.facility.contains(&facility))
+    }
+
+    pub(crate) fn level_map(&self) -> LevelMap {
+        self.level_map.iter().cloned().collect()
+    }
+
+    pub(crate) fn is_headless(&self) -> bool {
+        self.headless || self.daemon
+    }
+
+    /// Inputs the reader takes lines from, in the order it picks them.
+    pub(crate) fn inputs(&self) -> Vec<String> {
+        #[cfg(feature = "simulation")]
+        if let Some(ref path) = self.simulate {
+            return vec![format!("simulation:{}", path.display())];
+        }
+        if let Some(ref dir) = self.k8s {
+            return vec![format!("k8s:{}", dir.display())];
+        }
+        if !self.docker.is_empty() {
+            return self.docker.iter().map(|name| format!("docker:{}", name)).collect();
+        }
+        #[cfg(feature = "cloudwatch")]
+        if let Some(ref group) = self.cloudwatch {
+            return vec![format!("cloudwatch:{}", group)];
+        }
+        #[cfg(feature = "pubsub")]
+        if let Some(ref subscription) = self.pubsub {
+            return vec![format!("pubsub:{}", subscription)];
+        }
+        #[cfg(feature = "eventhubs")]
+        if let Some(ref hub) = self.eventhub {
+            return vec![format!("eventhub:{}", hub)];
+        }
+        #[cfg(feature = "redis")]
+        if let Some(ref stream) = self.redis_stream {
+            return vec![format!("redis:{}", stream)];
+        }
+        #[cfg(feature = "nats")]
+        if let Some(ref subject) = self.nats_subject {
+            return vec![format!("nats:{}", subject)];
+        }
+        #[cfg(feature = "mqtt")]
+        if let Some(ref broker) = self.mqtt {
+            return self
+                .mqtt_topic
+                .iter()
+                .map(|topic| format!("{}/{}", broker, topic))
+                .collect();
+        }
+        #[cfg(feature = "s3")]
+        if let Some(ref location) = self.s3 {
+            return vec![format!("s3://{}/{}", location.bucket, location.prefix)];
+        }
+        if let Some(ref address) = self.listen {
+            return vec![address.to_string()];
+        }
+        if !self.files.is_empty() {
+            return self
+                .files
+                .iter()
+                .map(|path| {
+                    if path.as_os_str() == STDIN_PATH {
+                        STDIN_SOURCE.to_string()
+                    }
+                    else {
+                        path.display().to_string()
+                    }
+                })
+                .collect();
+        }
+        vec!["stdin".to_string()]
+    }
+
+    /// Hash of the options, the same for runs configured alike.
+    pub(crate) fn hash(&self) -> String {
+        let mut hasher = Sha256::new();
+        hasher.update(format!("{:?}", self).as_bytes());
+        hasher.finalize()[..8].iter().map(|b| format!("{:02x}", b)).collect()
+    }
+
+    /// When and how the files written to are rotated.
+    pub(crate) fn rotation(&self) -> Rotation {
+        Rotation {
+            size:        self.rotate_size,
+            interval:    self.rotate_interval,
+            keep:        self.rotate_keep,
+            #[cfg(feature = "compress")]
+            compression: self.rotate_compress,
+        }
+    }
+
+    /// Add the rules of `--self-monitor` and `--slo` to the alert rules.
+    pub(crate) fn add_builtin_rules(&mut self) -> Result<()> {
+        let mut rules = Vec::new();
+        if self.self_monitor {
+            rules.extend([
+                ("--self-monitor", format!("self-backlog=backlog>{}", self.self_backlog)),
+                ("--self-monitor", "self-drops=drop_rate>0".to_string()),
+                ("--self-monitor", format!("self-malformed=malformed_ratio>{}", self.self_malformed_ratio)),
+            ]);
+        }
+        if self.slo.is_some() {
+            // Spending 2% of a 30-day budget within an hour or 5% within 6 hours pages, 10% within 3 days is a ticket.
+            rules.extend([
+                ("--slo", "slo-fast=burn_rate_1h>14.4 AND burn_rate_5m>14.4 OR burn_rate_6h>6 AND burn_rate_30m>6"),
+                ("--slo", "slo-slow=burn_rate_1d>3 AND burn_rate_2h>3 OR burn_rate_3d>1 AND burn_rate_6h>1"),
+            ]
+            .map(|(option, rule)| (option, rule.to_string())));
+        }
+        for (option, rule) in rules {
+            let rule = rule.parse::<AlertSpec>()?;
+            if self.alerts.iter().any(|alert| alert.name == rule.name) {
+                return Err(anyhow!("The alert rule name '{}' is taken by {}", rule.name, option));
+            }
+            self.alerts.push(rule);
+        }
+        Ok(())
+    }
+
+    /// Add the rules of the `--rules` file, after the ones of `--self-monitor` and `--slo` are added.
+    pub(crate) fn add_file_rules(&mut self) -> Result<()> {
+        self.command_line_rules = Rules {
+            mute:        self.mute.clone(),
+            escalations: self.escalations.clone(),
+            alerts:      self.alerts.clone(),
+        };
+        let rules = self.load_rules()?;
+        self.mute = rules.mute;
+        self.escalations = rules.escalations;
+        self.alerts = rules.alerts;
+        Ok(())
+    }
+
+    /// The rules of the command line with the ones of the `--rules` file as it is now.
+    pub(crate) fn load_rules(&self) -> Result<Rules> {
+        let mut rules = self.command_line_rules.clone();
+        let Some(ref path) = self.rules
+        else {
+            return Ok(rules);
+        };
+        let content = std::fs::read_to_string(path).map_err(|e| anyhow!("Can't read rules file {:?}: {}", path, e))?;
+        let args = content
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty() && !line.starts_with('#'))
+            .flat_map(|line| match line.split_once(char::is_whitespace) {
+                Some((option, value)) => vec![option, value.trim()],
+                None => vec![line],
+            });
+        // Only the first line of a clap error tells the problem, the rest is usage.
+        let file = Rules::try_parse_from(args).map_err(|e| {
+            let error = e.to_string();
+            let problem = error.lines().next().unwrap_or_default().trim_start_matches("error: ").to_string();
+            anyhow!("Bad rules file {:?}: {}", path, problem)
+        })?;
+
+        for rule in file.alerts {
+            if rules.alerts.iter().any(|alert| alert.name == rule.name) {
+                return Err(anyhow!("The alert rule name '{}' of the rules file {:?} is taken", rule.name, path));
+            }
+            rules.alerts.push(rule);
+        }
+        rules.mute.extend(file.mute);
+        rules.escalations.extend(file.escalations);
+        self.check_alert_rules(&rules.alerts)?;
+        Ok(rules)
+    }
+
+    /// Check constraints between options which clap can't express.
+    pub(crate) fn validate(&self) -> Result<()> {
+        if self.renderers.iter().filter(|spec| spec.path.is_none()).count() > 1 {
+            return Err(anyhow!("Only one --renderer can write to standard output"));
+        }
+        for path in self.files.iter() {
+            FileGlob::new(path)?;
+        }
+        if self.files.iter().filter(|path| path.as_os_str() == STDIN_PATH).count() > 1 {
+            return Err(anyhow!("Standard input can be given as --file only once"));
+        }
+        if self.compare.len() > 2 {
+            return Err(anyhow!("At most two --compare filters can be given"));
+        }
+        if !self.alert_notify.is_empty() && self.alerts.is_empty() && self.heartbeats.is_empty() {
+            return Err(anyhow!("--alert-notify needs --alert or --heartbeat"));
+        }
+        self.check_alert_rules(&self.alerts)?;
+        if let (Some(from), Some(to)) = (self.from, self.to) {
+            if from >= to {
+                return Err(anyhow!("--from must be before --to"));
+            }
+        }
+        if !(0.1..=600.0).contains(&self.refresh) {
+            return Err(anyhow!("--refresh must be from 0.1 to 600 seconds"));
+        }
+        #[cfg(feature = "tls")]
+        if self.listen_tls_cert.is_some() && !matches!(self.listen, Some(ListenAddress::Tcp(_))) {
+            return Err(anyhow!("--listen-tls-cert needs --listen tcp://"));
+        }
+        if let Some(address) = self.http {
+            match self.http_auth {
+                // The credentials are read again by the server, a mistake is better found early.
+                Some(ref path) => {
+                    crate::http::Credentials::load(path)?;
+                }
+                None if !address.ip().is_loopback() => {
+                    return Err(anyhow!("--http on a non-loopback address, {}, needs --http-auth", address));
+                }
+                None => (),
+            }
+        }
+        // The certificates are read by the connections when they are made, a mistake is better found early.
+        #[cfg(feature = "tls")]
+        if self.tls_ca.is_some() || self.tls_cert.is_some() {
+            crate::tls::ClientTls::from_config(self).client_config()?;
+        }
+        #[cfg(feature = "sql")]
+        crate::query::check(&self.queries)?;
+        // Checks of a simulation must see every line before them counted.
+        #[cfg(feature = "simulation")]
+        if self.simulate.is_some() && self.stats_shards > 1 {
+            return Err(anyhow!("--simulate can't be used with --stats-shards"));
+        }
+        Ok(())
+    }
+
+    /// Check the alert rules against the options they depend on.
+    fn check_alert_rules(&self, alerts: &[AlertSpec]) -> Result<()> {
+        for target in self.alert_to.iter() {
+            let known = match target.rule.as_str() {
+                "*" => true,
+                "heartbeat" => !self.heartbeats.is_empty(),
+                "self" => self.self_monitor,
+                "slo" => self.slo.is_some(),
+                name => alerts.iter().any(|rule| rule.name == name),
+            };
+            if !known {
+                let rule = &target.rule;
+                return Err(anyhow!(
+                    "--alert-to names no --alert rule, --heartbeat, --self-monitor or --slo: '{}'",
+                    rule
+                ));
+            }
+        }
+        if self.slo.is_none() {
+            if let Some(condition) = alerts.iter().flat_map(|rule| rule.all_conditions()).find(|condition| {
+                matches!(condition.metric, AlertMetric::BurnRate(_) | AlertMetric::ErrorBudget)
+            }) {
+                return Err(anyhow!("The alert metric {} needs --slo", condition.metric.name()));
+            }
+        }
+        Ok(())
+    }
+
+    /// Renderers to use, the terminal one if none is requested.
+    pub(crate) fn renderers(&self) -> Vec<RendererSpec> {
+        if self.renderers.is_empty() {
+            vec![RendererSpec {
+                kind: if self.minimal { RendererKind::Minimal } else { RendererKind::Terminal },
+                path: None,
+            }]
+        }
+        else {
+            self.renderers.clone()
+        }
+    }
+
+    /// Time the windows are computed against.
+    pub(crate) fn window_clock(&self) -> Clock {
+        #[cfg(feature = "simulation")]
+        if self.simulate.is_some() {
+            return Clock::Log;
+        }
+        self.clock.into()
+    }
+
+    /// Filters of the comparison panes, if any.
+    pub(crate) fn compare_filters(&self) -> Vec<Filter> {
+        match self.compare.as_slice() {
+            [filter] => vec![filter.clone(), filter.negate()],
+            filters => filters.to_vec(),
+        }
+    }
+
+    /// Find the rate limit applicable to the named source.
+    pub(crate) fn rate_limit_for(&self, source: &str) -> Option<&RateLimitSpec> {
+        self.rate_limits
+            .iter()
+            .find(|rl| rl.source.as_deref() == Some(source))
+            .or_else(|| self.rate_limits.iter().find(|rl| rl.source.is_none()))
+    }
+}
+
+#[cfg(feature = "protobuf")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub(crate) enum InputFormat {
+    Text,
+    Protobuf,
+}
+
+#[cfg(feature = "compress")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub(crate) enum Compression {
+    Gzip,
+    Zstd,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum RendererKind {
+    Terminal,
+    Minimal,
+    Plain,
+    Json,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub(crate) enum TimeFormat {
+    /// Local time
+    Local,
+    Utc,
+    /// ISO 8601 with the local offset
+    Iso,
+    /// Time passed since, like `12s ago`
+    Relative,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub(crate) enum WindowClock {
+    Receive,
+    Log,
+}
+
+impl From<WindowClock> for Clock {
+    fn from(clock: WindowClock) -> Self {
+        match clock {
+            WindowClock::Receive => Clock::Receive,
+            WindowClock::Log => Clock::Log,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub(crate) enum NumberStyle {
+    Grouped,
+    Compact,
+    Raw,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub(crate) enum AlertNotify {
+    Bell,
+    Flash,
+}
+
+/// Where the alerts of a rule are sent, besides the report.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum AlertSink {
+    /// SNMPv2c trap to `HOST:PORT`
+    Snmp { address: String, community: String },
+    /// Passive check result written to the external command file of Nagios or Icinga
+    Nagios(PathBuf),
+    /// Event of the PagerDuty Events API v2, with the routing key of the service
+    #[cfg(feature = "paging")]
+    PagerDuty(String),
+    /// Alert of the Opsgenie Alert API, with the API key of the integration
+    #[cfg(feature = "paging")]
+    Opsgenie(String),
+}
+
+#[derive(Debug, Clone)]
+pub(crate) struct AlertTarget {
+    /// Name of the alert rule, `heartbeat` for the heartbeats or `*` for everything
+    pub(crate) rule: String,
+    pub(crate) sink: AlertSink,
+}
+
+impl AlertTarget {
+    pub(crate) fn matches(&self, event: &AlertEvent) -> bool {
+        self.rule == "*"
+            || self.rule == event.rule
+            || (["self", "slo"].contains(&self.rule.as_str()) && event.rule.starts_with(&format!("{}-", self.rule)))
+    }
+}
+
+impl FromStr for AlertTarget {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let (rule, target) = s.split_once('=').ok_or_else(|| anyhow!("Expected RULE=TARGET, got '{}'", s))?;
+        let sink = if let Some(address) = target.strip_prefix("snmp://") {
+            let (community, address) = match address.rsplit_once('@') {
+                Some((community, address)) => (community.to_string(), address),
+                None => ("public".to_string(), address),
+            };
+            if address.is_empty() {
+                return Err(anyhow!("No host in '{}'", target));
+            }
+            // IPv6 addresses come in brackets, like `[::1]:162`.
+            let has_port = address.rsplit_once(':').is_some_and(|(_, port)| !port.ends_with(']'));
+            let address = if has_port { address.to_string() } else { format!("{}:162", address) };
+            AlertSink::Snmp { address, community }
+        }
+        else if let Some(path) = target.strip_prefix("nagios:") {
+            if path.is_empty() {
+                return Err(anyhow!("No command file path in '{}'", target));
+            }
+            AlertSink::Nagios(PathBuf::from(path))
+        }
+        else {
+            #[cfg(feature = "paging")]
+            if let Some(routing_key) = target.strip_prefix("pagerduty:") {
+                return Ok(Self {
+                    rule: rule.trim().to_string(),
+                    sink: AlertSink::PagerDuty(secret(routing_key)?),
+                });
+            }
+            else if let Some(api_key) = target.strip_prefix("opsgenie:") {
+                return Ok(Self {
+                    rule: rule.trim().to_string(),
+                    sink: AlertSink::Opsgenie(secret(api_key)?),
+                });
+            }
+            return Err(anyhow!("Expected snmp://[COMMUNITY@]HOST[:PORT] or nagios:PATH, got '{}'", target));
+        };
+        Ok(Self {
+            rule: rule.trim().to_string(),
+            sink,
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum AlertMetric {
+    Rate,
+    CurrentRate,
+    PeakRate,
+    ErrorRate,
+    Errors,
+    Malformed,
+    AvgLag,
+    DistinctIps,
+    SequenceLoss,
+    Burstiness,
+    Backlog,
+    DropRate,
+    MalformedRatio,
+    Attacks,
+    /// Of the `--slo` window by its name, like `1h`
+    BurnRate(&'static str),
+    ErrorBudget,
+}
+
+impl AlertMetric {
+    pub(crate) fn name(&self) -> &'static str {
+        match self {
+            Self::Rate => "rate",
+            Self::CurrentRate => "current_rate",
+            Self::PeakRate => "peak_rate",
+            Self::ErrorRate => "error_rate",
+            Self::Errors => "errors",
+            Self::Malformed => "malformed",
+            Self::AvgLag => "avg_lag",
+            Self::DistinctIps => "distinct_ips",
+            Self::SequenceLoss => "sequence_loss",
+            Self::Burstiness => "burstiness",
+            Self::Backlog => "backlog",
+            Self::DropRate => "drop_rate",
+            Self::MalformedRatio => "malformed_ratio",
+            Self::Attacks => "attacks",
+            Self::BurnRate(window) => BURN_WINDOWS
+                .iter()
+                .find(|(name, ..)| name == window)
+                .map_or("burn_rate", |(_, metric, _)| metric),
+            Self::ErrorBudget => "error_budget",
+        }
+    }
+}
+
+impl FromStr for AlertMetric {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        Ok(match s {
+            "rate" => Self::Rate,
+            "current_rate" => Self::CurrentRate,
+            "peak_rate" => Self::PeakRate,
+            "error_rate" => Self::ErrorRate,
+            "errors" => Self::Errors,
+            "malformed" => Self::Malformed,
+            "avg_lag" => Self::AvgLag,
+            "distinct_ips" => Self::DistinctIps,
+            "sequence_loss" => Self::SequenceLoss,
+            "burstiness" => Self::Burstiness,
+            "backlog" => Self::Backlog,
+            "drop_rate" => Self::DropRate,
+            "malformed_ratio" => Self::MalformedRatio,
+            "attacks" => Self::Attacks,
+            "error_budget" => Self::ErrorBudget,
+            _ => match BURN_WINDOWS.iter().find(|(_, metric, _)| *metric == s) {
+                Some((window, ..)) => Self::BurnRate(window),
+                None => return Err(anyhow!("Unknown alert metric '{}'", s)),
+            },
+        })
+    }
+}
+
+/// A threshold on a metric, like `error_rate>0.05`.
+#[derive(Debug, Clone)]
+pub(crate) struct AlertCondition {
+    pub(crate) metric:    AlertMetric,
+    /// Holds above the threshold if set, below it otherwise
+    pub(crate) above:     bool,
+    pub(crate) threshold: f64,
+}
+
+impl AlertCondition {
+    pub(crate) fn value(&self, view: &SnapshotView) -> f64 {
+        metric_value(self.metric, view)
+    }
+
+    fn holds(&self, view: &SnapshotView) -> bool {
+        let value = self.value(view);
+        if self.above {
+            value > self.threshold
+        }
+        else {
+            value < self.threshold
+        }
+    }
+
+    pub(crate) fn operator(&self) -> char {
+        if self.above {
+            '>'
+        }
+        else {
+            '<'
+        }
+    }
+}
+
+impl FromStr for AlertCondition {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let (metric, threshold, above) = match (s.split_once('>'), s.split_once('<')) {
+            (Some((metric, threshold)), None) => (metric, threshold, true),
+            (None, Some((metric, threshold))) => (metric, threshold, false),
+            _ => return Err(anyhow!("Expected METRIC>VALUE or METRIC<VALUE in '{}'", s)),
+        };
+        Ok(Self {
+            metric: metric.trim().parse()?,
+            above,
+            threshold: parse_threshold(threshold.trim())?,
+        })
+    }
+}
+
+// A number, a percentage like `2%` or a rate like `500/s`.
+fn parse_threshold(s: &str) -> Result<f64> {
+    let bad = |e| anyhow!("Bad threshold '{}': {}", s, e);
+    match s.strip_suffix('%') {
+        Some(percent) => Ok(percent.trim().parse::<f64>().map_err(bad)? / 100.0),
+        None => s.strip_suffix("/s").unwrap_or(s).trim().parse().map_err(bad),
+    }
+}
+
+#[derive(Debug, Clone)]
+pub(crate) struct AlertSpec {
+    pub(crate) name:       String,
+    /// Alternatives joined by `OR`, any of which fires the rule, each of conditions joined by `AND`
+    pub(crate) conditions: Vec<Vec<AlertCondition>>,
+    /// A firing rule of a single condition resolves once the value is back past it, the threshold by default
+    pub(crate) clear:      Option<f64>,
+    /// How long the conditions must hold before the rule fires
+    pub(crate) hold:       chrono::Duration,
+    /// How long a resolved rule can't fire again
+    pub(crate) cooldown:   chrono::Duration,
+}
+
+impl AlertSpec {
+    /// The first condition, whose value stands for the rule.
+    pub(crate) fn primary(&self) -> &AlertCondition {
+        &self.conditions[0][0]
+    }
+
+    pub(crate) fn all_conditions(&self) -> impl Iterator<Item = &AlertCondition> {
+        self.conditions.iter().flatten()
+    }
+
+    pub(crate) fn is_breached(&self, view: &SnapshotView) -> bool {
+        self.conditions
+            .iter()
+            .any(|conditions| conditions.iter().all(|condition| condition.holds(view)))
+    }
+
+    pub(crate) fn is_cleared(&self, view: &SnapshotView) -> bool {
+        match self.clear {
+            Some(clear) => {
+                let value = self.primary().value(view);
+                if self.primary().above {
+                    value <= clear
+                }
+                else {
+                    value >= clear
+                }
+            }
+            None => !self.is_breached(view),
+        }
+    }
+
+    /// Like `error_rate > 0.02 AND rate > 500`.
+    pub(crate) fn condition(&self) -> String {
+        self.conditions
+            .iter()
+            .map(|conditions| {
+                conditions
+                    .iter()
+                    .map(|condition| {
+                        format!("{} {} {}", condition.metric.name(), condition.operator(), condition.threshold)
+                    })
+                    .collect::<Vec<_>>()
+                    .join(" AND ")
+            })
+            .collect::<Vec<_>>()
+            .join(" OR ")
+    }
+}
+
+impl FromStr for AlertSpec {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let (name, rule) = match s.split_once('=') {
+            Some((name, rule)) => (Some(name.trim().to_string()), rule),
+            None => (None, s),
+        };
+
+        // The condition may be followed by `for DURATION`, `clear VALUE` and `cooldown DURATION`.
+        let words = rule.split_whitespace().collect::<Vec<_>>();
+        let modifiers = words
+            .iter()
+            .position(|word| ["for", "clear", "cooldown"].contains(word))
+            .unwrap_or(words.len());
+        let expression = words[..modifiers].join(" ");
+
+        // `AND` binds tighter than `OR`.
+        let mut conditions = vec![Vec::new()];
+        let mut condition = Vec::new();
+        for word in words[..modifiers].iter().chain(std::iter::once(&"OR")) {
+            match word.to_ascii_uppercase().as_str() {
+                junction @ ("AND" | "OR") => {
+                    if condition.is_empty() {
+                        return Err(anyhow!("Missing a condition in '{}'", s));
+                    }
+                    conditions.last_mut().unwrap().push(condition.join(" ").parse::<AlertCondition>()?);
+                    condition.clear();
+                    if junction == "OR" {
+                        conditions.push(Vec::new());
+                    }
+                }
+                _ => condition.push(*word),
+            }
+        }
+        conditions.pop();
+
+        let mut spec = Self {
+            name: name.unwrap_or(expression),
+            conditions,
+            clear: None,
+            hold: chrono::Duration::zero(),
+            cooldown: chrono::Duration::zero(),
+        };
+        for modifier in words[modifiers..].chunks(2) {
+            match modifier {
+                ["for", duration] => spec.hold = parse_duration(duration)?,
+                ["cooldown", duration] => spec.cooldown = parse_duration(duration)?,
+                ["clear", value] => spec.clear = Some(parse_threshold(value)?),
+                _ => return Err(anyhow!("Expected for DURATION, clear VALUE or cooldown DURATION in '{}'", s)),
+            }
+        }
+        if let Some(clear) = spec.clear {
+            if spec.all_conditions().count() > 1 {
+                return Err(anyhow!("A clear threshold needs a single condition in '{}'", s));
+            }
+            // A rule resolving before it stops breaching would fire again right away.
+            let primary = spec.primary();
+            if (primary.above && clear > primary.threshold) || (!primary.above && clear < primary.threshold) {
+                return Err(anyhow!("The clear threshold of '{}' must be on the resolved side of the threshold", s));
+            }
+        }
+        Ok(spec)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub(crate) struct RendererSpec {
+    pub(crate) kind: RendererKind,
+    /// File to write into instead of standard output
+    pub(crate) path: Option<PathBuf>,
+}
+
+impl FromStr for RendererSpec {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let (name, path) = match s.split_once('=') {
+            Some((name, path)) => (name, Some(PathBuf::from(path))),
+            None => (s, None),
+        };
+        let kind = match name {
+            "terminal" => RendererKind::Terminal,
+            "minimal" => RendererKind::Minimal,
+            "plain" => RendererKind::Plain,
+            "json" => RendererKind::Json,
+            _ => return Err(anyhow!("Unknown renderer '{}', expected terminal, minimal, plain or json", name)),
+        };
+        if matches!(kind, RendererKind::Terminal | RendererKind::Minimal) && path.is_some() {
+            return Err(anyhow!("The {} renderer can't write into a file", name));
+        }
+
+        Ok(Self { kind, path })
+    }
+}
+
+/// Promotes records of a level with matching messages to ERROR while they are seen more often than the limit.
+#[derive(Debug, Clone)]
+pub(crate) struct EscalationSpec {
+    pub(crate) expr:      String,
+    pub(crate) level:     Level,
+    pub(crate) message:   Regex,
+    /// Occurrences allowed within the period
+    pub(crate) count:     usize,
+    pub(crate) period_ms: i64,
+}
+
+impl FromStr for EscalationSpec {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let bad = || anyhow!("Expected LEVEL~REGEX>COUNT/PERIOD with a period of s, min or h, got '{}'", s);
+        let (rule, limit) = s.rsplit_once('>').ok_or_else(bad)?;
+        let (level, message) = rule.split_once('~').ok_or_else(bad)?;
+        let (count, period) = limit.split_once('/').ok_or_else(bad)?;
+
+        let level = parse_level(level)?;
+        let message = Regex::new(message).map_err(|e| anyhow!("Bad regex '{}': {}", message, e))?;
+        let count = count.trim().parse().map_err(|e| anyhow!("Bad count '{}': {}", count, e))?;
+        let period_ms = match period.trim() {
+            "s" | "sec" => 1_000,
+            "m" | "min" => 60_000,
+            "h" | "hour" => 3_600_000,
+            _ => return Err(bad()),
+        };
+
+        Ok(Self {
+            expr: s.to_string(),
+            level,
+            message,
+            count,
+            period_ms,
+        })
+    }
+}
+
+/// A message a source is expected to log regularly.
+#[derive(Debug, Clone)]
+pub(crate) struct HeartbeatSpec {
+    pub(crate) expr:        String,
+    /// Every source if not set
+    pub(crate) source:      Option<String>,
+    pub(crate) message:     Regex,
+    pub(crate) interval_ms: i64,
+}
+
+impl FromStr for HeartbeatSpec {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let (rule, interval) =
+            s.rsplit_once('/').ok_or_else(|| anyhow!("Expected [SOURCE=]REGEX/INTERVAL, got '{}'", s))?;
+        let (source, message) = match rule.split_once('=') {
+            Some(("*", message)) => (None, message),
+            Some((source, message)) => (Some(source.trim().to_string()), message),
+            None => (None, rule),
+        };
+        let message = Regex::new(message).map_err(|e| anyhow!("Bad regex '{}': {}", message, e))?;
+        let interval_ms = parse_duration(interval.trim())?.num_milliseconds();
+        if interval_ms <= 0 {
+            return Err(anyhow!("The interval must be positive in '{}'", s));
+        }
+
+        Ok(Self {
+            expr: s.to_string(),
+            source,
+            message,
+            interval_ms,
+        })
+    }
+}
+
+/// A service level objective of the share of entries which are not errors.
+#[derive(Debug, Clone)]
+pub(crate) struct SloSpec {
+    /// Like 0.999
+    pub(crate) objective:   f64,
+    pub(crate) period_secs: i64,
+}
+
+impl FromStr for SloSpec {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let (objective, period) = s.split_once('/').unwrap_or((s, "30d"));
+        let objective = parse_threshold(objective.trim())?;
+        if objective <= 0.0 || objective >= 1.0 {
+            return Err(anyhow!("The objective must be above 0 and below 100% in '{}'", s));
+        }
+        let period_secs = parse_duration(period.trim())?.num_seconds();
+        if period_secs < 3600 {
+            return Err(anyhow!("The period must be at least an hour in '{}'", s));
+        }
+        Ok(Self {
+            objective,
+            period_secs,
+        })
+    }
+}
+
+#[derive(Debug, Clone)]
+pub(crate) struct RateLimitSpec {
+    pub(crate) source: Option<String>,
+    pub(crate) rate:   f64,
+    pub(crate) burst:  f64,
+}
+
+impl FromStr for RateLimitSpec {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let (source, limit) = match s.split_once('=') {
+            Some((source, limit)) => (Some(source.trim().to_string()), limit),
+            None => (None, s),
+        };
+
+        let (rate, burst) = match limit.split_once(':') {
+            Some((rate, burst)) => (rate, Some(burst)),
+            None => (limit, None),
+        };
+
+        let rate: f64 = rate.trim().parse().map_err(|e| anyhow!("Bad rate '{}': {}", rate, e))?;
+        let burst: f64 = match burst {
+            Some(burst) => burst.trim().parse().map_err(|e| anyhow!("Bad burst '{}': {}", burst, e))?,
+            // By default allow a full second worth of lines to arrive at once.
+            None => rate,
+        };
+
+        if rate <= 0.0 || burst < 1.0 {
+            return Err(anyhow!("Rate must be positive and burst must be at least 1 in '{}'", s));
+        }
+
+        Ok(Self { source, rate, burst })
+    }
+}
+
+/// Parse a level name in any case.
+// A key given as `$NAME` is taken from the environment variable, to keep it out of the command line.
+#[cfg(feature = "paging")]
+fn secret(s: &str) -> Result<String> {
+    match s.strip_prefix('$') {
+        Some(name) => std::env::var(name).map_err(|_| anyhow!("Environment variable {} is not set", name)),
+        None if s.is_empty() => Err(anyhow!("No key given")),
+        None => Ok(s.to_string()),
+    }
+}
+
+fn parse_facility(s: &str) -> Result<u8> {
+    facility_by_name(s).ok_or_else(|| anyhow!("Unknown syslog facility '{}'", s))
+}
+
+fn parse_level_mapping(s: &str) -> Result<(String, Level)> {
+    match s.split_once('=') {
+        Some((name, level)) if !name.trim().is_empty() => Ok((name.trim().to_string(), parse_level(level)?)),
+        _ => Err(anyhow!("Expected NAME=LEVEL, got '{}'", s)),
+    }
+}
+
+fn parse_level(s: &str) -> Result<Level> {
+    s.trim()
+        .to_uppercase()
+        .parse()
+        .map_err(|_| anyhow!("Unknown level '{}', expected INFO, ERROR or DEBUG", s))
+}
+
+/// Parse an RFC 3339 timestamp into milliseconds since the epoch.
+fn parse_interval(s: &str) -> Result<Duration> {
+    parse_duration(s)?
+        .to_std()
+        .ok()
+        .filter(|interval| !interval.is_zero())
+        .ok_or_else(|| anyhow!("Expected a positive duration, got '{}'", s))
+}
+
+fn parse_time(s: &str) -> Result<i64> {
+    let dt = chrono::DateTime::parse_from_rfc3339(s).map_err(|e| anyhow!("Expected RFC 3339 timestamp, {}", e))?;
+    Ok(dt.timestamp_millis())
+}
+
+/// Parse either an RFC 3339 timestamp or a relative `<N>[smhd]` time ago into milliseconds since the epoch.
+#[cfg(feature = "cloudwatch")]
+fn parse_start_time(s: &str) -> Result<i64> {
+    if let Ok(dt) = chrono::DateTime::parse_from_rfc3339(s) {
+        return Ok(dt.timestamp_millis());
+    }
+
+    let ago = parse_duration(s).map_err(|e| anyhow!("Expected RFC 3339 timestamp or how long ago: {}", e))?;
+    chrono::Utc::now()
+        .timestamp_millis()
+        .checked_sub(ago.num_milliseconds())
+        .ok_or_else(|| anyhow!("Time '{}' is too far back", s))
+}
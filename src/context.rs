@@ -0,0 +1,72 @@
+use std::collections::{HashMap, VecDeque};
+
+use chrono::{DateTime, Local};
+use qnode_logproc_core::Context;
+use regex::Regex;
+
+// Number of the latest captures kept.
+const MAX_CAPTURES: usize = 10;
+
+/// Keeps the lines around occurrences of selected messages, per source: the ones preceding an occurrence and the ones
+/// following it, of any level.
+#[derive(Debug)]
+pub(crate) struct ContextCapture {
+    patterns: Vec<Regex>,
+    /// Number of lines kept before and after an occurrence
+    lines:    usize,
+    /// The latest lines of every source
+    recent:   HashMap<String, VecDeque<String>>,
+    /// The latest captures, the newest last; those still waiting for the following lines included
+    captures: VecDeque<Context>,
+}
+
+impl ContextCapture {
+    pub(crate) fn new(patterns: Vec<Regex>, lines: usize) -> Self {
+        Self {
+            patterns,
+            lines,
+            recent: HashMap::new(),
+            captures: VecDeque::new(),
+        }
+    }
+
+    /// Whether any messages are selected; lines don't need to be pushed otherwise.
+    pub(crate) fn is_enabled(&self) -> bool {
+        !self.patterns.is_empty()
+    }
+
+    /// Take the next line of the source. `message` is the message of a well-formed record, checked against the
+    /// patterns.
+    pub(crate) fn push(&mut self, source: &str, line: String, message: Option<&str>, received: DateTime<Local>) {
+        for capture in self.captures.iter_mut() {
+            if capture.source == source && capture.after.len() < self.lines {
+                capture.after.push(line.clone());
+            }
+        }
+
+        let recent = self.recent.entry(source.to_string()).or_default();
+        if message.is_some_and(|message| self.patterns.iter().any(|re| re.is_match(message))) {
+            if self.captures.len() == MAX_CAPTURES {
+                self.captures.pop_front();
+            }
+            self.captures.push_back(Context {
+                source:    source.to_string(),
+                timestamp: received,
+                line:      line.clone(),
+                before:    recent.iter().cloned().collect(),
+                after:     Vec::new(),
+            });
+        }
+
+        if self.lines > 0 {
+            if recent.len() == self.lines {
+                recent.pop_front();
+            }
+            recent.push_back(line);
+        }
+    }
+
+    pub(crate) fn captures(&self) -> Vec<Context> {
+        self.captures.iter().cloned().collect()
+    }
+}
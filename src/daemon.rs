@@ -0,0 +1,62 @@
+use std::{
+    ffi::CString,
+    fs,
+    os::unix::ffi::OsStrExt,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{anyhow, Result};
+
+use crate::config::Config;
+
+/// Detach from the controlling terminal using the classic double-fork technique. Must be called before the async
+/// runtime is started since `fork` only preserves the calling thread.
+///
+/// Standard input is left intact so that `producer | qnode-logproc --daemon` keeps working. Standard output and error
+/// are redirected to `--log-file` or to `/dev/null`. The working directory is not changed so that relative paths in
+/// the command line remain valid.
+pub(crate) fn daemonize(config: &Config) -> Result<()> {
+    fork_and_exit_parent()?;
+
+    if unsafe { libc::setsid() } < 0 {
+        return Err(anyhow!("setsid failed: {}", std::io::Error::last_os_error()));
+    }
+
+    // The second fork guarantees we will never re-acquire a controlling terminal.
+    fork_and_exit_parent()?;
+
+    let log_path = config.log_file.clone().unwrap_or_else(|| PathBuf::from("/dev/null"));
+    let log_path = CString::new(log_path.as_os_str().as_bytes())?;
+    let fd = unsafe { libc::open(log_path.as_ptr(), libc::O_WRONLY | libc::O_CREAT | libc::O_APPEND, 0o640) };
+    if fd < 0 {
+        return Err(anyhow!("Can't open log file: {}", std::io::Error::last_os_error()));
+    }
+    unsafe {
+        libc::dup2(fd, libc::STDOUT_FILENO);
+        libc::dup2(fd, libc::STDERR_FILENO);
+        if fd > libc::STDERR_FILENO {
+            libc::close(fd);
+        }
+    }
+
+    Ok(())
+}
+
+fn fork_and_exit_parent() -> Result<()> {
+    match unsafe { libc::fork() } {
+        -1 => Err(anyhow!("fork failed: {}", std::io::Error::last_os_error())),
+        0 => Ok(()),
+        _ => unsafe { libc::_exit(0) },
+    }
+}
+
+pub(crate) fn write_pid_file(path: &Path) -> Result<()> {
+    fs::write(path, format!("{}\n", std::process::id()))
+        .map_err(|e| anyhow!("Can't write PID file {}: {}", path.display(), e))
+}
+
+pub(crate) fn remove_pid_file(path: &Path) {
+    if let Err(err) = fs::remove_file(path) {
+        eprintln!("Can't remove PID file {}: {}", path.display(), err);
+    }
+}
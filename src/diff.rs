@@ -0,0 +1,228 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    fs::File,
+    io::{BufRead, BufReader},
+    path::Path,
+};
+
+use anyhow::{anyhow, Result};
+use qnode_logproc_core::{try_parse_line_mapped, ErrorType, Level};
+
+use crate::{
+    config::{Config, DiffArgs},
+    format::NumberFormat,
+    redact::{FieldHasher, Redactor},
+    render::rejected_label,
+};
+
+/// Messages listed per section of the comparison.
+const TOP_MESSAGES: usize = 10;
+
+// Occurrences of a message in a file.
+#[derive(Debug, Default, Clone, Copy)]
+struct MessageCounts {
+    count:  u64,
+    errors: u64,
+}
+
+// Totals of a log file read from start to end.
+#[derive(Debug, Default)]
+struct Profile {
+    lines:    u64,
+    rejected: BTreeMap<&'static str, u64>,
+    errors:   u64,
+    infos:    u64,
+    debugs:   u64,
+    /// Log times of the oldest and the newest records, in milliseconds
+    span:     Option<(i64, i64)>,
+    messages: HashMap<String, MessageCounts>,
+}
+
+impl Profile {
+    fn read(path: &Path, config: &Config, redactor: &Redactor, hasher: &FieldHasher) -> Result<Self> {
+        let file = File::open(path).map_err(|e| anyhow!("Can't read {:?}: {}", path, e))?;
+        let mut profile = Self::default();
+        let levels = config.level_map();
+        for line in BufReader::new(file).split(b'\n') {
+            let line = line.map_err(|e| anyhow!("Can't read {:?}: {}", path, e))?;
+            let line = String::from_utf8_lossy(line.strip_suffix(b"\r").unwrap_or(&line)).into_owned();
+            profile.lines += 1;
+
+            let parsed = if line.len() > config.max_line_length {
+                Err(ErrorType::Oversized)
+            }
+            else {
+                try_parse_line_mapped(&line, &levels)
+            };
+            let record = match parsed {
+                Ok(record) => record,
+                Err(err_type) => {
+                    *profile.rejected.entry(err_type.name()).or_default() += 1;
+                    continue;
+                }
+            };
+
+            match record.level {
+                Level::ERROR => profile.errors += 1,
+                Level::INFO => profile.infos += 1,
+                Level::DEBUG => profile.debugs += 1,
+            }
+            profile.span = Some(match profile.span {
+                Some((first, last)) => (first.min(record.logged_millis), last.max(record.logged_millis)),
+                None => (record.logged_millis, record.logged_millis),
+            });
+            let message = hasher.hash_text(&redactor.redact(&record.message)).into_owned();
+            let counts = profile.messages.entry(message).or_default();
+            counts.count += 1;
+            if record.level == Level::ERROR {
+                counts.errors += 1;
+            }
+        }
+        Ok(profile)
+    }
+
+    fn records(&self) -> u64 {
+        self.errors + self.infos + self.debugs
+    }
+
+    /// Records per second of log time.
+    fn rate(&self) -> f64 {
+        match self.span {
+            Some((first, last)) if last > first => self.records() as f64 / ((last - first) as f64 / 1000.0),
+            _ => 0.0,
+        }
+    }
+
+    fn share(&self, count: u64) -> f64 {
+        count as f64 / self.records().max(1) as f64 * 100.0
+    }
+}
+
+/// Run the `diff` command: read both files to the end and print how the second one differs from the first in rates,
+/// levels and messages.
+pub(crate) fn run(config: &Config, args: &DiffArgs) -> Result<usize> {
+    let redactor = Redactor::new(&config.redact, &config.redact_pattern)?;
+    // Without `--hash-salt` both files are hashed alike, which is all the comparison needs.
+    let hasher = FieldHasher::new(
+        config.hash_salt.clone().unwrap_or_default(),
+        &config.hash_field,
+        &config.hash_pattern,
+    );
+    let old = Profile::read(&args.old, config, &redactor, &hasher)?;
+    let new = Profile::read(&args.new, config, &redactor, &hasher)?;
+    let num = NumberFormat::new(config.locale.as_deref(), config.number_style);
+
+    println!("{} -> {}", args.old.display(), args.new.display());
+    println!("{:<14}{:>16}{:>16}{:>12}", "", "old", "new", "change");
+    let row = |label: &str, old: String, new: String, change: String| {
+        println!("{:<14}{:>16}{:>16}{:>12}", label, old, new, change);
+    };
+    let counts = |label: &str, old_count: u64, new_count: u64| {
+        row(label, num.int(old_count), num.int(new_count), change(&num, old_count as f64, new_count as f64));
+    };
+    counts("Lines", old.lines, new.lines);
+    counts("Records", old.records(), new.records());
+    row(
+        "Rate/sec",
+        num.float(old.rate(), 2),
+        num.float(new.rate(), 2),
+        change(&num, old.rate(), new.rate()),
+    );
+    for (label, old_count, new_count) in [
+        ("Errors", old.errors, new.errors),
+        ("Infos", old.infos, new.infos),
+        ("Debugs", old.debugs, new.debugs),
+    ] {
+        row(
+            label,
+            format!("{}%", num.float(old.share(old_count), 2)),
+            format!("{}%", num.float(new.share(new_count), 2)),
+            format!("{}pp", signed(&num, new.share(new_count) - old.share(old_count))),
+        );
+    }
+    for err_type in ErrorType::ALL {
+        let name = err_type.name();
+        let (old_count, new_count) = (old.rejected.get(name).copied(), new.rejected.get(name).copied());
+        if old_count.is_some() || new_count.is_some() {
+            counts(rejected_label(err_type), old_count.unwrap_or(0), new_count.unwrap_or(0));
+        }
+    }
+
+    let mut appeared = new
+        .messages
+        .iter()
+        .filter(|(message, _)| !old.messages.contains_key(*message))
+        .collect::<Vec<_>>();
+    appeared.sort_by(|a, b| b.1.count.cmp(&a.1.count).then_with(|| a.0.cmp(b.0)));
+    print_messages("New messages", &appeared, &num);
+
+    let mut disappeared = old
+        .messages
+        .iter()
+        .filter(|(message, _)| !new.messages.contains_key(*message))
+        .collect::<Vec<_>>();
+    disappeared.sort_by(|a, b| b.1.count.cmp(&a.1.count).then_with(|| a.0.cmp(b.0)));
+    print_messages("Disappeared messages", &disappeared, &num);
+
+    // Counts are compared by their shares of the records, so files of different lengths compare.
+    let mut changed = new
+        .messages
+        .iter()
+        .filter_map(|(message, counts)| {
+            let before = old.messages.get(message)?;
+            let delta = new.share(counts.count) - old.share(before.count);
+            (delta.abs() >= 0.01).then_some((message, before.count, counts.count, delta))
+        })
+        .collect::<Vec<_>>();
+    changed.sort_by(|a, b| b.3.abs().total_cmp(&a.3.abs()).then_with(|| a.0.cmp(b.0)));
+    if !changed.is_empty() {
+        println!();
+        println!("Changed messages ({}):", num.int(changed.len()));
+    }
+    for (message, before, after, delta) in changed.into_iter().take(TOP_MESSAGES) {
+        println!(
+            "  {} -> {} ({}pp of the records): {}",
+            num.int(before),
+            num.int(after),
+            signed(&num, delta),
+            message
+        );
+    }
+
+    Ok(0)
+}
+
+fn print_messages(title: &str, messages: &[(&String, &MessageCounts)], num: &NumberFormat) {
+    if messages.is_empty() {
+        return;
+    }
+    println!();
+    println!("{} ({}):", title, num.int(messages.len()));
+    for (message, counts) in messages.iter().take(TOP_MESSAGES) {
+        let errors = if counts.errors > 0 {
+            format!(" ({} errors)", num.int(counts.errors))
+        }
+        else {
+            String::new()
+        };
+        println!("  {}{}: {}", num.int(counts.count), errors, message);
+    }
+}
+
+fn signed(num: &NumberFormat, x: f64) -> String {
+    let sign = if x >= 0.0 { "+" } else { "" };
+    format!("{}{}", sign, num.float(x, 2))
+}
+
+// Relative change, like `+12.50%`.
+fn change(num: &NumberFormat, old: f64, new: f64) -> String {
+    if old > 0.0 {
+        format!("{}%", signed(num, (new - old) / old * 100.0))
+    }
+    else if new > 0.0 {
+        "new".to_string()
+    }
+    else {
+        String::new()
+    }
+}
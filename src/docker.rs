@@ -0,0 +1,150 @@
+use std::path::PathBuf;
+
+use anyhow::{anyhow, Result};
+use tokio::{
+    io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader},
+    net::UnixStream,
+};
+
+/// Minimal Docker Engine API client talking over the daemon's Unix socket. HTTP/1.0 is used on purpose: the daemon
+/// then sends bodies as is, without chunked encoding, and closes the connection at the end.
+pub(crate) struct DockerClient {
+    socket: PathBuf,
+}
+
+pub(crate) struct ContainerInfo {
+    pub(crate) name: String,
+    pub(crate) tty:  bool,
+}
+
+impl DockerClient {
+    pub(crate) fn new<P: Into<PathBuf>>(socket: P) -> Self {
+        Self { socket: socket.into() }
+    }
+
+    pub(crate) async fn inspect(&self, container: &str) -> Result<ContainerInfo> {
+        let mut body = String::new();
+        self.get(&format!("/containers/{}/json", container))
+            .await?
+            .read_to_string(&mut body)
+            .await?;
+        let info: serde_json::Value = serde_json::from_str(&body)?;
+
+        Ok(ContainerInfo {
+            name: info["Name"]
+                .as_str()
+                .map_or(container, |name| name.trim_start_matches('/'))
+                .to_string(),
+            tty:  info["Config"]["Tty"].as_bool().unwrap_or(false),
+        })
+    }
+
+    /// Follow stdout and stderr of the container, starting with new lines only.
+    pub(crate) async fn follow_logs(&self, container: &str, tty: bool) -> Result<DockerLogStream> {
+        let reader = self
+            .get(&format!(
+                "/containers/{}/logs?follow=1&stdout=1&stderr=1&tail=0",
+                container
+            ))
+            .await?;
+        Ok(DockerLogStream {
+            reader,
+            tty,
+            bufs: Default::default(),
+        })
+    }
+
+    // Send a GET request and return the reader positioned at the response body.
+    async fn get(&self, path: &str) -> Result<BufReader<UnixStream>> {
+        let mut stream = UnixStream::connect(&self.socket)
+            .await
+            .map_err(|e| anyhow!("Can't connect to Docker at {}: {}", self.socket.display(), e))?;
+        stream
+            .write_all(format!("GET {} HTTP/1.0\r\nHost: docker\r\n\r\n", path).as_bytes())
+            .await?;
+
+        let mut reader = BufReader::new(stream);
+        let mut status = String::new();
+        reader.read_line(&mut status).await?;
+        if status.split_whitespace().nth(1) != Some("200") {
+            return Err(anyhow!("Docker API request {} failed: {}", path, status.trim_end()));
+        }
+
+        // Skip the headers.
+        let mut header = String::new();
+        loop {
+            header.clear();
+            if reader.read_line(&mut header).await? == 0 || header.trim_end().is_empty() {
+                break;
+            }
+        }
+
+        Ok(reader)
+    }
+}
+
+/// Log lines of a container. Unless the container has a TTY allocated, the output is multiplexed: each frame is
+/// prefixed with an 8-byte header containing the stream type and the payload size.
+pub(crate) struct DockerLogStream {
+    reader: BufReader<UnixStream>,
+    tty:    bool,
+    /// Payload bytes not forming a complete line yet, per stream type: stdin, stdout and stderr. A partial line of
+    /// one stream must not be continued by a frame of another.
+    bufs:   [Vec<u8>; 3],
+}
+
+// Stream type of the frames of a TTY and of the ones with an unknown type.
+const STDOUT: usize = 1;
+
+impl DockerLogStream {
+    /// Returns `None` when the log stream ends, e.g. when the container stops.
+    pub(crate) async fn next_line(&mut self) -> Result<Option<String>> {
+        loop {
+            for buf in self.bufs.iter_mut() {
+                if let Some(pos) = buf.iter().position(|b| *b == b'\n') {
+                    let rest = buf.split_off(pos + 1);
+                    let line = std::mem::replace(buf, rest);
+                    return Ok(Some(Self::to_line(&line)));
+                }
+            }
+
+            let more = if self.tty {
+                self.reader.read_until(b'\n', &mut self.bufs[STDOUT]).await? > 0
+            }
+            else {
+                self.read_frame().await?
+            };
+
+            if !more {
+                // The unterminated lines left are flushed one per call.
+                return Ok(self
+                    .bufs
+                    .iter_mut()
+                    .find(|buf| !buf.is_empty())
+                    .map(|buf| Self::to_line(&std::mem::take(buf))));
+            }
+        }
+    }
+
+    async fn read_frame(&mut self) -> Result<bool> {
+        let mut header = [0u8; 8];
+        match self.reader.read_exact(&mut header).await {
+            Ok(_) => (),
+            Err(err) if err.kind() == std::io::ErrorKind::UnexpectedEof => return Ok(false),
+            Err(err) => return Err(err.into()),
+        }
+
+        let stream = header[0] as usize;
+        let buf = &mut self.bufs[if stream < self.bufs.len() { stream } else { STDOUT }];
+        let size = u32::from_be_bytes([header[4], header[5], header[6], header[7]]) as usize;
+        let start = buf.len();
+        buf.resize(start + size, 0);
+        self.reader.read_exact(&mut buf[start..]).await?;
+
+        Ok(true)
+    }
+
+    fn to_line(bytes: &[u8]) -> String {
+        String::from_utf8_lossy(bytes).trim_end_matches(['\n', '\r']).to_string()
+    }
+}
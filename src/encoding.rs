@@ -0,0 +1,92 @@
+use tokio::io::{self, AsyncBufRead, AsyncBufReadExt};
+
+const UTF8_BOM: &[u8] = &[0xEF, 0xBB, 0xBF];
+const UTF16LE_BOM: &[u8] = &[0xFF, 0xFE];
+const UTF16BE_BOM: &[u8] = &[0xFE, 0xFF];
+
+/// Text encoding of a file input.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub(crate) enum Encoding {
+    #[default]
+    Utf8,
+    Utf16Le,
+    Utf16Be,
+}
+
+impl Encoding {
+    /// Tell the encoding by the first bytes of a file, returning it with the length of the byte order mark to skip.
+    /// Without a mark, ASCII text interleaved with zero bytes is taken for UTF-16, like exports of Windows tools.
+    pub(crate) fn detect(head: &[u8]) -> (Self, usize) {
+        if head.starts_with(UTF8_BOM) {
+            (Self::Utf8, UTF8_BOM.len())
+        }
+        else if head.starts_with(UTF16LE_BOM) {
+            (Self::Utf16Le, UTF16LE_BOM.len())
+        }
+        else if head.starts_with(UTF16BE_BOM) {
+            (Self::Utf16Be, UTF16BE_BOM.len())
+        }
+        else {
+            match head {
+                [a, 0, b, 0, ..] if a.is_ascii() && b.is_ascii() && *a != 0 && *b != 0 => (Self::Utf16Le, 0),
+                [0, a, 0, b, ..] if a.is_ascii() && b.is_ascii() && *a != 0 && *b != 0 => (Self::Utf16Be, 0),
+                _ => (Self::Utf8, 0),
+            }
+        }
+    }
+
+    /// Append the bytes up to and including the next line terminator to `buf`. Returns the number of bytes read and
+    /// whether the line is terminated, which it isn't at the end of the input. `buf` must start at a line boundary.
+    pub(crate) async fn read_line<R: AsyncBufRead + Unpin>(
+        &self,
+        reader: &mut R,
+        buf: &mut Vec<u8>,
+    ) -> io::Result<(usize, bool)> {
+        let mut read = 0;
+        loop {
+            let n = reader.read_until(b'\n', buf).await?;
+            read += n;
+            if n == 0 || buf.last() != Some(&b'\n') {
+                return Ok((read, false));
+            }
+            match self {
+                Self::Utf8 => return Ok((read, true)),
+                // The newline is `0A 00`; a `0A` at an odd offset is the high byte of another character.
+                Self::Utf16Le if !buf.len().is_multiple_of(2) => {
+                    let Some(&byte) = reader.fill_buf().await?.first()
+                    else {
+                        return Ok((read, false));
+                    };
+                    reader.consume(1);
+                    buf.push(byte);
+                    read += 1;
+                    if byte == 0 {
+                        return Ok((read, true));
+                    }
+                }
+                Self::Utf16Be if buf.len().is_multiple_of(2) && buf[buf.len() - 2] == 0 => return Ok((read, true)),
+                _ => (),
+            }
+        }
+    }
+
+    /// Decode a line read by `read_line`, without the line terminator. Invalid sequences are replaced.
+    pub(crate) fn decode(&self, bytes: &[u8]) -> String {
+        let units = |from_bytes: fn([u8; 2]) -> u16| {
+            // A dangling odd byte is broken input and decodes to the replacement character.
+            bytes
+                .chunks(2)
+                .map(|pair| if pair.len() == 2 { from_bytes([pair[0], pair[1]]) } else { 0xFFFD })
+                .collect::<Vec<_>>()
+        };
+        let mut line = match self {
+            Self::Utf8 => String::from_utf8_lossy(bytes).into_owned(),
+            Self::Utf16Le => String::from_utf16_lossy(&units(u16::from_le_bytes)),
+            Self::Utf16Be => String::from_utf16_lossy(&units(u16::from_be_bytes)),
+        };
+        while line.ends_with(['\n', '\r']) {
+            line.pop();
+        }
+        line
+    }
+}
@@ -0,0 +1,68 @@
+use std::{ffi::OsString, sync::Arc};
+
+use anyhow::Result;
+use tokio::sync::watch;
+
+use qnode_logproc_core::SnapshotView;
+
+use crate::{
+    app::App,
+    types::{LineMessage, ParsedLine},
+};
+
+/// Name of the source lines and records pushed through the engine are attributed to.
+pub const ENGINE_SOURCE: &str = "engine";
+
+/// The analyzer embedded in-process. Instead of reading an input, it takes lines and records pushed by the host
+/// service; statistics snapshots are delivered over a watch channel on every refresh. All analyzer tasks are stopped
+/// when the engine is dropped.
+pub struct StatsEngine {
+    app:    Arc<App>,
+    source: Arc<str>,
+}
+
+impl StatsEngine {
+    /// Create and start the engine. `args` are the command line options of the tool, like `["--redact", "email"]`;
+    /// input, output and terminal options have no effect. Must be called within a multi-threaded Tokio runtime.
+    pub fn new<I, T>(args: I) -> Result<Self>
+    where
+        I: IntoIterator<Item = T>,
+        T: Into<OsString> + Clone,
+    {
+        let app = App::new_embedded(args)?;
+        app.start();
+        Ok(Self {
+            app,
+            source: ENGINE_SOURCE.into(),
+        })
+    }
+
+    /// Push a raw log line to be parsed.
+    pub fn push_line<S: Into<String>>(&self, line: S) -> Result<()> {
+        self.app
+            .channel_tx()
+            .send(LineMessage::new(line.into(), chrono::Utc::now().timestamp_millis(), self.source.clone()))?;
+        Ok(())
+    }
+
+    /// Push an already parsed record; it skips the parser except for redaction and hashing.
+    pub fn push_record(&self, record: ParsedLine) -> Result<()> {
+        self.app.channel_tx().send(LineMessage::from_record(
+            record,
+            chrono::Utc::now().timestamp_millis(),
+            self.source.clone(),
+        ))?;
+        Ok(())
+    }
+
+    /// Receive statistics snapshots. The initial value is an empty snapshot.
+    pub fn subscribe(&self) -> Result<watch::Receiver<SnapshotView>> {
+        Ok(self.app.stats()?.subscribe())
+    }
+}
+
+impl Drop for StatsEngine {
+    fn drop(&mut self) {
+        self.app.stop();
+    }
+}
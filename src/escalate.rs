@@ -0,0 +1,74 @@
+use std::collections::{BTreeMap, VecDeque};
+
+use qnode_logproc_core::Clock;
+
+use crate::{config::EscalationSpec, types::*};
+
+/// Applies the `--escalate` rules: tracks how often each one matches and promotes the records while the rule is over
+/// its limit.
+#[derive(Debug)]
+pub(crate) struct Escalations {
+    rules:     Vec<EscalationSpec>,
+    /// Whether the frequency is measured by log time rather than receive time
+    clock:     Clock,
+    /// Times of the recent matches of every rule, in milliseconds
+    matches:   Vec<VecDeque<i64>>,
+    /// Records promoted since the start, per rule
+    escalated: BTreeMap<String, u64>,
+}
+
+impl Escalations {
+    pub(crate) fn new(rules: Vec<EscalationSpec>, clock: Clock) -> Self {
+        Self {
+            matches: vec![VecDeque::new(); rules.len()],
+            rules,
+            clock,
+            escalated: BTreeMap::new(),
+        }
+    }
+
+    /// Replace the rules with the reloaded ones. A rule keeps its recent matches if one of the same expression was
+    /// there before, and the records promoted so far stay counted.
+    pub(crate) fn set_rules(&mut self, rules: Vec<EscalationSpec>) {
+        let mut matches = self
+            .rules
+            .iter()
+            .map(|rule| rule.expr.clone())
+            .zip(std::mem::take(&mut self.matches))
+            .collect::<BTreeMap<_, _>>();
+        self.matches = rules.iter().map(|rule| matches.remove(&rule.expr).unwrap_or_default()).collect();
+        self.rules = rules;
+    }
+
+    pub(crate) fn is_enabled(&self) -> bool {
+        !self.rules.is_empty()
+    }
+
+    /// The level the record received at `received_millis` is to be counted with.
+    pub(crate) fn level_of(&mut self, record: &ParsedLine, received_millis: i64) -> Level {
+        let now = match self.clock {
+            Clock::Receive => received_millis,
+            Clock::Log => record.logged_millis,
+        };
+        let mut level = record.level;
+        for (rule, matches) in self.rules.iter().zip(self.matches.iter_mut()) {
+            if record.level != rule.level || !rule.message.is_match(&record.message) {
+                continue;
+            }
+
+            matches.push_back(now);
+            while matches.front().is_some_and(|time| now - time >= rule.period_ms) {
+                matches.pop_front();
+            }
+            if matches.len() > rule.count && level != Level::ERROR {
+                level = Level::ERROR;
+                *self.escalated.entry(rule.expr.clone()).or_insert(0) += 1;
+            }
+        }
+        level
+    }
+
+    pub(crate) fn escalated(&self) -> &BTreeMap<String, u64> {
+        &self.escalated
+    }
+}
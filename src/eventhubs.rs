@@ -0,0 +1,145 @@
+use std::sync::Arc;
+
+use anyhow::{anyhow, Result};
+use rdkafka::{
+    config::RDKafkaLogLevel,
+    consumer::{CommitMode, Consumer, StreamConsumer},
+    error::{KafkaError, RDKafkaErrorCode},
+    ClientConfig, Message,
+};
+
+use crate::{failures::Failures, tls::ClientTls};
+
+// Event Hubs namespaces serve the Kafka protocol on this port.
+const KAFKA_PORT: u16 = 9093;
+
+pub(crate) struct EventHubMessage {
+    pub(crate) payload:       Vec<u8>,
+    /// When the event was enqueued in the hub, in milliseconds
+    pub(crate) enqueued_time: i64,
+    partition:                i32,
+    offset:                   i64,
+}
+
+/// Consumes an event hub through the Kafka endpoint of its namespace, as a member of a consumer group. The namespace
+/// and the credentials are taken from a connection string as shown by the Azure portal:
+/// `Endpoint=sb://<namespace>.servicebus.windows.net/;SharedAccessKeyName=<name>;SharedAccessKey=<key>`.
+pub(crate) struct EventHubInput {
+    consumer: StreamConsumer,
+    hub:      String,
+    failures: Arc<Failures>,
+    subject:  String,
+}
+
+impl EventHubInput {
+    /// A consumer group new to the hub starts with the events enqueued from now on. The connection is made with
+    /// OpenSSL by librdkafka, which is given the certificate files of `tls` as they are.
+    pub(crate) fn new(
+        connection_string: &str,
+        hub: &str,
+        group: &str,
+        tls: &ClientTls,
+        failures: Arc<Failures>,
+    ) -> Result<Self> {
+        let namespace = connection_string
+            .split(';')
+            .find_map(|part| part.trim().strip_prefix("Endpoint="))
+            .and_then(|endpoint| endpoint.strip_prefix("sb://"))
+            .map(|host| host.trim_end_matches('/'))
+            .filter(|host| !host.is_empty())
+            .ok_or_else(|| anyhow!("No Endpoint=sb://<namespace> in the Event Hubs connection string"))?;
+
+        let mut client_config = ClientConfig::new();
+        if let Some(ref ca) = tls.ca {
+            client_config.set("ssl.ca.location", ca.to_string_lossy());
+        }
+        if let (Some(cert), Some(key)) = (&tls.cert, &tls.key) {
+            client_config
+                .set("ssl.certificate.location", cert.to_string_lossy())
+                .set("ssl.key.location", key.to_string_lossy());
+        }
+        let consumer: StreamConsumer = client_config
+            .set("bootstrap.servers", format!("{}:{}", namespace, KAFKA_PORT))
+            .set("security.protocol", "SASL_SSL")
+            .set("sasl.mechanism", "PLAIN")
+            .set("sasl.username", "$ConnectionString")
+            .set("sasl.password", connection_string)
+            .set("group.id", group)
+            .set("auto.offset.reset", "latest")
+            // Offsets are stored once the lines of an event are queued; the stored ones are committed periodically.
+            .set("enable.auto.offset.store", "false")
+            // Connection problems are reported through the failures instead of the log of librdkafka.
+            .set_log_level(RDKafkaLogLevel::Emerg)
+            .create()
+            .map_err(|e| anyhow!("Can't create Event Hubs consumer: {}", e))?;
+        consumer
+            .subscribe(&[hub])
+            .map_err(|e| anyhow!("Can't subscribe to event hub '{}': {}", hub, e))?;
+
+        Ok(Self {
+            consumer,
+            hub: hub.to_string(),
+            failures,
+            subject: format!("Event Hubs input from {}/{}", namespace, hub),
+        })
+    }
+
+    /// Wait for the next event. Errors are recorded but not returned since the consumer reconnects by itself. With
+    /// `idle` given, `None` is returned once no event arrives for that long after the consumer got its partitions;
+    /// joining the group doesn't count.
+    pub(crate) async fn next(&self, idle: Option<tokio::time::Duration>) -> Result<Option<EventHubMessage>> {
+        loop {
+            let message = match idle {
+                Some(idle) => match tokio::time::timeout(idle, self.consumer.recv()).await {
+                    Ok(message) => message,
+                    Err(_) if self.assigned() => return Ok(None),
+                    Err(_) => continue,
+                },
+                None => self.consumer.recv().await,
+            };
+            let message = match message {
+                Ok(message) => message,
+                Err(err) => {
+                    self.failures.fail(&self.subject, err);
+                    continue;
+                }
+            };
+
+            self.failures.recover(&self.subject);
+            return Ok(Some(EventHubMessage {
+                payload:       message.payload().unwrap_or_default().to_vec(),
+                enqueued_time: message
+                    .timestamp()
+                    .to_millis()
+                    .unwrap_or_else(|| chrono::Utc::now().timestamp_millis()),
+                partition:     message.partition(),
+                offset:        message.offset(),
+            }));
+        }
+    }
+
+    /// Mark the event as processed. The group resumes after it once restarted.
+    pub(crate) fn ack(&self, message: &EventHubMessage) -> Result<()> {
+        self.consumer
+            .store_offset(&self.hub, message.partition, message.offset + 1)
+            .map_err(|e| anyhow!("Failed to store Event Hubs offset: {}", e))
+    }
+
+    /// Commit the stored offsets right away instead of waiting for the periodic commit, which never comes when the
+    /// input stops.
+    pub(crate) fn commit(&self) -> Result<()> {
+        match self.consumer.commit_consumer_state(CommitMode::Sync) {
+            // Nothing was stored since the last commit.
+            Ok(()) | Err(KafkaError::ConsumerCommit(RDKafkaErrorCode::NoOffset)) => Ok(()),
+            Err(err) => Err(anyhow!("Failed to commit Event Hubs offsets: {}", err)),
+        }
+    }
+
+    pub(crate) fn source_name(&self) -> String {
+        format!("eventhub/{}", self.hub)
+    }
+
+    fn assigned(&self) -> bool {
+        self.consumer.assignment().is_ok_and(|assignment| assignment.count() > 0)
+    }
+}
@@ -0,0 +1,81 @@
+use std::{os::unix::fs::MetadataExt, path::PathBuf, sync::Arc};
+
+use anyhow::Result;
+use qnode_logproc_core::ParsedLine;
+use serde_json::json;
+
+use crate::{
+    config::Config,
+    rotate::RotatingFile,
+    sink::{Delivery, Sink},
+};
+
+// Records are written in batches of up to this many, the first waiting for the rest at most `WRITE_DELAY`.
+const WRITE_BATCH: usize = 1000;
+const WRITE_DELAY: std::time::Duration = std::time::Duration::from_secs(1);
+// Records waiting to be written; a disk which can't keep up drops the rest.
+const EVENTS_BUFFER: usize = 100_000;
+
+/// Appends every record counted to the file of `--events` as a JSON line, with the level it is counted at. The file is
+/// rotated like the `--jsonl` one and re-opened once moved away, as the sink is out of reach of SIGHUP.
+pub(crate) struct EventsFile {
+    path:  PathBuf,
+    file:  RotatingFile,
+    name:  String,
+    /// Inode of the file written to
+    inode: Option<u64>,
+}
+
+impl EventsFile {
+    pub(crate) fn from_config(config: &Config) -> Option<Self> {
+        let path = config.events.clone()?;
+        Some(Self {
+            name:  format!("Events output to {}", path.display()),
+            file:  RotatingFile::new(path.clone(), config.rotation()),
+            path,
+            inode: None,
+        })
+    }
+}
+
+impl Sink for EventsFile {
+    type Item = Arc<ParsedLine>;
+
+    fn name(&self) -> String {
+        self.name.clone()
+    }
+
+    fn delivery(&self) -> Delivery {
+        Delivery {
+            batch: WRITE_BATCH,
+            max_delay: WRITE_DELAY,
+            buffer: EVENTS_BUFFER,
+            ..Delivery::default()
+        }
+    }
+
+    async fn deliver(&mut self, batch: &[Arc<ParsedLine>]) -> Result<()> {
+        let mut lines = String::new();
+        for record in batch {
+            let mut event = json!({
+                "logged_millis": record.logged_millis,
+                "level":         format!("{:?}", record.level),
+                "ip":            record.ip,
+                "message":       record.message,
+            });
+            if let Some(priority) = record.priority {
+                event["priority"] = priority.into();
+            }
+            lines.push_str(&event.to_string());
+            lines.push('\n');
+        }
+
+        let inode = std::fs::metadata(&self.path).map(|meta| meta.ino()).ok();
+        if inode != self.inode {
+            self.file.reopen();
+        }
+        self.file.append(lines.as_bytes())?;
+        self.inode = std::fs::metadata(&self.path).map(|meta| meta.ino()).ok();
+        Ok(())
+    }
+}
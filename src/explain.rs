@@ -0,0 +1,168 @@
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use qnode_logproc_core::{
+    split_line, try_parse_line_mapped, ErrorType, ParsedLine, StatsWindow, FACILITIES, SEVERITIES,
+};
+
+use crate::{
+    config::Config,
+    redact::{FieldHasher, HashField, Redactor},
+    stats::new_window,
+};
+
+/// Show step by step how the line is parsed and what the statistics make of it, every step on its own line after the
+/// line itself, all of them indented with `pad`. Returns whether the line makes a record.
+pub(crate) fn explain(config: &Config, source: &str, line: &str, pad: &str) -> Result<bool> {
+    println!("{}{}", pad, line);
+    let step = |text: String| println!("{}  {}", pad, text);
+
+    if line.contains(char::REPLACEMENT_CHARACTER) {
+        step("rejected: decode_error, the line is not valid UTF-8".to_string());
+        return Ok(false);
+    }
+    if line.len() > config.max_line_length {
+        step(format!(
+            "rejected: oversized, {} bytes while --max-line-length is {}",
+            line.len(),
+            config.max_line_length
+        ));
+        return Ok(false);
+    }
+    let Some(fields) = split_line(line)
+    else {
+        step(format!("rejected: malformed, {}", format_hint(line)));
+        return Ok(false);
+    };
+    step(format!(
+        "fields: timestamp {:?}, level {:?}, IP {:?}, message {:?}",
+        fields.timestamp, fields.level, fields.ip, fields.message
+    ));
+
+    let levels = config.level_map();
+    if let Some(level) = levels.level(fields.level).filter(|level| format!("{:?}", level) != fields.level) {
+        step(format!("level {:?} counted as {:?} by --level-map", fields.level, level));
+    }
+    let parsed = match try_parse_line_mapped(line, &levels) {
+        Ok(parsed) => parsed,
+        Err(err_type) => {
+            let why = match err_type {
+                ErrorType::BadTimestamp => {
+                    "only UTC timestamps like 2024-01-01T12:00:00Z or 2024-01-01T12:00:00.250Z are accepted"
+                }
+                ErrorType::UnknownLevel => "the level is not one of INFO, ERROR or DEBUG, nor mapped by --level-map",
+                // The fields are there, only the priority can be wrong.
+                ErrorType::Malformed => "the syslog priority is above 191",
+                _ => "the line doesn't make a record",
+            };
+            step(format!("rejected: {}, {}", err_type.name(), why));
+            return Ok(false);
+        }
+    };
+    let logged = DateTime::from_timestamp_millis(parsed.logged_millis).unwrap_or_default();
+    step(format!("logged at {}, level {:?}", logged.to_rfc3339(), parsed.level));
+    if let (Some(facility), Some(severity)) = (parsed.facility(), parsed.severity()) {
+        step(format!(
+            "syslog facility {} ({}), severity {} ({})",
+            FACILITIES[facility as usize], facility, SEVERITIES[severity as usize], severity
+        ));
+    }
+    if !config.counts_facility(parsed.facility()) {
+        step("dropped: not of a --facility".to_string());
+        return Ok(false);
+    }
+    if let Some(secs) = config.late_after {
+        if (Utc::now().timestamp_millis() - parsed.logged_millis) as f64 > secs * 1000.0 {
+            step(format!("rejected: late_arrival if received now, over --late-after {} seconds", secs));
+            return Ok(false);
+        }
+    }
+    if let Some(min) = config.min_level.filter(|min| parsed.level.severity() < min.severity()) {
+        step(format!("dropped: below --min-level {:?}", min));
+        return Ok(false);
+    }
+
+    // Without `--hash-salt` the hashes differ from those of a run anyway.
+    let salt = config.hash_salt.clone().unwrap_or_default();
+    let hasher = FieldHasher::new(salt, &config.hash_field, &config.hash_pattern);
+    let redacted = Redactor::new(&config.redact, &config.redact_pattern)?.redact(&parsed.message).into_owned();
+    let record = ParsedLine {
+        ip: hasher.hash_field(HashField::Ip, &parsed.ip).into_owned(),
+        message: hasher.hash_text(&redacted).into_owned(),
+        ..parsed
+    };
+    if record.ip != fields.ip {
+        step(format!("IP hashed: {}", record.ip));
+    }
+    if record.message != fields.message {
+        step(format!("message redacted: {}", record.message));
+    }
+    step(format!("message ID: {}", StatsWindow::message_id(&record.message)));
+
+    let extracted = new_window(config).extractor().extract(&record.message);
+    for (what, value) in [
+        ("endpoint", extracted.endpoint().map(str::to_string)),
+        ("status", extracted.status().map(|status| status.to_string())),
+        ("client", extracted.client().map(str::to_string)),
+        ("trace ID", extracted.trace().map(str::to_string)),
+        ("sequence number", extracted.sequence().map(|seq| seq.to_string())),
+    ] {
+        if let Some(value) = value {
+            step(format!("{}: {}", what, value));
+        }
+    }
+
+    for rule in config.escalations.iter() {
+        if rule.level == record.level && rule.message.is_match(&record.message) {
+            step(format!("escalated by {}", rule.expr));
+        }
+    }
+    for rule in config.heartbeats.iter() {
+        if rule.source.as_ref().is_none_or(|name| name == source) && rule.message.is_match(&record.message) {
+            step(format!("heartbeat of {}", rule.expr));
+        }
+    }
+    for pattern in config.mute.iter().filter(|re| re.is_match(&record.message)) {
+        step(format!("muted by {}", pattern));
+    }
+    for pattern in config.context.iter().filter(|re| re.is_match(&record.message)) {
+        step(format!("context captured by {}", pattern));
+    }
+    for filter in config.compare_filters() {
+        if filter.matches(Some(&record), source) {
+            step(format!("shown in the {} pane", filter));
+        }
+    }
+    Ok(true)
+}
+
+// Tell where a line which is not in the log format goes astray of `[TIMESTAMP] LEVEL - IP:ADDRESS MESSAGE`.
+fn format_hint(line: &str) -> &'static str {
+    // A syslog priority may come first.
+    let line = match line.strip_prefix('<').and_then(|rest| rest.split_once('>')) {
+        Some((priority, rest)) if priority.bytes().all(|b| b.is_ascii_digit()) => rest,
+        _ => line,
+    };
+    let Some(rest) = line.strip_prefix('[')
+    else {
+        return "the line doesn't start with a [TIMESTAMP]";
+    };
+    let Some((_, rest)) = rest.split_once(']')
+    else {
+        return "the timestamp is not closed with ]";
+    };
+    let rest = rest.trim_start();
+    let level_len = rest.find(|c: char| !c.is_ascii_uppercase()).unwrap_or(rest.len());
+    if level_len == 0 {
+        return "the timestamp is not followed by an upper case level";
+    }
+    let Some(rest) = rest[level_len..].trim_start().strip_prefix('-')
+    else {
+        return "the level is not followed by a dash";
+    };
+    if !rest.trim_start().starts_with("IP:") {
+        "the dash is not followed by IP:ADDRESS"
+    }
+    else {
+        "the IP address is not followed by a message"
+    }
+}
@@ -0,0 +1,127 @@
+use std::{
+    collections::BTreeMap,
+    fmt::Display,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Mutex,
+    },
+    time::{Duration, Instant},
+};
+
+use chrono::Local;
+use qnode_logproc_core::Failure;
+
+use crate::format::format_duration;
+
+// How often a failure which keeps repeating is reported again.
+const REPEAT_INTERVAL: Duration = Duration::from_secs(60);
+
+struct Failing {
+    failure:  Failure,
+    /// When the failure was last printed
+    reported: Option<Instant>,
+}
+
+/// Errors of inputs, outputs and tasks which retry their work. A failure which repeats is printed once a minute as
+/// "Redis output failing for 5m 0s: <last error> ×312" rather than on every retry, and not printed at all while the
+/// live report is drawn on the terminal, as the report shows the failures in its own panel.
+#[derive(Default)]
+pub(crate) struct Failures {
+    failing: Mutex<BTreeMap<String, Failing>>,
+    /// Items the outputs dropped since the start, as their buffers were full or their batches were given up
+    dropped: Mutex<BTreeMap<String, u64>>,
+    quiet:   AtomicBool,
+}
+
+impl Failures {
+    /// Record another failure of the subject.
+    pub(crate) fn fail(&self, subject: &str, err: impl Display) {
+        let now = Local::now();
+        let mut failing = self.failing.lock().unwrap();
+        let entry = failing.entry(subject.to_string()).or_insert_with(|| Failing {
+            failure:  Failure {
+                subject: subject.to_string(),
+                error:   String::new(),
+                count:   0,
+                since:   now,
+                last:    now,
+            },
+            reported: None,
+        });
+        entry.failure.error = format!("{:#}", err);
+        entry.failure.count += 1;
+        entry.failure.last = now;
+
+        if self.quiet.load(Ordering::Relaxed)
+            || entry.reported.is_some_and(|reported| reported.elapsed() < REPEAT_INTERVAL)
+        {
+            return;
+        }
+        let failure = &entry.failure;
+        if failure.count == 1 {
+            eprintln!("{} failed: {}", failure.subject, failure.error);
+        }
+        else {
+            eprintln!(
+                "{} failing for {}: {} ×{}",
+                failure.subject,
+                format_duration((now - failure.since).num_seconds()),
+                failure.error,
+                failure.count
+            );
+        }
+        entry.reported = Some(Instant::now());
+    }
+
+    /// The subject works again.
+    pub(crate) fn recover(&self, subject: &str) {
+        let Some(entry) = self.failing.lock().unwrap().remove(subject)
+        else {
+            return;
+        };
+        // Only a recovery from a failure which was printed is worth mentioning.
+        if entry.reported.is_some() && !self.quiet.load(Ordering::Relaxed) {
+            eprintln!(
+                "{} recovered after {} and {} failures",
+                subject,
+                format_duration((Local::now() - entry.failure.since).num_seconds()),
+                entry.failure.count
+            );
+        }
+    }
+
+    /// Count an item the output dropped.
+    pub(crate) fn drop_item(&self, subject: &str) {
+        self.drop_items(subject, 1);
+    }
+
+    /// Count items the output dropped at once, like a batch it gave up.
+    pub(crate) fn drop_items(&self, subject: &str, count: usize) {
+        if count > 0 {
+            *self.dropped.lock().unwrap().entry(subject.to_string()).or_default() += count as u64;
+        }
+    }
+
+    /// Items dropped since the start, by output.
+    pub(crate) fn dropped(&self) -> BTreeMap<String, u64> {
+        self.dropped.lock().unwrap().clone()
+    }
+
+    /// Stop printing failures, they are shown by the live report.
+    pub(crate) fn set_quiet(&self, quiet: bool) {
+        self.quiet.store(quiet, Ordering::Relaxed);
+    }
+
+    /// Current failures, the longest failing first.
+    pub(crate) fn list(&self) -> Vec<Failure> {
+        let mut failures = self
+            .failing
+            .lock()
+            .unwrap()
+            .values()
+            .map(|entry| entry.failure.clone())
+            .collect::<Vec<_>>();
+        failures.sort_by_key(|failure| failure.since);
+        failures
+    }
+}
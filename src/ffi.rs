@@ -0,0 +1,128 @@
+//! C ABI over [`StatsEngine`]. The engine runs on its own Tokio runtime, so the host needs no async support.
+//! Snapshots are handed out as JSON strings which must be released with `qlp_string_free`.
+
+use std::{
+    ffi::{c_char, c_int, CStr, CString},
+    ptr,
+};
+
+use anyhow::{anyhow, Result};
+use qnode_logproc_core::SnapshotView;
+use tokio::{runtime::Runtime, sync::watch};
+
+use crate::engine::StatsEngine;
+
+/// Opaque engine handle.
+pub struct QlpEngine {
+    // Dropped before the runtime it runs on.
+    engine:    Option<StatsEngine>,
+    snapshots: watch::Receiver<SnapshotView>,
+    runtime:   Runtime,
+}
+
+impl QlpEngine {
+    fn new(args: Vec<String>) -> Result<Self> {
+        let runtime = tokio::runtime::Builder::new_multi_thread().enable_all().build()?;
+        let engine = {
+            let _guard = runtime.enter();
+            StatsEngine::new(args)?
+        };
+        Ok(Self {
+            snapshots: engine.subscribe()?,
+            engine: Some(engine),
+            runtime,
+        })
+    }
+}
+
+impl Drop for QlpEngine {
+    fn drop(&mut self) {
+        let _guard = self.runtime.enter();
+        self.engine.take();
+    }
+}
+
+unsafe fn c_str<'a>(s: *const c_char) -> Result<&'a str> {
+    if s.is_null() {
+        return Err(anyhow!("NULL string"));
+    }
+    Ok(CStr::from_ptr(s).to_str()?)
+}
+
+/// Create an engine. `argv` holds `argc` command line options, without the program name. Returns NULL if the
+/// options are invalid.
+///
+/// # Safety
+///
+/// `argv` must point to `argc` valid NUL-terminated strings; it may be NULL if `argc` is 0.
+#[no_mangle]
+pub unsafe extern "C" fn qlp_engine_new(argv: *const *const c_char, argc: usize) -> *mut QlpEngine {
+    let args = (0..argc).map(|i| c_str(*argv.add(i)).map(str::to_string)).collect::<Result<Vec<_>>>();
+    match args.and_then(QlpEngine::new) {
+        Ok(engine) => Box::into_raw(Box::new(engine)),
+        Err(err) => {
+            eprintln!("Failed to create the stats engine: {:#}", err);
+            ptr::null_mut()
+        }
+    }
+}
+
+/// Push a raw log line. Returns 0 on success, -1 on failure.
+///
+/// # Safety
+///
+/// `engine` must come from `qlp_engine_new`; `line` must be a valid NUL-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn qlp_engine_push_line(engine: *mut QlpEngine, line: *const c_char) -> c_int {
+    let Some(engine) = engine.as_ref()
+    else {
+        return -1;
+    };
+    match c_str(line).and_then(|line| engine.engine.as_ref().unwrap().push_line(line)) {
+        Ok(()) => 0,
+        Err(_) => -1,
+    }
+}
+
+/// The latest statistics snapshot as a JSON object. The string must be released with `qlp_string_free`. Returns NULL
+/// on failure.
+///
+/// # Safety
+///
+/// `engine` must come from `qlp_engine_new`.
+#[no_mangle]
+pub unsafe extern "C" fn qlp_engine_snapshot(engine: *mut QlpEngine) -> *mut c_char {
+    let Some(engine) = engine.as_ref()
+    else {
+        return ptr::null_mut();
+    };
+    let json = serde_json::to_string(&*engine.snapshots.borrow());
+    match json.ok().and_then(|json| CString::new(json).ok()) {
+        Some(json) => json.into_raw(),
+        None => ptr::null_mut(),
+    }
+}
+
+/// Stop the engine and release it.
+///
+/// # Safety
+///
+/// `engine` must come from `qlp_engine_new` and must not be used afterwards. NULL is ignored.
+#[no_mangle]
+pub unsafe extern "C" fn qlp_engine_free(engine: *mut QlpEngine) {
+    if !engine.is_null() {
+        drop(Box::from_raw(engine));
+    }
+}
+
+/// Release a string returned by the library.
+///
+/// # Safety
+///
+/// `s` must come from this library and must not be used afterwards. NULL is ignored.
+#[no_mangle]
+pub unsafe extern "C" fn qlp_string_free(s: *mut c_char) {
+    if !s.is_null() {
+        drop(CString::from_raw(s));
+    }
+}
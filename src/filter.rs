@@ -0,0 +1,137 @@
+use std::{fmt, net::IpAddr, str::FromStr};
+
+use anyhow::{anyhow, Result};
+use regex::Regex;
+
+use crate::types::ParsedLine;
+
+/// A single condition of a filter.
+#[derive(Debug, Clone)]
+enum Term {
+    /// The IP address is within the network
+    Ip { network: IpAddr, prefix: u8 },
+    Level(String),
+    Source(String),
+    Message(Regex),
+}
+
+impl Term {
+    fn matches(&self, record: Option<&ParsedLine>, source: &str) -> bool {
+        match self {
+            Self::Source(name) => name == source,
+            // Malformed lines have no fields to check.
+            _ if record.is_none() => false,
+            Self::Ip { network, prefix } => record
+                .and_then(|record| record.ip.parse::<IpAddr>().ok())
+                .is_some_and(|ip| in_network(ip, *network, *prefix)),
+            Self::Level(level) => {
+                record.is_some_and(|record| format!("{:?}", record.level).eq_ignore_ascii_case(level))
+            }
+            Self::Message(re) => record.is_some_and(|record| re.is_match(&record.message)),
+        }
+    }
+}
+
+impl FromStr for Term {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        if let Some((field, value)) = s.split_once('~') {
+            return match field.trim() {
+                "message" => Ok(Self::Message(
+                    Regex::new(value.trim()).map_err(|e| anyhow!("Bad regex '{}': {}", value, e))?,
+                )),
+                field => Err(anyhow!("Field '{}' can't be matched with a regex, only message can", field)),
+            };
+        }
+
+        let Some((field, value)) = s.split_once('=')
+        else {
+            return Err(anyhow!("Expected FIELD=VALUE or message~REGEX, got '{}'", s));
+        };
+        let value = value.trim();
+        match field.trim() {
+            "ip" => {
+                let (network, prefix) = match value.split_once('/') {
+                    Some((network, prefix)) => (network, Some(prefix)),
+                    None => (value, None),
+                };
+                let network: IpAddr = network.parse().map_err(|e| anyhow!("Bad IP address '{}': {}", network, e))?;
+                let max_prefix = if network.is_ipv4() { 32 } else { 128 };
+                let prefix = match prefix {
+                    Some(prefix) => prefix
+                        .parse()
+                        .ok()
+                        .filter(|prefix| *prefix <= max_prefix)
+                        .ok_or_else(|| anyhow!("Bad network prefix '{}'", prefix))?,
+                    None => max_prefix,
+                };
+                Ok(Self::Ip { network, prefix })
+            }
+            "level" => Ok(Self::Level(value.to_string())),
+            "source" => Ok(Self::Source(value.to_string())),
+            field => Err(anyhow!("Unknown filter field '{}', expected ip, level, source or message", field)),
+        }
+    }
+}
+
+/// Selects records by their fields. Conditions like `ip=10.0.0.0/8`, `level=ERROR`, `source=NAME` or
+/// `message~REGEX` are separated with commas and must all match.
+#[derive(Debug, Clone)]
+pub(crate) struct Filter {
+    expr:    String,
+    terms:   Vec<Term>,
+    negated: bool,
+}
+
+impl Filter {
+    /// `record` is `None` for lines which failed to parse.
+    pub(crate) fn matches(&self, record: Option<&ParsedLine>, source: &str) -> bool {
+        self.terms.iter().all(|term| term.matches(record, source)) != self.negated
+    }
+
+    /// The filter which matches everything this one doesn't.
+    pub(crate) fn negate(&self) -> Self {
+        Self {
+            negated: !self.negated,
+            ..self.clone()
+        }
+    }
+}
+
+impl FromStr for Filter {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        Ok(Self {
+            expr:    s.to_string(),
+            terms:   s.split(',').map(str::parse).collect::<Result<_>>()?,
+            negated: false,
+        })
+    }
+}
+
+impl fmt::Display for Filter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.negated {
+            write!(f, "not {}", self.expr)
+        }
+        else {
+            f.write_str(&self.expr)
+        }
+    }
+}
+
+fn in_network(ip: IpAddr, network: IpAddr, prefix: u8) -> bool {
+    match (ip, network) {
+        (IpAddr::V4(ip), IpAddr::V4(network)) => {
+            let mask = u32::MAX.checked_shl(32 - prefix as u32).unwrap_or(0);
+            u32::from(ip) & mask == u32::from(network) & mask
+        }
+        (IpAddr::V6(ip), IpAddr::V6(network)) => {
+            let mask = u128::MAX.checked_shl(128 - prefix as u32).unwrap_or(0);
+            u128::from(ip) & mask == u128::from(network) & mask
+        }
+        _ => false,
+    }
+}
@@ -0,0 +1,151 @@
+use chrono::{DateTime, Local, SecondsFormat, Utc};
+
+use crate::config::{NumberStyle, TimeFormat};
+
+/// Display a report timestamp.
+pub(crate) fn format_time(ts: DateTime<Local>, format: TimeFormat) -> String {
+    match format {
+        TimeFormat::Local => ts.format("%Y-%m-%d %H:%M:%S%.3f").to_string(),
+        TimeFormat::Utc => ts.with_timezone(&Utc).format("%Y-%m-%d %H:%M:%S%.3f UTC").to_string(),
+        TimeFormat::Iso => ts.to_rfc3339_opts(SecondsFormat::Millis, false),
+        TimeFormat::Relative => match (Local::now() - ts).num_seconds() {
+            ..1 => "just now".to_string(),
+            secs => format!("{} ago", format_duration(secs)),
+        },
+    }
+}
+
+/// Display a duration in seconds, like `12s`, `3m 5s` or `1h 20m`.
+pub(crate) fn format_duration(secs: i64) -> String {
+    match secs {
+        ..60 => format!("{}s", secs.max(0)),
+        60..3600 => format!("{}m {}s", secs / 60, secs % 60),
+        _ => format!("{}h {}m", secs / 3600, secs % 3600 / 60),
+    }
+}
+
+/// Display a size in bytes with a binary unit, like `512 B` or `1.5 MiB`.
+pub(crate) fn format_bytes(bytes: u64) -> String {
+    const UNITS: [&str; 5] = ["B", "KiB", "MiB", "GiB", "TiB"];
+    let mut size = bytes as f64;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{} B", bytes)
+    }
+    else {
+        format!("{:.1} {}", size, UNITS[unit])
+    }
+}
+
+// Suffixes of the compact style, for each power of 1000.
+const COMPACT_SUFFIXES: [&str; 5] = ["", "K", "M", "B", "T"];
+
+/// Formats numbers according to a locale, digit group and decimal separators, and the number style.
+#[derive(Debug, Clone)]
+pub(crate) struct NumberFormat {
+    group:   Option<char>,
+    decimal: char,
+    style:   NumberStyle,
+}
+
+impl NumberFormat {
+    /// `locale` is like `de_DE.UTF-8`. Without it, the locale is taken from `LC_ALL`, `LC_NUMERIC` or `LANG`.
+    pub(crate) fn new(locale: Option<&str>, style: NumberStyle) -> Self {
+        let locale = locale.map(str::to_string).or_else(|| {
+            ["LC_ALL", "LC_NUMERIC", "LANG"]
+                .iter()
+                .filter_map(|var| std::env::var(var).ok())
+                .find(|value| !value.is_empty())
+        });
+        let locale = locale.unwrap_or_default();
+        // Drop the encoding and the modifier: `de_CH.UTF-8@euro` -> `de_CH`.
+        let name = locale.split(['.', '@']).next().unwrap_or_default();
+        let (language, territory) = name.split_once(['_', '-']).unwrap_or((name, ""));
+
+        let (group, decimal) = match (language, territory) {
+            ("" | "C" | "POSIX", _) => (None, '.'),
+            ("de" | "it" | "fr" | "rm", "CH" | "LI") => (Some('\''), '.'),
+            (
+                "de" | "nl" | "it" | "es" | "pt" | "id" | "da" | "tr" | "el" | "ro" | "sl" | "hr" | "sr" | "vi",
+                _,
+            ) => (Some('.'), ','),
+            (
+                "fr" | "ru" | "pl" | "cs" | "sk" | "sv" | "fi" | "nb" | "nn" | "no" | "uk" | "hu" | "bg" | "et" | "lv"
+                | "lt" | "be" | "kk",
+                _,
+            ) => (Some('\u{a0}'), ','),
+            _ => (Some(','), '.'),
+        };
+
+        Self { group, decimal, style }
+    }
+
+    /// Format an integer.
+    pub(crate) fn int<N: ToString>(&self, n: N) -> String {
+        let digits = n.to_string();
+        match digits.parse::<f64>() {
+            Ok(x) if self.style == NumberStyle::Compact && x.abs() >= 1000.0 => self.compact(x),
+            _ => self.group_digits(&digits),
+        }
+    }
+
+    /// Format a floating point number with the given number of decimal places.
+    pub(crate) fn float(&self, x: f64, precision: usize) -> String {
+        if self.style == NumberStyle::Compact && x.abs() >= 1000.0 {
+            return self.compact(x);
+        }
+        self.fixed(x, precision)
+    }
+
+    fn fixed(&self, x: f64, precision: usize) -> String {
+        let formatted = format!("{:.*}", precision, x);
+        match formatted.split_once('.') {
+            Some((int, frac)) => format!("{}{}{}", self.group_digits(int), self.decimal, frac),
+            // NaN, infinity, or no decimal places
+            None => self.group_digits(&formatted),
+        }
+    }
+
+    // Three significant digits with a suffix: 1.23K, 12.3M, 123B.
+    fn compact(&self, x: f64) -> String {
+        let mut scaled = x;
+        let mut power = 0;
+        while scaled.abs() >= 999.5 && power < COMPACT_SUFFIXES.len() - 1 {
+            scaled /= 1000.0;
+            power += 1;
+        }
+        let precision = match scaled.abs() {
+            x if x < 9.995 => 2,
+            x if x < 99.95 => 1,
+            _ => 0,
+        };
+        format!("{}{}", self.fixed(scaled, precision), COMPACT_SUFFIXES[power])
+    }
+
+    fn group_digits(&self, digits: &str) -> String {
+        let Some(group) = self.group.filter(|_| self.style != NumberStyle::Raw)
+        else {
+            return digits.to_string();
+        };
+        let (sign, digits) = match digits.strip_prefix('-') {
+            Some(digits) => ("-", digits),
+            None => ("", digits),
+        };
+        if !digits.bytes().all(|b| b.is_ascii_digit()) {
+            return format!("{}{}", sign, digits);
+        }
+
+        let mut grouped = String::with_capacity(digits.len() + digits.len() / 3);
+        for (pos, digit) in digits.chars().enumerate() {
+            if pos > 0 && (digits.len() - pos) % 3 == 0 {
+                grouped.push(group);
+            }
+            grouped.push(digit);
+        }
+        format!("{}{}", sign, grouped)
+    }
+}
@@ -0,0 +1,71 @@
+use std::path::{Path, PathBuf};
+
+use anyhow::{anyhow, Result};
+use regex::Regex;
+
+/// A file name pattern with `*`, `?` and `[...]` wildcards in its last component, like `/var/log/qnode/*.log`.
+#[derive(Debug, Clone)]
+pub(crate) struct FileGlob {
+    dir:  PathBuf,
+    name: Regex,
+}
+
+impl FileGlob {
+    /// Returns `None` if the path has no wildcards.
+    pub(crate) fn new(path: &Path) -> Result<Option<Self>> {
+        let pattern = path.to_string_lossy();
+        if !pattern.contains(['*', '?', '[']) {
+            return Ok(None);
+        }
+
+        let (dir, name) = match pattern.rsplit_once('/') {
+            Some(("", name)) => ("/", name),
+            Some((dir, name)) => (dir, name),
+            None => (".", pattern.as_ref()),
+        };
+        if dir.contains(['*', '?', '[']) {
+            return Err(anyhow!("Wildcards are only allowed in the file name, got '{}'", pattern));
+        }
+
+        let mut re = String::from("^");
+        let mut chars = name.chars();
+        while let Some(c) = chars.next() {
+            match c {
+                '*' => re.push_str("[^/]*"),
+                '?' => re.push_str("[^/]"),
+                '[' => {
+                    re.push('[');
+                    for c in chars.by_ref() {
+                        match c {
+                            ']' => break,
+                            '!' if re.ends_with('[') => re.push('^'),
+                            '\\' => re.push_str("\\\\"),
+                            c => re.push(c),
+                        }
+                    }
+                    re.push(']');
+                }
+                c => re.push_str(&regex::escape(&c.to_string())),
+            }
+        }
+        re.push('$');
+
+        Ok(Some(Self {
+            dir:  PathBuf::from(dir),
+            name: Regex::new(&re).map_err(|e| anyhow!("Bad file pattern '{}': {}", pattern, e))?,
+        }))
+    }
+
+    /// Regular files currently matching the pattern.
+    pub(crate) async fn matches(&self) -> Result<Vec<PathBuf>> {
+        let mut found = Vec::new();
+        let mut entries = tokio::fs::read_dir(&self.dir).await?;
+        while let Some(entry) = entries.next_entry().await? {
+            let matched = entry.file_name().to_str().is_some_and(|name| self.name.is_match(name));
+            if matched && entry.file_type().await?.is_file() {
+                found.push(entry.path());
+            }
+        }
+        Ok(found)
+    }
+}
@@ -0,0 +1,97 @@
+use std::collections::BTreeMap;
+
+use chrono::{DateTime, Local, Utc};
+use qnode_logproc_core::{Clock, Heartbeat};
+
+use crate::{config::HeartbeatSpec, types::*};
+
+// When a source logged its heartbeat last, or when it was first expected to.
+#[derive(Debug, Clone, Copy)]
+struct Beat {
+    millis: i64,
+    seen:   bool,
+}
+
+/// Applies the `--heartbeat` rules: tracks when every source logged its heartbeat messages last.
+#[derive(Debug)]
+pub(crate) struct Heartbeats {
+    rules:   Vec<HeartbeatSpec>,
+    /// Whether the intervals are measured by log time rather than receive time
+    clock:   Clock,
+    /// Heartbeats per rule and source
+    beats:   Vec<BTreeMap<String, Beat>>,
+    started: bool,
+}
+
+impl Heartbeats {
+    pub(crate) fn new(rules: Vec<HeartbeatSpec>, clock: Clock) -> Self {
+        let mut heartbeats = Self {
+            beats: vec![BTreeMap::new(); rules.len()],
+            rules,
+            clock,
+            started: false,
+        };
+        // By log time the clock starts with the first record.
+        if clock == Clock::Receive {
+            heartbeats.start(Utc::now().timestamp_millis());
+        }
+        heartbeats
+    }
+
+    pub(crate) fn is_enabled(&self) -> bool {
+        !self.rules.is_empty()
+    }
+
+    pub(crate) fn push(&mut self, record: &ParsedLine, source: &str, received_millis: i64) {
+        let now = match self.clock {
+            Clock::Receive => received_millis,
+            Clock::Log => record.logged_millis,
+        };
+        if !self.started {
+            self.start(now);
+        }
+        for (rule, beats) in self.rules.iter().zip(self.beats.iter_mut()) {
+            if rule.source.as_ref().is_some_and(|name| name != source) {
+                continue;
+            }
+            let beat = beats.entry(source.to_string()).or_insert(Beat {
+                millis: now,
+                seen:   false,
+            });
+            if rule.message.is_match(&record.message) {
+                *beat = Beat {
+                    millis: beat.millis.max(now),
+                    seen:   true,
+                };
+            }
+        }
+    }
+
+    /// The heartbeats as of `now`.
+    pub(crate) fn heartbeats(&self, now: DateTime<Local>) -> Vec<Heartbeat> {
+        let time = |millis| DateTime::from_timestamp_millis(millis).unwrap_or_default().with_timezone(&Local);
+        self.rules
+            .iter()
+            .zip(self.beats.iter())
+            .flat_map(|(rule, beats)| {
+                beats.iter().map(move |(source, beat)| Heartbeat {
+                    source:        source.clone(),
+                    rule:          rule.expr.clone(),
+                    interval_secs: rule.interval_ms as f64 / 1000.0,
+                    last_seen:     beat.seen.then(|| time(beat.millis)),
+                    age_secs:      ((now.timestamp_millis() - beat.millis) as f64 / 1000.0).max(0.0),
+                })
+            })
+            .collect()
+    }
+
+    // Sources named by the rules are expected to send their heartbeats from the start.
+    fn start(&mut self, now: i64) {
+        self.started = true;
+        for (rule, beats) in self.rules.iter().zip(self.beats.iter_mut()) {
+            if let Some(ref source) = rule.source {
+                beats.insert(source.clone(), Beat { millis: now, seen: false });
+            }
+        }
+    }
+}
@@ -0,0 +1,328 @@
+use std::{net::SocketAddr, path::Path, sync::Arc};
+
+use anyhow::{anyhow, Result};
+use base64::Engine;
+use fieldx_plus::fx_plus;
+use sha2::{Digest, Sha256};
+use tokio::{
+    io::{AsyncBufRead, AsyncBufReadExt, AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, BufReader},
+    net::TcpListener,
+    task::JoinSet,
+    time::Duration,
+};
+
+use crate::{app::App, output::Output};
+
+// Longest request line and headers taken, altogether.
+const MAX_HEAD: u64 = 16 * 1024;
+// Largest body taken by `/ingest`.
+const MAX_BODY: usize = 16 * 1024 * 1024;
+// A client must send its request and take the response within it, the TLS handshake included.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
+const ACCEPT_RETRY_INTERVAL: Duration = Duration::from_secs(1);
+
+/// The `--http` server. Every request is served on a connection of its own: the response is sent with
+/// `Connection: close`, which is all Prometheus and `curl` need.
+#[fx_plus(agent(App, unwrap(error(anyhow::Error, App::app_is_gone()))), sync, rc)]
+pub(crate) struct HttpServer {
+    /// Credentials of `--http-auth`.
+    #[fieldx(lock, get(clone), set, default(Arc::new(Credentials::default())))]
+    credentials: Arc<Credentials>,
+}
+
+impl HttpServer {
+    pub(crate) async fn start(&self, address: SocketAddr) -> Result<()> {
+        let app = self.app()?;
+        self.reload_credentials()?;
+        let listener = TcpListener::bind(address)
+            .await
+            .map_err(|e| anyhow!("Can't serve HTTP on {}: {}", address, e))?;
+        let subject = format!("Serving HTTP on {}", address);
+        #[cfg(feature = "tls")]
+        let acceptor = crate::tls::http_acceptor(&app.config())?;
+        let mut connections = JoinSet::new();
+
+        loop {
+            let (stream, peer) = match listener.accept().await {
+                Ok(accepted) => accepted,
+                Err(err) => {
+                    app.failures().fail(&subject, err);
+                    tokio::time::sleep(ACCEPT_RETRY_INTERVAL).await;
+                    continue;
+                }
+            };
+            app.failures().recover(&subject);
+            while connections.try_join_next().is_some() {}
+
+            let myself = self.myself().unwrap();
+            #[cfg(feature = "tls")]
+            let acceptor = acceptor.clone();
+            connections.spawn(async move {
+                let served = tokio::time::timeout(REQUEST_TIMEOUT, async {
+                    #[cfg(feature = "tls")]
+                    if let Some(acceptor) = acceptor {
+                        return myself.serve(acceptor.accept(stream).await?, peer).await;
+                    }
+                    myself.serve(stream, peer).await
+                });
+                // A client failing its own request is of no concern to the server.
+                let _ = served.await;
+            });
+        }
+    }
+
+    /// Read the `--http-auth` file again, the credentials in use are kept if it fails.
+    pub(crate) fn reload_credentials(&self) -> Result<()> {
+        if let Some(ref path) = self.app()?.config().http_auth {
+            self.set_credentials(Arc::new(Credentials::load(path)?));
+        }
+        Ok(())
+    }
+
+    async fn serve<S: AsyncRead + AsyncWrite + Unpin>(&self, stream: S, peer: SocketAddr) -> Result<()> {
+        let mut reader = BufReader::new(stream);
+        let response = match read_request(&mut reader).await? {
+            Some(request) => self.respond(request, &mut reader, peer).await?,
+            None => Response::error("400 Bad Request"),
+        };
+        response.write(reader.get_mut()).await
+    }
+
+    async fn respond<R: AsyncRead + Unpin>(
+        &self,
+        request: Request,
+        body: &mut R,
+        peer: SocketAddr,
+    ) -> Result<Response> {
+        let app = self.app()?;
+        if app.config().http_auth.is_some() && !self.credentials().accepts(request.authorization.as_deref()) {
+            return Ok(Response::unauthorized());
+        }
+        Ok(match (request.method.as_str(), request.path()) {
+            ("GET", "/metrics") => Response::ok(
+                "text/plain; version=0.0.4",
+                Output::prometheus(&app.stats()?.latest_snapshot()),
+            ),
+            ("GET", "/stats") => Response::ok(
+                "application/json",
+                serde_json::to_string(&app.stats()?.latest_snapshot())?,
+            ),
+            ("POST", "/ingest") => self.ingest(&request, body, peer).await?,
+            (_, "/metrics" | "/stats" | "/ingest") => Response::error("405 Method Not Allowed"),
+            _ => Response::error("404 Not Found"),
+        })
+    }
+
+    // The lines of the body are taken like those of a `--listen tcp://` connection, tagged with the `http/<peer
+    // address>` source name.
+    async fn ingest<R: AsyncRead + Unpin>(
+        &self,
+        request: &Request,
+        body: &mut R,
+        peer: SocketAddr,
+    ) -> Result<Response> {
+        let length = match request.content_length {
+            Some(length) if !request.chunked => length,
+            _ => return Ok(Response::error("411 Length Required")),
+        };
+        if length > MAX_BODY {
+            return Ok(Response::error("413 Content Too Large"));
+        }
+        let mut lines = vec![0; length];
+        body.read_exact(&mut lines).await?;
+
+        let app = self.app()?;
+        let source: Arc<str> = format!("http/{}", peer.ip()).into();
+        app.reader()?.ingest(&lines, &source, app.channel_tx()).await?;
+        Ok(Response {
+            status:       "204 No Content",
+            content_type: "text/plain",
+            body:         String::new(),
+            challenge:    false,
+        })
+    }
+}
+
+/// Credentials of `--http-auth`. Only their digests are kept; the one a request presents is compared with all of
+/// them in constant time, so that the time a rejection takes tells nothing about how close it was.
+#[derive(Default)]
+pub(crate) struct Credentials {
+    bearer: Vec<Secret>,
+    /// Digests of `USER:PASSWORD`, as carried by the `Basic` scheme.
+    basic:  Vec<Secret>,
+}
+
+type Secret = [u8; 32];
+
+impl Credentials {
+    pub(crate) fn load(path: &Path) -> Result<Self> {
+        let content = std::fs::read_to_string(path).map_err(|e| anyhow!("Can't read --http-auth {:?}: {}", path, e))?;
+        let mut credentials = Self::default();
+        for (number, line) in content.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let (scheme, value) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+            let value = value.trim();
+            match scheme {
+                "bearer" if !value.is_empty() => credentials.bearer.push(secret(value)),
+                "basic" if value.contains(':') => credentials.basic.push(secret(value)),
+                _ => {
+                    return Err(anyhow!(
+                        "{:?}, line {}: expected `bearer TOKEN` or `basic USER:PASSWORD`",
+                        path,
+                        number + 1
+                    ))
+                }
+            }
+        }
+        if credentials.bearer.is_empty() && credentials.basic.is_empty() {
+            return Err(anyhow!("No credentials in --http-auth {:?}", path));
+        }
+        Ok(credentials)
+    }
+
+    fn accepts(&self, authorization: Option<&str>) -> bool {
+        let Some((scheme, value)) = authorization.and_then(|value| value.trim().split_once(' '))
+        else {
+            return false;
+        };
+        let (known, presented) = if scheme.eq_ignore_ascii_case("bearer") {
+            (&self.bearer, secret(value.trim()))
+        }
+        else if scheme.eq_ignore_ascii_case("basic") {
+            match base64::engine::general_purpose::STANDARD.decode(value.trim()) {
+                Ok(decoded) => (&self.basic, secret(decoded)),
+                Err(_) => return false,
+            }
+        }
+        else {
+            return false;
+        };
+        known
+            .iter()
+            .fold(false, |accepted, secret| accepted | same_secret(secret, &presented))
+    }
+}
+
+fn secret<V: AsRef<[u8]>>(value: V) -> Secret {
+    Sha256::digest(value).into()
+}
+
+fn same_secret(a: &Secret, b: &Secret) -> bool {
+    a.iter().zip(b).fold(0, |diff, (a, b)| diff | (a ^ b)) == 0
+}
+
+struct Request {
+    method:         String,
+    target:         String,
+    authorization:  Option<String>,
+    content_length: Option<usize>,
+    chunked:        bool,
+}
+
+impl Request {
+    fn path(&self) -> &str {
+        self.target.split_once('?').map_or(&self.target, |(path, _)| path)
+    }
+}
+
+// `None` if the request is malformed or its head is longer than `MAX_HEAD`.
+async fn read_request<R: AsyncBufRead + Unpin>(reader: &mut R) -> Result<Option<Request>> {
+    let mut head = (&mut *reader).take(MAX_HEAD);
+    let mut line = String::new();
+    head.read_line(&mut line).await?;
+    let mut parts = line.split_whitespace();
+    let (Some(method), Some(target)) = (parts.next(), parts.next())
+    else {
+        return Ok(None);
+    };
+    let mut request = Request {
+        method:         method.to_string(),
+        target:         target.to_string(),
+        authorization:  None,
+        content_length: None,
+        chunked:        false,
+    };
+
+    loop {
+        line.clear();
+        if head.read_line(&mut line).await? == 0 {
+            return Ok(None);
+        }
+        let header = line.trim_end();
+        if header.is_empty() {
+            break;
+        }
+        let Some((name, value)) = header.split_once(':')
+        else {
+            return Ok(None);
+        };
+        let value = value.trim();
+        match name.trim().to_ascii_lowercase().as_str() {
+            "authorization" => request.authorization = Some(value.to_string()),
+            "content-length" => match value.parse() {
+                Ok(length) => request.content_length = Some(length),
+                Err(_) => return Ok(None),
+            },
+            "transfer-encoding" => request.chunked = true,
+            _ => (),
+        }
+    }
+
+    Ok(Some(request))
+}
+
+struct Response {
+    status:       &'static str,
+    content_type: &'static str,
+    body:         String,
+    /// Tell the client which authentication schemes are accepted.
+    challenge:    bool,
+}
+
+impl Response {
+    fn ok(content_type: &'static str, body: String) -> Self {
+        Self {
+            status: "200 OK",
+            content_type,
+            body,
+            challenge: false,
+        }
+    }
+
+    fn error(status: &'static str) -> Self {
+        Self {
+            status,
+            content_type: "text/plain",
+            body: format!("{}\n", status),
+            challenge: false,
+        }
+    }
+
+    fn unauthorized() -> Self {
+        Self {
+            challenge: true,
+            ..Self::error("401 Unauthorized")
+        }
+    }
+
+    async fn write<W: AsyncWrite + Unpin>(&self, writer: &mut W) -> Result<()> {
+        let mut head = format!(
+            "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n",
+            self.status,
+            self.content_type,
+            self.body.len()
+        );
+        if self.challenge {
+            head.push_str("WWW-Authenticate: Bearer realm=\"qnode-logproc\"\r\n");
+            head.push_str("WWW-Authenticate: Basic realm=\"qnode-logproc\"\r\n");
+        }
+        head.push_str("\r\n");
+        writer.write_all(head.as_bytes()).await?;
+        writer.write_all(self.body.as_bytes()).await?;
+        writer.shutdown().await?;
+        Ok(())
+    }
+}
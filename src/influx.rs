@@ -0,0 +1,193 @@
+use std::sync::Arc;
+
+use anyhow::{anyhow, Result};
+use qnode_logproc_core::SnapshotView;
+use tokio::net::UdpSocket;
+
+use crate::{
+    config::Config,
+    sink::{Delivery, Sink, SNAPSHOT_BUFFER},
+    tls::HttpClient,
+};
+
+// Lines sent in a single UDP datagram are kept below this size, to get through without fragmentation.
+const DATAGRAM_SIZE: usize = 1400;
+// Snapshots queued behind a slow write are sent in a single one.
+const WRITE_BATCH: usize = 16;
+
+/// The window aggregates of the snapshot in InfluxDB line protocol: the headline numbers as fields of the
+/// `MEASUREMENT` point, the entries per source and per HTTP status class as `MEASUREMENT_sources` and
+/// `MEASUREMENT_statuses` points tagged with `source` and `class`. Every point gets the tags.
+pub(crate) fn lines(view: &SnapshotView, measurement: &str, tags: &[(String, String)]) -> String {
+    let timestamp = view.timestamp.timestamp_nanos_opt().unwrap_or_default();
+    let tags = tags
+        .iter()
+        .map(|(name, value)| format!(",{}={}", escape_tag(name), escape_tag(value)))
+        .collect::<String>();
+    let measurement = escape_measurement(measurement);
+    let mut out = String::new();
+
+    let mut fields = vec![
+        ("entries", format!("{}i", view.entries)),
+        ("errors", format!("{}i", view.errors)),
+        ("infos", format!("{}i", view.infos)),
+        ("debugs", format!("{}i", view.debugs)),
+        ("malformed", format!("{}i", view.malformed)),
+        ("distinct_ips", format!("{}i", view.distinct_ips)),
+        ("sampled_out", format!("{}i", view.sampled_out)),
+        ("below_min_level", format!("{}i", view.below_min_level)),
+        ("current_rate", format!("{}i", view.current_rate)),
+    ];
+    // Line protocol has no representation for NaN and infinities.
+    for (name, value) in [
+        ("rate", view.rate),
+        ("peak_rate", view.peak_rate),
+        ("avg_lag", view.avg_lag),
+        ("burstiness", view.burstiness()),
+    ] {
+        if value.is_finite() {
+            fields.push((name, value.to_string()));
+        }
+    }
+    if view.error_rate.is_finite() {
+        fields.push(("error_rate", view.error_rate.to_string()));
+    }
+    let fields = fields
+        .into_iter()
+        .map(|(name, value)| format!("{}={}", name, value))
+        .collect::<Vec<_>>()
+        .join(",");
+    out.push_str(&format!("{}{} {} {}\n", measurement, tags, fields, timestamp));
+
+    for (source, entries) in view.sources.iter() {
+        out.push_str(&format!(
+            "{}_sources{},source={} entries={}i {}\n",
+            measurement,
+            tags,
+            escape_tag(source),
+            entries,
+            timestamp
+        ));
+    }
+    for (class, entries) in view.statuses.iter() {
+        out.push_str(&format!(
+            "{}_statuses{},class={} entries={}i {}\n",
+            measurement,
+            tags,
+            escape_tag(class),
+            entries,
+            timestamp
+        ));
+    }
+
+    out
+}
+
+/// Parse a `NAME=VALUE` tag of the points.
+pub(crate) fn parse_tag(s: &str) -> Result<(String, String)> {
+    match s.split_once('=') {
+        Some((name, value)) if !name.is_empty() && !value.is_empty() => Ok((name.to_string(), value.to_string())),
+        _ => Err(anyhow!("Expected NAME=VALUE, got '{}'", s)),
+    }
+}
+
+/// Sends the window aggregates of the snapshots to the InfluxDB HTTP write endpoint of `--influx`, or as datagrams to
+/// a `udp://HOST:PORT` listener. Lines which fail to be sent are dropped, the next refresh brings new ones.
+pub(crate) struct InfluxSink {
+    url:         String,
+    token:       Option<String>,
+    measurement: String,
+    tags:        Vec<(String, String)>,
+    client:      HttpClient,
+    socket:      Option<UdpSocket>,
+}
+
+impl InfluxSink {
+    pub(crate) fn from_config(config: &Config) -> Option<Self> {
+        Some(Self {
+            url:         config.influx.clone()?,
+            token:       config.influx_token.clone(),
+            measurement: config.influx_measurement.clone(),
+            tags:        config.influx_tags.clone(),
+            client:      HttpClient::new(config),
+            socket:      None,
+        })
+    }
+}
+
+impl Sink for InfluxSink {
+    type Item = Arc<SnapshotView>;
+
+    fn name(&self) -> String {
+        format!("InfluxDB output to {}", self.url)
+    }
+
+    fn delivery(&self) -> Delivery {
+        Delivery {
+            batch: WRITE_BATCH,
+            buffer: SNAPSHOT_BUFFER,
+            ..Delivery::default()
+        }
+    }
+
+    async fn deliver(&mut self, batch: &[Arc<SnapshotView>]) -> Result<()> {
+        let lines = batch
+            .iter()
+            .map(|view| lines(view, &self.measurement, &self.tags))
+            .collect::<String>();
+        let result = match self.url.strip_prefix("udp://") {
+            Some(address) => send_udp(&mut self.socket, address, &lines).await,
+            None => post(self.client.get()?, &self.url, self.token.as_deref(), lines).await,
+        };
+        if result.is_err() {
+            self.socket = None;
+        }
+        result
+    }
+}
+
+async fn send_udp(socket: &mut Option<UdpSocket>, address: &str, lines: &str) -> Result<()> {
+    if socket.is_none() {
+        let udp = UdpSocket::bind("0.0.0.0:0").await?;
+        udp.connect(address).await?;
+        *socket = Some(udp);
+    }
+    let socket = socket.as_ref().unwrap();
+    let mut datagram = String::new();
+    for line in lines.split_inclusive('\n') {
+        if !datagram.is_empty() && datagram.len() + line.len() > DATAGRAM_SIZE {
+            socket.send(datagram.as_bytes()).await?;
+            datagram.clear();
+        }
+        datagram.push_str(line);
+    }
+    if !datagram.is_empty() {
+        socket.send(datagram.as_bytes()).await?;
+    }
+    Ok(())
+}
+
+async fn post(client: &reqwest::Client, url: &str, token: Option<&str>, lines: String) -> Result<()> {
+    let mut request = client
+        .post(url)
+        .header("Content-Type", "text/plain; charset=utf-8")
+        .body(lines);
+    if let Some(token) = token {
+        request = request.header("Authorization", format!("Token {}", token));
+    }
+    let response = request.send().await?;
+    let status = response.status();
+    if !status.is_success() {
+        let text = response.text().await.unwrap_or_default();
+        return Err(anyhow!("InfluxDB write failed with {}: {}", status, text.trim_end()));
+    }
+    Ok(())
+}
+
+fn escape_measurement(value: &str) -> String {
+    value.replace(',', "\\,").replace(' ', "\\ ")
+}
+
+fn escape_tag(value: &str) -> String {
+    value.replace(',', "\\,").replace('=', "\\=").replace(' ', "\\ ")
+}
@@ -0,0 +1,58 @@
+use std::path::Path;
+
+/// Metadata encoded by kubelet in the name of a container log file: `<pod>_<namespace>_<container>-<id>.log`.
+pub(crate) struct ContainerLog {
+    pub(crate) pod:       String,
+    pub(crate) namespace: String,
+    pub(crate) container: String,
+}
+
+impl ContainerLog {
+    pub(crate) fn from_path(path: &Path) -> Option<Self> {
+        if path.extension()? != "log" {
+            return None;
+        }
+
+        let stem = path.file_stem()?.to_str()?;
+        let mut parts = stem.splitn(3, '_');
+        let pod = parts.next()?;
+        let namespace = parts.next()?;
+        // Container names may contain dashes, the ID may not.
+        let (container, _id) = parts.next()?.rsplit_once('-')?;
+
+        Some(Self {
+            pod:       pod.to_string(),
+            namespace: namespace.to_string(),
+            container: container.to_string(),
+        })
+    }
+
+    pub(crate) fn source_name(&self) -> String {
+        format!("{}/{}/{}", self.namespace, self.pod, self.container)
+    }
+}
+
+/// Extracts application lines from the CRI log format: `<timestamp> <stream> <P|F> <content>`. Long lines are split by
+/// the runtime into partial (`P`) chunks terminated by a final (`F`) one, they are joined back here.
+#[derive(Default)]
+pub(crate) struct CriAssembler {
+    partial: String,
+}
+
+impl CriAssembler {
+    /// Returns a complete application line, if there is one.
+    pub(crate) fn push(&mut self, line: &str) -> Option<String> {
+        let mut fields = line.splitn(4, ' ');
+        let (_time, _stream, tag) = (fields.next()?, fields.next()?, fields.next()?);
+        let content = fields.next().unwrap_or("");
+
+        self.partial.push_str(content);
+
+        if tag == "P" {
+            None
+        }
+        else {
+            Some(std::mem::take(&mut self.partial))
+        }
+    }
+}
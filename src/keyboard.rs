@@ -0,0 +1,67 @@
+use std::{
+    io::{self, IsTerminal},
+    sync::OnceLock,
+    thread,
+};
+
+use anyhow::Result;
+use console::{Key, Term};
+
+// How long a key is waited for before checking again; the terminal is in raw mode only while a key is being read.
+const POLL_TIMEOUT_MS: libc::c_int = 200;
+
+// Terminal settings to restore on shutdown.
+static ORIGINAL: OnceLock<libc::termios> = OnceLock::new();
+
+/// Read key presses from the terminal on standard input in a background thread and pass them to `on_key`. Does
+/// nothing if standard input is not a terminal, like when logs are piped in.
+pub(crate) fn spawn<F>(term: Term, mut on_key: F) -> Result<()>
+where
+    F: FnMut(Key) + Send + 'static,
+{
+    if !io::stdin().is_terminal() {
+        return Ok(());
+    }
+
+    // Keys must be available without waiting for Enter and must not be echoed. Ctrl-C still raises SIGINT.
+    let mut termios = std::mem::MaybeUninit::uninit();
+    if unsafe { libc::tcgetattr(libc::STDIN_FILENO, termios.as_mut_ptr()) } < 0 {
+        return Err(io::Error::last_os_error().into());
+    }
+    let original = unsafe { termios.assume_init() };
+    let mut termios = original;
+    termios.c_lflag &= !(libc::ICANON | libc::ECHO);
+    termios.c_cc[libc::VMIN] = 1;
+    termios.c_cc[libc::VTIME] = 0;
+    if unsafe { libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &termios) } < 0 {
+        return Err(io::Error::last_os_error().into());
+    }
+    let _ = ORIGINAL.set(original);
+
+    thread::spawn(move || loop {
+        let mut fds = libc::pollfd {
+            fd:      libc::STDIN_FILENO,
+            events:  libc::POLLIN,
+            revents: 0,
+        };
+        match unsafe { libc::poll(&mut fds, 1, POLL_TIMEOUT_MS) } {
+            0 => continue,
+            n if n < 0 && io::Error::last_os_error().kind() == io::ErrorKind::Interrupted => continue,
+            n if n < 0 => break,
+            _ => (),
+        }
+        match term.read_key() {
+            Ok(key) => on_key(key),
+            Err(_) => break,
+        }
+    });
+
+    Ok(())
+}
+
+/// Return the terminal to the settings it had before `spawn`.
+pub(crate) fn restore() {
+    if let Some(original) = ORIGINAL.get() {
+        unsafe { libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, original) };
+    }
+}
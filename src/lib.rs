@@ -0,0 +1,143 @@
+//! Real-time log analyzer. Besides the command line tool, the crate provides [`StatsEngine`] to embed the analyzer
+//! into other services.
+
+mod alert;
+mod app;
+mod attacks;
+mod baseline;
+mod check;
+#[cfg(feature = "cloudwatch")]
+mod cloudwatch;
+mod config;
+mod context;
+mod daemon;
+mod diff;
+mod docker;
+mod encoding;
+mod engine;
+mod explain;
+mod escalate;
+mod events;
+#[cfg(feature = "eventhubs")]
+mod eventhubs;
+mod failures;
+#[cfg(feature = "ffi")]
+pub mod ffi;
+mod filter;
+mod format;
+mod glob;
+mod heartbeat;
+mod http;
+#[cfg(feature = "influx")]
+mod influx;
+mod k8s;
+mod keyboard;
+mod listen;
+mod maintenance;
+mod merge;
+#[cfg(feature = "mqtt")]
+mod mqtt;
+#[cfg(feature = "nats")]
+mod nats;
+mod notify;
+mod output;
+#[cfg(feature = "paging")]
+mod paging;
+mod panic;
+mod parser;
+#[cfg(feature = "sql")]
+mod query;
+#[cfg(feature = "pubsub")]
+mod pubsub;
+mod ratelimit;
+mod reader;
+#[cfg(feature = "protobuf")]
+mod record;
+mod redact;
+#[cfg(feature = "redis")]
+mod redis_stream;
+mod remote_write;
+mod render;
+mod rotate;
+#[cfg(feature = "s3")]
+mod s3;
+mod seconds;
+mod seek;
+mod session;
+#[cfg(feature = "share")]
+mod share;
+#[cfg(feature = "simulation")]
+mod simulation;
+mod sink;
+mod slo;
+mod spool;
+mod stats;
+mod summary;
+mod suspicious;
+mod systemd;
+mod tail;
+#[cfg(feature = "tls")]
+mod tls;
+mod types;
+
+pub use engine::StatsEngine;
+pub use qnode_logproc_core::{
+    Context, Example, FileProgress, Level, MessageCount, MessageRate, ParsedLine as Record, SnapshotView,
+};
+
+/// Entry point of the command line tool.
+#[doc(hidden)]
+pub fn run() {
+    let mut config = <config::Config as clap::Parser>::parse();
+    if let Err(err) = config
+        .add_builtin_rules()
+        .and_then(|()| config.add_file_rules())
+        .and_then(|()| config.validate())
+    {
+        <config::Config as clap::CommandFactory>::command()
+            .error(clap::error::ErrorKind::ArgumentConflict, err)
+            .exit();
+    }
+
+    if let Some(ref command) = config.command {
+        let problems = match command {
+            config::Command::Check(args) => tokio::runtime::Builder::new_current_thread()
+                .enable_all()
+                .build()
+                .expect("Failed to start async runtime")
+                .block_on(check::run(&config, args)),
+            config::Command::Diff(args) => diff::run(&config, args),
+            config::Command::Merge(args) => merge::run(&config, args),
+            config::Command::Playback(args) => session::play(&config, args),
+            config::Command::Explain(args) => args
+                .lines
+                .iter()
+                .map(|line| explain::explain(&config, &args.source, line, ""))
+                .try_fold(0, |problems, made| made.map(|made| problems + usize::from(!made))),
+        };
+        let problems = problems.unwrap_or_else(|err| {
+            eprintln!("{:#}", err);
+            1
+        });
+        if problems > 0 {
+            eprintln!("Problems found: {}", problems);
+            std::process::exit(1);
+        }
+        return;
+    }
+
+    // Forking must happen before the runtime spawns its threads.
+    if config.daemon {
+        daemon::daemonize(&config).expect("Failed to daemonize");
+    }
+
+    if let Some(ref pid_file) = config.pid_file {
+        daemon::write_pid_file(pid_file).expect("Failed to write PID file");
+    }
+
+    tokio::runtime::Builder::new_multi_thread()
+        .enable_all()
+        .build()
+        .expect("Failed to start async runtime")
+        .block_on(async { app::App::run().await.expect("Application must not fail") });
+}
@@ -0,0 +1,43 @@
+use std::{fmt, str::FromStr};
+
+use anyhow::{anyhow, Result};
+
+/// Where `--listen` takes lines from: `tcp://HOST:PORT` or `udp://HOST:PORT`. An empty host stands for all
+/// interfaces.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum ListenAddress {
+    Tcp(String),
+    /// Syslog datagrams, a line each
+    Udp(String),
+}
+
+impl FromStr for ListenAddress {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let (scheme, address) = s
+            .split_once("://")
+            .ok_or_else(|| anyhow!("Expected tcp://HOST:PORT or udp://HOST:PORT, got '{}'", s))?;
+        let (host, port) = address
+            .rsplit_once(':')
+            .ok_or_else(|| anyhow!("No port in listen address '{}'", s))?;
+        port.parse::<u16>()
+            .map_err(|_| anyhow!("Invalid port in listen address '{}'", s))?;
+        let host = if host.is_empty() { "0.0.0.0" } else { host };
+        let address = format!("{}:{}", host, port);
+        match scheme {
+            "tcp" => Ok(Self::Tcp(address)),
+            "udp" => Ok(Self::Udp(address)),
+            _ => Err(anyhow!("Unsupported listen address '{}', expected tcp:// or udp://", s)),
+        }
+    }
+}
+
+impl fmt::Display for ListenAddress {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Tcp(address) => write!(f, "tcp://{}", address),
+            Self::Udp(address) => write!(f, "udp://{}", address),
+        }
+    }
+}
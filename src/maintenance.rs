@@ -0,0 +1,119 @@
+use std::str::FromStr;
+
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, Datelike, Duration, Local, NaiveTime, Timelike};
+
+const DAYS: [&str; 7] = ["mon", "tue", "wed", "thu", "fri", "sat", "sun"];
+
+#[derive(Debug, Clone)]
+enum Schedule {
+    /// Every listed day, indexed from Monday, between the times. The end before the start means the next day.
+    Daily {
+        days:  [bool; 7],
+        start: NaiveTime,
+        end:   NaiveTime,
+    },
+    /// From the start of the program until the time
+    Until(DateTime<Local>),
+}
+
+/// A period during which alerting is suppressed: either recurring, like `mon-fri 02:00-03:00`, or ad-hoc from the
+/// start of the program, like `for 30m`.
+#[derive(Debug, Clone)]
+pub(crate) struct MaintenanceWindow {
+    schedule: Schedule,
+}
+
+impl MaintenanceWindow {
+    pub(crate) fn is_active(&self, now: DateTime<Local>) -> bool {
+        match self.schedule {
+            Schedule::Until(until) => now < until,
+            Schedule::Daily { days, start, end } => {
+                let time = now.time().with_nanosecond(0).unwrap_or(now.time());
+                let today = now.weekday().num_days_from_monday() as usize;
+                let yesterday = (today + 6) % 7;
+                if start <= end {
+                    days[today] && time >= start && time < end
+                }
+                else {
+                    (days[today] && time >= start) || (days[yesterday] && time < end)
+                }
+            }
+        }
+    }
+}
+
+impl FromStr for MaintenanceWindow {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let spec = s.trim();
+        let schedule = if let Some(duration) = spec.strip_prefix("for ") {
+            Schedule::Until(Local::now() + parse_duration(duration.trim())?)
+        }
+        else {
+            let (days, range) = match spec.rsplit_once(' ') {
+                Some((days, range)) => (parse_days(days.trim())?, range),
+                None => ([true; 7], spec),
+            };
+            let (start, end) = range
+                .split_once('-')
+                .ok_or_else(|| anyhow!("Expected [DAYS] HH:MM-HH:MM or 'for DURATION', got '{}'", s))?;
+            Schedule::Daily {
+                days,
+                start: parse_time(start)?,
+                end: parse_time(end)?,
+            }
+        };
+
+        Ok(Self { schedule })
+    }
+}
+
+// Days are names like `mon`, ranges like `mon-fri` or `*` for every day, separated with commas.
+pub(crate) fn parse_days(s: &str) -> Result<[bool; 7]> {
+    let day = |name: &str| {
+        DAYS.iter()
+            .position(|day| name.trim().eq_ignore_ascii_case(day))
+            .ok_or_else(|| anyhow!("Unknown day '{}', expected one of {}", name, DAYS.join(", ")))
+    };
+
+    let mut days = [false; 7];
+    for part in s.split(',') {
+        match part.split_once('-') {
+            _ if part.trim() == "*" => days = [true; 7],
+            Some((from, to)) => {
+                let (from, to) = (day(from)?, day(to)?);
+                let mut d = from;
+                loop {
+                    days[d] = true;
+                    if d == to {
+                        break;
+                    }
+                    d = (d + 1) % 7;
+                }
+            }
+            None => days[day(part)?] = true,
+        }
+    }
+    Ok(days)
+}
+
+pub(crate) fn parse_time(s: &str) -> Result<NaiveTime> {
+    NaiveTime::parse_from_str(s.trim(), "%H:%M").map_err(|e| anyhow!("Bad time '{}', expected HH:MM: {}", s, e))
+}
+
+pub(crate) fn parse_duration(s: &str) -> Result<Duration> {
+    let expected = || anyhow!("Expected a duration like 30m, got '{}'", s);
+    let (at, _) = s.char_indices().next_back().ok_or_else(expected)?;
+    let (num, unit) = s.split_at(at);
+    let num: i64 = num.parse().map_err(|_| expected())?;
+    let duration = match unit {
+        "s" => Duration::try_seconds(num),
+        "m" => Duration::try_minutes(num),
+        "h" => Duration::try_hours(num),
+        "d" => Duration::try_days(num),
+        _ => return Err(anyhow!("Unknown duration unit '{}', expected s, m, h or d", unit)),
+    };
+    duration.ok_or_else(|| anyhow!("Duration '{}' is too long", s))
+}
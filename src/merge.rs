@@ -0,0 +1,38 @@
+use std::{fs, path::Path};
+
+use anyhow::{anyhow, Result};
+use qnode_logproc_core::{SnapshotView, StatsWindow};
+
+use crate::{
+    config::{Config, MergeArgs, RendererKind},
+    render::{JsonRenderer, PlainTextRenderer, Renderer, ReportOptions, Target},
+};
+
+/// Run the `merge` command: combine the latest snapshot of every file into one and render it like the final report
+/// of a session.
+pub(crate) fn run(config: &Config, args: &MergeArgs) -> Result<usize> {
+    let views = args.files.iter().map(|path| latest_snapshot(path)).collect::<Result<Vec<_>>>()?;
+    let view = SnapshotView::merge(views);
+    // The report only needs the window for what the view doesn't carry.
+    let window = StatsWindow::default();
+    let options = ReportOptions::new(config);
+    for spec in config.renderers() {
+        let target = Target::new(spec.path.clone(), config.rotation());
+        let renderer: Box<dyn Renderer> = match spec.kind {
+            RendererKind::Json => Box::new(JsonRenderer::new(target)),
+            // There is nothing to redraw, so the terminal renderers print the report as text.
+            _ => Box::new(PlainTextRenderer::new(options.clone(), target)),
+        };
+        renderer.render(&view, &window, &[])?;
+    }
+    Ok(0)
+}
+
+// The last snapshot of a `--jsonl` file, or the one of a file with a single JSON document.
+fn latest_snapshot(path: &Path) -> Result<SnapshotView> {
+    let content = fs::read_to_string(path).map_err(|e| anyhow!("Can't read {:?}: {}", path, e))?;
+    let last = content.lines().rev().find(|line| !line.trim().is_empty()).unwrap_or_default();
+    serde_json::from_str(last)
+        .or_else(|_| serde_json::from_str(&content))
+        .map_err(|e| anyhow!("No snapshot in {:?}: {}", path, e))
+}
@@ -0,0 +1,145 @@
+use std::{fmt, str::FromStr, sync::Arc};
+
+use anyhow::{anyhow, Result};
+use rumqttc::{AsyncClient, Event, EventLoop, MqttOptions, Packet, SubscribeFilter, TlsConfiguration, Transport};
+
+use crate::{failures::Failures, tls::ClientTls};
+
+// Capacity of the request channel between the client and the event loop.
+const REQUEST_CAPACITY: usize = 100;
+const KEEP_ALIVE: tokio::time::Duration = tokio::time::Duration::from_secs(30);
+// How long to wait before reconnecting to the broker.
+const RECONNECT_INTERVAL: tokio::time::Duration = tokio::time::Duration::from_secs(5);
+
+/// `HOST[:PORT]` of an MQTT broker, `mqtts://HOST[:PORT]` for one connected with TLS.
+#[derive(Debug, Clone)]
+pub(crate) struct MqttBroker {
+    pub(crate) host: String,
+    pub(crate) port: u16,
+    pub(crate) tls:  bool,
+}
+
+impl FromStr for MqttBroker {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let (address, tls) = match s.split_once("://") {
+            Some(("mqtt", address)) => (address, false),
+            Some(("mqtts", address)) => (address, true),
+            Some(_) => return Err(anyhow!("Expected mqtt:// or mqtts:// in MQTT broker address '{}'", s)),
+            None => (s, false),
+        };
+        let (host, port) = match address.rsplit_once(':') {
+            Some((host, port)) => (
+                host,
+                port.parse().map_err(|_| anyhow!("Invalid port in MQTT broker address '{}'", s))?,
+            ),
+            None if tls => (address, 8883),
+            None => (address, 1883),
+        };
+        if host.is_empty() {
+            return Err(anyhow!("No host in MQTT broker address '{}'", s));
+        }
+        Ok(Self {
+            host: host.to_string(),
+            port,
+            tls,
+        })
+    }
+}
+
+impl fmt::Display for MqttBroker {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let scheme = if self.tls { "mqtts" } else { "mqtt" };
+        write!(f, "{}://{}:{}", scheme, self.host, self.port)
+    }
+}
+
+/// A message published to one of the subscribed topics.
+pub(crate) struct MqttMessage {
+    pub(crate) topic:   String,
+    pub(crate) payload: Vec<u8>,
+}
+
+/// Subscribes to the topics and receives messages published to them. Subscriptions are renewed whenever the
+/// connection to the broker is re-established.
+pub(crate) struct MqttInput {
+    client:     AsyncClient,
+    event_loop: EventLoop,
+    topics:     Vec<SubscribeFilter>,
+    failures:   Arc<Failures>,
+    subject:    String,
+    connected:  bool,
+}
+
+impl MqttInput {
+    pub(crate) fn new(
+        broker: &MqttBroker,
+        client_id: &str,
+        credentials: Option<(&str, &str)>,
+        tls: &ClientTls,
+        topics: &[String],
+        qos: u8,
+        failures: Arc<Failures>,
+    ) -> Result<Self> {
+        let qos = rumqttc::qos(qos).map_err(|e| anyhow!("Invalid MQTT QoS: {}", e))?;
+        let mut options = MqttOptions::new(client_id, &broker.host, broker.port);
+        options.set_keep_alive(KEEP_ALIVE);
+        if let Some((username, password)) = credentials {
+            options.set_credentials(username, password);
+        }
+        if broker.tls {
+            let config = TlsConfiguration::Rustls(Arc::new(tls.client_config()?));
+            options.set_transport(Transport::tls_with_config(config));
+        }
+        let (client, event_loop) = AsyncClient::new(options, REQUEST_CAPACITY);
+
+        Ok(Self {
+            client,
+            event_loop,
+            topics: topics
+                .iter()
+                .map(|topic| SubscribeFilter::new(topic.clone(), qos))
+                .collect(),
+            failures,
+            subject: format!("MQTT input from {}", broker),
+            connected: false,
+        })
+    }
+
+    /// Wait for the next message. Connection failures are recorded but not returned since the broker is expected to
+    /// come back. With `idle` given, `None` is returned once no message arrives for that long while connected; the
+    /// time spent connecting or waiting to reconnect doesn't count.
+    pub(crate) async fn next(&mut self, idle: Option<tokio::time::Duration>) -> Result<Option<MqttMessage>> {
+        let mut deadline = tokio::time::Instant::now() + idle.unwrap_or_default();
+        loop {
+            let event = match idle {
+                Some(_) if self.connected => match tokio::time::timeout_at(deadline, self.event_loop.poll()).await {
+                    Ok(event) => event,
+                    Err(_) => return Ok(None),
+                },
+                _ => self.event_loop.poll().await,
+            };
+            match event {
+                Ok(Event::Incoming(Packet::ConnAck(_))) => {
+                    self.failures.recover(&self.subject);
+                    self.client.try_subscribe_many(self.topics.clone())?;
+                    self.connected = true;
+                    deadline = tokio::time::Instant::now() + idle.unwrap_or_default();
+                }
+                Ok(Event::Incoming(Packet::Publish(publish))) => {
+                    return Ok(Some(MqttMessage {
+                        topic:   publish.topic,
+                        payload: publish.payload.to_vec(),
+                    }));
+                }
+                Ok(_) => (),
+                Err(err) => {
+                    self.connected = false;
+                    self.failures.fail(&self.subject, err);
+                    tokio::time::sleep(RECONNECT_INTERVAL).await;
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,183 @@
+use std::{
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use anyhow::{anyhow, Result};
+use async_nats::{
+    jetstream::{
+        self,
+        consumer::{pull, AckPolicy, DeliverPolicy},
+    },
+    Client, ConnectOptions, Subscriber,
+};
+use futures::StreamExt;
+use qnode_logproc_core::SnapshotView;
+
+use crate::{
+    config::Config,
+    sink::{Delivery, Sink, SNAPSHOT_BUFFER},
+    tls::ClientTls,
+};
+
+// Snapshots queued behind a slow publish are published together.
+const PUBLISH_BATCH: usize = 16;
+
+/// Connect to the server, with TLS for `tls://` URLs or if the server asks for it. With `retry` the connection is
+/// established in the background, the client is usable immediately.
+pub(crate) async fn connect(url: &str, creds: Option<&Path>, tls: &ClientTls, retry: bool) -> Result<Client> {
+    let mut options = ConnectOptions::new().tls_client_config(tls.client_config()?);
+    if retry {
+        options = options.retry_on_initial_connect();
+    }
+    if let Some(creds) = creds {
+        options = options.credentials_file(creds).await?;
+    }
+    options
+        .connect(url)
+        .await
+        .map_err(|e| anyhow!("Can't connect to NATS at {}: {}", url, e))
+}
+
+/// A message received from NATS.
+pub(crate) struct NatsMessage {
+    pub(crate) subject:   String,
+    pub(crate) payload:   Vec<u8>,
+    /// When JetStream stored the message, or when it was received for core NATS subscriptions
+    pub(crate) recv_time: i64,
+    ack:                  Option<jetstream::Message>,
+}
+
+impl NatsMessage {
+    /// Acknowledge a JetStream message. Core NATS messages need no acknowledgement.
+    pub(crate) async fn ack(self) -> Result<()> {
+        if let Some(message) = self.ack {
+            message.ack().await.map_err(|e| anyhow!("Failed to ack NATS message: {}", e))?;
+        }
+        Ok(())
+    }
+}
+
+/// Either a plain subject subscription or a durable JetStream pull consumer.
+pub(crate) enum NatsInput {
+    Core(Subscriber),
+    JetStream(Box<pull::Stream>),
+}
+
+impl NatsInput {
+    /// Subscribe to the subject, wildcards allowed. Messages published while not subscribed are lost.
+    pub(crate) async fn subscribe(client: &Client, subject: &str) -> Result<Self> {
+        Ok(Self::Core(client.subscribe(subject.to_string()).await?))
+    }
+
+    /// Consume messages of the stream matching the subject with a durable consumer. The consumer is created if
+    /// missing and then starts from the beginning of the stream; otherwise it resumes where it stopped.
+    pub(crate) async fn jetstream(client: &Client, stream: &str, durable: &str, subject: &str) -> Result<Self> {
+        let stream = jetstream::new(client.clone())
+            .get_stream(stream)
+            .await
+            .map_err(|e| anyhow!("Can't get JetStream stream '{}': {}", stream, e))?;
+        let consumer = stream
+            .get_or_create_consumer(
+                durable,
+                pull::Config {
+                    durable_name: Some(durable.to_string()),
+                    filter_subject: subject.to_string(),
+                    deliver_policy: DeliverPolicy::All,
+                    ack_policy: AckPolicy::Explicit,
+                    ..Default::default()
+                },
+            )
+            .await
+            .map_err(|e| anyhow!("Can't create JetStream consumer '{}': {}", durable, e))?;
+        Ok(Self::JetStream(Box::new(consumer.messages().await?)))
+    }
+
+    /// Returns `None` when the subscription is closed.
+    pub(crate) async fn next(&mut self) -> Result<Option<NatsMessage>> {
+        match self {
+            Self::Core(subscriber) => Ok(subscriber.next().await.map(|message| NatsMessage {
+                subject:   message.subject.to_string(),
+                payload:   message.payload.to_vec(),
+                recv_time: chrono::Utc::now().timestamp_millis(),
+                ack:       None,
+            })),
+            Self::JetStream(messages) => {
+                let Some(message) = messages.next().await
+                else {
+                    return Ok(None);
+                };
+                let message = message.map_err(|e| anyhow!("JetStream error: {}", e))?;
+                let recv_time = message.info().map_or_else(
+                    |_| chrono::Utc::now().timestamp_millis(),
+                    |info| (info.published.unix_timestamp_nanos() / 1_000_000) as i64,
+                );
+                Ok(Some(NatsMessage {
+                    subject: message.subject.to_string(),
+                    payload: message.payload.to_vec(),
+                    recv_time,
+                    ack: Some(message),
+                }))
+            }
+        }
+    }
+}
+
+/// Publishes statistics snapshots as JSON to the subject of `--nats-output`. With a JetStream stream bound to the
+/// subject they are persisted too.
+pub(crate) struct NatsSink {
+    url:     String,
+    creds:   Option<PathBuf>,
+    tls:     ClientTls,
+    subject: String,
+    /// Connected with the first snapshot
+    client:  Option<Client>,
+}
+
+impl NatsSink {
+    pub(crate) fn from_config(config: &Config) -> Option<Self> {
+        Some(Self {
+            url:     config.nats_url.clone(),
+            creds:   config.nats_creds.clone(),
+            tls:     ClientTls::from_config(config),
+            subject: config.nats_output.clone()?,
+            client:  None,
+        })
+    }
+}
+
+impl Sink for NatsSink {
+    type Item = Arc<SnapshotView>;
+
+    fn name(&self) -> String {
+        format!("NATS output to {}", self.subject)
+    }
+
+    fn delivery(&self) -> Delivery {
+        Delivery {
+            batch: PUBLISH_BATCH,
+            buffer: SNAPSHOT_BUFFER,
+            ..Delivery::default()
+        }
+    }
+
+    async fn deliver(&mut self, batch: &[Arc<SnapshotView>]) -> Result<()> {
+        if self.client.is_none() {
+            self.client = Some(connect(&self.url, self.creds.as_deref(), &self.tls, true).await?);
+        }
+        let client = self.client.as_ref().unwrap();
+        for snapshot in batch {
+            // The client buffers and reconnects by itself, an error means the buffer is full.
+            client
+                .publish(self.subject.clone(), serde_json::to_vec(&**snapshot)?.into())
+                .await?;
+        }
+        Ok(())
+    }
+
+    async fn finish(&mut self) {
+        if let Some(ref client) = self.client {
+            let _ = client.flush().await;
+        }
+    }
+}
@@ -0,0 +1,373 @@
+use std::{
+    fs::OpenOptions,
+    io::Write,
+    path::PathBuf,
+    str::FromStr,
+    sync::Arc,
+    time::Instant,
+};
+
+use anyhow::{anyhow, Result};
+use qnode_logproc_core::SnapshotView;
+use serde::{Deserialize, Serialize};
+use tokio::{net::UdpSocket, time::Duration};
+
+use crate::{
+    alert::{AlertEvent, AlertTemplate, Alerts},
+    config::{AlertSink, AlertSpec, AlertTarget, Config},
+    failures::Failures,
+    output::Publisher,
+    sink::{Delivery, Sink},
+    spool::Spooled,
+};
+#[cfg(feature = "paging")]
+use crate::tls::HttpClient;
+
+// sysUpTime.0 and snmpTrapOID.0 of SNMPv2-MIB, which every trap starts with.
+const SYS_UP_TIME: &[u32] = &[1, 3, 6, 1, 2, 1, 1, 3, 0];
+const SNMP_TRAP_OID: &[u32] = &[1, 3, 6, 1, 6, 3, 1, 1, 4, 1, 0];
+// Alerts kept while a target is slow to take them.
+const QUEUE_LEN: usize = 100;
+// Attempts to send an alert, with the delay doubling after each failure: a minute of them.
+const ATTEMPTS: u32 = 6;
+const FIRST_RETRY_DELAY: Duration = Duration::from_secs(2);
+
+/// An SNMP object identifier, like `1.3.6.1.4.1.8072`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct Oid(pub(crate) Vec<u32>);
+
+impl FromStr for Oid {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let arcs = s
+            .trim_start_matches('.')
+            .split('.')
+            .map(|arc| arc.parse::<u32>())
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|_| anyhow!("Bad OID '{}'", s))?;
+        if arcs.len() < 2 || arcs[0] > 2 || (arcs[0] < 2 && arcs[1] >= 40) {
+            return Err(anyhow!("Bad OID '{}'", s));
+        }
+        Ok(Self(arcs))
+    }
+}
+
+/// An alert to deliver to the targets of its rule.
+#[derive(Debug, Serialize, Deserialize)]
+pub(crate) struct Notification {
+    event: AlertEvent,
+    /// The host the alert is about
+    host:  String,
+}
+
+impl Spooled for Arc<Notification> {
+    fn to_spool(&self) -> Option<String> {
+        serde_json::to_string(&**self).ok()
+    }
+
+    fn from_spool(line: &str) -> Option<Self> {
+        serde_json::from_str(line).ok().map(Arc::new)
+    }
+}
+
+/// What the alerts are sent with.
+#[derive(Clone)]
+struct Settings {
+    nagios_host:   Option<String>,
+    trap_oid:      Oid,
+    #[cfg(feature = "paging")]
+    opsgenie_url:  String,
+    template:      Option<AlertTemplate>,
+    dashboard_url: Option<String>,
+}
+
+impl Settings {
+    fn new(config: &Config) -> Self {
+        Self {
+            nagios_host:   config.nagios_host.clone(),
+            trap_oid:      config.snmp_trap_oid.clone(),
+            #[cfg(feature = "paging")]
+            opsgenie_url:  config.opsgenie_url.clone(),
+            template:      config.alert_template.clone(),
+            dashboard_url: config.dashboard_url.clone(),
+        }
+    }
+
+    // The message of the alert by the template, if there is one.
+    fn message(&self, event: &AlertEvent, host: &str) -> Option<String> {
+        let template = self.template.as_ref()?;
+        Some(template.render(event, host, self.dashboard_url.as_deref()))
+    }
+}
+
+/// Checks the alert rules which have `--alert-to` targets on every snapshot written, independently of the report, and
+/// hands their alerts over to the sinks of the targets.
+pub(crate) struct Notifier {
+    alerts: Alerts,
+    /// The sinks with the targets sending to them
+    sinks:  Vec<(Vec<AlertTarget>, Publisher<Arc<Notification>>)>,
+}
+
+impl Notifier {
+    pub(crate) fn new(config: &Config, failures: Arc<Failures>) -> Option<Self> {
+        if config.alert_to.is_empty() {
+            return None;
+        }
+        Some(Self {
+            alerts: Alerts::new(config.alerts.clone()),
+            sinks:  crate::sink::notification_sinks(config, &failures),
+        })
+    }
+
+    /// Notify about the reloaded alert rules from now on.
+    pub(crate) fn set_rules(&self, rules: &[AlertSpec]) {
+        self.alerts.set_rules(rules.to_vec());
+    }
+
+    pub(crate) fn notify(&self, view: &SnapshotView) {
+        for event in self.alerts.update(view) {
+            let notification = Arc::new(Notification {
+                event,
+                host: view.run.host.clone(),
+            });
+            for (targets, sink) in self.sinks.iter() {
+                if targets.iter().any(|target| target.matches(&notification.event)) {
+                    sink.send(notification.clone());
+                }
+            }
+        }
+    }
+
+    pub(crate) async fn close(self, timeout: tokio::time::Duration) {
+        for (_, sink) in self.sinks {
+            sink.close(timeout).await;
+        }
+    }
+}
+
+/// Sends the alerts to a target of `--alert-to`, one at a time and in order. An alert is retried for about a minute;
+/// one resolving an incident must not overtake the one which opened it.
+pub(crate) struct AlertTargetSink {
+    sink:       AlertSink,
+    settings:   Settings,
+    #[cfg(feature = "paging")]
+    client:     HttpClient,
+    /// For the uptime of the SNMP traps
+    started:    Instant,
+    request_id: i64,
+}
+
+impl AlertTargetSink {
+    pub(crate) fn new(sink: AlertSink, config: &Config) -> Self {
+        Self {
+            sink,
+            settings:   Settings::new(config),
+            #[cfg(feature = "paging")]
+            client:     HttpClient::new(config),
+            started:    Instant::now(),
+            request_id: 0,
+        }
+    }
+
+    async fn send(&mut self, notification: &Notification) -> Result<()> {
+        let Notification { event, host } = notification;
+        let message = self.settings.message(event, host);
+        match self.sink {
+            AlertSink::Snmp {
+                ref address,
+                ref community,
+            } => {
+                self.request_id += 1;
+                let uptime = (self.started.elapsed().as_millis() / 10) as u32;
+                let description = message.unwrap_or_else(|| event.summary());
+                let oid = &self.settings.trap_oid;
+                let trap = snmp_trap(community, self.request_id, uptime, oid, event, &description, host);
+                send_trap(address, trap).await
+            }
+            AlertSink::Nagios(ref path) => {
+                let output = message.unwrap_or_else(|| event.summary());
+                let host = self.settings.nagios_host.as_deref().unwrap_or(host);
+                let command = nagios_command(host, event, &output);
+                let path = path.clone();
+                tokio::task::spawn_blocking(move || write_command(path, command)).await?
+            }
+            #[cfg(feature = "paging")]
+            AlertSink::PagerDuty(ref routing_key) => {
+                let message = message.unwrap_or_else(|| format!("{}: {}", event.name(), event.summary()));
+                let alert = crate::paging::Alert {
+                    event,
+                    message:   &message,
+                    dashboard: self.settings.dashboard_url.as_deref(),
+                    host,
+                };
+                crate::paging::pagerduty(&mut self.client, routing_key, alert).await
+            }
+            #[cfg(feature = "paging")]
+            AlertSink::Opsgenie(ref api_key) => {
+                let message = message.unwrap_or_else(|| format!("{}: {}", event.name(), event.summary()));
+                let alert = crate::paging::Alert {
+                    event,
+                    message:   &message,
+                    dashboard: self.settings.dashboard_url.as_deref(),
+                    host,
+                };
+                crate::paging::opsgenie(&mut self.client, &self.settings.opsgenie_url, api_key, alert).await
+            }
+        }
+    }
+}
+
+impl Sink for AlertTargetSink {
+    type Item = Arc<Notification>;
+
+    // The keys of the paging services are secrets; their last characters tell them apart well enough.
+    fn name(&self) -> String {
+        match self.sink {
+            AlertSink::Snmp { ref address, .. } => format!("SNMP traps to {}", address),
+            AlertSink::Nagios(ref path) => format!("Nagios passive checks to {}", path.display()),
+            #[cfg(feature = "paging")]
+            AlertSink::PagerDuty(ref routing_key) => format!("PagerDuty events to key ...{}", key_tail(routing_key)),
+            #[cfg(feature = "paging")]
+            AlertSink::Opsgenie(ref api_key) => format!("Opsgenie alerts to key ...{}", key_tail(api_key)),
+        }
+    }
+
+    fn delivery(&self) -> Delivery {
+        Delivery {
+            buffer: QUEUE_LEN,
+            attempts: ATTEMPTS,
+            backoff: FIRST_RETRY_DELAY,
+            ..Delivery::default()
+        }
+    }
+
+    async fn deliver(&mut self, batch: &[Arc<Notification>]) -> Result<()> {
+        for notification in batch {
+            self.send(notification).await?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(feature = "paging")]
+fn key_tail(key: &str) -> String {
+    let chars = key.chars().collect::<Vec<_>>();
+    chars[chars.len().saturating_sub(4)..].iter().collect()
+}
+
+async fn send_trap(address: &str, trap: Vec<u8>) -> Result<()> {
+    let target = tokio::net::lookup_host(address)
+        .await?
+        .next()
+        .ok_or_else(|| anyhow!("No address of '{}'", address))?;
+    let socket = UdpSocket::bind(if target.is_ipv4() { "0.0.0.0:0" } else { "[::]:0" }).await?;
+    socket.send_to(&trap, target).await?;
+    Ok(())
+}
+
+// The command file is usually a FIFO, which is only opened once Nagios reads it. It is never created.
+fn write_command(path: PathBuf, command: String) -> Result<()> {
+    let mut file = OpenOptions::new().append(true).open(&path)?;
+    file.write_all(command.as_bytes())?;
+    Ok(())
+}
+
+// `PROCESS_SERVICE_CHECK_RESULT` of the alert, CRITICAL when it fires and OK when it resolves, with the value as
+// performance data.
+fn nagios_command(host: &str, event: &AlertEvent, output: &str) -> String {
+    // Line breaks end the command, semicolons separate its fields and `|` starts the performance data.
+    let clean = |s: &str| s.replace(['\n', '\r'], " ").replace([';', '|'], ",");
+    let (code, state) = if event.firing { (2, "CRITICAL") } else { (0, "OK") };
+    format!(
+        "[{}] PROCESS_SERVICE_CHECK_RESULT;{};{};{};{} - {}|value={}\n",
+        event.timestamp.timestamp(),
+        clean(host),
+        clean(&event.name()),
+        code,
+        state,
+        clean(output),
+        event.value
+    )
+}
+
+// SNMPv2c trap of the alert in BER.
+fn snmp_trap(
+    community: &str,
+    request_id: i64,
+    uptime: u32,
+    trap_oid: &Oid,
+    event: &AlertEvent,
+    description: &str,
+    host: &str,
+) -> Vec<u8> {
+    let under = |arcs: &[u32]| [trap_oid.0.as_slice(), arcs].concat();
+    let varbind = |name: &[u32], value: Vec<u8>| tlv(0x30, &[oid(name), value].concat());
+    let string = |s: &str| tlv(0x04, s.as_bytes());
+
+    let mut varbinds = [
+        varbind(SYS_UP_TIME, integer(0x43, uptime as i64)),
+        varbind(SNMP_TRAP_OID, oid(&under(&[0, if event.firing { 1 } else { 2 }]))),
+        varbind(&under(&[1, 1]), string(&event.name())),
+        varbind(&under(&[1, 2]), string(description)),
+        varbind(&under(&[1, 3]), string(&event.value.to_string())),
+        varbind(&under(&[1, 4]), string(host)),
+    ]
+    .concat();
+    if let Some(ref message) = event.message {
+        varbinds.extend(varbind(&under(&[1, 5]), string(message)));
+    }
+    let pdu = [
+        integer(0x02, request_id),
+        // error-status and error-index
+        integer(0x02, 0),
+        integer(0x02, 0),
+        tlv(0x30, &varbinds),
+    ]
+    .concat();
+    // Version 1 is SNMPv2c.
+    tlv(0x30, &[integer(0x02, 1), string(community), tlv(0xa7, &pdu)].concat())
+}
+
+fn tlv(tag: u8, content: &[u8]) -> Vec<u8> {
+    let mut out = vec![tag];
+    if content.len() < 0x80 {
+        out.push(content.len() as u8);
+    }
+    else {
+        let length = content.len().to_be_bytes();
+        let skip = length.iter().take_while(|&&byte| byte == 0).count();
+        out.push(0x80 | (length.len() - skip) as u8);
+        out.extend_from_slice(&length[skip..]);
+    }
+    out.extend_from_slice(content);
+    out
+}
+
+// The shortest two's complement form.
+fn integer(tag: u8, value: i64) -> Vec<u8> {
+    let bytes = value.to_be_bytes();
+    let mut start = 0;
+    while start < bytes.len() - 1
+        && ((bytes[start] == 0x00 && bytes[start + 1] & 0x80 == 0)
+            || (bytes[start] == 0xff && bytes[start + 1] & 0x80 != 0))
+    {
+        start += 1;
+    }
+    tlv(tag, &bytes[start..])
+}
+
+fn oid(arcs: &[u32]) -> Vec<u8> {
+    let mut content = Vec::new();
+    let first = arcs[0] * 40 + arcs[1];
+    for arc in std::iter::once(first).chain(arcs[2..].iter().copied()) {
+        let mut bytes = vec![(arc & 0x7f) as u8];
+        let mut rest = arc >> 7;
+        while rest > 0 {
+            bytes.push(0x80 | (rest & 0x7f) as u8);
+            rest >>= 7;
+        }
+        content.extend(bytes.into_iter().rev());
+    }
+    tlv(0x06, &content)
+}
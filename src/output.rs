@@ -0,0 +1,653 @@
+use std::{
+    fs,
+    future::Future,
+    path::Path,
+    sync::Arc,
+};
+
+use anyhow::Result;
+use chrono::{DateTime, Local};
+use fieldx_plus::fx_plus;
+use qnode_logproc_core::{ErrorType, GroupCount, SequenceStats, SnapshotView, StatsWindow};
+use tokio::{
+    sync::mpsc::{self, error::TrySendError, Receiver, Sender},
+    task::JoinHandle,
+};
+
+use crate::{
+    app::App,
+    config::{AlertSpec, RendererKind},
+    failures::Failures,
+    render::{
+        JsonRenderer, MinimalRenderer, Pane, PlainTextRenderer, Renderer, ReportOptions, Target, TerminalRenderer,
+    },
+    notify::Notifier,
+    rotate::RotatingFile,
+    slo::BURN_WINDOWS,
+};
+
+// How long to wait on shutdown for background outputs to deliver the last snapshots.
+const CLOSE_TIMEOUT: tokio::time::Duration = tokio::time::Duration::from_secs(5);
+
+/// An output running as a background task, so that network I/O doesn't hold the statistics loop. What the task is
+/// behind with waits in a bounded buffer; items which don't fit are dropped and counted under the subject.
+pub(crate) struct Publisher<T> {
+    subject:  String,
+    tx:       Sender<T>,
+    failures: Arc<Failures>,
+    task:     JoinHandle<()>,
+}
+
+impl<M: Send + 'static> Publisher<M> {
+    /// Spawn the task produced by `publish` from the receiving end of a channel buffering `capacity` items. The task
+    /// is expected to end when the channel is closed.
+    pub(crate) fn spawn<F, T>(subject: String, capacity: usize, failures: Arc<Failures>, publish: F) -> Self
+    where
+        F: FnOnce(Receiver<M>) -> T,
+        T: Future<Output = ()> + Send + 'static,
+    {
+        let (tx, rx) = mpsc::channel(capacity.max(1));
+        Self {
+            subject,
+            tx,
+            failures,
+            task: tokio::spawn(publish(rx)),
+        }
+    }
+
+    pub(crate) fn send(&self, item: M) {
+        // The task only ends when the sender is dropped.
+        if let Err(TrySendError::Full(_)) = self.tx.try_send(item) {
+            self.failures.drop_item(&self.subject);
+        }
+    }
+
+    // Wait for the queued items to be delivered, but not longer than `timeout`.
+    pub(crate) async fn close(self, timeout: tokio::time::Duration) {
+        drop(self.tx);
+        let _ = tokio::time::timeout(timeout, self.task).await;
+    }
+}
+
+/// Manages everything statistics snapshots are presented with: the renderers of the report and the outputs of
+/// snapshot data. Any number of them work at the same time.
+#[fx_plus(agent(App, unwrap(error(anyhow::Error, App::app_is_gone()))), sync, fallible(off, error(anyhow::Error)))]
+pub(crate) struct Output {
+    #[fieldx(lazy, lock, private, get(off), get_mut)]
+    jsonl: Option<RotatingFile>,
+
+    /// Snapshot sinks delivering from background tasks.
+    #[fieldx(lazy, lock, private, get, get_mut)]
+    sinks: Vec<Publisher<Arc<SnapshotView>>>,
+
+    /// Alerts sent to `--alert-to` targets.
+    #[fieldx(lazy, lock, private, get(off), get_mut)]
+    notifier: Option<Notifier>,
+
+    #[fieldx(lazy, fallible, private, get)]
+    renderers: Vec<Box<dyn Renderer>>,
+}
+
+impl Output {
+    /// Render the report with all renderers.
+    pub(crate) fn render(&self, view: &SnapshotView, window: &StatsWindow, panes: &[Pane]) -> Result<()> {
+        for renderer in self.renderers()?.iter() {
+            renderer.render(view, window, panes)?;
+        }
+        Ok(())
+    }
+
+    /// Let the renderers know there is nothing to report yet.
+    pub(crate) fn idle(&self, now: DateTime<Local>) -> Result<()> {
+        for renderer in self.renderers()?.iter() {
+            renderer.idle(now)?;
+        }
+        Ok(())
+    }
+
+    /// Write the snapshot to all configured outputs.
+    pub(crate) fn write(&self, view: &SnapshotView) -> Result<()> {
+        if !self.has_outputs()? {
+            return Ok(());
+        }
+
+        let app = self.app()?;
+        let config = app.config();
+        let json = serde_json::to_value(view)?;
+
+        if let Some(ref notifier) = *self.notifier_mut() {
+            notifier.notify(view);
+        }
+
+        self.write_jsonl(&json)?;
+
+        if let Some(ref path) = config.prometheus_file {
+            Self::write_atomically(path, &Self::prometheus(view))?;
+        }
+
+        let sinks = self.sinks();
+        if !sinks.is_empty() {
+            let view = Arc::new(view.clone());
+            for sink in sinks.iter() {
+                sink.send(view.clone());
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Close the output files so they are re-opened with the next write.
+    pub(crate) fn reopen(&self) -> Result<()> {
+        if let Some(jsonl) = self.jsonl_mut().as_mut() {
+            jsonl.reopen();
+        }
+        for renderer in self.renderers()?.iter() {
+            renderer.reopen();
+        }
+        Ok(())
+    }
+
+    /// Check the alert rules reloaded from the `--rules` file in the report and for the notifications.
+    pub(crate) fn set_alert_rules(&self, rules: &[AlertSpec]) -> Result<()> {
+        if let Some(ref notifier) = *self.notifier_mut() {
+            notifier.set_rules(rules);
+        }
+        for renderer in self.renderers()?.iter() {
+            renderer.set_alert_rules(rules);
+        }
+        Ok(())
+    }
+
+    /// Let the outputs which publish in the background deliver what is already written.
+    pub(crate) async fn close(&self) {
+        let sinks = std::mem::take(&mut *self.sinks_mut());
+        for sink in sinks {
+            sink.close(CLOSE_TIMEOUT).await;
+        }
+        let notifier = self.notifier_mut().take();
+        if let Some(notifier) = notifier {
+            notifier.close(CLOSE_TIMEOUT).await;
+        }
+    }
+
+    pub(crate) fn has_outputs(&self) -> Result<bool> {
+        let app = self.app()?;
+        let config = app.config();
+        let has_outputs =
+            config.jsonl.is_some() || config.prometheus_file.is_some() || !config.alert_to.is_empty();
+        #[cfg(feature = "nats")]
+        let has_outputs = has_outputs || config.nats_output.is_some();
+        #[cfg(feature = "influx")]
+        let has_outputs = has_outputs || config.influx.is_some();
+        Ok(has_outputs)
+    }
+
+    fn write_jsonl(&self, json: &serde_json::Value) -> Result<()> {
+        if let Some(jsonl) = self.jsonl_mut().as_mut() {
+            jsonl.append(format!("{}\n", json).as_bytes())?;
+        }
+        Ok(())
+    }
+
+    fn build_jsonl(&self) -> Option<RotatingFile> {
+        let app = self.app().ok()?;
+        let config = app.config();
+        Some(RotatingFile::new(config.jsonl.clone()?, config.rotation()))
+    }
+
+    fn build_sinks(&self) -> Vec<Publisher<Arc<SnapshotView>>> {
+        self.app()
+            .map_or_else(|_| Vec::new(), |app| crate::sink::snapshot_sinks(&app.config(), &app.failures()))
+    }
+
+    fn build_notifier(&self) -> Option<Notifier> {
+        let app = self.app().ok()?;
+        let config = app.config();
+        Notifier::new(&config, app.failures())
+    }
+
+    // Renderers writing to standard output are skipped when headless.
+    fn build_renderers(&self) -> Result<Vec<Box<dyn Renderer>>> {
+        let app = self.app()?;
+        let config = app.config();
+        let options = ReportOptions::new(&config);
+        let mut renderers: Vec<Box<dyn Renderer>> = Vec::new();
+        for spec in config.renderers() {
+            if spec.path.is_none() && config.is_headless() {
+                continue;
+            }
+            // Without a terminal, like under cron or nohup, there is nothing to draw on and to move the cursor around:
+            // the final report is printed as plain text, the live one is not shown at all.
+            let kind = match spec.kind {
+                RendererKind::Terminal | RendererKind::Minimal if !app.term().is_term() => {
+                    if !config.once {
+                        eprintln!("Standard output is not a terminal, the live report is off");
+                        continue;
+                    }
+                    RendererKind::Plain
+                }
+                kind => kind,
+            };
+            // Errors printed over the live report would scroll it away, it shows the failures itself.
+            if matches!(kind, RendererKind::Terminal | RendererKind::Minimal) {
+                app.failures().set_quiet(true);
+            }
+            let target = Target::new(spec.path, config.rotation());
+            renderers.push(match kind {
+                RendererKind::Terminal => {
+                    let (stats, dump_stats, export_stats) =
+                        (app.stats()?.clone(), app.stats()?.clone(), app.stats()?.clone());
+                    let has_outputs = self.has_outputs()?;
+                    #[cfg(feature = "share")]
+                    let sharer = crate::share::Sharer::new(&config);
+                    #[cfg(feature = "sql")]
+                    let on_query = {
+                        let query_stats = app.stats()?.clone();
+                        Some(move |query: &str| query_stats.query(query))
+                    };
+                    #[cfg(not(feature = "sql"))]
+                    let on_query = None::<fn(&str) -> Vec<String>>;
+                    Box::new(TerminalRenderer::new(
+                        app.term().clone(),
+                        options.clone(),
+                        config.once,
+                        config.history,
+                        move |message| stats.mute_message(message),
+                        move || {
+                            if let Err(err) = dump_stats.dump_dictionary() {
+                                eprintln!("Failed to write dictionary: {:?}", err);
+                            }
+                        },
+                        move |notice| {
+                            if let Err(err) = export_stats.dump_snapshot() {
+                                *notice.lock().unwrap() = vec![format!("Failed to export the snapshot: {:#}", err)];
+                                return;
+                            }
+                            #[cfg(feature = "share")]
+                            if let Some(ref sharer) = sharer {
+                                sharer.share(export_stats.latest_snapshot(), notice);
+                                return;
+                            }
+                            *notice.lock().unwrap() = vec![if has_outputs {
+                                "The snapshot is written to the outputs.".to_string()
+                            }
+                            else {
+                                "No outputs to export the snapshot to.".to_string()
+                            }];
+                        },
+                        on_query,
+                    )?)
+                }
+                RendererKind::Minimal => {
+                    Box::new(MinimalRenderer::new(app.term().clone(), options.clone(), config.once))
+                }
+                RendererKind::Plain => Box::new(PlainTextRenderer::new(options.clone(), target)),
+                RendererKind::Json => Box::new(JsonRenderer::new(target)),
+            });
+        }
+
+        Ok(renderers)
+    }
+
+    // Readers, like node_exporter textfile collector, must never see a partially written file.
+    fn write_atomically(path: &Path, content: &str) -> Result<()> {
+        let mut tmp_path = path.as_os_str().to_owned();
+        tmp_path.push(".tmp");
+        fs::write(&tmp_path, content)?;
+        fs::rename(&tmp_path, path)?;
+        Ok(())
+    }
+
+    pub(crate) fn prometheus(view: &SnapshotView) -> String {
+        let mut out = String::new();
+
+        let mut metric = |name: &str, kind: &str, help: &str, samples: Vec<(String, f64)>| {
+            out.push_str(&format!("# HELP qnode_logproc_{} {}\n", name, help));
+            out.push_str(&format!("# TYPE qnode_logproc_{} {}\n", name, kind));
+            for (labels, value) in samples {
+                out.push_str(&format!("qnode_logproc_{}{} {}\n", name, labels, value));
+            }
+        };
+
+        metric(
+            "run_info",
+            "gauge",
+            "The run the statistics are of, always 1.",
+            vec![(
+                format!(
+                    "{{version=\"{}\",host=\"{}\",config_hash=\"{}\"}}",
+                    prometheus_escape(&view.run.version),
+                    prometheus_escape(&view.run.host),
+                    prometheus_escape(&view.run.config_hash)
+                ),
+                1.0,
+            )],
+        );
+        metric(
+            "start_time_seconds",
+            "gauge",
+            "Start time of the run since the epoch.",
+            vec![(String::new(), view.run.started.timestamp() as f64)],
+        );
+        metric(
+            "window_entries",
+            "gauge",
+            "Number of entries in the sliding window.",
+            vec![(String::new(), view.entries as f64)],
+        );
+        metric(
+            "rate",
+            "gauge",
+            "Average entries per second over the window.",
+            vec![(String::new(), view.rate)],
+        );
+        metric(
+            "peak_rate",
+            "gauge",
+            "Peak entries per second.",
+            vec![(String::new(), view.peak_rate)],
+        );
+        metric(
+            "burstiness",
+            "gauge",
+            "The 99th percentile of entries per bucket of the window against their mean.",
+            vec![(String::new(), view.burstiness())],
+        );
+        metric(
+            "avg_lag_seconds",
+            "gauge",
+            "Average difference between receive and log times of entries in the window.",
+            vec![(String::new(), view.avg_lag)],
+        );
+        metric(
+            "level_entries",
+            "gauge",
+            "Number of entries in the window per level.",
+            vec![
+                ("{level=\"ERROR\"}".to_string(), view.errors as f64),
+                ("{level=\"INFO\"}".to_string(), view.infos as f64),
+                ("{level=\"DEBUG\"}".to_string(), view.debugs as f64),
+            ],
+        );
+        metric(
+            "malformed_entries",
+            "gauge",
+            "Number of malformed lines in the window.",
+            vec![(String::new(), view.malformed as f64)],
+        );
+        metric(
+            "rejected_entries",
+            "gauge",
+            "Number of lines in the window which didn't make a record, per cause.",
+            ErrorType::ALL
+                .into_iter()
+                .map(|err_type| (format!("{{type=\"{}\"}}", err_type.name()), view.rejected(err_type) as f64))
+                .collect(),
+        );
+        metric(
+            "distinct_ips",
+            "gauge",
+            "Number of distinct client IPs in the window.",
+            vec![(String::new(), view.distinct_ips as f64)],
+        );
+        metric(
+            "endpoint_errors",
+            "gauge",
+            "Errors in the window of the endpoints with the most errors.",
+            view.top_endpoints
+                .iter()
+                .map(|top| (format!("{{endpoint=\"{}\"}}", prometheus_escape(&top.endpoint)), top.errors as f64))
+                .collect(),
+        );
+        let sequences = |value: fn(&SequenceStats) -> f64| {
+            view.sequences
+                .iter()
+                .map(|(source, seq)| (format!("{{source=\"{}\"}}", prometheus_escape(source)), value(seq)))
+                .collect()
+        };
+        metric(
+            "sequence_missing",
+            "gauge",
+            "Sequence numbers missing per source since the start.",
+            sequences(|seq| seq.missing as f64),
+        );
+        metric(
+            "sequence_duplicates",
+            "gauge",
+            "Sequence numbers seen more than once per source since the start.",
+            sequences(|seq| seq.duplicates as f64),
+        );
+        metric(
+            "sequence_loss_ratio",
+            "gauge",
+            "Share of the lines sent per source which were not received, by sequence numbers.",
+            sequences(SequenceStats::loss),
+        );
+        metric(
+            "heartbeat_age_seconds",
+            "gauge",
+            "Seconds since the source logged the heartbeat expected by the rule.",
+            view.heartbeats
+                .iter()
+                .map(|heartbeat| {
+                    let labels = format!(
+                        "{{source=\"{}\",rule=\"{}\"}}",
+                        prometheus_escape(&heartbeat.source),
+                        prometheus_escape(&heartbeat.rule)
+                    );
+                    (labels, heartbeat.age_secs)
+                })
+                .collect(),
+        );
+        metric(
+            "affected_requests",
+            "gauge",
+            "Distinct requests, by trace or request ID, with errors in the window.",
+            vec![(String::new(), view.affected_requests as f64)],
+        );
+        metric(
+            "client_errors",
+            "gauge",
+            "Errors in the window of the client families and versions with the most errors.",
+            view.top_clients
+                .iter()
+                .map(|top| {
+                    let labels = format!(
+                        "{{family=\"{}\",version=\"{}\"}}",
+                        prometheus_escape(&top.family),
+                        prometheus_escape(&top.version)
+                    );
+                    (labels, top.errors as f64)
+                })
+                .collect(),
+        );
+        let group_samples = |value: fn(&GroupCount) -> i64| {
+            view.groups
+                .iter()
+                .flat_map(|table| {
+                    table.rows.iter().map(move |row| {
+                        let labels = format!(
+                            "{{group=\"{}\",key=\"{}\"}}",
+                            prometheus_escape(&table.name),
+                            prometheus_escape(&row.key)
+                        );
+                        (labels, value(row) as f64)
+                    })
+                })
+                .collect()
+        };
+        metric(
+            "group_records",
+            "gauge",
+            "Records in the window of the values of the --group-by dimensions with the most errors.",
+            group_samples(|row| row.records),
+        );
+        metric(
+            "group_errors",
+            "gauge",
+            "Errors in the window of the values of the --group-by dimensions with the most errors.",
+            group_samples(|row| row.errors),
+        );
+        metric(
+            "http_status",
+            "gauge",
+            "Number of entries in the window per HTTP status class.",
+            view.statuses
+                .iter()
+                .map(|(class, count)| (format!("{{class=\"{}\"}}", class), *count as f64))
+                .collect(),
+        );
+        metric(
+            "facility_entries",
+            "gauge",
+            "Number of entries in the window per syslog facility and level.",
+            view.facilities
+                .iter()
+                .flat_map(|(facility, count)| {
+                    [("ERROR", count.errors), ("INFO", count.infos), ("DEBUG", count.debugs)]
+                        .into_iter()
+                        .map(move |(level, entries)| {
+                            (format!("{{facility=\"{}\",level=\"{}\"}}", facility, level), entries as f64)
+                        })
+                })
+                .collect(),
+        );
+        metric(
+            "maintenance",
+            "gauge",
+            "1 while a maintenance window suppresses alerting.",
+            vec![(String::new(), view.maintenance as u8 as f64)],
+        );
+        metric(
+            "source_entries",
+            "gauge",
+            "Number of entries in the window per input source.",
+            view.sources
+                .iter()
+                .map(|(source, count)| (format!("{{source=\"{}\"}}", prometheus_escape(source)), *count as f64))
+                .collect(),
+        );
+        metric(
+            "source_clock_skew",
+            "gauge",
+            "Average difference between log and receive times per input source; positive if the source clock is ahead.",
+            view.source_skew
+                .iter()
+                .map(|(source, skew)| (format!("{{source=\"{}\"}}", prometheus_escape(source)), *skew))
+                .collect(),
+        );
+        metric(
+            "sampled_out_total",
+            "counter",
+            "Lines skipped by sampling under overload.",
+            vec![(String::new(), view.sampled_out as f64)],
+        );
+        metric(
+            "below_min_level_total",
+            "counter",
+            "Records dropped for being less severe than --min-level.",
+            vec![(String::new(), view.below_min_level as f64)],
+        );
+        metric(
+            "attack_attempts",
+            "gauge",
+            "Failed authentications of the brute-force attempts, by IP, and of the scans, by message.",
+            view.attacks
+                .iter()
+                .map(|attack| {
+                    let labels = format!(
+                        "{{kind=\"{}\",subject=\"{}\"}}",
+                        attack.kind.name(),
+                        prometheus_escape(&attack.subject)
+                    );
+                    (labels, attack.attempts as f64)
+                })
+                .collect(),
+        );
+        if let Some(typical) = view.hourly.last().and_then(|hourly| hourly.typical) {
+            metric(
+                "typical_rate",
+                "gauge",
+                "Range the rate of the current hour usually falls in at the time of day, by the --baseline.",
+                vec![
+                    ("{bound=\"low\"}".to_string(), typical.low),
+                    ("{bound=\"high\"}".to_string(), typical.high),
+                ],
+            );
+        }
+        if let Some(ref slo) = view.slo {
+            metric(
+                "slo_error_budget_remaining",
+                "gauge",
+                "Share of the error budget of the --slo period left, negative once overspent.",
+                vec![(String::new(), slo.budget_remaining())],
+            );
+            metric(
+                "slo_burn_rate",
+                "gauge",
+                "How fast the errors within the window spend the error budget; 1 spends it exactly over the period.",
+                BURN_WINDOWS
+                    .iter()
+                    .map(|(window, ..)| (format!("{{window=\"{}\"}}", window), slo.burn_rate(window)))
+                    .collect(),
+            );
+        }
+        metric(
+            "suspicious_ip_errors",
+            "gauge",
+            "Errors per minute of the IPs flagged for erroring well above their usual rate.",
+            view.suspicious
+                .iter()
+                .map(|ip| (format!("{{ip=\"{}\"}}", prometheus_escape(&ip.ip)), ip.errors as f64))
+                .collect(),
+        );
+        metric(
+            "backlog",
+            "gauge",
+            "Lines waiting for the parser.",
+            vec![(String::new(), view.backlog as f64)],
+        );
+        metric(
+            "throttled_total",
+            "counter",
+            "Lines dropped by the rate limiter.",
+            view.throttled
+                .iter()
+                .map(|(source, count)| (format!("{{source=\"{}\"}}", prometheus_escape(source)), *count as f64))
+                .collect(),
+        );
+        metric(
+            "output_dropped_total",
+            "counter",
+            "Items the outputs dropped as they fell behind.",
+            view.output_drops
+                .iter()
+                .map(|(output, count)| (format!("{{output=\"{}\"}}", prometheus_escape(output)), *count as f64))
+                .collect(),
+        );
+        metric(
+            "truncated_datagrams_total",
+            "counter",
+            "Datagrams longer than --listen-max-size, cut to it.",
+            view.truncated_datagrams
+                .iter()
+                .map(|(source, count)| (format!("{{source=\"{}\"}}", prometheus_escape(source)), *count as f64))
+                .collect(),
+        );
+        metric(
+            "top_error_entries",
+            "gauge",
+            "Number of entries of the most frequent error messages in the window.",
+            view.top_errors
+                .iter()
+                .map(|top| (format!("{{message=\"{}\"}}", prometheus_escape(&top.message)), top.count as f64))
+                .collect(),
+        );
+
+        out
+    }
+}
+
+fn prometheus_escape(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n")
+}
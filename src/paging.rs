@@ -0,0 +1,122 @@
+use anyhow::{anyhow, Result};
+use serde_json::json;
+
+use crate::{alert::AlertEvent, tls::HttpClient};
+
+const PAGERDUTY_URL: &str = "https://events.pagerduty.com/v2/enqueue";
+// Opsgenie truncates longer alert messages, PagerDuty rejects longer summaries.
+const OPSGENIE_MESSAGE_LEN: usize = 130;
+const PAGERDUTY_SUMMARY_LEN: usize = 1024;
+
+/// An alert with what it is sent with.
+pub(crate) struct Alert<'a> {
+    pub(crate) event:     &'a AlertEvent,
+    /// By the template, or the name and the summary of the alert
+    pub(crate) message:   &'a str,
+    pub(crate) dashboard: Option<&'a str>,
+    pub(crate) host:      &'a str,
+}
+
+/// Trigger or resolve the PagerDuty incident of the alert with the Events API v2.
+pub(crate) async fn pagerduty(client: &mut HttpClient, routing_key: &str, alert: Alert<'_>) -> Result<()> {
+    let Alert { event, host, .. } = alert;
+    let body = if event.firing {
+        let mut body = json!({
+            "routing_key": routing_key,
+            "event_action": "trigger",
+            "dedup_key": event.dedup_key(host),
+            "payload": {
+                "summary": alert.message.chars().take(PAGERDUTY_SUMMARY_LEN).collect::<String>(),
+                "source": host,
+                "severity": "critical",
+                "timestamp": event.timestamp.to_rfc3339(),
+                "component": "qnode-logproc",
+                "custom_details": details(&alert),
+            },
+        });
+        if let Some(dashboard) = alert.dashboard {
+            body["links"] = json!([{ "href": dashboard, "text": "Dashboard" }]);
+        }
+        body
+    }
+    else {
+        json!({
+            "routing_key": routing_key,
+            "event_action": "resolve",
+            "dedup_key": event.dedup_key(host),
+        })
+    };
+    post(client.get()?.post(PAGERDUTY_URL), body, "PagerDuty").await
+}
+
+/// Create or close the Opsgenie alert of the alert. Opsgenie counts the alerts created with the alias of an open one
+/// instead of opening another.
+pub(crate) async fn opsgenie(client: &mut HttpClient, url: &str, api_key: &str, alert: Alert<'_>) -> Result<()> {
+    let Alert { event, host, .. } = alert;
+    let alias = event.dedup_key(host);
+    let client = client.get()?;
+    let (request, body) = if event.firing {
+        let request = client.post(format!("{}/v2/alerts", url.trim_end_matches('/')));
+        // The first line makes the message, the whole of it the description.
+        let first_line = alert.message.lines().next().unwrap_or_default();
+        let body = json!({
+            "message": first_line.chars().take(OPSGENIE_MESSAGE_LEN).collect::<String>(),
+            "alias": alias,
+            "description": alert.message,
+            "source": host,
+            "entity": host,
+            "details": details(&alert),
+        });
+        (request, body)
+    }
+    else {
+        let request = client.post(format!(
+            "{}/v2/alerts/{}/close?identifierType=alias",
+            url.trim_end_matches('/'),
+            alias
+        ));
+        (request, json!({ "source": host, "note": event.summary() }))
+    };
+    post(request.header("Authorization", format!("GenieKey {}", api_key)), body, "Opsgenie").await
+}
+
+// Opsgenie only takes strings as the values of details.
+fn details(alert: &Alert) -> serde_json::Value {
+    let event = alert.event;
+    let mut details = json!({
+        "rule": event.rule,
+        "condition": event.condition,
+        "value": event.value.to_string(),
+        "since": event.since.to_rfc3339(),
+        "rate": event.context.rate.to_string(),
+        "errors": event.context.errors.to_string(),
+        "distinct_ips": event.context.distinct_ips.to_string(),
+    });
+    if !event.context.top_errors.is_empty() {
+        details["top_errors"] = json!(event.context.top());
+    }
+    if let Some(dashboard) = alert.dashboard {
+        details["dashboard"] = json!(dashboard);
+    }
+    if let Some(ref source) = event.source {
+        details["source"] = json!(source);
+    }
+    if let Some(ref message) = event.message {
+        details["top_error"] = json!(message);
+    }
+    details
+}
+
+async fn post(request: reqwest::RequestBuilder, body: serde_json::Value, service: &str) -> Result<()> {
+    let response = request
+        .header("Content-Type", "application/json")
+        .body(body.to_string())
+        .send()
+        .await?;
+    let status = response.status();
+    if !status.is_success() {
+        let text = response.text().await.unwrap_or_default();
+        return Err(anyhow!("{} request failed with {}: {}", service, status, text.trim_end()));
+    }
+    Ok(())
+}
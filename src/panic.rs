@@ -0,0 +1,74 @@
+use std::{
+    backtrace::Backtrace,
+    io::IsTerminal,
+    panic::{self, PanicHookInfo},
+    path::PathBuf,
+    sync::{Arc, Weak},
+};
+
+use chrono::Local;
+
+use crate::{app::App, keyboard};
+
+/// Make a panic anywhere fatal and leave evidence behind: the terminal is restored, the panic is logged with its
+/// backtrace and the latest snapshot is saved next to the temporary files. Tasks would otherwise die one by one with
+/// the terminal left in raw mode.
+pub(crate) fn install(app: Weak<App>) {
+    let default_hook = panic::take_hook();
+    panic::set_hook(Box::new(move |info| {
+        keyboard::restore();
+        if std::io::stdout().is_terminal() {
+            let _ = console::Term::stdout().show_cursor();
+        }
+        default_hook(info);
+        report(info, app.upgrade());
+        std::process::exit(101);
+    }));
+}
+
+fn report(info: &PanicHookInfo, app: Option<Arc<App>>) {
+    eprintln!(
+        "qnode-logproc panicked in thread '{}' at {}",
+        std::thread::current().name().unwrap_or("<unnamed>"),
+        Local::now().to_rfc3339()
+    );
+    if let Some(location) = info.location() {
+        eprintln!("  location: {}", location);
+    }
+    // The default hook has printed the backtrace already if `RUST_BACKTRACE` asks for one.
+    if std::env::var_os("RUST_BACKTRACE").is_none_or(|value| value == "0") {
+        eprintln!("  backtrace:\n{}", Backtrace::force_capture());
+    }
+
+    let Some(stats) = app.as_ref().and_then(|app| app.stats().ok())
+    else {
+        return;
+    };
+    // The window may be locked by the panicking thread, only the snapshot of the latest refresh is safe to take.
+    let view = stats.latest_snapshot();
+    if view.run.version.is_empty() {
+        eprintln!("  no snapshot was made yet");
+        return;
+    }
+    eprintln!(
+        "  run: {} on {} since {}, reading {}; {} entries in the window at {}",
+        view.run.version,
+        view.run.host,
+        view.run.started.to_rfc3339(),
+        view.run.inputs.join(", "),
+        view.entries,
+        view.timestamp.to_rfc3339()
+    );
+    let path = snapshot_path();
+    match serde_json::to_string_pretty(&view).map_err(anyhow::Error::from).and_then(|json| {
+        std::fs::write(&path, json)?;
+        Ok(())
+    }) {
+        Ok(()) => eprintln!("  the latest snapshot is saved to {}", path.display()),
+        Err(err) => eprintln!("  failed to save the latest snapshot to {}: {}", path.display(), err),
+    }
+}
+
+fn snapshot_path() -> PathBuf {
+    std::env::temp_dir().join(format!("qnode-logproc-{}.panic.json", std::process::id()))
+}
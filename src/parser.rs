@@ -1,65 +1,233 @@
 use crate::{
     app::App,
-    types::{Level, LineMessage, StatErrRecord, StatErrType, StatOKRecord, StatRecord},
+    redact::{FieldHasher, HashField, Redactor},
+    types::{ErrorType, Level, LineMessage, ParsedLine, StatErrRecord, StatOKRecord, StatRecord},
 };
+use std::{
+    collections::hash_map::RandomState,
+    hash::{BuildHasher, Hasher},
+};
+
 use anyhow::Result;
-use chrono::{DateTime, Utc};
 use fieldx_plus::fx_plus;
-use once_cell::sync::Lazy;
-use regex::Regex;
+use qnode_logproc_core::LevelMap;
 use tokio::sync::mpsc::UnboundedReceiver;
 
-static LINE_RE: Lazy<Regex> = Lazy::new(|| {
-    Regex::new(r"^\[(?<dt>\d{4}-\d{2}-\d{2}T\d{2}:\d{2}:\d{2}Z)\]\s+(?<level>INFO|ERROR|DEBUG)\s+-\s+IP:(?<ip>\S+)\s+(?:Error \d+ -\s+)?(?<msg>.*)$")
-        .unwrap()
-});
+#[fx_plus(
+    agent(App, unwrap(error(anyhow::Error, App::app_is_gone()))),
+    sync,
+    fallible(off, error(anyhow::Error))
+)]
+pub(crate) struct Parser {
+    #[fieldx(lazy, fallible, private, get)]
+    redactor: Redactor,
+
+    #[fieldx(lazy, fallible, private, get)]
+    hasher: FieldHasher,
+
+    /// Level names mapped by `--level-map`.
+    #[fieldx(lazy, fallible, private, get)]
+    levels: LevelMap,
+
+    /// Number of non-ERROR lines skipped due to sampling under overload.
+    #[fieldx(lock, get, get_mut, default(0))]
+    sampled_out: u64,
+
+    /// Number of records dropped for being less severe than `--min-level`.
+    #[fieldx(lock, get, get_mut, default(0))]
+    below_min_level: u64,
 
-#[fx_plus(agent(App, unwrap(error(anyhow::Error, App::app_is_gone()))), sync)]
-pub(crate) struct Parser {}
+    /// Number of lines waiting to be parsed.
+    #[fieldx(lock, get, get_mut, default(0))]
+    backlog: usize,
+
+    /// Whether the backlog is over `--overload-backlog`.
+    #[fieldx(lock, get, get_mut, default(false))]
+    overloaded: bool,
+
+    /// Sequential number of non-ERROR lines seen under overload, used to pick every Nth one.
+    #[fieldx(lock, private, get_mut, default(0))]
+    sample_counter: u64,
+}
 
 impl Parser {
     pub(crate) async fn start(&self, rx: &mut UnboundedReceiver<LineMessage>) -> Result<()> {
         let app = self.app()?;
-        // let reader = app.reader()?;
+        let config = app.config();
+        // A backlog is natural when a batch is processed, it is not an overload.
+        let overload_backlog = if config.once {
+            usize::MAX
+        }
+        else {
+            config.overload_backlog
+        };
+        let reader_done = app.reader_done();
+
+        let mut overloaded = false;
 
         loop {
-            let line = rx.recv().await;
-            if let Some(line) = line {
+            let backlog = rx.len();
+            *self.backlog_mut() = backlog;
+            if overloaded != (backlog > overload_backlog) {
+                overloaded = !overloaded;
+                *self.overloaded_mut() = overloaded;
+            }
+            if overloaded {
+                self.drain_overloaded(rx, overload_backlog).await?;
+                continue;
+            }
+
+            tokio::select! {
+                line = rx.recv() => match line {
+                    Some(line) => self.parse_line(line).await?,
+                    None => break,
+                },
+                _ = reader_done.notified() => {
+                    // Nothing will be sent anymore, process what's left in the channel.
+                    while let Ok(line) = rx.try_recv() {
+                        self.parse_line(line).await?;
+                    }
+                    break;
+                }
+            }
+        }
+
+        app.stats()?.stop()?;
+
+        Ok(())
+    }
+
+    // Take a batch of lines from the channel and process ERROR ones first. Other lines are sampled.
+    async fn drain_overloaded(&self, rx: &mut UnboundedReceiver<LineMessage>, batch_size: usize) -> Result<()> {
+        let sample = self.app()?.config().overload_sample;
+        let mut batch = Vec::with_capacity(batch_size);
+        rx.recv_many(&mut batch, batch_size).await;
+
+        let levels = self.levels()?;
+        // A backlog of backfilled lines is expected, they are not sampled.
+        let (errors, others): (Vec<_>, Vec<_>) = batch.into_iter().partition(|l| {
+            l.backfill()
+                || match l.level() {
+                    Some(level) => matches!(level, Level::ERROR),
+                    None => Self::sniff_error(l.line(), &levels),
+                }
+        });
+
+        for line in errors {
+            self.parse_line(line).await?;
+        }
+
+        for line in others {
+            let pick = {
+                let mut counter = self.sample_counter_mut();
+                *counter += 1;
+                counter.is_multiple_of(sample)
+            };
+            if pick {
                 self.parse_line(line).await?;
             }
             else {
-                app.stats()?.stop()?;
-                break;
+                *self.sampled_out_mut() += 1;
             }
         }
 
         Ok(())
     }
 
+    fn build_levels(&self) -> Result<LevelMap> {
+        Ok(self.app()?.config().level_map())
+    }
+
+    fn build_redactor(&self) -> Result<Redactor> {
+        let app = self.app()?;
+        let config = app.config();
+        Redactor::new(&config.redact, &config.redact_pattern)
+    }
+
+    fn build_hasher(&self) -> Result<FieldHasher> {
+        let app = self.app()?;
+        let config = app.config();
+        let salt = config.hash_salt.clone().unwrap_or_else(|| {
+            // RandomState is seeded from the OS randomness source, good enough for a per-run salt.
+            format!("{:016x}", RandomState::new().build_hasher().finish())
+        });
+        Ok(FieldHasher::new(salt, &config.hash_field, &config.hash_pattern))
+    }
+
+    // A cheap check for the line level, without full parsing.
+    fn sniff_error(line: &str, levels: &LevelMap) -> bool {
+        line.find(']').is_some_and(|pos| {
+            let rest = line[pos + 1..].trim_start();
+            let name = &rest[..rest.find(|c: char| !c.is_alphabetic()).unwrap_or(rest.len())];
+            levels.level(name) == Some(Level::ERROR)
+        })
+    }
+
+    // Structured records are taken as is, text lines are parsed. Either way redaction and hashing apply.
     async fn parse_line(&self, line_msg: LineMessage) -> Result<()> {
         let app = self.app()?;
-        if let Some(captures) = LINE_RE.captures(line_msg.line()) {
-            let dt: DateTime<Utc> = captures.name("dt").unwrap().as_str().parse()?;
-            let level: Level = captures.name("level").unwrap().as_str().parse()?;
-            let msg = captures.name("msg").unwrap().as_str().to_string();
-
-            app.stats()?.push_record(
-                StatOKRecord::builder()
-                    .received_millis(line_msg.recv_time_millis())
-                    .logged_millis(dt.timestamp_millis())
-                    .level(level)
-                    .message(msg)
-                    .build()?,
-            )?;
+        #[cfg(feature = "simulation")]
+        if let Some(checkpoint) = line_msg.checkpoint() {
+            return app.stats()?.push_record(StatRecord::Check(checkpoint.clone()));
         }
-        else {
-            app.stats()?.push_record(StatRecord::Err(
-                StatErrRecord::builder()
-                    .received_millis(line_msg.recv_time_millis())
-                    .error_type(StatErrType::Malformed)
-                    .line(line_msg.line().to_string())
-                    .build()?,
-            ))?;
+        let config = app.config();
+        let levels = self.levels()?;
+        let parsed = match line_msg.record() {
+            Some(record) => Ok(record.clone()),
+            None if line_msg.undecoded() => Err(ErrorType::DecodeError),
+            None if line_msg.line().len() > config.max_line_length => Err(ErrorType::Oversized),
+            None => qnode_logproc_core::try_parse_line_mapped(line_msg.line(), &levels),
+        };
+        let parsed = parsed.and_then(|parsed| match config.late_after {
+            Some(secs) if (line_msg.recv_time_millis() - parsed.logged_millis) as f64 > secs * 1000.0 => {
+                Err(ErrorType::LateArrival)
+            }
+            _ => Ok(parsed),
+        });
+
+        match parsed {
+            // Records out of the `--from`/`--to` range or of other facilities are not counted at all.
+            Ok(parsed)
+                if config.from.is_some_and(|from| parsed.logged_millis < from)
+                    || config.to.is_some_and(|to| parsed.logged_millis >= to)
+                    || !config.counts_facility(parsed.facility()) => {}
+            Ok(parsed) if config.min_level.is_some_and(|min| parsed.level.severity() < min.severity()) => {
+                *self.below_min_level_mut() += 1;
+            }
+            Ok(parsed) => {
+                let hasher = self.hasher()?;
+                let ip = hasher.hash_field(HashField::Ip, &parsed.ip).into_owned();
+                let msg = self.redactor()?.redact(&parsed.message).into_owned();
+                let msg = hasher.hash_text(&msg).into_owned();
+
+                app.stats()?.push_record(
+                    StatOKRecord::builder()
+                        .received_millis(line_msg.recv_time_millis())
+                        .record(ParsedLine {
+                            ip,
+                            message: msg,
+                            ..parsed
+                        })
+                        .source(line_msg.source().clone())
+                        .provenance(line_msg.provenance().clone())
+                        .uncounted(line_msg.backfill() && config.backfill_exclude_rate)
+                        .build()?,
+                )?;
+            }
+            Err(err_type) => {
+                // The line is kept for the context panel, which must not show what a parsed message wouldn't.
+                let line = self.redactor()?.redact(line_msg.line()).into_owned();
+                let line = self.hasher()?.hash_text(&line).into_owned();
+
+                app.stats()?.push_record(StatRecord::Err(
+                    StatErrRecord::builder()
+                        .received_millis(line_msg.recv_time_millis())
+                        .error_type(err_type)
+                        .line(line)
+                        .source(line_msg.source().clone())
+                        .build()?,
+                ))?;
+            }
         }
 
         Ok(())
@@ -0,0 +1,160 @@
+use anyhow::{anyhow, Result};
+use base64::Engine;
+use serde_json::json;
+use tokio::time::{Duration, Instant};
+
+use crate::{config::Config, tls::HttpClient};
+
+const PUBSUB_URL: &str = "https://pubsub.googleapis.com";
+const METADATA_TOKEN_URL: &str =
+    "http://metadata.google.internal/computeMetadata/v1/instance/service-accounts/default/token";
+// Maximum number of messages fetched by a single pull request.
+const MAX_MESSAGES: u32 = 1000;
+
+/// Messages returned by a single pull. They must be acknowledged once processed, otherwise Pub/Sub redelivers them.
+pub(crate) struct PubSubBatch {
+    pub(crate) messages: Vec<PubSubMessage>,
+    pub(crate) ack_ids:  Vec<String>,
+}
+
+pub(crate) struct PubSubMessage {
+    /// The payload or why it couldn't be decoded
+    pub(crate) data:         Result<Vec<u8>, base64::DecodeError>,
+    /// When Pub/Sub received the message, in milliseconds
+    pub(crate) publish_time: i64,
+}
+
+/// Consumes a Pub/Sub subscription through the REST API. The access token is taken from `GOOGLE_OAUTH_ACCESS_TOKEN`
+/// if set, otherwise it is requested from the GCE metadata server. With `PUBSUB_EMULATOR_HOST` set the emulator is
+/// used instead, without authentication.
+pub(crate) struct PubSubInput {
+    client:       HttpClient,
+    base_url:     String,
+    subscription: String,
+    emulator:     bool,
+    /// Cached access token and its expiration time
+    token:        Option<(String, Option<Instant>)>,
+}
+
+impl PubSubInput {
+    /// The subscription is a full resource name: `projects/<project>/subscriptions/<name>`.
+    pub(crate) fn new(subscription: &str, config: &Config) -> Result<Self> {
+        let parts: Vec<&str> = subscription.split('/').collect();
+        if parts.len() != 4 || parts[0] != "projects" || parts[2] != "subscriptions" {
+            return Err(anyhow!(
+                "Pub/Sub subscription must be in form projects/<project>/subscriptions/<name>, got '{}'",
+                subscription
+            ));
+        }
+
+        let emulator_host = std::env::var("PUBSUB_EMULATOR_HOST").ok();
+        Ok(Self {
+            client:       HttpClient::new(config),
+            base_url:     emulator_host
+                .as_ref()
+                .map_or_else(|| PUBSUB_URL.to_string(), |host| format!("http://{}", host)),
+            subscription: subscription.to_string(),
+            emulator:     emulator_host.is_some(),
+            token:        None,
+        })
+    }
+
+    /// Fetch the messages available right now. An empty batch means there is nothing to read at the moment.
+    pub(crate) async fn pull(&mut self) -> Result<PubSubBatch> {
+        let response = self
+            .request("pull", json!({ "maxMessages": MAX_MESSAGES, "returnImmediately": true }))
+            .await?;
+        let mut batch = PubSubBatch {
+            messages: Vec::new(),
+            ack_ids:  Vec::new(),
+        };
+
+        for received in response["receivedMessages"].as_array().into_iter().flatten() {
+            let message = &received["message"];
+            // A message with broken data is still acknowledged, it wouldn't decode any better when redelivered.
+            let data = base64::engine::general_purpose::STANDARD.decode(message["data"].as_str().unwrap_or_default());
+            let publish_time = message["publishTime"]
+                .as_str()
+                .and_then(|t| chrono::DateTime::parse_from_rfc3339(t).ok())
+                .map_or_else(|| chrono::Utc::now().timestamp_millis(), |t| t.timestamp_millis());
+
+            batch.messages.push(PubSubMessage {
+                data,
+                publish_time,
+            });
+            if let Some(ack_id) = received["ackId"].as_str() {
+                batch.ack_ids.push(ack_id.to_string());
+            }
+        }
+
+        Ok(batch)
+    }
+
+    pub(crate) async fn acknowledge(&mut self, ack_ids: Vec<String>) -> Result<()> {
+        if !ack_ids.is_empty() {
+            self.request("acknowledge", json!({ "ackIds": ack_ids })).await?;
+        }
+        Ok(())
+    }
+
+    pub(crate) fn source_name(&self) -> String {
+        format!("pubsub/{}", self.subscription.rsplit('/').next().unwrap_or_default())
+    }
+
+    // Call a subscription method, like `pull` or `acknowledge`.
+    async fn request(&mut self, method: &str, body: serde_json::Value) -> Result<serde_json::Value> {
+        let mut request = self
+            .client
+            .get()?
+            .post(format!("{}/v1/{}:{}", self.base_url, self.subscription, method))
+            .header("Content-Type", "application/json")
+            .body(body.to_string());
+        if !self.emulator {
+            request = request.bearer_auth(self.access_token().await?);
+        }
+
+        let response = request.send().await?;
+        let status = response.status();
+        let text = response.text().await?;
+        if !status.is_success() {
+            return Err(anyhow!("Pub/Sub {} request failed with {}: {}", method, status, text.trim_end()));
+        }
+
+        Ok(serde_json::from_str(&text)?)
+    }
+
+    async fn access_token(&mut self) -> Result<String> {
+        if let Some((ref token, expires)) = self.token {
+            if expires.is_none_or(|expires| expires > Instant::now()) {
+                return Ok(token.clone());
+            }
+        }
+
+        if let Ok(token) = std::env::var("GOOGLE_OAUTH_ACCESS_TOKEN") {
+            self.token = Some((token.clone(), None));
+            return Ok(token);
+        }
+
+        let response = self
+            .client
+            .get()?
+            .get(METADATA_TOKEN_URL)
+            .header("Metadata-Flavor", "Google")
+            .send()
+            .await
+            .map_err(|e| anyhow!("No GOOGLE_OAUTH_ACCESS_TOKEN and the metadata server is unreachable: {}", e))?;
+        let token: serde_json::Value = serde_json::from_str(&response.error_for_status()?.text().await?)?;
+        let access_token = token["access_token"]
+            .as_str()
+            .ok_or_else(|| anyhow!("No access token in the metadata server response"))?
+            .to_string();
+        // Refresh a minute before the token actually expires.
+        let expires_in = token["expires_in"].as_u64().unwrap_or(0).saturating_sub(60);
+        self.token = Some((
+            access_token.clone(),
+            Some(Instant::now() + Duration::from_secs(expires_in)),
+        ));
+
+        Ok(access_token)
+    }
+}
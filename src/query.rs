@@ -0,0 +1,172 @@
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, SecondsFormat};
+use qnode_logproc_core::StatsWindow;
+use rusqlite::{limits::Limit, params, types::ValueRef, Connection, Statement};
+
+const SCHEMA: &str = "CREATE TABLE records (
+    time     TEXT NOT NULL,
+    received TEXT NOT NULL,
+    level    TEXT,
+    rejected TEXT,
+    msg_id   INTEGER,
+    message  TEXT,
+    template TEXT,
+    ip       TEXT,
+    source   TEXT NOT NULL,
+    endpoint TEXT,
+    status   INTEGER,
+    client   TEXT,
+    tags     TEXT NOT NULL
+)";
+
+// Rows of a result shown under the terminal report, the rest is only counted.
+const PROMPT_ROWS: usize = 20;
+
+/// Check the queries against the table before any input is read, a mistake is better found early.
+pub(crate) fn check(queries: &[String]) -> Result<()> {
+    let db = open()?;
+    for query in queries {
+        prepare(&db, query).map_err(|e| anyhow!("Bad --query '{}': {}", query, e))?;
+    }
+    Ok(())
+}
+
+/// Load the records of the window into the `records` table of an in-memory database, run the `--query`s over it and
+/// print their results.
+pub(crate) fn run(window: &StatsWindow, queries: &[String]) -> Result<()> {
+    let db = load(window)?;
+    for query in queries {
+        println!();
+        println!("{}", query);
+        match table(&db, query, usize::MAX) {
+            Ok(lines) => lines.iter().for_each(|line| println!("{}", line)),
+            Err(err) => eprintln!("Query '{}' failed: {}", query, err),
+        }
+    }
+
+    Ok(())
+}
+
+/// Run a query typed at the prompt of the terminal report over the records of the window, the lines of the result or
+/// of the error are shown under the report.
+pub(crate) fn prompt(window: &StatsWindow, query: &str) -> Vec<String> {
+    match load(window).and_then(|db| table(&db, query, PROMPT_ROWS)) {
+        Ok(lines) => lines,
+        Err(err) => vec![format!("Query failed: {}", err)],
+    }
+}
+
+// An in-memory database with the empty `records` table. Other databases can't be attached, so that no query reaches
+// the files of the host.
+fn open() -> Result<Connection> {
+    let db = Connection::open_in_memory()?;
+    db.set_limit(Limit::SQLITE_LIMIT_ATTACHED, 0)?;
+    db.execute(SCHEMA, [])?;
+    Ok(db)
+}
+
+// Only queries which change nothing are taken, the prompt of the live report must not be a way to alter the records.
+fn prepare<'db>(db: &'db Connection, query: &str) -> Result<Statement<'db>> {
+    let stmt = db.prepare(query)?;
+    if !stmt.readonly() {
+        return Err(anyhow!("Only read-only queries are allowed"));
+    }
+    Ok(stmt)
+}
+
+fn load(window: &StatsWindow) -> Result<Connection> {
+    let db = open()?;
+
+    // UTC times with milliseconds sort as text and are understood by the SQLite date functions.
+    let time = |millis| {
+        DateTime::from_timestamp_millis(millis)
+            .unwrap_or_default()
+            .to_rfc3339_opts(SecondsFormat::Millis, true)
+    };
+    let tx = db.unchecked_transaction()?;
+    {
+        let mut insert =
+            tx.prepare("INSERT INTO records VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13)")?;
+        for row in window.rows() {
+            // A JSON object, for `json_extract(tags, '$.facility')`.
+            let tags = row
+                .tags
+                .into_iter()
+                .map(|(name, value)| (name, value.into()))
+                .collect::<serde_json::Map<_, _>>();
+            insert.execute(params![
+                time(row.logged_millis),
+                time(row.received_millis),
+                row.level,
+                row.rejected,
+                // SQLite integers are signed, the bits of the ID are kept.
+                row.msg_id.map(|id| id as i64),
+                row.message,
+                row.template,
+                row.ip,
+                row.source,
+                row.endpoint,
+                row.status,
+                row.client,
+                serde_json::Value::from(tags).to_string(),
+            ])?;
+        }
+    }
+    tx.commit()?;
+
+    Ok(db)
+}
+
+/// The result of the query as an aligned table, with up to `limit` rows.
+fn table(db: &Connection, query: &str, limit: usize) -> Result<Vec<String>> {
+    let mut stmt = prepare(db, query)?;
+    let header = stmt.column_names().into_iter().map(str::to_string).collect::<Vec<_>>();
+    let mut table = vec![header];
+    let mut rows = stmt.query([])?;
+    let mut count = 0;
+    while let Some(row) = rows.next()? {
+        count += 1;
+        if count > limit {
+            continue;
+        }
+        let cells = (0..table[0].len())
+            .map(|i| {
+                Ok(match row.get_ref(i)? {
+                    ValueRef::Null => String::new(),
+                    ValueRef::Integer(n) => n.to_string(),
+                    ValueRef::Real(x) => x.to_string(),
+                    ValueRef::Text(text) => String::from_utf8_lossy(text).into_owned(),
+                    ValueRef::Blob(blob) => format!("<{} bytes>", blob.len()),
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
+        table.push(cells);
+    }
+
+    let mut widths = vec![0; table[0].len()];
+    for cells in table.iter() {
+        for (width, cell) in widths.iter_mut().zip(cells) {
+            *width = (*width).max(cell.chars().count());
+        }
+    }
+    let mut lines = table
+        .iter()
+        .map(|cells| {
+            let line = cells
+                .iter()
+                .zip(widths.iter())
+                .map(|(cell, width)| format!("{:<width$}", cell, width = width))
+                .collect::<Vec<_>>()
+                .join("  ");
+            line.trim_end().to_string()
+        })
+        .collect::<Vec<_>>();
+    if count > limit {
+        lines.push(format!("({} rows, the first {} shown)", count, limit));
+    }
+    else {
+        lines.push(format!("({} rows)", count));
+    }
+
+    Ok(lines)
+}
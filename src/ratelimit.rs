@@ -0,0 +1,44 @@
+use std::time::Instant;
+
+use crate::config::RateLimitSpec;
+
+/// Classic token bucket. Tokens are refilled continuously at `rate` per second, up to `burst` tokens.
+pub(crate) struct TokenBucket {
+    rate:        f64,
+    burst:       f64,
+    tokens:      f64,
+    last_refill: Instant,
+}
+
+impl TokenBucket {
+    pub(crate) fn new(rate: f64, burst: f64) -> Self {
+        Self {
+            rate,
+            burst,
+            tokens: burst,
+            last_refill: Instant::now(),
+        }
+    }
+
+    /// Take a token if there is one. Returns `false` if the caller must be throttled.
+    pub(crate) fn try_take(&mut self) -> bool {
+        let now = Instant::now();
+        let elapsed = now.duration_since(self.last_refill).as_secs_f64();
+        self.last_refill = now;
+        self.tokens = (self.tokens + elapsed * self.rate).min(self.burst);
+
+        if self.tokens >= 1.0 {
+            self.tokens -= 1.0;
+            true
+        }
+        else {
+            false
+        }
+    }
+}
+
+impl From<&RateLimitSpec> for TokenBucket {
+    fn from(spec: &RateLimitSpec) -> Self {
+        Self::new(spec.rate, spec.burst)
+    }
+}
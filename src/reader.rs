@@ -1,36 +1,1138 @@
-use std::sync::Arc;
+use std::{
+    collections::{hash_map::Entry, BTreeMap, HashMap, HashSet},
+    fmt,
+    hash::Hash,
+    io::SeekFrom,
+    net::IpAddr,
+    path::PathBuf,
+    sync::Arc,
+    time::Instant,
+};
 
-use crate::{app::App, types::LineMessage};
-use anyhow::Result;
+use crate::{
+    app::App,
+    docker::DockerClient,
+    encoding::Encoding,
+    glob::FileGlob,
+    k8s::{ContainerLog, CriAssembler},
+    listen::ListenAddress,
+    ratelimit::TokenBucket,
+    seek,
+    systemd,
+    tail::Tail,
+    types::LineMessage,
+};
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, Local};
 use fieldx_plus::fx_plus;
+use qnode_logproc_core::{try_parse_line, FileProgress};
 use tokio::{
-    io::{self, AsyncBufReadExt, BufReader},
+    io::{self, AsyncBufRead, AsyncBufReadExt, AsyncRead, AsyncReadExt, AsyncSeekExt, BufReader},
+    net::{TcpListener, UdpSocket},
     sync::mpsc::UnboundedSender,
+    task::{AbortHandle, JoinSet},
 };
 
-#[fx_plus(agent(App, unwrap(error(anyhow::Error, App::app_is_gone()))), sync)]
-pub(crate) struct Reader {}
+pub(crate) const STDIN_SOURCE: &str = "stdin";
+/// A `--file` standing for standard input.
+pub(crate) const STDIN_PATH: &str = "-";
+
+// How often to look for new and removed container log files.
+const K8S_RESCAN_INTERVAL: tokio::time::Duration = tokio::time::Duration::from_secs(2);
+// How often to poll CloudWatch Logs for new events.
+#[cfg(feature = "cloudwatch")]
+const CLOUDWATCH_POLL_INTERVAL: tokio::time::Duration = tokio::time::Duration::from_secs(5);
+// How long to wait before pulling again when a Pub/Sub subscription has no messages.
+#[cfg(feature = "pubsub")]
+const PUBSUB_IDLE_INTERVAL: tokio::time::Duration = tokio::time::Duration::from_secs(1);
+// How long a message bus input, like NATS or MQTT, may stay silent before `--once` mode considers it exhausted.
+#[cfg(any(feature = "eventhubs", feature = "nats", feature = "mqtt"))]
+const BUS_IDLE_TIMEOUT: tokio::time::Duration = tokio::time::Duration::from_secs(1);
+// How long to wait for a new writer after standard input has ended, with `--keep-stdin`.
+const STDIN_REOPEN_INTERVAL: tokio::time::Duration = tokio::time::Duration::from_millis(250);
+// How often to look for new files matching a `--file` pattern.
+const FILE_RESCAN_INTERVAL: tokio::time::Duration = tokio::time::Duration::from_secs(2);
+// How long to wait before retrying a file input which can't be read.
+const FILE_RETRY_INTERVAL: tokio::time::Duration = tokio::time::Duration::from_secs(10);
+// How long to wait before re-attaching to a stopped or unreachable Docker container.
+const DOCKER_RETRY_INTERVAL: tokio::time::Duration = tokio::time::Duration::from_secs(5);
+// How long a `--listen` client may take to complete the TLS handshake.
+#[cfg(feature = "tls")]
+const TLS_HANDSHAKE_TIMEOUT: tokio::time::Duration = tokio::time::Duration::from_secs(10);
+// How long to wait before accepting again when accepting a connection or a datagram fails.
+const ACCEPT_RETRY_INTERVAL: tokio::time::Duration = tokio::time::Duration::from_secs(1);
+
+// Sources of an input which tells them by a key, with their rate limiters.
+type Sources<K> = HashMap<K, (Arc<str>, Option<TokenBucket>)>;
+
+#[fx_plus(agent(App, unwrap(error(anyhow::Error, App::app_is_gone()))), sync, rc)]
+pub(crate) struct Reader {
+    /// Number of lines dropped by the rate limiter, per source name.
+    #[fieldx(lock, get, get_mut, default(BTreeMap::new()))]
+    throttled: BTreeMap<String, u64>,
+
+    /// Number of datagrams cut to `--listen-max-size`, per source name.
+    #[fieldx(lock, get, get_mut, default(BTreeMap::new()))]
+    truncated_datagrams: BTreeMap<String, u64>,
+
+    /// Errors of the inputs which currently can't be read, per source name.
+    #[fieldx(lock, get, get_mut, default(BTreeMap::new()))]
+    input_errors: BTreeMap<String, String>,
+
+    /// Reading progress of file inputs, per source name.
+    #[fieldx(lock, private, get_mut, default(BTreeMap::new()))]
+    files: BTreeMap<String, FileReading>,
+}
+
+// Progress of a file input as seen by its reader.
+struct FileReading {
+    path:         PathBuf,
+    offset:       u64,
+    lines:        u64,
+    last_read:    Option<DateTime<Local>>,
+    /// The line count and the moment the rate was last measured from
+    mark:         (u64, Instant),
+    rate:         f64,
+    /// Where the history of the file ends while it is being backfilled
+    backfill_end: Option<u64>,
+}
+
+impl FileReading {
+    fn new(path: PathBuf) -> Self {
+        Self {
+            path,
+            offset: 0,
+            lines: 0,
+            last_read: None,
+            mark: (0, Instant::now()),
+            rate: 0.0,
+            backfill_end: None,
+        }
+    }
+}
 
 impl Reader {
     pub(crate) async fn start(&self, tx: Arc<UnboundedSender<LineMessage>>) -> Result<()> {
-        let reader = BufReader::new(io::stdin());
-        let mut lines = reader.lines();
+        let app = self.app()?;
+
+        if app.embedded() {
+            // Lines are pushed by the host; returning would signal the end of input.
+            return std::future::pending().await;
+        }
+
+        #[cfg(feature = "simulation")]
+        if let Some(ref path) = app.config().simulate {
+            return self.read_simulation(path, tx).await;
+        }
+
+        if let Some(ref dir) = app.config().k8s {
+            return self.watch_k8s(dir.clone(), tx).await;
+        }
+
+        if !app.config().docker.is_empty() {
+            return self.follow_docker(tx).await;
+        }
+
+        #[cfg(feature = "cloudwatch")]
+        if let Some(ref group) = app.config().cloudwatch {
+            return self.read_cloudwatch(group, tx).await;
+        }
+
+        #[cfg(feature = "pubsub")]
+        if let Some(ref subscription) = app.config().pubsub {
+            return self.read_pubsub(subscription, tx).await;
+        }
+
+        #[cfg(feature = "eventhubs")]
+        if let Some(ref hub) = app.config().eventhub {
+            return self.read_eventhub(hub, tx).await;
+        }
+
+        #[cfg(feature = "redis")]
+        if let Some(ref stream) = app.config().redis_stream {
+            return self.read_redis(stream, tx).await;
+        }
+
+        #[cfg(feature = "nats")]
+        if let Some(ref subject) = app.config().nats_subject {
+            return self.read_nats(subject, tx).await;
+        }
+
+        #[cfg(feature = "mqtt")]
+        if let Some(ref broker) = app.config().mqtt {
+            return self.read_mqtt(broker, tx).await;
+        }
+
+        #[cfg(feature = "s3")]
+        if let Some(ref location) = app.config().s3 {
+            return self.read_s3(location, tx).await;
+        }
+
+        if let Some(ref address) = app.config().listen {
+            return match address {
+                ListenAddress::Tcp(address) => self.listen_tcp(address, tx).await,
+                ListenAddress::Udp(address) => self.listen_udp(address, tx).await,
+            };
+        }
+
+        if !app.config().files.is_empty() {
+            return self.read_files(tx).await;
+        }
+
+        self.read_stdin(tx).await
+    }
+
+    // With `--keep-stdin`, the end of input is not final: a named pipe delivers lines again once a new writer opens it.
+    async fn read_stdin(&self, tx: Arc<UnboundedSender<LineMessage>>) -> Result<()> {
+        let keep = self.app()?.config().keep_stdin;
+        let source: Arc<str> = STDIN_SOURCE.into();
+        let mut reader = BufReader::new(io::stdin());
+        let mut buf = Vec::new();
+        let mut bucket = self.bucket_for(&source)?;
 
         'read: loop {
-            let line = match lines.next_line().await {
-                Ok(Some(l)) => l,
+            let line = match next_lossy_line(&mut reader, &mut buf, Encoding::Utf8).await {
+                Ok(Some((l, _))) => l,
 
+                Ok(None) if keep => {
+                    tokio::time::sleep(STDIN_REOPEN_INTERVAL).await;
+                    continue 'read;
+                }
                 Ok(None) => break 'read,
                 Err(err) => {
                     return Err(err.into());
                 }
             };
 
-            let line_msg = LineMessage::new(line, chrono::Utc::now().timestamp_millis());
+            self.send_line(&mut bucket, &source, line, &tx)?;
+        }
+
+        Ok(())
+    }
+
+    // Read all files at once, standard input too if it is among them. A path with wildcards is watched for matching
+    // files. A file which fails is reported in `input_errors` and retried while the others go on; in `--once` mode it
+    // is given up instead.
+    async fn read_files(&self, tx: Arc<UnboundedSender<LineMessage>>) -> Result<()> {
+        let app = self.app()?;
+        let backfill = app.config().backfill;
+        let mut readers = JoinSet::new();
+
+        for path in app.config().files.iter() {
+            let myself = self.myself().unwrap();
+            let tx = tx.clone();
+            if path.as_os_str() == STDIN_PATH {
+                let failures = app.failures();
+                readers.spawn(async move {
+                    if let Err(err) = myself.read_stdin(tx).await {
+                        failures.fail("Reading standard input", err);
+                    }
+                });
+                continue;
+            }
+            match FileGlob::new(path)? {
+                Some(glob) => {
+                    let failures = app.failures();
+                    readers.spawn(async move {
+                        if let Err(err) = myself.watch_files(glob, tx).await {
+                            failures.fail("Watching files", err);
+                        }
+                    });
+                }
+                None => {
+                    let path = path.clone();
+                    readers.spawn(async move { myself.follow_file(path, backfill, tx).await });
+                }
+            }
+        }
+
+        while readers.join_next().await.is_some() {}
+
+        Ok(())
+    }
+
+    // Follow every file matching the pattern, rescanning for new ones. Files which appear after the start are read
+    // from their beginning, as are the initial ones with `--backfill`; followers of removed files are stopped. In
+    // `--once` mode the files matching at the start are read to the end.
+    async fn watch_files(&self, glob: FileGlob, tx: Arc<UnboundedSender<LineMessage>>) -> Result<()> {
+        let app = self.app()?;
+        let once = app.config().once;
+        let mut readers = JoinSet::new();
+        let mut followers: HashMap<PathBuf, AbortHandle> = HashMap::new();
+        let mut from_start = app.config().backfill;
+
+        loop {
+            let found: HashSet<PathBuf> = glob.matches().await?.into_iter().collect();
+
+            for path in found.iter() {
+                if let Entry::Vacant(entry) = followers.entry(path.clone()) {
+                    let myself = self.myself().unwrap();
+                    let tx = tx.clone();
+                    let path = path.clone();
+                    entry.insert(readers.spawn(async move { myself.follow_file(path, from_start, tx).await }));
+                }
+            }
+
+            followers.retain(|path, handle| {
+                if found.contains(path) {
+                    true
+                }
+                else {
+                    handle.abort();
+                    let source = path.display().to_string();
+                    self.input_recovered(&source);
+                    self.files_mut().remove(&source);
+                    false
+                }
+            });
+
+            if once {
+                while readers.join_next().await.is_some() {}
+                return Ok(());
+            }
+
+            // Finished followers are of no interest, they only report errors.
+            while readers.try_join_next().is_some() {}
+            from_start = true;
+            tokio::time::sleep(FILE_RESCAN_INTERVAL).await;
+        }
+    }
+
+    // Read the file, retrying on errors unless in `--once` mode.
+    async fn follow_file(&self, path: PathBuf, from_start: bool, tx: Arc<UnboundedSender<LineMessage>>) {
+        let Ok(app) = self.app()
+        else {
+            return;
+        };
+        let once = app.config().once;
+        let source = path.display().to_string();
+
+        loop {
+            match self.read_file(&path, &source, from_start, tx.clone(), once).await {
+                Ok(()) => break,
+                Err(err) => {
+                    self.input_errors_mut().insert(source.clone(), err.to_string());
+                    app.failures().fail(&source, err);
+                }
+            }
+            if once {
+                break;
+            }
+            tokio::time::sleep(FILE_RETRY_INTERVAL).await;
+        }
+    }
+
+    async fn read_file(
+        &self,
+        path: &PathBuf,
+        source: &str,
+        from_start: bool,
+        tx: Arc<UnboundedSender<LineMessage>>,
+        once: bool,
+    ) -> Result<()> {
+        let source: Arc<str> = source.into();
+        let mut bucket = self.bucket_for(&source)?;
+        self.files_mut().insert(source.to_string(), FileReading::new(path.clone()));
+
+        if once {
+            let app = self.app()?;
+            let (from, to) = (app.config().from, app.config().to);
+            let mut reader = BufReader::new(tokio::fs::File::open(path).await?);
+            let mut buf = Vec::new();
+            self.input_recovered(&source);
+            let (encoding, bom) = Encoding::detect(reader.fill_buf().await?);
+            reader.consume(bom);
+            let mut offset = bom as u64;
+            if let (Some(from), Encoding::Utf8) = (from, encoding) {
+                let seek_path = path.clone();
+                let start = tokio::task::spawn_blocking(move || seek::seek_time(&seek_path, from)).await??;
+                if start > offset {
+                    reader.seek(SeekFrom::Start(start)).await?;
+                    offset = start;
+                }
+            }
+            while let Some((line, read)) = next_lossy_line(&mut reader, &mut buf, encoding).await? {
+                // Sorted records past the range end mean there is nothing more to count in the file.
+                if let Some(to) = to {
+                    if try_parse_line(&line).is_ok_and(|record| record.logged_millis > to + seek::TIME_SLACK_MS) {
+                        break;
+                    }
+                }
+                let message = LineMessage::new(line, chrono::Utc::now().timestamp_millis(), source.clone())
+                    .at(format!("{}:{}", path.display(), offset));
+                offset += read as u64;
+                self.file_read(&source, offset);
+                self.send_message(&mut bucket, message, &tx)?;
+            }
+            return Ok(());
+        }
+
+        // A missing file is fine, it is waited for; any other error must be reported right away.
+        match tokio::fs::File::open(path).await {
+            Ok(file) => {
+                let size = file.metadata().await?.len();
+                if let Some(file) = self.files_mut().get_mut(&*source) {
+                    if from_start {
+                        // What is in the file already is its history, to be backfilled before following.
+                        file.backfill_end = Some(size).filter(|size| *size > 0);
+                    }
+                    else {
+                        file.offset = size;
+                    }
+                }
+            }
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => (),
+            Err(err) => return Err(err.into()),
+        }
+        self.input_recovered(&source);
+
+        let mut tail = Tail::new(path, from_start);
+        loop {
+            let line = tail.next_line().await?;
+            let message = LineMessage::new(line, chrono::Utc::now().timestamp_millis(), source.clone())
+                .at(format!("{}:{}", path.display(), tail.line_start()));
+            let message = if self.file_read(&source, tail.position()) {
+                message.backfilled()
+            }
+            else {
+                message
+            };
+            self.send_message(&mut bucket, message, &tx)?;
+        }
+    }
+
+    // Watch the directory with container logs and follow every log file in it. Followers of removed files are
+    // stopped.
+    async fn watch_k8s(&self, dir: PathBuf, tx: Arc<UnboundedSender<LineMessage>>) -> Result<()> {
+        let app = self.app()?;
+        let mut tails: HashMap<PathBuf, AbortHandle> = HashMap::new();
+
+        loop {
+            let mut found = HashSet::new();
+            let mut entries = tokio::fs::read_dir(&dir).await?;
+
+            while let Some(entry) = entries.next_entry().await? {
+                let path = entry.path();
+                let Some(container_log) = ContainerLog::from_path(&path)
+                else {
+                    continue;
+                };
 
-            tx.send(line_msg)?;
+                if let Some(ref pod_re) = app.config().k8s_pod {
+                    if !pod_re.is_match(&container_log.pod) {
+                        continue;
+                    }
+                }
+
+                found.insert(path.clone());
+
+                if let Entry::Vacant(entry) = tails.entry(path) {
+                    let myself = self.myself().unwrap();
+                    let tx = tx.clone();
+                    let path = entry.key().clone();
+                    let failures = app.failures();
+                    entry.insert(app.task_set_mut().spawn(async move {
+                        if let Err(err) = myself.follow_container_log(&path, container_log, tx).await {
+                            failures.fail(&path.display().to_string(), err);
+                        }
+                    }));
+                }
+            }
+
+            tails.retain(|path, handle| {
+                if found.contains(path) {
+                    true
+                }
+                else {
+                    handle.abort();
+                    false
+                }
+            });
+
+            tokio::time::sleep(K8S_RESCAN_INTERVAL).await;
+        }
+    }
+
+    async fn follow_container_log(
+        &self,
+        path: &PathBuf,
+        container_log: ContainerLog,
+        tx: Arc<UnboundedSender<LineMessage>>,
+    ) -> Result<()> {
+        let source: Arc<str> = container_log.source_name().into();
+        let mut bucket = self.bucket_for(&source)?;
+        let mut tail = Tail::new(path, false);
+        let mut cri = CriAssembler::default();
+
+        loop {
+            let line = tail.next_line().await?;
+            // A line split by the runtime is traced back to its last part.
+            if let Some(line) = cri.push(&line) {
+                let message = LineMessage::new(line, chrono::Utc::now().timestamp_millis(), source.clone())
+                    .at(format!("{}:{}", path.display(), tail.line_start()));
+                self.send_message(&mut bucket, message, &tx)?;
+            }
         }
+    }
+
+    // Follow all requested containers. Returns only when all followers fail, which normally doesn't happen since they
+    // keep re-attaching.
+    async fn follow_docker(&self, tx: Arc<UnboundedSender<LineMessage>>) -> Result<()> {
+        let app = self.app()?;
+        let mut followers = JoinSet::new();
+
+        for container in app.config().docker.iter() {
+            let myself = self.myself().unwrap();
+            let tx = tx.clone();
+            let container = container.clone();
+            let failures = app.failures();
+            followers.spawn(async move {
+                loop {
+                    if let Err(err) = myself.follow_container(&container, tx.clone()).await {
+                        failures.fail(&docker_subject(&container), err);
+                    }
+                    tokio::time::sleep(DOCKER_RETRY_INTERVAL).await;
+                }
+            });
+        }
+
+        while followers.join_next().await.is_some() {}
 
         Ok(())
     }
+
+    async fn follow_container(&self, container: &str, tx: Arc<UnboundedSender<LineMessage>>) -> Result<()> {
+        let app = self.app()?;
+        let client = DockerClient::new(&app.config().docker_socket);
+        let info = client.inspect(container).await?;
+        let source: Arc<str> = format!("docker/{}", info.name).into();
+        let mut bucket = self.bucket_for(&source)?;
+        let mut logs = client.follow_logs(container, info.tty).await?;
+        app.failures().recover(&docker_subject(container));
+
+        while let Some(line) = logs.next_line().await? {
+            self.send_line(&mut bucket, &source, line, &tx)?;
+        }
+
+        Ok(())
+    }
+
+    // Accept any number of connections, each sending lines until it is closed. A socket passed by systemd socket
+    // activation is taken instead of binding the address. A connection which breaks is reported
+    // under its source name until the peer connects again; failing to accept is reported and retried.
+    async fn listen_tcp(&self, address: &str, tx: Arc<UnboundedSender<LineMessage>>) -> Result<()> {
+        let app = self.app()?;
+        let listener = match systemd::activated_socket(libc::SOCK_STREAM)? {
+            Some(fd) => {
+                let listener = std::net::TcpListener::from(fd);
+                listener.set_nonblocking(true)?;
+                TcpListener::from_std(listener)?
+            }
+            None => TcpListener::bind(address)
+                .await
+                .map_err(|e| anyhow!("Can't listen on tcp://{}: {}", address, e))?,
+        };
+        let subject = format!("Listening on tcp://{}", listener.local_addr()?);
+        #[cfg(feature = "tls")]
+        let acceptor = crate::tls::acceptor(&app.config())?;
+        let mut connections = JoinSet::new();
+
+        loop {
+            let (stream, peer) = match listener.accept().await {
+                Ok(accepted) => accepted,
+                Err(err) => {
+                    app.failures().fail(&subject, err);
+                    tokio::time::sleep(ACCEPT_RETRY_INTERVAL).await;
+                    continue;
+                }
+            };
+            app.failures().recover(&subject);
+            while connections.try_join_next().is_some() {}
+
+            let myself = self.myself().unwrap();
+            let tx = tx.clone();
+            let failures = app.failures();
+            #[cfg(feature = "tls")]
+            let acceptor = acceptor.clone();
+            connections.spawn(async move {
+                let source: Arc<str> = format!("tcp/{}", peer.ip()).into();
+                #[cfg(feature = "tls")]
+                let read = match acceptor {
+                    Some(acceptor) => myself.read_tls_connection(&acceptor, stream, &source, tx).await,
+                    None => myself.read_connection(stream, &source, tx).await,
+                };
+                #[cfg(not(feature = "tls"))]
+                let read = myself.read_connection(stream, &source, tx).await;
+                match read {
+                    Ok(()) => failures.recover(&source),
+                    Err(err) => failures.fail(&source, err),
+                }
+            });
+        }
+    }
+
+    /// Send the lines of an `--http` ingestion request body.
+    pub(crate) async fn ingest(
+        &self,
+        body: &[u8],
+        source: &Arc<str>,
+        tx: Arc<UnboundedSender<LineMessage>>,
+    ) -> Result<()> {
+        self.read_connection(body, source, tx).await
+    }
+
+    // A peer can't make a line take more memory than `--max-line-length` allows, see `next_bounded_line`.
+    async fn read_connection<S: AsyncRead + Unpin>(
+        &self,
+        stream: S,
+        source: &Arc<str>,
+        tx: Arc<UnboundedSender<LineMessage>>,
+    ) -> Result<()> {
+        let max_length = self.app()?.config().max_line_length;
+        let mut bucket = self.bucket_for(source)?;
+        let mut reader = BufReader::new(stream);
+        let mut buf = Vec::new();
+
+        while let Some(line) = next_bounded_line(&mut reader, &mut buf, max_length).await? {
+            self.send_line(&mut bucket, source, line, &tx)?;
+        }
+
+        Ok(())
+    }
+
+    // A client which doesn't go on with the handshake is given up after a while.
+    #[cfg(feature = "tls")]
+    async fn read_tls_connection(
+        &self,
+        acceptor: &tokio_rustls::TlsAcceptor,
+        stream: tokio::net::TcpStream,
+        source: &Arc<str>,
+        tx: Arc<UnboundedSender<LineMessage>>,
+    ) -> Result<()> {
+        let stream = tokio::time::timeout(TLS_HANDSHAKE_TIMEOUT, acceptor.accept(stream))
+            .await
+            .map_err(|_| anyhow!("TLS handshake timed out"))?
+            .map_err(|e| anyhow!("TLS handshake failed: {}", e))?;
+        match self.read_connection(stream, source, tx).await {
+            // Clients often close the connection without notifying TLS of it, nothing of their lines is lost though.
+            Err(err)
+                if err
+                    .downcast_ref::<io::Error>()
+                    .is_some_and(|err| err.kind() == io::ErrorKind::UnexpectedEof) =>
+            {
+                Ok(())
+            }
+            read => read,
+        }
+    }
+
+    // Take every datagram as a line, with the trailing line breaks and NULs some senders add removed. A datagram
+    // longer than `--listen-max-size` is cut to it and counted. A socket passed by systemd is taken like for TCP.
+    async fn listen_udp(&self, address: &str, tx: Arc<UnboundedSender<LineMessage>>) -> Result<()> {
+        let app = self.app()?;
+        let max_size = app.config().listen_max_size as usize;
+        let socket = match systemd::activated_socket(libc::SOCK_DGRAM)? {
+            Some(fd) => {
+                let socket = std::net::UdpSocket::from(fd);
+                socket.set_nonblocking(true)?;
+                UdpSocket::from_std(socket)?
+            }
+            None => UdpSocket::bind(address)
+                .await
+                .map_err(|e| anyhow!("Can't listen on udp://{}: {}", address, e))?,
+        };
+        let subject = format!("Listening on udp://{}", socket.local_addr()?);
+        // One byte more tells a datagram which doesn't fit, the rest of it is discarded by the system.
+        let mut buf = vec![0; max_size + 1];
+        let mut sources: Sources<IpAddr> = HashMap::new();
+
+        loop {
+            let (len, peer) = match socket.recv_from(&mut buf).await {
+                Ok(received) => received,
+                Err(err) => {
+                    app.failures().fail(&subject, err);
+                    tokio::time::sleep(ACCEPT_RETRY_INTERVAL).await;
+                    continue;
+                }
+            };
+            app.failures().recover(&subject);
+
+            let (source, bucket) = self.source_for(&mut sources, peer.ip(), "udp")?;
+            if len > max_size {
+                *self.truncated_datagrams_mut().entry(source.to_string()).or_insert(0) += 1;
+            }
+            let datagram = String::from_utf8_lossy(&buf[..len.min(max_size)]);
+            let line = datagram.trim_end_matches(['\n', '\r', '\0']).to_string();
+            self.send_line(bucket, source, line, &tx)?;
+        }
+    }
+
+    // Lines are sent without rate limiting, as fast as the channel takes them; only their order matters.
+    #[cfg(feature = "simulation")]
+    async fn read_simulation(&self, path: &std::path::Path, tx: Arc<UnboundedSender<LineMessage>>) -> Result<()> {
+        use crate::simulation::{Event, Scenario};
+
+        for event in Scenario::load(path)?.events()? {
+            let message = match event {
+                Event::Line {
+                    line,
+                    received_millis,
+                    source,
+                } => LineMessage::new(line, received_millis, source.into()),
+                Event::Check(checkpoint) => LineMessage::from_checkpoint(checkpoint),
+            };
+            tx.send(message)?;
+        }
+
+        Ok(())
+    }
+
+    // Read all objects under the S3 prefix, several at a time. A failing object doesn't stop the others.
+    #[cfg(feature = "s3")]
+    async fn read_s3(&self, location: &crate::s3::S3Location, tx: Arc<UnboundedSender<LineMessage>>) -> Result<()> {
+        let input = Arc::new(crate::s3::S3Input::new(location)?);
+        let keys = input.list().await?;
+        let app = self.app()?;
+        let permits = Arc::new(tokio::sync::Semaphore::new(app.config().s3_parallel as usize));
+        let mut readers = JoinSet::new();
+
+        for key in keys {
+            let myself = self.myself().unwrap();
+            let input = input.clone();
+            let permits = permits.clone();
+            let tx = tx.clone();
+            let failures = app.failures();
+            readers.spawn(async move {
+                let _permit = permits.acquire_owned().await;
+                if let Err(err) = myself.read_s3_object(&input, &key, tx).await {
+                    failures.fail(&input.source_name(&key), err);
+                }
+            });
+        }
+
+        while readers.join_next().await.is_some() {}
+
+        Ok(())
+    }
+
+    #[cfg(feature = "s3")]
+    async fn read_s3_object(
+        &self,
+        input: &crate::s3::S3Input,
+        key: &str,
+        tx: Arc<UnboundedSender<LineMessage>>,
+    ) -> Result<()> {
+        let source: Arc<str> = input.source_name(key).into();
+        let mut bucket = self.bucket_for(&source)?;
+        let mut lines = input.lines(key).await?;
+
+        while let Some(line) = lines.next_line().await? {
+            self.send_line(&mut bucket, &source, line, &tx)?;
+        }
+
+        Ok(())
+    }
+
+    // Poll the log group for new events. In `--once` mode stop as soon as there are no more events.
+    #[cfg(feature = "cloudwatch")]
+    async fn read_cloudwatch(&self, group: &str, tx: Arc<UnboundedSender<LineMessage>>) -> Result<()> {
+        let app = self.app()?;
+        let config = app.config();
+        let start = config
+            .cloudwatch_start
+            .unwrap_or_else(|| chrono::Utc::now().timestamp_millis());
+        let mut input =
+            crate::cloudwatch::CloudWatchInput::new(group, config.cloudwatch_filter.clone(), start).await;
+        let prefix = input.source_prefix();
+        let mut sources: Sources<String> = HashMap::new();
+
+        loop {
+            let events = input.poll().await?;
+            if events.is_empty() && config.once {
+                return Ok(());
+            }
+
+            for event in events {
+                let (source, bucket) = self.source_for(&mut sources, event.stream, &prefix)?;
+                // CloudWatch ingestion time is the moment the line was received, lag statistics rely on it.
+                self.send_line_received(bucket, source, event.message, event.ingestion_time, &tx)?;
+            }
+
+            tokio::time::sleep(CLOUDWATCH_POLL_INTERVAL).await;
+        }
+    }
+
+    // Pull messages from the subscription and acknowledge them once their lines are queued. In `--once` mode stop when
+    // the subscription is drained.
+    #[cfg(feature = "pubsub")]
+    async fn read_pubsub(&self, subscription: &str, tx: Arc<UnboundedSender<LineMessage>>) -> Result<()> {
+        let app = self.app()?;
+        let once = app.config().once;
+        let mut input = crate::pubsub::PubSubInput::new(subscription, &app.config())?;
+        let source: Arc<str> = input.source_name().into();
+        let mut bucket = self.bucket_for(&source)?;
+
+        loop {
+            let batch = input.pull().await?;
+            if batch.messages.is_empty() {
+                if once {
+                    return Ok(());
+                }
+                tokio::time::sleep(PUBSUB_IDLE_INTERVAL).await;
+                continue;
+            }
+
+            for message in batch.messages {
+                match message.data {
+                    Ok(data) => self.send_payload(&mut bucket, &source, &data, message.publish_time, &tx)?,
+                    // Let it be counted as a decode error.
+                    Err(err) => {
+                        let line = format!("Bad message data from Pub/Sub: {}", err);
+                        let undecoded = LineMessage::new(line, message.publish_time, source.clone()).failed_decoding();
+                        self.send_message(&mut bucket, undecoded, &tx)?
+                    }
+                }
+            }
+
+            input.acknowledge(batch.ack_ids).await?;
+        }
+    }
+
+    // Consume the event hub as a consumer group member, storing offsets of events once their lines are queued. In
+    // `--once` mode stop when no events arrive for a while.
+    #[cfg(feature = "eventhubs")]
+    async fn read_eventhub(&self, hub: &str, tx: Arc<UnboundedSender<LineMessage>>) -> Result<()> {
+        let app = self.app()?;
+        let config = app.config();
+        let input = crate::eventhubs::EventHubInput::new(
+            config.eventhub_connection_string.as_deref().unwrap_or_default(),
+            hub,
+            &config.eventhub_consumer_group,
+            &crate::tls::ClientTls::from_config(&config),
+            app.failures(),
+        )?;
+        let source: Arc<str> = input.source_name().into();
+        let mut bucket = self.bucket_for(&source)?;
+
+        loop {
+            let Some(message) = input.next(config.once.then_some(BUS_IDLE_TIMEOUT)).await?
+            else {
+                return input.commit();
+            };
+            self.send_payload(&mut bucket, &source, &message.payload, message.enqueued_time, &tx)?;
+            input.ack(&message)?;
+        }
+    }
+
+    // Read the stream as a consumer group member, acknowledging entries once their lines are queued. In `--once` mode
+    // stop when no new entries arrive.
+    #[cfg(feature = "redis")]
+    async fn read_redis(&self, stream: &str, tx: Arc<UnboundedSender<LineMessage>>) -> Result<()> {
+        let app = self.app()?;
+        let config = app.config();
+        let mut input = crate::redis_stream::RedisStreamInput::new(
+            &config.redis_url,
+            &crate::tls::ClientTls::from_config(&config),
+            stream,
+            &config.redis_group,
+            &config.redis_consumer,
+            &config.redis_field,
+        )
+        .await?;
+        let source: Arc<str> = input.source_name().into();
+        let mut bucket = self.bucket_for(&source)?;
+
+        loop {
+            let entries = input.read().await?;
+            if entries.is_empty() {
+                if config.once {
+                    return Ok(());
+                }
+                continue;
+            }
+
+            let mut ids = Vec::with_capacity(entries.len());
+            for entry in entries {
+                if let Some(ref payload) = entry.payload {
+                    self.send_payload(&mut bucket, &source, payload, entry.recv_time, &tx)?;
+                }
+                ids.push(entry.id);
+            }
+
+            input.acknowledge(&ids).await?;
+        }
+    }
+
+    // Read messages of the subject, each may carry several lines. JetStream messages are acknowledged once their lines
+    // are queued. In `--once` mode stop when no messages arrive for a while.
+    #[cfg(feature = "nats")]
+    async fn read_nats(&self, subject: &str, tx: Arc<UnboundedSender<LineMessage>>) -> Result<()> {
+        use crate::nats::NatsInput;
+
+        let app = self.app()?;
+        let config = app.config();
+        let tls = crate::tls::ClientTls::from_config(&config);
+        let client = crate::nats::connect(&config.nats_url, config.nats_creds.as_deref(), &tls, false).await?;
+        let mut input = match config.nats_jetstream {
+            Some(ref stream) => NatsInput::jetstream(&client, stream, &config.nats_durable, subject).await?,
+            None => NatsInput::subscribe(&client, subject).await?,
+        };
+        let mut sources: Sources<String> = HashMap::new();
+
+        loop {
+            // Reconnects happen in the background client task, so the timeout only ever cuts a wait for a message.
+            let message = match tokio::time::timeout(BUS_IDLE_TIMEOUT, input.next()).await {
+                Ok(message) => message?,
+                Err(_) if config.once => return Ok(()),
+                Err(_) => continue,
+            };
+            let Some(message) = message
+            else {
+                return Ok(());
+            };
+
+            let (source, bucket) = self.source_for(&mut sources, message.subject.clone(), "nats")?;
+            self.send_payload(bucket, source, &message.payload, message.recv_time, &tx)?;
+
+            message.ack().await?;
+        }
+    }
+
+    // Receive messages published to the subscribed topics, each may carry several lines. In `--once` mode stop when no
+    // messages arrive for a while.
+    #[cfg(feature = "mqtt")]
+    async fn read_mqtt(&self, broker: &crate::mqtt::MqttBroker, tx: Arc<UnboundedSender<LineMessage>>) -> Result<()> {
+        let app = self.app()?;
+        let config = app.config();
+        let credentials = config.mqtt_username.as_deref().zip(config.mqtt_password.as_deref());
+        let mut input = crate::mqtt::MqttInput::new(
+            broker,
+            &config.mqtt_client_id,
+            credentials,
+            &crate::tls::ClientTls::from_config(&config),
+            &config.mqtt_topic,
+            config.mqtt_qos,
+            app.failures(),
+        )?;
+        let mut sources: Sources<String> = HashMap::new();
+
+        loop {
+            // The idle wait is kept inside the input so that it never cuts the pause before a reconnect short.
+            let Some(message) = input.next(config.once.then_some(BUS_IDLE_TIMEOUT)).await?
+            else {
+                return Ok(());
+            };
+
+            let (source, bucket) = self.source_for(&mut sources, message.topic, "mqtt")?;
+            self.send_payload(bucket, source, &message.payload, chrono::Utc::now().timestamp_millis(), &tx)?;
+        }
+    }
+
+    /// Reading progress of the file inputs with their current sizes.
+    pub(crate) fn file_progress(&self) -> BTreeMap<String, FileProgress> {
+        let mut files = self.files_mut();
+        files
+            .iter_mut()
+            .map(|(source, file)| {
+                // The rate is measured over at least a second so that out-of-schedule snapshots don't skew it.
+                let elapsed = file.mark.1.elapsed().as_secs_f64();
+                if elapsed >= 1.0 {
+                    file.rate = (file.lines - file.mark.0) as f64 / elapsed;
+                    file.mark = (file.lines, Instant::now());
+                }
+                let progress = FileProgress {
+                    offset:        file.offset,
+                    size:          std::fs::metadata(&file.path).map(|meta| meta.len()).unwrap_or(0),
+                    lines_per_sec: file.rate,
+                    last_read:     file.last_read,
+                    backfill_end:  file.backfill_end,
+                };
+                (source.clone(), progress)
+            })
+            .collect()
+    }
+
+    // Returns true while the line is a part of the file history being backfilled.
+    fn file_read(&self, source: &str, offset: u64) -> bool {
+        let mut files = self.files_mut();
+        let Some(file) = files.get_mut(source)
+        else {
+            return false;
+        };
+        file.offset = offset;
+        file.lines += 1;
+        file.last_read = Some(Local::now());
+        match file.backfill_end {
+            Some(end) if offset >= end => {
+                file.backfill_end = None;
+                // The last line of the history.
+                true
+            }
+            Some(_) => true,
+            None => false,
+        }
+    }
+
+    // The input works again after a failure.
+    fn input_recovered(&self, source: &str) {
+        self.input_errors_mut().remove(source);
+        if let Ok(app) = self.app() {
+            app.failures().recover(source);
+        }
+    }
+
+    fn bucket_for(&self, source: &str) -> Result<Option<TokenBucket>> {
+        Ok(self.app()?.config().rate_limit_for(source).map(TokenBucket::from))
+    }
+
+    // The name and the rate limiter of a source an input tells by a key, like a topic or a peer address. The source
+    // is named after the key under the prefix.
+    fn source_for<'a, K: Eq + Hash + fmt::Display>(
+        &self,
+        sources: &'a mut Sources<K>,
+        key: K,
+        prefix: &str,
+    ) -> Result<&'a mut (Arc<str>, Option<TokenBucket>)> {
+        Ok(match sources.entry(key) {
+            Entry::Occupied(entry) => entry.into_mut(),
+            Entry::Vacant(entry) => {
+                let source: Arc<str> = format!("{}/{}", prefix, entry.key()).into();
+                let bucket = self.bucket_for(&source)?;
+                entry.insert((source, bucket))
+            }
+        })
+    }
+
+    fn send_line(
+        &self,
+        bucket: &mut Option<TokenBucket>,
+        source: &Arc<str>,
+        line: String,
+        tx: &UnboundedSender<LineMessage>,
+    ) -> Result<()> {
+        self.send_line_received(bucket, source, line, chrono::Utc::now().timestamp_millis(), tx)
+    }
+
+    // Same as `send_line` for sources which know better when the line was received.
+    fn send_line_received(
+        &self,
+        bucket: &mut Option<TokenBucket>,
+        source: &Arc<str>,
+        line: String,
+        recv_time_millis: i64,
+        tx: &UnboundedSender<LineMessage>,
+    ) -> Result<()> {
+        self.send_message(bucket, LineMessage::new(line, recv_time_millis, source.clone()), tx)
+    }
+
+    // Send the lines or, with `--input-format protobuf`, the records carried by a message bus payload.
+    #[cfg(any(feature = "pubsub", feature = "eventhubs", feature = "redis", feature = "nats", feature = "mqtt"))]
+    fn send_payload(
+        &self,
+        bucket: &mut Option<TokenBucket>,
+        source: &Arc<str>,
+        payload: &[u8],
+        recv_time_millis: i64,
+        tx: &UnboundedSender<LineMessage>,
+    ) -> Result<()> {
+        #[cfg(feature = "protobuf")]
+        if self.app()?.config().input_format == crate::config::InputFormat::Protobuf {
+            match crate::record::decode(payload, recv_time_millis) {
+                Ok(records) => {
+                    for record in records {
+                        self.send_message(bucket, LineMessage::from_record(record, recv_time_millis, source.clone()), tx)?;
+                    }
+                }
+                // Let it be counted as a decode error.
+                Err(err) => self.send_message(
+                    bucket,
+                    LineMessage::new(format!("Invalid protobuf payload: {}", err), recv_time_millis, source.clone())
+                        .failed_decoding(),
+                    tx,
+                )?,
+            }
+            return Ok(());
+        }
+
+        for line in String::from_utf8_lossy(payload).lines() {
+            self.send_line_received(bucket, source, line.to_string(), recv_time_millis, tx)?;
+        }
+
+        Ok(())
+    }
+
+    fn send_message(
+        &self,
+        bucket: &mut Option<TokenBucket>,
+        message: LineMessage,
+        tx: &UnboundedSender<LineMessage>,
+    ) -> Result<()> {
+        if let Some(ref mut bucket) = bucket {
+            if !bucket.try_take() {
+                self.count_throttled(message.source());
+                return Ok(());
+            }
+        }
+
+        tx.send(message)?;
+
+        Ok(())
+    }
+
+    fn count_throttled(&self, source: &str) {
+        *self.throttled_mut().entry(source.to_string()).or_insert(0) += 1;
+    }
+}
+
+// Read the next line with the number of bytes it took. Invalid input is replaced instead of failing it, the parser
+// counts such lines as decode errors.
+async fn next_lossy_line<R: AsyncBufRead + Unpin>(
+    reader: &mut R,
+    buf: &mut Vec<u8>,
+    encoding: Encoding,
+) -> io::Result<Option<(String, usize)>> {
+    buf.clear();
+    let (read, _) = encoding.read_line(reader, buf).await?;
+    if read == 0 {
+        return Ok(None);
+    }
+    Ok(Some((encoding.decode(buf), read)))
+}
+
+// Read the next line like `next_lossy_line` does, keeping at most a byte more than `max` of it. A longer line is
+// cut there, so that the parser counts it as oversized, and the rest of it is skipped without being buffered.
+async fn next_bounded_line<R: AsyncBufRead + Unpin>(
+    reader: &mut R,
+    buf: &mut Vec<u8>,
+    max: usize,
+) -> io::Result<Option<String>> {
+    buf.clear();
+    let limit = max as u64 + 1;
+    if (&mut *reader).take(limit).read_until(b'\n', buf).await? == 0 {
+        return Ok(None);
+    }
+    if buf.len() as u64 == limit && buf.last() != Some(&b'\n') {
+        loop {
+            let chunk = reader.fill_buf().await?;
+            if chunk.is_empty() {
+                break;
+            }
+            match chunk.iter().position(|&byte| byte == b'\n') {
+                Some(end) => {
+                    reader.consume(end + 1);
+                    break;
+                }
+                None => {
+                    let len = chunk.len();
+                    reader.consume(len);
+                }
+            }
+        }
+    }
+    Ok(Some(Encoding::Utf8.decode(buf)))
+}
+
+// What a failing container is reported as.
+fn docker_subject(container: &str) -> String {
+    format!("Docker container {}", container)
 }
@@ -0,0 +1,62 @@
+// Decoding is only used by the message bus inputs, which are optional features on their own.
+#![allow(dead_code)]
+use anyhow::{anyhow, Result};
+use prost::Message;
+
+use crate::types::{Level, ParsedLine};
+
+// The types below follow `proto/record.proto`.
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, prost::Enumeration)]
+#[repr(i32)]
+pub(crate) enum RecordLevel {
+    Info  = 0,
+    Error = 1,
+    Debug = 2,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub(crate) struct LogRecord {
+    #[prost(int64, tag = "1")]
+    pub(crate) logged_millis: i64,
+    #[prost(enumeration = "RecordLevel", tag = "2")]
+    pub(crate) level:         i32,
+    #[prost(string, tag = "3")]
+    pub(crate) ip:            String,
+    #[prost(string, tag = "4")]
+    pub(crate) message:       String,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub(crate) struct LogRecordBatch {
+    #[prost(message, repeated, tag = "1")]
+    pub(crate) records: Vec<LogRecord>,
+}
+
+/// Decode a `LogRecordBatch`. Records without a time get the receive time.
+pub(crate) fn decode(payload: &[u8], recv_time_millis: i64) -> Result<Vec<ParsedLine>> {
+    LogRecordBatch::decode(payload)?
+        .records
+        .into_iter()
+        .map(|record| {
+            let level = match RecordLevel::try_from(record.level) {
+                Ok(RecordLevel::Info) => Level::INFO,
+                Ok(RecordLevel::Error) => Level::ERROR,
+                Ok(RecordLevel::Debug) => Level::DEBUG,
+                Err(_) => return Err(anyhow!("Unknown record level {}", record.level)),
+            };
+            Ok(ParsedLine {
+                logged_millis: if record.logged_millis == 0 {
+                    recv_time_millis
+                }
+                else {
+                    record.logged_millis
+                },
+                level,
+                ip: record.ip,
+                message: record.message,
+                priority: None,
+            })
+        })
+        .collect()
+}
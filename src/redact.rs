@@ -0,0 +1,134 @@
+use std::borrow::Cow;
+
+use anyhow::Result;
+use clap::ValueEnum;
+use regex::Regex;
+use sha2::{Digest, Sha256};
+
+/// Built-in kinds of sensitive data which can be masked.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub(crate) enum RedactKind {
+    /// E-mail addresses
+    Email,
+    /// Credit card-like sequences of 13 to 19 digits, possibly separated with spaces or dashes
+    Card,
+    /// `key=value` secrets like tokens, passwords, API keys; and long opaque token-looking strings
+    Token,
+}
+
+impl RedactKind {
+    fn rules(&self) -> Vec<(&'static str, &'static str)> {
+        match self {
+            Self::Email => vec![(r"[A-Za-z0-9._%+-]+@[A-Za-z0-9.-]+\.[A-Za-z]{2,}", "<email>")],
+            Self::Card => vec![(r"\b(?:\d[ -]?){12,18}\d\b", "<card>")],
+            Self::Token => vec![
+                (
+                    r"(?i)\b(token|api[_-]?key|secret|password|passwd|authorization)(\s*[=:]\s*)\S+",
+                    "${1}${2}<token>",
+                ),
+                (r"\b[A-Za-z0-9+/_-]{32,}={0,2}", "<token>"),
+            ],
+        }
+    }
+}
+
+/// Masks sensitive data in log messages before they get into the statistics.
+pub(crate) struct Redactor {
+    rules: Vec<(Regex, String)>,
+}
+
+impl Redactor {
+    pub(crate) fn new(kinds: &[RedactKind], patterns: &[Regex]) -> Result<Self> {
+        let mut rules = Vec::new();
+
+        for kind in kinds {
+            for (re, replacement) in kind.rules() {
+                rules.push((Regex::new(re)?, replacement.to_string()));
+            }
+        }
+
+        for re in patterns {
+            rules.push((re.clone(), "<redacted>".to_string()));
+        }
+
+        Ok(Self { rules })
+    }
+
+    pub(crate) fn redact<'a>(&self, text: &'a str) -> Cow<'a, str> {
+        let mut text = Cow::Borrowed(text);
+        for (re, replacement) in self.rules.iter() {
+            if let Cow::Owned(redacted) = re.replace_all(&text, replacement.as_str()) {
+                text = Cow::Owned(redacted);
+            }
+        }
+        text
+    }
+}
+
+/// Record fields which can be replaced with their salted hashes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub(crate) enum HashField {
+    Ip,
+}
+
+/// Replaces sensitive values with salted hashes. Unlike redaction, equal values produce equal hashes so cardinality
+/// and grouping statistics remain correct.
+pub(crate) struct FieldHasher {
+    salt:     String,
+    fields:   Vec<HashField>,
+    patterns: Vec<Regex>,
+}
+
+impl FieldHasher {
+    pub(crate) fn new(salt: String, fields: &[HashField], patterns: &[Regex]) -> Self {
+        Self {
+            salt,
+            fields: fields.to_vec(),
+            patterns: patterns.to_vec(),
+        }
+    }
+
+    pub(crate) fn hash(&self, value: &str) -> String {
+        let mut hasher = Sha256::new();
+        hasher.update(self.salt.as_bytes());
+        hasher.update(value.as_bytes());
+        // 64 bits are more than enough to keep the values distinct while keeping reports readable.
+        hasher.finalize()[..8].iter().map(|b| format!("{:02x}", b)).collect()
+    }
+
+    pub(crate) fn is_hashed(&self, field: HashField) -> bool {
+        self.fields.contains(&field)
+    }
+
+    pub(crate) fn hash_field<'a>(&self, field: HashField, value: &'a str) -> Cow<'a, str> {
+        if self.is_hashed(field) {
+            Cow::Owned(self.hash(value))
+        }
+        else {
+            Cow::Borrowed(value)
+        }
+    }
+
+    /// Replace every match of the hashing patterns in the text with its hash. If a pattern has a capture group then
+    /// only the first group is hashed, allowing to keep context like `user=` in `user=(\w+)`.
+    pub(crate) fn hash_text<'a>(&self, text: &'a str) -> Cow<'a, str> {
+        let mut text = Cow::Borrowed(text);
+        for re in self.patterns.iter() {
+            let hashed = re.replace_all(&text, |caps: &regex::Captures| {
+                let whole = caps.get(0).unwrap();
+                match caps.get(1) {
+                    Some(group) => {
+                        let (start, end) = (group.start() - whole.start(), group.end() - whole.start());
+                        let whole = whole.as_str();
+                        format!("{}#{}{}", &whole[..start], self.hash(group.as_str()), &whole[end..])
+                    }
+                    None => format!("#{}", self.hash(whole.as_str())),
+                }
+            });
+            if let Cow::Owned(hashed) = hashed {
+                text = Cow::Owned(hashed);
+            }
+        }
+        text
+    }
+}
@@ -0,0 +1,230 @@
+use std::path::Path;
+
+use anyhow::{anyhow, Result};
+use redis::{
+    aio::MultiplexedConnection,
+    streams::{StreamMaxlen, StreamReadOptions, StreamReadReply},
+    AsyncConnectionConfig, AsyncTypedCommands, Client, ClientTlsConfig, TlsCertificates,
+};
+
+use crate::{
+    config::Config,
+    seconds::SecondCounts,
+    sink::{Delivery, Sink},
+    tls::ClientTls,
+};
+
+// How long a single XREADGROUP waits for new entries, in milliseconds.
+const READ_BLOCK_MS: usize = 1000;
+// Maximum number of entries fetched by a single XREADGROUP.
+const READ_COUNT: usize = 1000;
+// How long to wait before reconnecting after a failed publish.
+const PUBLISH_RETRY_INTERVAL: tokio::time::Duration = tokio::time::Duration::from_secs(5);
+// Seconds queued behind a slow publish are appended together.
+const PUBLISH_BATCH: usize = 16;
+// Seconds kept while Redis is behind, ten minutes of them.
+const PUBLISH_BUFFER: usize = 600;
+
+/// A stream entry carrying log lines or records.
+pub(crate) struct StreamEntry {
+    pub(crate) id:        String,
+    /// Value of the payload field, if the entry has one
+    pub(crate) payload:   Option<Vec<u8>>,
+    /// Milliseconds part of the entry ID, i.e. when Redis received the entry
+    pub(crate) recv_time: i64,
+}
+
+/// Connect to the server, with TLS for `rediss://` URLs.
+pub(crate) async fn connect(url: &str, tls: &ClientTls) -> Result<MultiplexedConnection> {
+    // Blocking reads would hit the default response timeout.
+    let config = AsyncConnectionConfig::new().set_response_timeout(None);
+    let client = if url.starts_with("rediss://") {
+        crate::tls::install_provider();
+        Client::build_with_tls(url, tls_certificates(tls)?)?
+    }
+    else {
+        Client::open(url)?
+    };
+    Ok(client.get_multiplexed_async_connection_with_config(&config).await?)
+}
+
+// The client takes the PEM files as they are.
+fn tls_certificates(tls: &ClientTls) -> Result<TlsCertificates> {
+    let read = |path: &Path| std::fs::read(path).map_err(|e| anyhow!("Can't read {:?}: {}", path, e));
+    let client_tls = match (&tls.cert, &tls.key) {
+        (Some(cert), Some(key)) => Some(ClientTlsConfig {
+            client_cert: read(cert)?,
+            client_key:  read(key)?,
+        }),
+        _ => None,
+    };
+    Ok(TlsCertificates {
+        client_tls,
+        root_cert: tls.ca.as_deref().map(read).transpose()?,
+    })
+}
+
+/// Reads a Redis Stream as a member of a consumer group. The group is created if it doesn't exist yet. Entries
+/// delivered to this consumer but never acknowledged, e.g. because of a crash, are re-read first.
+pub(crate) struct RedisStreamInput {
+    conn:     MultiplexedConnection,
+    stream:   String,
+    group:    String,
+    consumer: String,
+    field:    String,
+    /// ID to read from: `0` while going through the pending entries, then `>` for new ones
+    next_id:  &'static str,
+}
+
+impl RedisStreamInput {
+    pub(crate) async fn new(
+        url: &str,
+        tls: &ClientTls,
+        stream: &str,
+        group: &str,
+        consumer: &str,
+        field: &str,
+    ) -> Result<Self> {
+        let mut conn = connect(url, tls).await?;
+
+        match conn.xgroup_create_mkstream(stream, group, "$").await {
+            Ok(_) => (),
+            Err(err) if err.code() == Some("BUSYGROUP") => (),
+            Err(err) => return Err(err.into()),
+        }
+
+        Ok(Self {
+            conn,
+            stream: stream.to_string(),
+            group: group.to_string(),
+            consumer: consumer.to_string(),
+            field: field.to_string(),
+            next_id: "0",
+        })
+    }
+
+    /// Wait for new entries. An empty result means none arrived in a while. Entries without the payload field must
+    /// still be acknowledged.
+    pub(crate) async fn read(&mut self) -> Result<Vec<StreamEntry>> {
+        loop {
+            let mut options = StreamReadOptions::default()
+                .group(&self.group, &self.consumer)
+                .count(READ_COUNT);
+            if self.next_id == ">" {
+                options = options.block(READ_BLOCK_MS);
+            }
+
+            let reply: Option<StreamReadReply> = self
+                .conn
+                .xread_options(&[&self.stream], &[self.next_id], &options)
+                .await?;
+            let entries: Vec<StreamEntry> = reply
+                .into_iter()
+                .flat_map(|reply| reply.keys)
+                .flat_map(|key| key.ids)
+                .map(|entry| StreamEntry {
+                    payload:   entry.get::<Vec<u8>>(&self.field),
+                    recv_time: entry
+                        .id
+                        .split_once('-')
+                        .and_then(|(millis, _)| millis.parse().ok())
+                        .unwrap_or_else(|| chrono::Utc::now().timestamp_millis()),
+                    id:        entry.id,
+                })
+                .collect();
+
+            if entries.is_empty() && self.next_id != ">" {
+                // No more pending entries, wait for new ones.
+                self.next_id = ">";
+                continue;
+            }
+
+            return Ok(entries);
+        }
+    }
+
+    pub(crate) async fn acknowledge(&mut self, ids: &[String]) -> Result<()> {
+        if !ids.is_empty() {
+            self.conn.xack(&self.stream, &self.group, ids).await?;
+        }
+        Ok(())
+    }
+
+    pub(crate) fn source_name(&self) -> String {
+        format!("redis/{}", self.stream)
+    }
+}
+
+/// Appends the records received per second to the stream of `--redis-output`, an entry per second with the
+/// `second`, `entries`, `errors`, `infos`, `debugs` and `parse_errors` fields. The stream is trimmed to approximately
+/// `maxlen` entries.
+pub(crate) struct RedisSink {
+    url:    String,
+    tls:    ClientTls,
+    stream: String,
+    maxlen: usize,
+    conn:   Option<MultiplexedConnection>,
+}
+
+impl RedisSink {
+    pub(crate) fn from_config(config: &Config) -> Option<Self> {
+        Some(Self {
+            url:    config.redis_url.clone(),
+            tls:    ClientTls::from_config(config),
+            stream: config.redis_output.clone()?,
+            maxlen: config.redis_output_maxlen,
+            conn:   None,
+        })
+    }
+
+    async fn append(&mut self, counts: &SecondCounts) -> Result<()> {
+        let fields = [
+            ("second", counts.second.to_string()),
+            ("entries", counts.entries.to_string()),
+            ("errors", counts.errors.to_string()),
+            ("infos", counts.infos.to_string()),
+            ("debugs", counts.debugs.to_string()),
+            ("parse_errors", counts.parse_errors.to_string()),
+        ];
+
+        if self.conn.is_none() {
+            self.conn = Some(connect(&self.url, &self.tls).await?);
+        }
+        self.conn
+            .as_mut()
+            .unwrap()
+            .xadd_maxlen(&self.stream, StreamMaxlen::Approx(self.maxlen), "*", &fields)
+            .await?;
+        Ok(())
+    }
+}
+
+impl Sink for RedisSink {
+    type Item = SecondCounts;
+
+    fn name(&self) -> String {
+        format!("Redis output to {}", self.stream)
+    }
+
+    // Seconds keep coming, losing some while Redis is unavailable is better than piling them up.
+    fn delivery(&self) -> Delivery {
+        Delivery {
+            batch: PUBLISH_BATCH,
+            buffer: PUBLISH_BUFFER,
+            attempts: 2,
+            backoff: PUBLISH_RETRY_INTERVAL,
+            discard: true,
+            ..Delivery::default()
+        }
+    }
+
+    async fn deliver(&mut self, batch: &[SecondCounts]) -> Result<()> {
+        for counts in batch {
+            if let Err(err) = self.append(counts).await {
+                self.conn = None;
+                return Err(err);
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,287 @@
+use std::{collections::BTreeMap, sync::Arc};
+
+#[cfg(feature = "remote-write")]
+use anyhow::{anyhow, Result};
+#[cfg(feature = "remote-write")]
+use prost::Message;
+#[cfg(feature = "remote-write")]
+use tokio::time::Duration;
+
+#[cfg(feature = "remote-write")]
+use crate::{
+    sink::{Delivery, Rejected, Sink},
+    spool::Spooled,
+    tls::HttpClient,
+};
+use crate::{config::Config, failures::Failures, output::Publisher, types::Level};
+
+// How far behind the newest record a second is still expected to get records, before it is sent.
+const SLACK_SECS: i64 = 60;
+// Gaps of at most this many seconds between records are filled with zero samples, longer ones are left as gaps.
+const GAP_FILL_SECS: i64 = 300;
+// Seconds of samples in a single request.
+const REQUEST_SECS: usize = 1000;
+// Attempts to send a request, with the delay doubling after each failure.
+#[cfg(feature = "remote-write")]
+const ATTEMPTS: u32 = 5;
+#[cfg(feature = "remote-write")]
+const FIRST_RETRY_DELAY: Duration = Duration::from_secs(1);
+// Requests waiting to be sent; a backfill queues all of its seconds at exit, so there is room for months of them.
+#[cfg(feature = "remote-write")]
+const QUEUE_LEN: usize = 10_000;
+
+/// Records of a second: ERROR, INFO, DEBUG.
+type Second = (i64, [u64; 3]);
+
+// The types below follow `prompb/remote.proto` and `prompb/types.proto` of Prometheus.
+
+#[cfg(feature = "remote-write")]
+#[derive(Clone, PartialEq, prost::Message)]
+struct WriteRequest {
+    #[prost(message, repeated, tag = "1")]
+    timeseries: Vec<TimeSeries>,
+}
+
+#[cfg(feature = "remote-write")]
+#[derive(Clone, PartialEq, prost::Message)]
+struct TimeSeries {
+    #[prost(message, repeated, tag = "1")]
+    labels:  Vec<Label>,
+    #[prost(message, repeated, tag = "2")]
+    samples: Vec<Sample>,
+}
+
+#[cfg(feature = "remote-write")]
+#[derive(Clone, PartialEq, prost::Message)]
+struct Label {
+    #[prost(string, tag = "1")]
+    name:  String,
+    #[prost(string, tag = "2")]
+    value: String,
+}
+
+#[cfg(feature = "remote-write")]
+#[derive(Clone, PartialEq, prost::Message)]
+struct Sample {
+    #[prost(double, tag = "1")]
+    value:     f64,
+    #[prost(int64, tag = "2")]
+    timestamp: i64,
+}
+
+/// Records per second of log time and level, written with their original timestamps to a Prometheus remote-write
+/// endpoint as `qnode_logproc_records{level="..."}`, so that batch analysis of historical logs backfills a TSDB.
+/// Seconds are sent once no more records are expected for them; records arriving later than that are not counted.
+/// Without the `remote-write` feature it is always disabled.
+pub(crate) struct RemoteWrite {
+    /// Records of the seconds not sent yet
+    seconds: BTreeMap<i64, [u64; 3]>,
+    newest:  i64,
+    /// The last second sent
+    sent:    Option<i64>,
+    /// Sink of the chunks of seconds
+    sink:    Option<Publisher<Vec<Second>>>,
+}
+
+impl RemoteWrite {
+    #[cfg_attr(not(feature = "remote-write"), allow(unused_variables))]
+    pub(crate) fn new(config: &Config, failures: Arc<Failures>) -> Self {
+        #[cfg(feature = "remote-write")]
+        if let Some(ref url) = config.remote_write {
+            let sink = RemoteWriteSink {
+                url:    url.clone(),
+                labels: config.remote_write_labels.clone(),
+                client: HttpClient::new(config),
+            };
+            return Self {
+                sink: Some(crate::sink::spawn(sink, config, failures)),
+                ..Self::disabled()
+            };
+        }
+        Self::disabled()
+    }
+
+    pub(crate) fn disabled() -> Self {
+        Self {
+            seconds: BTreeMap::new(),
+            newest:  i64::MIN,
+            sent:    None,
+            sink:    None,
+        }
+    }
+
+    pub(crate) fn is_enabled(&self) -> bool {
+        self.sink.is_some()
+    }
+
+    pub(crate) fn push(&mut self, logged_millis: i64, level: Level) {
+        let second = logged_millis.div_euclid(1000);
+        if self.sent.is_some_and(|sent| second <= sent) {
+            return;
+        }
+        let column = match level {
+            Level::ERROR => 0,
+            Level::INFO => 1,
+            Level::DEBUG => 2,
+        };
+        self.seconds.entry(second).or_default()[column] += 1;
+        self.newest = self.newest.max(second);
+    }
+
+    /// Send the seconds no more records are expected for; all of them with `all`.
+    pub(crate) fn flush(&mut self, all: bool) {
+        let Some(ref sink) = self.sink
+        else {
+            return;
+        };
+        if self.seconds.is_empty() {
+            return;
+        }
+        let rest = if all {
+            BTreeMap::new()
+        }
+        else {
+            self.seconds.split_off(&(self.newest - SLACK_SECS))
+        };
+        let ready = std::mem::replace(&mut self.seconds, rest);
+        let Some(&last) = ready.keys().next_back()
+        else {
+            return;
+        };
+
+        // Zero samples make the gaps between records graph as no records rather than as missing data.
+        let mut filled = Vec::new();
+        let mut previous = self.sent;
+        for (second, counts) in ready {
+            if let Some(previous) = previous.filter(|previous| second - previous <= GAP_FILL_SECS) {
+                filled.extend((previous + 1..second).map(|second| (second, [0; 3])));
+            }
+            filled.push((second, counts));
+            previous = Some(second);
+        }
+        self.sent = Some(last);
+
+        for chunk in filled.chunks(REQUEST_SECS) {
+            sink.send(chunk.to_vec());
+        }
+    }
+
+    /// Send all seconds left; the sink is returned to wait for the delivery.
+    pub(crate) fn close(&mut self) -> Option<Publisher<Vec<Second>>> {
+        self.flush(true);
+        self.sink.take()
+    }
+}
+
+#[cfg(feature = "remote-write")]
+fn write_request(seconds: &[Second], labels: &[(String, String)]) -> WriteRequest {
+    let timeseries = ["ERROR", "INFO", "DEBUG"]
+        .into_iter()
+        .enumerate()
+        .map(|(column, level)| {
+            // Sorted by name as the protocol requires.
+            let mut series_labels = vec![
+                ("__name__".to_string(), "qnode_logproc_records".to_string()),
+                ("level".to_string(), level.to_string()),
+            ];
+            series_labels.extend(labels.iter().cloned());
+            series_labels.sort();
+            TimeSeries {
+                labels:  series_labels.into_iter().map(|(name, value)| Label { name, value }).collect(),
+                samples: seconds
+                    .iter()
+                    .map(|(second, counts)| Sample {
+                        value:     counts[column] as f64,
+                        timestamp: second * 1000,
+                    })
+                    .collect(),
+            }
+        })
+        .collect();
+    WriteRequest { timeseries }
+}
+
+/// Parse a `NAME=VALUE` label of the remote-written series.
+#[cfg(feature = "remote-write")]
+pub(crate) fn parse_label(s: &str) -> Result<(String, String)> {
+    let (name, value) = s.split_once('=').ok_or_else(|| anyhow!("Expected NAME=VALUE, got '{}'", s))?;
+    let valid = name.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_')
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+    if !valid || name.starts_with("__") || name == "level" {
+        return Err(anyhow!("Bad label name '{}'", name));
+    }
+    Ok((name.to_string(), value.to_string()))
+}
+
+#[cfg(feature = "remote-write")]
+impl Spooled for Vec<Second> {
+    fn to_spool(&self) -> Option<String> {
+        serde_json::to_string(self).ok()
+    }
+
+    fn from_spool(line: &str) -> Option<Self> {
+        serde_json::from_str(line).ok()
+    }
+}
+
+/// Sends the chunks of seconds as requests of their own, in order.
+#[cfg(feature = "remote-write")]
+struct RemoteWriteSink {
+    url:    String,
+    labels: Vec<(String, String)>,
+    client: HttpClient,
+}
+
+#[cfg(feature = "remote-write")]
+impl Sink for RemoteWriteSink {
+    type Item = Vec<Second>;
+
+    fn name(&self) -> String {
+        format!("Remote write to {}", self.url)
+    }
+
+    fn delivery(&self) -> Delivery {
+        Delivery {
+            buffer: QUEUE_LEN,
+            attempts: ATTEMPTS,
+            backoff: FIRST_RETRY_DELAY,
+            ..Delivery::default()
+        }
+    }
+
+    async fn deliver(&mut self, batch: &[Vec<Second>]) -> Result<()> {
+        for seconds in batch {
+            let request = write_request(seconds, &self.labels);
+            let body = snap::raw::Encoder::new()
+                .compress_vec(&request.encode_to_vec())
+                .map_err(|e| Rejected(e.to_string()))?;
+            post(self.client.get()?, &self.url, body).await?;
+        }
+        Ok(())
+    }
+}
+
+// An error is worth retrying; a rejection, like of samples out of order, is not.
+#[cfg(feature = "remote-write")]
+async fn post(client: &reqwest::Client, url: &str, body: Vec<u8>) -> Result<()> {
+    let response = client
+        .post(url)
+        .header("Content-Type", "application/x-protobuf")
+        .header("Content-Encoding", "snappy")
+        .header("X-Prometheus-Remote-Write-Version", "0.1.0")
+        .body(body)
+        .send()
+        .await?;
+    let status = response.status();
+    if status.is_success() {
+        return Ok(());
+    }
+    let text = response.text().await.unwrap_or_default();
+    let message = format!("Remote write failed with {}: {}", status, text.trim_end());
+    if status.is_server_error() || status == reqwest::StatusCode::TOO_MANY_REQUESTS {
+        Err(anyhow!(message))
+    }
+    else {
+        Err(Rejected(message).into())
+    }
+}
@@ -0,0 +1,1163 @@
+use std::{
+    collections::VecDeque,
+    io::Write,
+    path::PathBuf,
+    sync::{Arc, Mutex},
+};
+
+use anyhow::Result;
+use chrono::{DateTime, Local};
+use console::{pad_str, style, truncate_str, Alignment, Key, Term};
+use qnode_logproc_core::{AttackKind, ErrorType, SnapshotView, StatsWindow};
+
+use crate::{
+    alert::Alerts,
+    config::{AlertNotify, AlertSpec, Config, TimeFormat},
+    format::{format_bytes, format_duration, format_time, NumberFormat},
+    keyboard,
+    redact::HashField,
+    rotate::{Rotation, RotatingFile},
+    session::Recorder,
+    slo::BURN_WINDOWS,
+};
+
+// Width of the progress bar of a file being backfilled.
+const BACKFILL_BAR_WIDTH: usize = 20;
+// Width of the bars of the rates by hour.
+const HOURLY_BAR_WIDTH: usize = 30;
+// Width of a comparison pane column.
+const PANE_WIDTH: usize = 48;
+
+/// Statistics of the records selected by a `--compare` filter.
+#[derive(Debug)]
+pub(crate) struct Pane {
+    pub(crate) label: String,
+    pub(crate) view:  SnapshotView,
+}
+
+/// Presents the statistics report. It is invoked on every refresh and once more for the final report.
+pub(crate) trait Renderer: Send + Sync {
+    /// `panes` are empty unless `--compare` is used.
+    fn render(&self, view: &SnapshotView, window: &StatsWindow, panes: &[Pane]) -> Result<()>;
+
+    /// Called on refresh while there are no records yet.
+    fn idle(&self, _now: DateTime<Local>) -> Result<()> {
+        Ok(())
+    }
+
+    /// Close the output file, if any, so it is re-opened with the next report.
+    fn reopen(&self) {}
+
+    /// Check the alert rules reloaded from the `--rules` file from now on, if the report has an alerts panel.
+    fn set_alert_rules(&self, _rules: &[AlertSpec]) {}
+}
+
+/// Where a textual renderer writes to.
+pub(crate) enum Target {
+    Stdout,
+    File(Mutex<RotatingFile>),
+}
+
+impl Target {
+    pub(crate) fn new(path: Option<PathBuf>, rotation: Rotation) -> Self {
+        match path {
+            Some(path) => Self::File(Mutex::new(RotatingFile::new(path, rotation))),
+            None => Self::Stdout,
+        }
+    }
+
+    fn write_lines<I: IntoIterator<Item = String>>(&self, lines: I) -> Result<()> {
+        match self {
+            Self::Stdout => {
+                let mut out = std::io::stdout().lock();
+                for line in lines {
+                    writeln!(out, "{}", line)?;
+                }
+                out.flush()?;
+                Ok(())
+            }
+            // A report is never split between a file and its rotated predecessor.
+            Self::File(file) => {
+                let text = lines.into_iter().map(|line| line + "\n").collect::<String>();
+                file.lock().unwrap().append(text.as_bytes())
+            }
+        }
+    }
+
+    fn reopen(&self) {
+        if let Self::File(file) = self {
+            file.lock().unwrap().reopen();
+        }
+    }
+}
+
+/// Options of the textual report which come from the configuration.
+#[derive(Debug, Clone)]
+pub(crate) struct ReportOptions {
+    hashed_ips:     bool,
+    show_throttled: bool,
+    show_escalated: bool,
+    time_format:    TimeFormat,
+    numbers:        NumberFormat,
+    alerts:         Vec<AlertSpec>,
+    bell:           bool,
+    flash:          bool,
+    skew_threshold: f64,
+    record:         Option<PathBuf>,
+}
+
+impl ReportOptions {
+    pub(crate) fn new(config: &Config) -> Self {
+        Self {
+            hashed_ips:     config.hash_field.contains(&HashField::Ip),
+            show_throttled: !config.rate_limits.is_empty(),
+            show_escalated: !config.escalations.is_empty(),
+            time_format:    config.time_format,
+            numbers:        NumberFormat::new(config.locale.as_deref(), config.number_style),
+            alerts:         config.alerts.clone(),
+            bell:           config.alert_notify.contains(&AlertNotify::Bell),
+            flash:          config.alert_notify.contains(&AlertNotify::Flash),
+            skew_threshold: config.skew_threshold,
+            record:         config.record.clone(),
+        }
+    }
+}
+
+/// Headline numbers of a report, kept to show how they change.
+#[derive(Debug, Clone, Copy)]
+struct Headline {
+    timestamp:  DateTime<Local>,
+    rate:       f64,
+    error_rate: f64,
+    malformed:  f64,
+}
+
+impl Headline {
+    fn new(view: &SnapshotView) -> Self {
+        Self {
+            timestamp:  view.timestamp,
+            rate:       view.rate,
+            error_rate: view.error_rate as f64,
+            malformed:  view.malformed as f64,
+        }
+    }
+}
+
+/// What a textual renderer remembers from one report to the next.
+#[derive(Debug)]
+struct ReportState {
+    trends: Trends,
+    alerts: Alerts,
+}
+
+impl ReportState {
+    fn new(options: &ReportOptions) -> Self {
+        Self {
+            trends: Trends::default(),
+            alerts: Alerts::new(options.alerts.clone()),
+        }
+    }
+}
+
+/// Headlines of the previous reports, back to one window ago.
+#[derive(Debug, Default)]
+struct Trends {
+    history: Mutex<VecDeque<Headline>>,
+}
+
+impl Trends {
+    /// Record the headline of the view and return the ones of the previous refresh and of the previous window.
+    fn record(&self, view: &SnapshotView) -> (Option<Headline>, Option<Headline>) {
+        let mut history = self.history.lock().unwrap();
+        let cutoff = view.timestamp - chrono::Duration::milliseconds(view.window_ms as i64);
+        // Keep the newest headline that is at least a window old in front.
+        while history.len() > 1 && history[1].timestamp <= cutoff {
+            history.pop_front();
+        }
+        let last = history.back().copied();
+        let last_window = history.front().copied().filter(|h| h.timestamp <= cutoff);
+        history.push_back(Headline::new(view));
+        (last, last_window)
+    }
+}
+
+/// Reports shown on the terminal, to browse back through them with the arrow keys.
+#[derive(Debug)]
+struct History {
+    reports:  VecDeque<(DateTime<Local>, Vec<String>)>,
+    capacity: usize,
+    /// Index of the report shown while paused, `None` when live
+    cursor:   Option<usize>,
+}
+
+impl History {
+    fn new(capacity: usize) -> Self {
+        Self {
+            reports: VecDeque::with_capacity(capacity),
+            capacity,
+            cursor: None,
+        }
+    }
+
+    fn push(&mut self, timestamp: DateTime<Local>, lines: Vec<String>) {
+        if self.capacity == 0 {
+            return;
+        }
+        if self.reports.len() == self.capacity {
+            self.reports.pop_front();
+            // Keep showing the same report, unless it is gone.
+            self.cursor = self.cursor.map(|cursor| cursor.saturating_sub(1));
+        }
+        self.reports.push_back((timestamp, lines));
+    }
+
+    // Move the cursor by `step` reports; moving past the newest one goes live again.
+    fn step(&mut self, step: isize) {
+        let Some(newest) = self.reports.len().checked_sub(1)
+        else {
+            return;
+        };
+        let cursor = self.cursor.unwrap_or(newest) as isize + step;
+        self.cursor = if cursor > newest as isize {
+            None
+        }
+        else {
+            Some(cursor.max(0) as usize)
+        };
+    }
+
+    // Apply a key press. Returns true if what is shown has changed.
+    fn on_key(&mut self, key: &Key) -> bool {
+        let cursor = self.cursor;
+        match key {
+            Key::ArrowLeft | Key::ArrowUp => self.step(-1),
+            Key::ArrowRight | Key::ArrowDown => self.step(1),
+            Key::PageUp => self.step(-10),
+            Key::PageDown => self.step(10),
+            Key::Home if !self.reports.is_empty() => self.cursor = Some(0),
+            Key::End | Key::Escape => self.cursor = None,
+            _ => (),
+        }
+        cursor != self.cursor
+    }
+
+    // The report on the screen and the status line under it.
+    fn current(&self, time_format: TimeFormat) -> Option<(&[String], String)> {
+        match self.cursor {
+            None => {
+                let (_, lines) = self.reports.back()?;
+                Some((
+                    lines,
+                    "LIVE. Left/Right to browse history, 1-9 to mute a top error, s to export, Ctrl-C to stop."
+                        .to_string(),
+                ))
+            }
+            Some(cursor) => {
+                let (timestamp, lines) = &self.reports[cursor];
+                let status = format!(
+                    "PAUSED at {} ({}/{}). Left/Right to browse, End to return live, Ctrl-C to stop.",
+                    format_time(*timestamp, time_format),
+                    cursor + 1,
+                    self.reports.len()
+                );
+                Some((lines, status))
+            }
+        }
+    }
+}
+
+/// Lines shown under the terminal report until the next key press, like the outcome of an export.
+pub(crate) type Notice = Arc<Mutex<Vec<String>>>;
+
+/// Redraws the report in place on the terminal. Unless the input is finite, earlier reports can be browsed with the
+/// arrow keys.
+pub(crate) struct TerminalRenderer {
+    term:       Term,
+    options:    ReportOptions,
+    state:      ReportState,
+    history:    Arc<Mutex<History>>,
+    /// Suggest to stop with Ctrl-C unless the input is finite
+    once:       bool,
+    /// Top error messages of the live report, to be muted by their positions
+    top_errors: Arc<Mutex<Vec<String>>>,
+    notice:     Notice,
+    recorder:   Option<Mutex<Recorder>>,
+}
+
+impl TerminalRenderer {
+    /// Digit keys acknowledge the top error messages of the live report, `on_mute` is called with the message. The `d`
+    /// key calls `on_dump`, the `s` key `on_export` with the notice to tell the outcome in. With `on_query` the `:` key
+    /// opens a prompt in the notice, the query typed is passed to it on Enter and the lines it returns are shown.
+    #[allow(clippy::too_many_arguments)]
+    pub(crate) fn new<F, D, E, Q>(
+        term: Term,
+        options: ReportOptions,
+        once: bool,
+        history: usize,
+        on_mute: F,
+        on_dump: D,
+        on_export: E,
+        on_query: Option<Q>,
+    ) -> Result<Self>
+    where
+        F: Fn(&str) + Send + 'static,
+        D: Fn() + Send + 'static,
+        E: Fn(Notice) + Send + 'static,
+        Q: Fn(&str) -> Vec<String> + Send + 'static,
+    {
+        let history = Arc::new(Mutex::new(History::new(if once { 0 } else { history })));
+        let top_errors = Arc::new(Mutex::new(Vec::<String>::new()));
+        let notice = Notice::default();
+        if !once {
+            let (term, history, top_errors, notice, time_format) =
+                (term.clone(), history.clone(), top_errors.clone(), notice.clone(), options.time_format);
+            // The query being typed while the prompt is open.
+            let mut prompt: Option<String> = None;
+            keyboard::spawn(term.clone(), move |key| {
+                let editing = match prompt.take() {
+                    Some(mut query) => {
+                        match key {
+                            Key::Enter => {
+                                if let Some(ref on_query) = on_query {
+                                    *notice.lock().unwrap() = on_query(&query);
+                                }
+                            }
+                            Key::Escape => notice.lock().unwrap().clear(),
+                            Key::Backspace => {
+                                query.pop();
+                                prompt = Some(query);
+                            }
+                            Key::Char(c) => {
+                                query.push(c);
+                                prompt = Some(query);
+                            }
+                            _ => prompt = Some(query),
+                        }
+                        true
+                    }
+                    None if key == Key::Char(':') && on_query.is_some() => {
+                        prompt = Some(String::new());
+                        true
+                    }
+                    None => false,
+                };
+                if editing {
+                    if let Some(ref query) = prompt {
+                        *notice.lock().unwrap() = vec![format!("SQL> {}", query)];
+                    }
+                    let history = history.lock().unwrap();
+                    if history.capacity > 0 {
+                        let _ = Self::draw(&term, &history, &notice.lock().unwrap(), time_format);
+                    }
+                    return;
+                }
+                if key == Key::Char('s') {
+                    on_export(notice.clone());
+                    return;
+                }
+                notice.lock().unwrap().clear();
+                if let Key::Char(digit @ '1'..='9') = key {
+                    let pos = digit as usize - '1' as usize;
+                    if let Some(message) = top_errors.lock().unwrap().get(pos) {
+                        on_mute(message);
+                    }
+                    return;
+                }
+                if key == Key::Char('d') {
+                    on_dump();
+                    return;
+                }
+                let mut history = history.lock().unwrap();
+                if history.capacity > 0 && history.on_key(&key) {
+                    // The renderer draws over it anyway on the next refresh.
+                    let _ = Self::draw(&term, &history, &notice.lock().unwrap(), time_format);
+                }
+            })?;
+        }
+        let recorder = options.record.as_deref().map(Recorder::create).transpose()?.map(Mutex::new);
+        Ok(Self {
+            term,
+            state: ReportState::new(&options),
+            options,
+            once,
+            history,
+            top_errors,
+            notice,
+            recorder,
+        })
+    }
+
+    fn draw(term: &Term, history: &History, notice: &[String], time_format: TimeFormat) -> Result<()> {
+        let Some((lines, status)) = history.current(time_format)
+        else {
+            return Ok(());
+        };
+        term.clear_screen()?;
+        term.move_cursor_to(0, 0)?;
+        for line in lines {
+            term.write_line(line)?;
+        }
+        term.write_line(&status)?;
+        for line in notice {
+            term.write_line(line)?;
+        }
+        term.flush()?;
+        Ok(())
+    }
+}
+
+impl Renderer for TerminalRenderer {
+    fn render(&self, view: &SnapshotView, window: &StatsWindow, panes: &[Pane]) -> Result<()> {
+        let fired = self.state.alerts.update(view).iter().any(|event| event.firing);
+        *self.top_errors.lock().unwrap() = view.top_errors.iter().map(|top| top.message.clone()).collect();
+        let mut lines = report_lines(view, window, &self.options, &self.state);
+        lines.extend(comparison_lines(panes, &self.options));
+        if self.options.flash && self.state.alerts.is_firing() {
+            lines[0] = style(&lines[0]).reverse().bold().to_string();
+        }
+        if self.options.bell && fired {
+            self.term.write_str("\x07")?;
+        }
+        if let Some(ref recorder) = self.recorder {
+            recorder.lock().unwrap().record(&lines)?;
+        }
+
+        let mut history = self.history.lock().unwrap();
+        if history.capacity == 0 {
+            let term = &self.term;
+            term.clear_screen()?;
+            term.move_cursor_to(0, 0)?;
+            for line in lines {
+                term.write_line(&line)?;
+            }
+            if !self.once {
+                term.write_line("s to export, Ctrl-C to stop.")?;
+            }
+            for line in self.notice.lock().unwrap().iter() {
+                term.write_line(line)?;
+            }
+            term.flush()?;
+            return Ok(());
+        }
+
+        history.push(view.timestamp, lines);
+        // While paused the same report is drawn again, with its updated position in the history.
+        Self::draw(&self.term, &history, &self.notice.lock().unwrap(), self.options.time_format)
+    }
+
+    fn idle(&self, now: DateTime<Local>) -> Result<()> {
+        let term = &self.term;
+        term.clear_screen()?;
+        term.move_cursor_to(0, 0)?;
+        term.write_line(&format!("{} No records yet.", format_time(now, self.options.time_format)))?;
+        Ok(())
+    }
+
+    fn set_alert_rules(&self, rules: &[AlertSpec]) {
+        self.state.alerts.set_rules(rules.to_vec());
+    }
+}
+
+/// Keeps a single status line with the rate, the error share and the top error updated in place. Fits a narrow
+/// terminal pane.
+pub(crate) struct MinimalRenderer {
+    term:    Term,
+    options: ReportOptions,
+    /// Only the final report is rendered, which must end with a newline
+    once:    bool,
+}
+
+impl MinimalRenderer {
+    pub(crate) fn new(term: Term, options: ReportOptions, once: bool) -> Self {
+        Self { term, options, once }
+    }
+
+    fn draw(&self, line: &str) -> Result<()> {
+        let line = truncate_str(line, self.term.size().1 as usize, "…");
+        self.term.clear_line()?;
+        if self.once {
+            self.term.write_line(&line)?;
+        }
+        else {
+            self.term.write_str(&line)?;
+        }
+        self.term.flush()?;
+        Ok(())
+    }
+}
+
+impl Renderer for MinimalRenderer {
+    fn render(&self, view: &SnapshotView, _window: &StatsWindow, _panes: &[Pane]) -> Result<()> {
+        let num = &self.options.numbers;
+        let mut line = format!(
+            "{} | {} entries/sec | errors {}%",
+            format_time(view.timestamp, self.options.time_format),
+            num.float(view.rate, 2),
+            num.float(view.errors as f64 / view.entries as f64 * 100.0, 2)
+        );
+        if let Some(top) = view.top_errors.first() {
+            line.push_str(&format!(" | top: \"{}\" ({})", top.message, num.int(top.count)));
+        }
+        if !view.failures.is_empty() {
+            line.push_str(&format!(" | {} failing", view.failures.len()));
+        }
+        self.draw(&line)
+    }
+
+    fn idle(&self, now: DateTime<Local>) -> Result<()> {
+        self.draw(&format!("{} | No records yet.", format_time(now, self.options.time_format)))
+    }
+}
+
+/// Prints every report after the previous one, without terminal control sequences. Suitable for pipes and logs.
+pub(crate) struct PlainTextRenderer {
+    options: ReportOptions,
+    target:  Target,
+    state:   ReportState,
+}
+
+impl PlainTextRenderer {
+    pub(crate) fn new(options: ReportOptions, target: Target) -> Self {
+        Self {
+            state: ReportState::new(&options),
+            options,
+            target,
+        }
+    }
+}
+
+impl Renderer for PlainTextRenderer {
+    fn render(&self, view: &SnapshotView, window: &StatsWindow, panes: &[Pane]) -> Result<()> {
+        self.state.alerts.update(view);
+        let mut lines = report_lines(view, window, &self.options, &self.state);
+        lines.extend(comparison_lines(panes, &self.options));
+        self.target.write_lines(lines)
+    }
+
+    fn reopen(&self) {
+        self.target.reopen();
+    }
+
+    fn set_alert_rules(&self, rules: &[AlertSpec]) {
+        self.state.alerts.set_rules(rules.to_vec());
+    }
+}
+
+/// Prints every snapshot as a JSON line.
+pub(crate) struct JsonRenderer {
+    target: Target,
+}
+
+impl JsonRenderer {
+    pub(crate) fn new(target: Target) -> Self {
+        Self { target }
+    }
+}
+
+impl Renderer for JsonRenderer {
+    fn render(&self, view: &SnapshotView, _window: &StatsWindow, _panes: &[Pane]) -> Result<()> {
+        self.target.write_lines([serde_json::to_string(view)?])
+    }
+
+    fn reopen(&self) {
+        self.target.reopen();
+    }
+}
+
+// Change of a headline number as ▲/▼ with percentage, versus the previous refresh and the previous window.
+fn deltas(num: &NumberFormat, current: f64, last: Option<f64>, last_window: Option<f64>) -> String {
+    let delta = |previous: f64| {
+        if current == previous {
+            "=".to_string()
+        }
+        else if previous == 0.0 {
+            "▲ new".to_string()
+        }
+        else {
+            let change = (current - previous) / previous.abs() * 100.0;
+            format!("{} {}%", if change > 0.0 { '▲' } else { '▼' }, num.float(change.abs(), 1))
+        }
+    };
+    let mut parts = Vec::new();
+    if let Some(last) = last {
+        parts.push(format!("{} since last", delta(last)));
+    }
+    if let Some(last_window) = last_window {
+        parts.push(format!("{} since previous window", delta(last_window)));
+    }
+    if parts.is_empty() {
+        String::new()
+    }
+    else {
+        format!(" ({})", parts.join(", "))
+    }
+}
+
+// The textual report shared by the terminal and the plain text renderers. The alerts must be updated by then.
+// The latest occurrences of the message, newest first.
+fn example_lines(lines: &mut Vec<String>, view: &SnapshotView, message: &str, options: &ReportOptions) {
+    for example in view.examples.get(message).into_iter().flatten().rev() {
+        let provenance = &example.provenance;
+        // Files are their own sources, only the offset is added then.
+        let mut origin = match provenance.origin {
+            Some(ref origin) => match origin.strip_prefix(example.source.as_str()) {
+                Some(offset) if offset.starts_with(':') => offset.to_string(),
+                _ => format!(" at {}", origin),
+            },
+            None => String::new(),
+        };
+        if provenance.seq > 0 {
+            origin.push_str(&format!(", line #{}", provenance.seq));
+        }
+        lines.push(format!(
+            "       {} IP:{} from {}{}",
+            format_time(example.timestamp, options.time_format),
+            example.ip,
+            example.source,
+            origin
+        ));
+    }
+}
+
+pub(crate) fn rejected_label(err_type: ErrorType) -> &'static str {
+    match err_type {
+        ErrorType::Malformed => "Malformed",
+        ErrorType::BadTimestamp => "Bad timestamps",
+        ErrorType::UnknownLevel => "Unknown levels",
+        ErrorType::Oversized => "Oversized",
+        ErrorType::DecodeError => "Decode errors",
+        ErrorType::LateArrival => "Late arrivals",
+    }
+}
+
+fn report_lines(
+    view: &SnapshotView,
+    window: &StatsWindow,
+    options: &ReportOptions,
+    state: &ReportState,
+) -> Vec<String> {
+    let num = &options.numbers;
+    let (last, last_window) = state.trends.record(view);
+    let trend = |field: fn(&Headline) -> f64| {
+        deltas(
+            num,
+            field(&Headline::new(view)),
+            last.as_ref().map(field),
+            last_window.as_ref().map(field),
+        )
+    };
+    let share = |count: i64| num.float(count as f64 / view.entries as f64 * 100.0, 2);
+    let mut lines = vec![
+        format!(
+            "Stats as of {}{}",
+            format_time(view.timestamp, options.time_format),
+            if view.maintenance { " [MAINTENANCE]" } else { "" }
+        ),
+        "-".repeat(80),
+        format!(
+            "Entries: {} per {} seconds (window: {}sec)",
+            num.int(view.entries),
+            num.float(view.collected_interval_ms as f64 / 1000.0, 2),
+            view.window_ms / 1000
+        ),
+        format!(
+            "Current rate: {} entries/sec{}",
+            num.int(view.current_rate),
+            if view.bucket_ms != 1000 { format!(" (last {}ms)", view.bucket_ms) } else { String::new() }
+        ),
+        format!(
+            "Rate        : {} entries/sec{}",
+            num.float(view.rate, 2),
+            trend(|h| h.rate)
+        ),
+        format!("Peak rate   : {} entries/sec", num.float(view.peak_rate, 2)),
+        format!("Average lag : {} sec", num.float(view.avg_lag, 2)),
+    ];
+    if view.bucket_mean > 0.0 {
+        lines.push(format!(
+            "Burstiness  : {} (p99 {} entries per {}ms, mean {})",
+            num.float(view.burstiness(), 2),
+            num.int(view.bucket_p99),
+            view.bucket_ms,
+            num.float(view.bucket_mean, 2)
+        ));
+    }
+    lines.extend([
+        String::new(),
+        format!(
+            "Errors: {}% ({} entries{}); rate: {} errors/sec{}",
+            share(view.errors),
+            num.int(view.errors),
+            if view.affected_requests > 0 {
+                format!(" in {} requests", num.int(view.affected_requests))
+            }
+            else {
+                String::new()
+            },
+            num.float(view.error_rate as f64, 2),
+            trend(|h| h.error_rate)
+        ),
+        format!("Infos: {}% ({} entries)", share(view.infos), num.int(view.infos)),
+        format!("Debugs: {}% ({} entries)", share(view.debugs), num.int(view.debugs)),
+        format!("Malformed: {}{}", num.int(view.malformed), trend(|h| h.malformed)),
+    ]);
+    // Other causes lines are rejected for are only shown when they occur.
+    for err_type in ErrorType::ALL.into_iter().skip(1) {
+        let count = view.rejected(err_type);
+        if count > 0 {
+            lines.push(format!("{}: {}", rejected_label(err_type), num.int(count)));
+        }
+    }
+    if view.muted > 0 {
+        lines.push(format!("Muted errors: {}", num.int(view.muted)));
+    }
+    lines.push(format!(
+        "Distinct IPs: {}{}",
+        num.int(view.distinct_ips),
+        if options.hashed_ips { " (hashed)" } else { "" }
+    ));
+
+    if !view.hourly.is_empty() {
+        lines.push(String::new());
+        lines.push("Rate by hour (typical range shaded):".to_string());
+        lines.extend(hourly_lines(view, num));
+    }
+
+    // The alerts panel goes on top to be seen first.
+    let alerts = state.alerts.lines(view, num);
+    if !alerts.is_empty() {
+        lines.splice(2..2, alerts.into_iter().chain([String::new()]));
+    }
+    if view.sampled_out > 0 {
+        lines.push(format!("Sampled out under overload: {}", num.int(view.sampled_out)));
+    }
+    if view.below_min_level > 0 {
+        lines.push(format!("Below --min-level: {}", num.int(view.below_min_level)));
+    }
+
+    if !view.files.is_empty() {
+        lines.push(String::new());
+        lines.push("Files:".to_string());
+        for (source, file) in view.files.iter() {
+            let last_read = match file.last_read {
+                Some(ts) => format!("last read {}", format_time(ts, TimeFormat::Relative)),
+                None => "nothing read yet".to_string(),
+            };
+            let Some(end) = file.backfill_end
+            else {
+                lines.push(format!(
+                    "  {}: {} of {} ({}%), {} lines/sec, {}",
+                    source,
+                    format_bytes(file.offset),
+                    format_bytes(file.size),
+                    num.float(file.offset as f64 / file.size.max(1) as f64 * 100.0, 1),
+                    num.float(file.lines_per_sec, 2),
+                    last_read
+                ));
+                continue;
+            };
+            let done = file.offset as f64 / end.max(1) as f64;
+            let filled = (done * BACKFILL_BAR_WIDTH as f64) as usize;
+            lines.push(format!(
+                "  {}: backfilling [{}{}] {}% of {}, {} lines/sec",
+                source,
+                "#".repeat(filled),
+                ".".repeat(BACKFILL_BAR_WIDTH - filled),
+                num.float(done * 100.0, 1),
+                format_bytes(end),
+                num.float(file.lines_per_sec, 2)
+            ));
+        }
+    }
+
+    // Snapshots of older versions only know about failing inputs.
+    let failing_inputs = view
+        .input_errors
+        .iter()
+        .filter(|(source, _)| !view.failures.iter().any(|failure| &failure.subject == *source))
+        .collect::<Vec<_>>();
+    if !view.failures.is_empty() || !failing_inputs.is_empty() {
+        lines.push(String::new());
+        lines.push("Failing:".to_string());
+        for failure in view.failures.iter() {
+            lines.push(format!(
+                "  {} failing for {}: {} ×{}",
+                failure.subject,
+                format_duration((failure.last - failure.since).num_seconds()),
+                failure.error,
+                num.int(failure.count)
+            ));
+        }
+        for (source, error) in failing_inputs {
+            lines.push(format!("  {}: {}", source, error));
+        }
+    }
+    if !view.output_drops.is_empty() {
+        lines.push(String::new());
+        lines.push("Dropped by outputs falling behind:".to_string());
+        for (output, count) in view.output_drops.iter() {
+            lines.push(format!("  {}: {}", output, num.int(count)));
+        }
+    }
+
+    let skewed = view
+        .source_skew
+        .iter()
+        .filter(|(_, skew)| skew.abs() > options.skew_threshold)
+        .collect::<Vec<_>>();
+    if !skewed.is_empty() {
+        lines.push(String::new());
+        lines.push("Clock skew:".to_string());
+        for (source, skew) in skewed {
+            let direction = if *skew > 0.0 { "ahead" } else { "behind" };
+            lines.push(format!("  {}: {} {}", source, format_duration(skew.abs().round() as i64), direction));
+        }
+    }
+
+    if view.sources.len() > 1 {
+        lines.push(String::new());
+        lines.push("Entries by source:".to_string());
+        let mut sources = view.sources.iter().collect::<Vec<_>>();
+        sources.sort_by(|a, b| b.1.cmp(a.1));
+        for (source, count) in sources.into_iter().take(5) {
+            lines.push(format!("  {}: {}", source, num.int(count)));
+        }
+    }
+
+    if options.show_throttled {
+        lines.push(String::new());
+        lines.push("Throttled lines:".to_string());
+        if view.throttled.is_empty() {
+            lines.push("  none".to_string());
+        }
+        for (source, count) in view.throttled.iter() {
+            lines.push(format!("  {}: {}", source, num.int(count)));
+        }
+    }
+
+    if !view.truncated_datagrams.is_empty() {
+        lines.push(String::new());
+        lines.push("Truncated datagrams:".to_string());
+        for (source, count) in view.truncated_datagrams.iter() {
+            lines.push(format!("  {}: {}", source, num.int(count)));
+        }
+    }
+
+    if options.show_escalated {
+        lines.push(String::new());
+        lines.push("Escalated to ERROR:".to_string());
+        if view.escalated.is_empty() {
+            lines.push("  none".to_string());
+        }
+        for (rule, count) in view.escalated.iter() {
+            lines.push(format!("  {}: {}", rule, num.int(count)));
+        }
+    }
+
+    if !view.attacks.is_empty() {
+        lines.push(String::new());
+        lines.push("Attacks:".to_string());
+        for attack in view.attacks.iter() {
+            let what = match attack.kind {
+                AttackKind::BruteForce => {
+                    format!("brute force from {}: {} distinct messages", attack.subject, attack.distinct)
+                }
+                AttackKind::Scan => format!("scan \"{}\": from {} IPs", attack.subject, attack.distinct),
+            };
+            lines.push(format!("  {}, {} attempts", what, num.int(attack.attempts)));
+        }
+    }
+
+    if !view.suspicious.is_empty() {
+        lines.push(String::new());
+        lines.push("Suspicious sources:".to_string());
+        for ip in view.suspicious.iter() {
+            lines.push(format!(
+                "  {}: {} errors/min, usually {}{}, since {}",
+                ip.ip,
+                num.int(ip.errors),
+                num.float(ip.baseline, 1),
+                if ip.new { " (new)" } else { "" },
+                format_time(ip.since, options.time_format)
+            ));
+        }
+    }
+
+    if let Some(ref slo) = view.slo {
+        let period = match slo.period_secs {
+            secs if secs % 86_400 == 0 => format!("{}d", secs / 86_400),
+            secs => format!("{}h", secs / 3_600),
+        };
+        lines.push(String::new());
+        lines.push(format!("Error budget ({}% over {}):", num.float(slo.objective * 100.0, 3), period));
+        lines.push(format!(
+            "  {}% of {} entries good, {}% of the budget left",
+            num.float((1.0 - slo.total.error_ratio()) * 100.0, 3),
+            num.int(slo.total.entries),
+            num.float(slo.budget_remaining() * 100.0, 1)
+        ));
+        let burn_rates = BURN_WINDOWS
+            .iter()
+            .map(|(window, ..)| format!("{} {}", window, num.float(slo.burn_rate(window), 2)))
+            .collect::<Vec<_>>();
+        lines.push(format!("  burn rate: {}", burn_rates.join(", ")));
+    }
+
+    lines.push(String::new());
+    lines.push("Top error messages:".to_string());
+    for (pos, top) in view.top_errors.iter().enumerate() {
+        lines.push(format!("  {}. \"{}\" ({} entries)", pos + 1, top.message, num.int(top.count)));
+        example_lines(&mut lines, view, &top.message, options);
+    }
+
+    if !view.statuses.is_empty() {
+        let total = view.statuses.values().sum::<i64>();
+        lines.push(String::new());
+        lines.push("HTTP status:".to_string());
+        for (class, count) in view.statuses.iter() {
+            lines.push(format!(
+                "  {}: {:>12} ({:>6}%) {}",
+                class,
+                num.int(count),
+                num.float(*count as f64 / total as f64 * 100.0, 2),
+                view.status_series.get(class).map(|series| sparkline(series)).unwrap_or_default()
+            ));
+        }
+    }
+
+    if !view.facilities.is_empty() {
+        lines.push(String::new());
+        lines.push("Syslog facilities:".to_string());
+        let mut facilities = view.facilities.iter().collect::<Vec<_>>();
+        facilities.sort_by_key(|(_, count)| std::cmp::Reverse(count.errors));
+        for (facility, count) in facilities {
+            lines.push(format!(
+                "  {:<12} ERROR: {:>10} INFO: {:>10} DEBUG: {:>10}",
+                facility,
+                num.int(count.errors),
+                num.int(count.infos),
+                num.int(count.debugs)
+            ));
+        }
+    }
+
+    if !view.top_endpoints.is_empty() {
+        lines.push(String::new());
+        lines.push("Top failing endpoints:".to_string());
+        for top in view.top_endpoints.iter() {
+            lines.push(format!(
+                "  {}: {} errors of {} requests ({}%)",
+                top.endpoint,
+                num.int(top.errors),
+                num.int(top.requests),
+                num.float(top.errors as f64 / top.requests as f64 * 100.0, 2)
+            ));
+        }
+    }
+
+    if !view.top_requests.is_empty() {
+        lines.push(String::new());
+        lines.push("Requests with the most errors:".to_string());
+        for top in view.top_requests.iter() {
+            lines.push(format!("  {}: {} errors", top.request, num.int(top.errors)));
+        }
+    }
+
+    if !view.top_clients.is_empty() {
+        lines.push(String::new());
+        lines.push("Clients with errors:".to_string());
+        for top in view.top_clients.iter() {
+            lines.push(format!(
+                "  {} {}: {} errors of {} requests ({}%)",
+                top.family,
+                top.version,
+                num.int(top.errors),
+                num.int(top.requests),
+                num.float(top.errors as f64 / top.requests as f64 * 100.0, 2)
+            ));
+        }
+    }
+
+    for table in view.groups.iter().filter(|table| !table.rows.is_empty()) {
+        lines.push(String::new());
+        lines.push(format!("By {}:", table.name));
+        for row in table.rows.iter() {
+            lines.push(format!(
+                "  {}: {} records, {} errors ({}%)",
+                row.key,
+                num.int(row.records),
+                num.int(row.errors),
+                num.float(row.errors as f64 / row.records as f64 * 100.0, 2)
+            ));
+        }
+    }
+
+    if !view.sequences.is_empty() {
+        lines.push(String::new());
+        lines.push("Sequence gaps:".to_string());
+        for (source, seq) in view.sequences.iter() {
+            let mut line = format!(
+                "  {}: {} missing ({}% lost), {} duplicates",
+                source,
+                num.int(seq.missing),
+                num.float(seq.loss() * 100.0, 2),
+                num.int(seq.duplicates)
+            );
+            if seq.restarts > 0 {
+                line.push_str(&format!(", restarted {} times", num.int(seq.restarts)));
+            }
+            lines.push(line);
+        }
+    }
+
+    lines.push(String::new());
+    lines.push("Trending messages:".to_string());
+    for trending in &view.trending {
+        lines.push(format!("  \"{}\" (rate: {})", trending.message, num.float(trending.rate, 2)));
+    }
+
+    // Only the latest capture fits the report, snapshots carry the others.
+    if let Some(context) = view.contexts.last() {
+        lines.push(String::new());
+        lines.push(format!(
+            "Latest context ({}, {}):",
+            context.source,
+            format_time(context.timestamp, options.time_format)
+        ));
+        lines.extend(context.before.iter().map(|line| format!("    {}", line)));
+        lines.push(format!("  > {}", context.line));
+        lines.extend(context.after.iter().map(|line| format!("    {}", line)));
+    }
+
+    // Snapshots rendered without their window, like merged ones, have no insights into it.
+    if !window.is_empty() {
+        lines.push(String::new());
+        lines.push("Insights:".to_string());
+        lines.push(format!("Error messages per bucket table size: {}", window.error_buckets()));
+    }
+    if !view.run.version.is_empty() {
+        lines.push(String::new());
+        lines.push(format!(
+            "qnode-logproc {} on {} since {}, config {}, reading {}",
+            view.run.version,
+            view.run.host,
+            format_time(view.run.started, options.time_format),
+            view.run.config_hash,
+            view.run.inputs.join(", ")
+        ));
+    }
+    lines.push("-".repeat(80));
+
+    lines
+}
+
+// A bar of the rate of every hour with the typical range of the hour shaded behind it, on a scale shared by all of
+// them.
+fn hourly_lines(view: &SnapshotView, num: &NumberFormat) -> Vec<String> {
+    let scale = view
+        .hourly
+        .iter()
+        .map(|hourly| hourly.typical.map_or(hourly.rate, |typical| typical.high.max(hourly.rate)))
+        .fold(f64::MIN_POSITIVE, f64::max);
+    let cells = |rate: f64| (rate / scale * HOURLY_BAR_WIDTH as f64).round() as usize;
+    view.hourly
+        .iter()
+        .map(|hourly| {
+            let bar = cells(hourly.rate);
+            // Even the narrowest range gets a cell to be seen.
+            let band = hourly.typical.map_or(0..0, |typical| {
+                let low = cells(typical.low).min(HOURLY_BAR_WIDTH - 1);
+                low..cells(typical.high).max(low + 1)
+            });
+            let chart = (0..HOURLY_BAR_WIDTH)
+                .map(|cell| match (cell < bar, band.contains(&cell)) {
+                    (true, true) => '▓',
+                    (true, false) => '█',
+                    (false, true) => '░',
+                    (false, false) => ' ',
+                })
+                .collect::<String>();
+            let typical = match hourly.typical {
+                Some(typical) => format!(
+                    ", typical {}-{} over {} days{}",
+                    num.float(typical.low, 2),
+                    num.float(typical.high, 2),
+                    typical.days,
+                    if hourly.rate > typical.high {
+                        " ABOVE"
+                    }
+                    else if hourly.rate < typical.low {
+                        " BELOW"
+                    }
+                    else {
+                        ""
+                    }
+                ),
+                None => String::new(),
+            };
+            format!(
+                "  {} |{}| {} entries/sec{}",
+                hourly.hour.format("%H:%M"),
+                chart,
+                num.float(hourly.rate, 2),
+                typical
+            )
+        })
+        .collect()
+}
+
+// Bars of the values relative to the largest one, a blank for zero.
+fn sparkline(values: &[i64]) -> String {
+    const BARS: [char; 8] = ['▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
+    let max = values.iter().copied().max().unwrap_or(0).max(1);
+    values
+        .iter()
+        .map(|value| match *value {
+            value if value <= 0 => ' ',
+            value => BARS[((value - 1) * BARS.len() as i64 / max) as usize],
+        })
+        .collect()
+}
+
+// The comparison panes side by side, empty without panes.
+fn comparison_lines(panes: &[Pane], options: &ReportOptions) -> Vec<String> {
+    if panes.is_empty() {
+        return Vec::new();
+    }
+
+    let num = &options.numbers;
+    let columns: Vec<Vec<String>> = panes
+        .iter()
+        .map(|Pane { label, view }| {
+            let share = |count: i64| num.float(count as f64 / view.entries.max(1) as f64 * 100.0, 2);
+            let mut column = vec![
+                format!("[{}]", label),
+                format!("Entries     : {}", num.int(view.entries)),
+                format!("Rate        : {} entries/sec", num.float(view.rate, 2)),
+                format!("Errors      : {}% ({})", share(view.errors), num.int(view.errors)),
+                format!("Malformed   : {}", num.int(view.malformed)),
+                format!("Distinct IPs: {}", num.int(view.distinct_ips)),
+                "Top errors:".to_string(),
+            ];
+            for top in view.top_errors.iter().take(5) {
+                column.push(format!("  {} \"{}\"", num.int(top.count), top.message));
+            }
+            column
+        })
+        .collect();
+
+    let mut lines = vec![String::new(), "Comparison:".to_string()];
+    let height = columns.iter().map(Vec::len).max().unwrap_or(0);
+    for row in 0..height {
+        let cells: Vec<String> = columns
+            .iter()
+            .map(|column| {
+                let cell = column.get(row).map(String::as_str).unwrap_or("");
+                pad_str(&truncate_str(cell, PANE_WIDTH - 2, "…"), PANE_WIDTH, Alignment::Left, None).into_owned()
+            })
+            .collect();
+        lines.push(cells.concat().trim_end().to_string());
+    }
+    lines.push("-".repeat(80));
+    lines
+}
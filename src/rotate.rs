@@ -0,0 +1,177 @@
+use std::{
+    fs::{self, File, OpenOptions},
+    io::Write,
+    path::{Path, PathBuf},
+    time::{Duration, Instant},
+};
+
+use anyhow::Result;
+use chrono::{Local, TimeDelta};
+
+#[cfg(feature = "compress")]
+use crate::config::Compression;
+
+/// When and how a file written to is rotated.
+#[derive(Debug, Clone)]
+pub(crate) struct Rotation {
+    pub(crate) size:        Option<u64>,
+    pub(crate) interval:    Option<Duration>,
+    /// Number of rotated files to keep
+    pub(crate) keep:        usize,
+    #[cfg(feature = "compress")]
+    pub(crate) compression: Option<Compression>,
+}
+
+/// A file appended to, rotated by its size or age. A rotated file is renamed to `PATH.TIMESTAMP` and compressed in
+/// the background. Opened on demand and dropped on `reopen` to support external log rotation as well.
+pub(crate) struct RotatingFile {
+    path:     PathBuf,
+    rotation: Rotation,
+    file:     Option<File>,
+    size:     u64,
+    opened:   Instant,
+}
+
+impl RotatingFile {
+    pub(crate) fn new(path: PathBuf, rotation: Rotation) -> Self {
+        Self {
+            path,
+            rotation,
+            file: None,
+            size: 0,
+            opened: Instant::now(),
+        }
+    }
+
+    /// Append the data, rotating the file first if it is due. The data always goes into a single file.
+    pub(crate) fn append(&mut self, data: &[u8]) -> Result<()> {
+        if self.file.is_none() {
+            self.open()?;
+        }
+        if self.is_due(data.len() as u64) {
+            self.rotate()?;
+            self.open()?;
+        }
+        let file = self.file.as_mut().unwrap();
+        file.write_all(data)?;
+        file.flush()?;
+        self.size += data.len() as u64;
+        Ok(())
+    }
+
+    /// Close the file so it is re-opened with the next write.
+    pub(crate) fn reopen(&mut self) {
+        self.file = None;
+    }
+
+    fn open(&mut self) -> Result<()> {
+        let file = OpenOptions::new().create(true).append(true).open(&self.path)?;
+        self.size = file.metadata()?.len();
+        self.opened = Instant::now();
+        self.file = Some(file);
+        Ok(())
+    }
+
+    // An empty file is never rotated, whatever its age.
+    fn is_due(&self, adding: u64) -> bool {
+        self.size > 0
+            && (self.rotation.size.is_some_and(|size| self.size + adding > size)
+                || self.rotation.interval.is_some_and(|interval| self.opened.elapsed() >= interval))
+    }
+
+    fn rotate(&mut self) -> Result<()> {
+        self.file = None;
+        let rotated = rotated_path(&self.path);
+        fs::rename(&self.path, &rotated)?;
+
+        let (path, rotation) = (self.path.clone(), self.rotation.clone());
+        std::thread::spawn(move || {
+            #[cfg(feature = "compress")]
+            if let Some(compression) = rotation.compression {
+                if let Err(err) = compress(&rotated, compression) {
+                    eprintln!("Failed to compress {}: {:?}", rotated.display(), err);
+                }
+            }
+            if let Err(err) = prune(&path, rotation.keep) {
+                eprintln!("Failed to remove old rotated files of {}: {:?}", path.display(), err);
+            }
+        });
+        Ok(())
+    }
+}
+
+// `PATH.YYYYmmddTHHMMSSmmm`, which sorts by time, the same with any compression suffix.
+fn rotated_path(path: &Path) -> PathBuf {
+    let mut time = Local::now();
+    loop {
+        let mut rotated = path.as_os_str().to_owned();
+        rotated.push(time.format(".%Y%m%dT%H%M%S%3f").to_string());
+        let taken = rotated_files(path).is_ok_and(|files| {
+            files
+                .iter()
+                .any(|file| file.as_os_str().to_string_lossy().starts_with(&*rotated.to_string_lossy()))
+        });
+        if !taken {
+            return PathBuf::from(rotated);
+        }
+        time += TimeDelta::milliseconds(1);
+    }
+}
+
+// Rotated files of the path, the oldest first.
+fn rotated_files(path: &Path) -> Result<Vec<PathBuf>> {
+    let dir = match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+    let prefix = format!("{}.", path.file_name().unwrap_or_default().to_string_lossy());
+    let mut files = fs::read_dir(dir)?
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| {
+            entry
+                .file_name()
+                .to_string_lossy()
+                .strip_prefix(&prefix)
+                .is_some_and(|rest| rest.starts_with(|c: char| c.is_ascii_digit()))
+        })
+        .map(|entry| dir.join(entry.file_name()))
+        .collect::<Vec<_>>();
+    files.sort();
+    Ok(files)
+}
+
+fn prune(path: &Path, keep: usize) -> Result<()> {
+    let files = rotated_files(path)?;
+    for file in files.iter().take(files.len().saturating_sub(keep)) {
+        fs::remove_file(file)?;
+    }
+    Ok(())
+}
+
+// Replace the file with its compressed version.
+#[cfg(feature = "compress")]
+fn compress(path: &Path, compression: Compression) -> Result<()> {
+    let mut compressed = path.as_os_str().to_owned();
+    compressed.push(match compression {
+        Compression::Gzip => ".gz",
+        Compression::Zstd => ".zst",
+    });
+    let mut input = File::open(path)?;
+    let output = File::create(&compressed)?;
+    let result = match compression {
+        Compression::Gzip => {
+            let mut encoder = flate2::write::GzEncoder::new(output, flate2::Compression::default());
+            std::io::copy(&mut input, &mut encoder).and_then(|_| encoder.finish().map(|_| ()))
+        }
+        Compression::Zstd => zstd::stream::copy_encode(&mut input, output, 0),
+    };
+    match result {
+        Ok(()) => fs::remove_file(path)?,
+        Err(err) => {
+            // A partial file is worse than none.
+            let _ = fs::remove_file(&compressed);
+            return Err(err.into());
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,101 @@
+use std::{io, pin::Pin, str::FromStr, sync::Arc};
+
+use anyhow::{anyhow, Result};
+use async_compression::tokio::bufread::GzipDecoder;
+use futures::{StreamExt, TryStreamExt};
+use object_store::{
+    aws::{AmazonS3, AmazonS3Builder},
+    path::Path as ObjectPath,
+    ObjectStore, ObjectStoreExt,
+};
+use tokio::io::{AsyncBufRead, BufReader, Lines};
+use tokio_util::io::StreamReader;
+
+pub(crate) type ObjectLines = Lines<Pin<Box<dyn AsyncBufRead + Send>>>;
+
+/// `s3://bucket/prefix` location of archived logs.
+#[derive(Debug, Clone)]
+pub(crate) struct S3Location {
+    pub(crate) bucket: String,
+    pub(crate) prefix: String,
+}
+
+impl FromStr for S3Location {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let rest = s
+            .strip_prefix("s3://")
+            .ok_or_else(|| anyhow!("S3 location must start with s3://, got '{}'", s))?;
+        let (bucket, prefix) = rest.split_once('/').unwrap_or((rest, ""));
+        if bucket.is_empty() {
+            return Err(anyhow!("No bucket name in '{}'", s));
+        }
+        Ok(Self {
+            bucket: bucket.to_string(),
+            prefix: prefix.to_string(),
+        })
+    }
+}
+
+/// Reads log objects from an S3 bucket. Credentials, region and endpoint are taken from the standard `AWS_*`
+/// environment variables.
+pub(crate) struct S3Input {
+    store:    Arc<AmazonS3>,
+    location: S3Location,
+}
+
+impl S3Input {
+    pub(crate) fn new(location: &S3Location) -> Result<Self> {
+        let store = AmazonS3Builder::from_env()
+            .with_bucket_name(&location.bucket)
+            .build()?;
+        Ok(Self {
+            store:    Arc::new(store),
+            location: location.clone(),
+        })
+    }
+
+    /// Keys of all objects under the prefix, in lexicographical order, which for dated log names is also the
+    /// chronological one.
+    pub(crate) async fn list(&self) -> Result<Vec<String>> {
+        let prefix = ObjectPath::from(self.location.prefix.as_str());
+        let mut keys = self
+            .store
+            .list(Some(&prefix))
+            .map_ok(|meta| meta.location.to_string())
+            .try_collect::<Vec<_>>()
+            .await?;
+        keys.sort();
+        Ok(keys)
+    }
+
+    /// Stream lines of the object. Objects with `.gz` suffix are decompressed on the fly.
+    pub(crate) async fn lines(&self, key: &str) -> Result<ObjectLines> {
+        use tokio::io::AsyncBufReadExt;
+
+        let stream = self
+            .store
+            .get(&ObjectPath::from(key))
+            .await?
+            .into_stream()
+            .map(|chunk| chunk.map_err(io::Error::other));
+        let reader = StreamReader::new(stream);
+
+        let reader: Pin<Box<dyn AsyncBufRead + Send>> = if key.ends_with(".gz") {
+            let mut decoder = GzipDecoder::new(reader);
+            // Concatenated gzip files are common for appended logs.
+            decoder.multiple_members(true);
+            Box::pin(BufReader::new(decoder))
+        }
+        else {
+            Box::pin(reader)
+        };
+
+        Ok(reader.lines())
+    }
+
+    pub(crate) fn source_name(&self, key: &str) -> String {
+        format!("s3://{}/{}", self.location.bucket, key)
+    }
+}
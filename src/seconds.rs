@@ -0,0 +1,110 @@
+use std::{collections::BTreeMap, sync::Arc};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{config::Config, failures::Failures, output::Publisher, spool::Spooled, types::Level};
+
+// How long after its end a second is still expected to get records, the parser being behind the reader.
+const SLACK_SECS: i64 = 2;
+
+/// Records received during a second of wall-clock time.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub(crate) struct SecondCounts {
+    /// Unix time of the second
+    pub(crate) second:       i64,
+    pub(crate) entries:      u64,
+    pub(crate) errors:       u64,
+    pub(crate) infos:        u64,
+    pub(crate) debugs:       u64,
+    /// Lines which couldn't be parsed or decoded
+    pub(crate) parse_errors: u64,
+}
+
+impl Spooled for SecondCounts {
+    fn to_spool(&self) -> Option<String> {
+        serde_json::to_string(self).ok()
+    }
+
+    fn from_spool(line: &str) -> Option<Self> {
+        serde_json::from_str(line).ok()
+    }
+}
+
+/// Records per second of receive time, the rate and error buckets sent to the second sinks, like `--redis-output`.
+/// Every second is sent, quiet ones with zero counts, once no more records are expected for it; records counted later
+/// than that go to the next second not sent yet. Without a second sink it is disabled.
+pub(crate) struct Seconds {
+    /// Counts of the seconds not sent yet
+    seconds: BTreeMap<i64, SecondCounts>,
+    /// The last second sent
+    sent:    i64,
+    sinks:   Vec<Publisher<SecondCounts>>,
+}
+
+impl Seconds {
+    pub(crate) fn new(config: &Config, failures: Arc<Failures>) -> Self {
+        Self {
+            sinks: crate::sink::second_sinks(config, &failures),
+            ..Self::disabled()
+        }
+    }
+
+    pub(crate) fn disabled() -> Self {
+        Self {
+            seconds: BTreeMap::new(),
+            sent:    chrono::Utc::now().timestamp() - SLACK_SECS,
+            sinks:   Vec::new(),
+        }
+    }
+
+    pub(crate) fn is_enabled(&self) -> bool {
+        !self.sinks.is_empty()
+    }
+
+    pub(crate) fn push_ok(&mut self, received_millis: i64, level: Level) {
+        let counts = self.counts(received_millis);
+        counts.entries += 1;
+        match level {
+            Level::ERROR => counts.errors += 1,
+            Level::INFO => counts.infos += 1,
+            Level::DEBUG => counts.debugs += 1,
+        }
+    }
+
+    pub(crate) fn push_err(&mut self, received_millis: i64) {
+        self.counts(received_millis).parse_errors += 1;
+    }
+
+    /// Send the seconds no more records are expected for; all of them up to now with `all`.
+    pub(crate) fn flush(&mut self, all: bool) {
+        if self.sinks.is_empty() {
+            return;
+        }
+        let now = chrono::Utc::now().timestamp();
+        let last = if all { now } else { now - SLACK_SECS };
+        for second in self.sent + 1..=last {
+            let counts = self.seconds.remove(&second).unwrap_or_else(|| SecondCounts {
+                second,
+                ..SecondCounts::default()
+            });
+            for sink in self.sinks.iter() {
+                sink.send(counts.clone());
+            }
+        }
+        self.sent = self.sent.max(last);
+    }
+
+    /// Send all seconds left; the sinks are returned to wait for the delivery.
+    pub(crate) fn close(&mut self) -> Vec<Publisher<SecondCounts>> {
+        self.flush(true);
+        std::mem::take(&mut self.sinks)
+    }
+
+    fn counts(&mut self, received_millis: i64) -> &mut SecondCounts {
+        let second = received_millis.div_euclid(1000).max(self.sent + 1);
+        self.seconds.entry(second).or_insert_with(|| SecondCounts {
+            second,
+            ..SecondCounts::default()
+        })
+    }
+}
@@ -0,0 +1,63 @@
+use std::{
+    fs::File,
+    io::{BufRead, BufReader, Seek, SeekFrom},
+    path::Path,
+};
+
+use qnode_logproc_core::try_parse_line;
+
+/// How far out of order records of a file may be. Seeking starts this much before `--from`, reading stops this much
+/// after `--to`.
+pub(crate) const TIME_SLACK_MS: i64 = 60_000;
+
+// Below this size a range is read through rather than split further.
+const MIN_RANGE: u64 = 64 * 1024;
+
+// Lines to look through for a record with a time after a seek, for files with runs of rejected lines.
+const MAX_PROBE_LINES: usize = 100;
+
+/// Find by a binary search the offset of a line in the UTF-8 log file, sorted by time, such that records before it
+/// are older than `from_millis` less the slack. The offset is always at a line start; 0 if nothing can be skipped.
+pub(crate) fn seek_time(path: &Path, from_millis: i64) -> std::io::Result<u64> {
+    let mut reader = BufReader::new(File::open(path)?);
+    let target = from_millis - TIME_SLACK_MS;
+    let (mut low, mut high) = (0, reader.get_ref().metadata()?.len());
+
+    while high - low > MIN_RANGE {
+        let middle = low + (high - low) / 2;
+        match probe(&mut reader, middle, high)? {
+            Some(millis) if millis < target => low = middle,
+            _ => high = middle,
+        }
+    }
+
+    if low == 0 {
+        return Ok(0);
+    }
+    // The line `low` points into is older than the target too, the next one is where to start.
+    reader.seek(SeekFrom::Start(low))?;
+    let mut line = Vec::new();
+    Ok(low + reader.read_until(b'\n', &mut line)? as u64)
+}
+
+// Time of the first record starting after `offset` and before `limit`.
+fn probe(reader: &mut BufReader<File>, offset: u64, limit: u64) -> std::io::Result<Option<i64>> {
+    reader.seek(SeekFrom::Start(offset))?;
+    let mut line = Vec::new();
+    let mut pos = offset + reader.read_until(b'\n', &mut line)? as u64;
+    for _ in 0..MAX_PROBE_LINES {
+        if pos >= limit {
+            break;
+        }
+        line.clear();
+        let read = reader.read_until(b'\n', &mut line)?;
+        if read == 0 {
+            break;
+        }
+        pos += read as u64;
+        if let Ok(record) = try_parse_line(String::from_utf8_lossy(&line).trim_end()) {
+            return Ok(Some(record.logged_millis));
+        }
+    }
+    Ok(None)
+}
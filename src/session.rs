@@ -0,0 +1,141 @@
+use std::{
+    fs::{self, File},
+    io::{BufRead, BufReader, Write},
+    path::Path,
+    thread,
+    time::{Duration, Instant},
+};
+
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, Local};
+use console::Term;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    config::{Config, PlaybackArgs},
+    format::format_time,
+};
+
+const FORMAT: &str = "qnode-logproc session";
+const VERSION: u32 = 1;
+
+/// The first line of a session file.
+#[derive(Debug, Serialize, Deserialize)]
+struct Header {
+    format:  String,
+    version: u32,
+    started: DateTime<Local>,
+}
+
+/// A report as drawn on the terminal. Only the lines which differ from the previous frame are stored, most of the
+/// report stays the same from one refresh to the next.
+#[derive(Debug, Serialize, Deserialize)]
+struct Frame {
+    /// Milliseconds since the recording started
+    ms:      u64,
+    /// Number of lines of the report
+    len:     usize,
+    /// Changed lines by their index
+    changed: Vec<(usize, String)>,
+}
+
+/// Records the reports the terminal renderer draws, with their timing, into a session file for `playback`.
+#[derive(Debug)]
+pub(crate) struct Recorder {
+    file:     File,
+    started:  Instant,
+    previous: Vec<String>,
+}
+
+impl Recorder {
+    pub(crate) fn create(path: &Path) -> Result<Self> {
+        let mut file = File::create(path).map_err(|e| anyhow!("Can't create session file {:?}: {}", path, e))?;
+        let header = Header {
+            format:  FORMAT.to_string(),
+            version: VERSION,
+            started: Local::now(),
+        };
+        writeln!(file, "{}", serde_json::to_string(&header)?)?;
+        Ok(Self {
+            file,
+            started: Instant::now(),
+            previous: Vec::new(),
+        })
+    }
+
+    pub(crate) fn record(&mut self, lines: &[String]) -> Result<()> {
+        let changed = lines
+            .iter()
+            .enumerate()
+            .filter(|(idx, line)| self.previous.get(*idx) != Some(*line))
+            .map(|(idx, line)| (idx, line.clone()))
+            .collect();
+        let frame = Frame {
+            ms: self.started.elapsed().as_millis() as u64,
+            len: lines.len(),
+            changed,
+        };
+        writeln!(self.file, "{}", serde_json::to_string(&frame)?)?;
+        self.previous = lines.to_vec();
+        Ok(())
+    }
+}
+
+/// Replay a session file on the terminal with the timing it was recorded with.
+pub(crate) fn play(config: &Config, args: &PlaybackArgs) -> Result<usize> {
+    if args.speed <= 0.0 || args.max_gap < 0.0 {
+        return Err(anyhow!("--speed must be above 0 and --max-gap not below"));
+    }
+    let file = File::open(&args.file).map_err(|e| anyhow!("Can't read {:?}: {}", args.file, e))?;
+    let mut lines = BufReader::new(file).lines();
+    let header: Header = match lines.next() {
+        Some(line) => serde_json::from_str(&line?).map_err(|e| anyhow!("Not a session file {:?}: {}", args.file, e))?,
+        None => return Err(anyhow!("Empty session file {:?}", args.file)),
+    };
+    if header.format != FORMAT || header.version > VERSION {
+        return Err(anyhow!("Unsupported session file {:?}", args.file));
+    }
+    // Frames are counted beforehand to show the position.
+    let total = fs::read_to_string(&args.file)?.lines().skip(1).filter(|line| !line.is_empty()).count();
+
+    let term = Term::stdout();
+    let mut due = Instant::now();
+    let mut previous_ms = 0;
+    let mut report = Vec::<String>::new();
+    for (idx, line) in lines.enumerate() {
+        let line = line?;
+        if line.is_empty() {
+            continue;
+        }
+        let frame: Frame = serde_json::from_str(&line).map_err(|e| anyhow!("Bad frame {}: {}", idx + 1, e))?;
+        report.resize(frame.len, String::new());
+        for (idx, line) in frame.changed {
+            if idx < report.len() {
+                report[idx] = line;
+            }
+        }
+
+        // Long quiet periods of the recording are cut short.
+        let gap = frame.ms.saturating_sub(previous_ms) as f64 / 1000.0 / args.speed;
+        previous_ms = frame.ms;
+        due += Duration::from_secs_f64(gap.min(args.max_gap));
+        if let Some(wait) = due.checked_duration_since(Instant::now()) {
+            thread::sleep(wait);
+        }
+        term.clear_screen()?;
+        term.move_cursor_to(0, 0)?;
+        for line in report.iter() {
+            term.write_line(line)?;
+        }
+        let recorded = header.started + chrono::Duration::milliseconds(frame.ms as i64);
+        term.write_line(&format!(
+            "PLAYBACK of {} at {} ({}/{}). Ctrl-C to stop.",
+            args.file.display(),
+            format_time(recorded, config.time_format),
+            idx + 1,
+            total
+        ))?;
+        term.flush()?;
+    }
+    Ok(0)
+}
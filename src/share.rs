@@ -0,0 +1,94 @@
+use anyhow::{anyhow, Result};
+use qnode_logproc_core::SnapshotView;
+use qrcode::{render::unicode::Dense1x2, QrCode};
+use tokio::runtime::Handle;
+
+use crate::{config::Config, render::Notice, tls::HttpClient};
+
+/// Uploads the snapshots exported from the terminal report to the `--share-url` endpoint and shows the links to them,
+/// so that what is on the screen can be passed around in a chat.
+pub(crate) struct Sharer {
+    url:     String,
+    token:   Option<String>,
+    qr:      bool,
+    client:  HttpClient,
+    /// Exports come from the keyboard thread, outside of the runtime
+    runtime: Handle,
+}
+
+impl Sharer {
+    pub(crate) fn new(config: &Config) -> Option<Self> {
+        Some(Self {
+            url:     config.share_url.clone()?,
+            token:   config.share_token.clone(),
+            qr:      config.share_qr,
+            client:  HttpClient::new(config),
+            runtime: Handle::try_current().ok()?,
+        })
+    }
+
+    /// Upload the snapshot in the background, telling how it goes in the notice.
+    pub(crate) fn share(&self, view: SnapshotView, notice: Notice) {
+        *notice.lock().unwrap() = vec![format!("Sharing the snapshot with {}...", self.url)];
+        let (mut client, url, token, qr) = (self.client.clone(), self.url.clone(), self.token.clone(), self.qr);
+        self.runtime.spawn(async move {
+            let lines = match upload(&mut client, &url, token.as_deref(), &view).await {
+                Ok(link) if qr => {
+                    let mut lines = vec![format!("Shared as {}", link)];
+                    match QrCode::new(link.as_bytes()) {
+                        // Light modules on the dark background most terminals have.
+                        Ok(code) => lines.extend(
+                            code.render::<Dense1x2>()
+                                .dark_color(Dense1x2::Light)
+                                .light_color(Dense1x2::Dark)
+                                .build()
+                                .lines()
+                                .map(String::from),
+                        ),
+                        Err(err) => lines.push(format!("No QR code of the link: {}", err)),
+                    }
+                    lines
+                }
+                Ok(link) => vec![format!("Shared as {}", link)],
+                Err(err) => vec![format!("Failed to share the snapshot: {:#}", err)],
+            };
+            *notice.lock().unwrap() = lines;
+        });
+    }
+}
+
+async fn upload(client: &mut HttpClient, url: &str, token: Option<&str>, view: &SnapshotView) -> Result<String> {
+    let mut request = client
+        .get()?
+        .post(url)
+        .header("Content-Type", "application/json")
+        .body(serde_json::to_string(view)?);
+    if let Some(token) = token {
+        request = request.bearer_auth(token);
+    }
+    let response = request.send().await?;
+    let status = response.status();
+    let location = response
+        .headers()
+        .get(reqwest::header::LOCATION)
+        .and_then(|location| location.to_str().ok())
+        .map(String::from);
+    let text = response.text().await?;
+    if !status.is_success() {
+        return Err(anyhow!("Upload failed with {}: {}", status, text.trim_end()));
+    }
+    // The location may be relative to the endpoint.
+    if let Some(location) = location {
+        return Ok(reqwest::Url::parse(url)?.join(&location)?.to_string());
+    }
+    let text = text.trim();
+    if let Ok(serde_json::Value::Object(body)) = serde_json::from_str(text) {
+        if let Some(link) = ["url", "link", "short_url"].iter().find_map(|field| body.get(*field)?.as_str()) {
+            return Ok(link.to_string());
+        }
+    }
+    else if text.starts_with("http://") || text.starts_with("https://") {
+        return Ok(text.to_string());
+    }
+    Err(anyhow!("No link in the response of {}", url))
+}
@@ -0,0 +1,196 @@
+use std::{
+    path::Path,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, Utc};
+use qnode_logproc_core::SnapshotView;
+use serde::Deserialize;
+
+use crate::{config::AlertSpec, types::Checkpoint};
+
+/// Source name of simulated lines which don't set their own.
+const SOURCE: &str = "simulation";
+
+// Checks which failed so far; any makes the run exit with an error.
+static FAILED: AtomicUsize = AtomicUsize::new(0);
+
+/// A scripted scenario, loaded from a JSON file:
+///
+/// ```json
+/// {
+///     "start": "2024-01-01T00:00:00Z",
+///     "steps": [
+///         { "at": 0, "for": 60, "rate": 100 },
+///         { "at": 20, "for": 5, "rate": 50, "level": "ERROR", "message": "Database is down" },
+///         { "at": 30, "for": 10, "rate": 20, "malformed": 0.5 }
+///     ],
+///     "checks": [
+///         { "at": 25, "expect": "errors>200" },
+///         { "at": 60, "expect": "malformed<101" }
+///     ]
+/// }
+/// ```
+///
+/// Times are in seconds from the start.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct Scenario {
+    #[serde(default = "default_start")]
+    start:  DateTime<Utc>,
+    #[serde(default)]
+    steps:  Vec<Step>,
+    #[serde(default)]
+    checks: Vec<Check>,
+}
+
+/// Lines sent at an even rate over a period of time.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Step {
+    at:        f64,
+    #[serde(rename = "for", default = "default_duration")]
+    duration:  f64,
+    /// Lines per second
+    rate:      f64,
+    /// Any level, unknown ones make unknown-level lines
+    #[serde(default = "default_level")]
+    level:     String,
+    #[serde(default = "default_message")]
+    message:   String,
+    #[serde(default = "default_ip")]
+    ip:        String,
+    source:    Option<String>,
+    /// Share of the lines replaced with malformed ones
+    #[serde(default)]
+    malformed: f64,
+    /// Seconds the lines are received after they are logged
+    #[serde(default)]
+    lag:       f64,
+    /// Send this line as is instead of generating one
+    line:      Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Check {
+    at:     f64,
+    /// A rule in the `--alert` syntax which must hold
+    expect: String,
+}
+
+/// What the simulated reader sends, in order.
+pub(crate) enum Event {
+    Line { line: String, received_millis: i64, source: String },
+    Check(Checkpoint),
+}
+
+impl Scenario {
+    pub(crate) fn load(path: &Path) -> Result<Self> {
+        let text = std::fs::read_to_string(path).map_err(|e| anyhow!("Can't read scenario {:?}: {}", path, e))?;
+        serde_json::from_str(&text).map_err(|e| anyhow!("Bad scenario {:?}: {}", path, e))
+    }
+
+    /// All lines of the scenario ordered by the time they are received, with the checks placed after the lines
+    /// received by their time.
+    pub(crate) fn events(&self) -> Result<Vec<Event>> {
+        let start = self.start.timestamp_millis();
+        let mut lines = Vec::new();
+        for step in self.steps.iter() {
+            let count = (step.rate * step.duration).round() as usize;
+            for i in 0..count {
+                let received_millis = start + ((step.at + i as f64 / step.rate) * 1000.0) as i64;
+                let malformed = ((i + 1) as f64 * step.malformed).floor() > (i as f64 * step.malformed).floor();
+                let line = match step.line {
+                    Some(ref line) => line.clone(),
+                    None if malformed => format!("Malformed simulated line {}", i),
+                    None => {
+                        let logged_millis = received_millis - (step.lag * 1000.0) as i64;
+                        let logged = DateTime::from_timestamp_millis(logged_millis)
+                            .ok_or_else(|| anyhow!("Simulated time is out of range"))?;
+                        format!(
+                            "[{}] {} - IP:{} {}",
+                            logged.format("%Y-%m-%dT%H:%M:%SZ"),
+                            step.level,
+                            step.ip,
+                            step.message
+                        )
+                    }
+                };
+                let source = step.source.clone().unwrap_or_else(|| SOURCE.to_string());
+                lines.push((received_millis, line, source));
+            }
+        }
+        // The sort is stable, lines of the same time keep the order of the steps.
+        lines.sort_by_key(|(received_millis, ..)| *received_millis);
+
+        let mut checks = Vec::new();
+        for check in self.checks.iter() {
+            let rule = check
+                .expect
+                .parse::<AlertSpec>()
+                .map_err(|e| anyhow!("Bad check '{}': {}", check.expect, e))?;
+            checks.push((start + (check.at * 1000.0) as i64, Checkpoint { at: check.at, rule }));
+        }
+        checks.sort_by_key(|(at_millis, _)| *at_millis);
+
+        let mut events = Vec::with_capacity(lines.len() + checks.len());
+        let mut checks = checks.into_iter().peekable();
+        for (received_millis, line, source) in lines {
+            while let Some((_, checkpoint)) = checks.next_if(|(at_millis, _)| *at_millis < received_millis) {
+                events.push(Event::Check(checkpoint));
+            }
+            events.push(Event::Line {
+                line,
+                received_millis,
+                source,
+            });
+        }
+        events.extend(checks.map(|(_, checkpoint)| Event::Check(checkpoint)));
+
+        Ok(events)
+    }
+}
+
+/// Check the snapshot against the expectation of the checkpoint and print the outcome.
+pub(crate) fn check(checkpoint: &Checkpoint, view: &SnapshotView) {
+    let rule = &checkpoint.rule;
+    let value = rule.primary().value(view);
+    let passed = rule.is_breached(view);
+    if !passed {
+        FAILED.fetch_add(1, Ordering::Relaxed);
+    }
+    println!(
+        "{} at {}s: {} (got {})",
+        if passed { "PASS" } else { "FAIL" },
+        checkpoint.at,
+        rule.name,
+        value
+    );
+}
+
+/// Number of checks which failed.
+pub(crate) fn failed() -> usize {
+    FAILED.load(Ordering::Relaxed)
+}
+
+fn default_start() -> DateTime<Utc> {
+    DateTime::from_timestamp(1_704_067_200, 0).unwrap()
+}
+
+fn default_duration() -> f64 {
+    1.0
+}
+
+fn default_level() -> String {
+    "INFO".to_string()
+}
+
+fn default_message() -> String {
+    "Simulated message".to_string()
+}
+
+fn default_ip() -> String {
+    "10.0.0.1".to_string()
+}
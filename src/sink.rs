@@ -0,0 +1,404 @@
+use std::{
+    collections::hash_map::RandomState,
+    fmt,
+    future::Future,
+    hash::{BuildHasher, Hasher},
+    sync::Arc,
+    time::Duration,
+};
+
+use anyhow::Result;
+use qnode_logproc_core::{ParsedLine, SnapshotView};
+use tokio::{sync::mpsc::Receiver, time::Instant};
+
+#[cfg(feature = "summary")]
+use crate::summary::Summary;
+use crate::{
+    config::{AlertSink, AlertTarget, Config},
+    failures::Failures,
+    notify::Notification,
+    output::Publisher,
+    seconds::SecondCounts,
+    spool::{Spool, Spooled},
+};
+
+// The longest delay between the attempts to deliver a batch.
+const MAX_BACKOFF: Duration = Duration::from_secs(60);
+// How often the spool of a sink is retried while it fails.
+const REPLAY_INTERVAL: Duration = Duration::from_secs(10);
+/// Snapshots kept for a snapshot sink behind on its deliveries, a minute of them at the default refresh.
+#[cfg(any(feature = "nats", feature = "influx"))]
+pub(crate) const SNAPSHOT_BUFFER: usize = 60;
+
+/// How a sink wants its items handed over.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Delivery {
+    /// The most items delivered at once
+    pub(crate) batch:     usize,
+    /// How long the first item of a batch waits for more to fill it
+    pub(crate) max_delay: Duration,
+    /// The most items waiting for the delivery; those which don't fit are dropped and counted
+    pub(crate) buffer:    usize,
+    /// Attempts to deliver a batch before it is given up
+    pub(crate) attempts:  u32,
+    /// Delay before the second attempt, doubling with each following one up to a minute, with a random half of it
+    /// added or taken so that sinks failing together don't retry together
+    pub(crate) backoff:   Duration,
+    /// Whether the items queued by the time a batch is given up are dropped with it
+    pub(crate) discard:   bool,
+}
+
+impl Default for Delivery {
+    fn default() -> Self {
+        Self {
+            batch:     1,
+            max_delay: Duration::ZERO,
+            buffer:    1000,
+            attempts:  1,
+            backoff:   Duration::from_secs(1),
+            discard:   false,
+        }
+    }
+}
+
+/// An error of a delivery not worth retrying, like of data the receiving end refuses.
+#[derive(Debug)]
+pub(crate) struct Rejected(pub(crate) String);
+
+impl fmt::Display for Rejected {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl std::error::Error for Rejected {}
+
+/// A destination of the data of the statistics loop. Every sink runs in a background task of its own, which batches
+/// the items queued meanwhile and retries the failing batches as its [`Delivery`] tells; failures are reported under
+/// the name of the sink.
+pub(crate) trait Sink: Send + 'static {
+    type Item: Spooled + Send + Sync + 'static;
+
+    fn name(&self) -> String;
+
+    fn delivery(&self) -> Delivery {
+        Delivery::default()
+    }
+
+    /// Deliver the batch; an error caused by [`Rejected`] is not retried.
+    fn deliver(&mut self, batch: &[Self::Item]) -> impl Future<Output = Result<()>> + Send;
+
+    /// Called once there is nothing more to deliver.
+    fn finish(&mut self) -> impl Future<Output = ()> + Send {
+        async {}
+    }
+}
+
+/// A sink of the snapshot of every refresh.
+#[cfg(any(feature = "nats", feature = "influx"))]
+pub(crate) trait SnapshotSink: Sink<Item = Arc<SnapshotView>> {}
+
+#[cfg(any(feature = "nats", feature = "influx"))]
+impl<S: Sink<Item = Arc<SnapshotView>>> SnapshotSink for S {}
+
+/// A sink of every record counted, with the level it is counted at.
+pub(crate) trait EventSink: Sink<Item = Arc<ParsedLine>> {}
+
+impl<S: Sink<Item = Arc<ParsedLine>>> EventSink for S {}
+
+/// A sink of the counts of every second of receive time, once no more records are expected for it.
+#[cfg(feature = "redis")]
+pub(crate) trait SecondSink: Sink<Item = SecondCounts> {}
+
+#[cfg(feature = "redis")]
+impl<S: Sink<Item = SecondCounts>> SecondSink for S {}
+
+/// A sink of the alerts of the rules whose `--alert-to` targets send to it.
+pub(crate) trait NotificationSink: Sink<Item = Arc<Notification>> {}
+
+impl<S: Sink<Item = Arc<Notification>>> NotificationSink for S {}
+
+/// A sink of the summary of every period of `--summary-schedule`.
+#[cfg(feature = "summary")]
+pub(crate) trait SummarySink: Sink<Item = Arc<Summary>> {}
+
+#[cfg(feature = "summary")]
+impl<S: Sink<Item = Arc<Summary>>> SummarySink for S {}
+
+/// The snapshot sinks the options ask for, each running in a task of its own.
+#[cfg_attr(
+    not(any(feature = "nats", feature = "influx")),
+    allow(unused_mut, unused_variables)
+)]
+pub(crate) fn snapshot_sinks(config: &Config, failures: &Arc<Failures>) -> Vec<Publisher<Arc<SnapshotView>>> {
+    let mut sinks = Vec::new();
+    #[cfg(feature = "nats")]
+    register_snapshot_sink(&mut sinks, crate::nats::NatsSink::from_config(config), config, failures);
+    #[cfg(feature = "influx")]
+    register_snapshot_sink(&mut sinks, crate::influx::InfluxSink::from_config(config), config, failures);
+    sinks
+}
+
+/// The event sinks the options ask for, each running in a task of its own.
+pub(crate) fn event_sinks(config: &Config, failures: &Arc<Failures>) -> Vec<Publisher<Arc<ParsedLine>>> {
+    let mut sinks = Vec::new();
+    register_event_sink(&mut sinks, crate::events::EventsFile::from_config(config), config, failures);
+    sinks
+}
+
+/// The sinks of the counts of every second the options ask for, each running in a task of its own.
+#[cfg_attr(not(feature = "redis"), allow(unused_mut, unused_variables))]
+pub(crate) fn second_sinks(config: &Config, failures: &Arc<Failures>) -> Vec<Publisher<SecondCounts>> {
+    let mut sinks = Vec::new();
+    #[cfg(feature = "redis")]
+    register_second_sink(&mut sinks, crate::redis_stream::RedisSink::from_config(config), config, failures);
+    sinks
+}
+
+/// The sinks of the `--alert-to` targets, each running in a task of its own, with the targets sending to it. Targets
+/// of several rules sending to the same place share the sink, so that an alert is sent there once.
+pub(crate) fn notification_sinks(
+    config: &Config,
+    failures: &Arc<Failures>,
+) -> Vec<(Vec<AlertTarget>, Publisher<Arc<Notification>>)> {
+    let mut grouped: Vec<(Vec<AlertTarget>, AlertSink)> = Vec::new();
+    for target in config.alert_to.iter() {
+        match grouped.iter_mut().find(|(_, sink)| *sink == target.sink) {
+            Some((targets, _)) => targets.push(target.clone()),
+            None => grouped.push((vec![target.clone()], target.sink.clone())),
+        }
+    }
+    grouped
+        .into_iter()
+        .map(|(targets, sink)| {
+            (targets, spawn_notification_sink(crate::notify::AlertTargetSink::new(sink, config), config, failures))
+        })
+        .collect()
+}
+
+/// The sinks of the `--summary-to` targets, each running in a task of its own.
+#[cfg(feature = "summary")]
+pub(crate) fn summary_sinks(config: &Config, failures: &Arc<Failures>) -> Vec<Publisher<Arc<Summary>>> {
+    config
+        .summary_to
+        .iter()
+        .map(|target| spawn_summary_sink(crate::summary::SummaryTargetSink::new(target, config), config, failures))
+        .collect()
+}
+
+#[cfg(any(feature = "nats", feature = "influx"))]
+fn register_snapshot_sink<S: SnapshotSink>(
+    sinks: &mut Vec<Publisher<Arc<SnapshotView>>>,
+    sink: Option<S>,
+    config: &Config,
+    failures: &Arc<Failures>,
+) {
+    sinks.extend(sink.map(|sink| spawn(sink, config, failures.clone())));
+}
+
+fn register_event_sink<S: EventSink>(
+    sinks: &mut Vec<Publisher<Arc<ParsedLine>>>,
+    sink: Option<S>,
+    config: &Config,
+    failures: &Arc<Failures>,
+) {
+    sinks.extend(sink.map(|sink| spawn(sink, config, failures.clone())));
+}
+
+#[cfg(feature = "redis")]
+fn register_second_sink<S: SecondSink>(
+    sinks: &mut Vec<Publisher<SecondCounts>>,
+    sink: Option<S>,
+    config: &Config,
+    failures: &Arc<Failures>,
+) {
+    sinks.extend(sink.map(|sink| spawn(sink, config, failures.clone())));
+}
+
+fn spawn_notification_sink<S: NotificationSink>(
+    sink: S,
+    config: &Config,
+    failures: &Arc<Failures>,
+) -> Publisher<Arc<Notification>> {
+    spawn(sink, config, failures.clone())
+}
+
+#[cfg(feature = "summary")]
+fn spawn_summary_sink<S: SummarySink>(sink: S, config: &Config, failures: &Arc<Failures>) -> Publisher<Arc<Summary>> {
+    spawn(sink, config, failures.clone())
+}
+
+/// Run the sink in a background task, with a spool if `--spool-dir` is given.
+pub(crate) fn spawn<S: Sink>(sink: S, config: &Config, failures: Arc<Failures>) -> Publisher<S::Item> {
+    let (subject, buffer) = (sink.name(), sink.delivery().buffer);
+    let spool = config.spool_dir.as_ref().map(|dir| Spool::new(dir, &subject, config.spool_size));
+    Publisher::spawn(subject, buffer, failures.clone(), |rx| dispatch(sink, spool, failures, rx))
+}
+
+// Deliver the batches in order until the channel is closed. A batch which still fails after the last attempt is
+// spooled if there is a spool, and the following ones are spooled behind it until the spool is delivered; otherwise
+// it is given up so that the following ones have a chance. A rejected batch is given up either way.
+async fn dispatch<S: Sink>(mut sink: S, mut spool: Option<Spool>, failures: Arc<Failures>, mut rx: Receiver<S::Item>) {
+    let (subject, delivery) = (sink.name(), sink.delivery());
+    let limit = delivery.batch.max(1);
+    let mut batch = Vec::with_capacity(limit);
+    // A spool left by the previous run is delivered first.
+    let mut replayed = Instant::now().checked_sub(REPLAY_INTERVAL).unwrap_or_else(Instant::now);
+
+    loop {
+        let backlog = spool.as_mut().filter(|spool| !spool.is_empty());
+        let received = match backlog {
+            // The spool is retried now and then even with nothing new to deliver.
+            Some(spool) => {
+                let next_replay = replayed + REPLAY_INTERVAL;
+                match tokio::time::timeout_at(next_replay, rx.recv_many(&mut batch, limit)).await {
+                    Ok(received) => received,
+                    Err(_) => {
+                        replay(&mut sink, spool, &subject, limit, &failures).await;
+                        replayed = Instant::now();
+                        continue;
+                    }
+                }
+            }
+            None => rx.recv_many(&mut batch, limit).await,
+        };
+        if received == 0 {
+            break;
+        }
+        let deadline = Instant::now() + delivery.max_delay;
+        while batch.len() < limit {
+            let room = limit - batch.len();
+            match tokio::time::timeout_at(deadline, rx.recv_many(&mut batch, room)).await {
+                Ok(0) | Err(_) => break,
+                Ok(_) => (),
+            }
+        }
+
+        if let Some(spool) = spool.as_mut().filter(|spool| !spool.is_empty()) {
+            let delivered = replayed.elapsed() >= REPLAY_INTERVAL && {
+                replayed = Instant::now();
+                replay(&mut sink, spool, &subject, limit, &failures).await
+            };
+            if !delivered {
+                save(spool, &batch, &subject, &failures).await;
+                batch.clear();
+                continue;
+            }
+        }
+
+        if !deliver(&mut sink, &batch, &subject, &delivery, &failures).await {
+            match spool.as_mut() {
+                Some(spool) => {
+                    save(spool, &batch, &subject, &failures).await;
+                    replayed = Instant::now();
+                }
+                None => {
+                    let mut dropped = batch.len();
+                    if delivery.discard {
+                        while rx.try_recv().is_ok() {
+                            dropped += 1;
+                        }
+                    }
+                    failures.drop_items(&subject, dropped);
+                }
+            }
+        }
+        batch.clear();
+    }
+    sink.finish().await;
+}
+
+// Deliver the batch, retrying it as the sink asks. Returns false if it still fails after the last attempt; a rejected
+// batch is counted as dropped here.
+async fn deliver<S: Sink>(
+    sink: &mut S,
+    batch: &[S::Item],
+    subject: &str,
+    delivery: &Delivery,
+    failures: &Failures,
+) -> bool {
+    let mut delay = delivery.backoff;
+    for attempt in 1..=delivery.attempts.max(1) {
+        match sink.deliver(batch).await {
+            Ok(()) => {
+                failures.recover(subject);
+                return true;
+            }
+            Err(err) => {
+                let rejected = err.is::<Rejected>();
+                failures.fail(subject, err);
+                if rejected {
+                    failures.drop_items(subject, batch.len());
+                    return true;
+                }
+                if attempt < delivery.attempts {
+                    tokio::time::sleep(jitter(delay)).await;
+                    delay = (delay * 2).min(MAX_BACKOFF);
+                }
+            }
+        }
+    }
+    false
+}
+
+// Deliver the spooled items, the oldest first; what is left after a batch fails stays in the spool. Returns whether
+// the spool is delivered.
+async fn replay<S: Sink>(sink: &mut S, spool: &mut Spool, subject: &str, limit: usize, failures: &Failures) -> bool {
+    let spool_subject = format!("Spool of {}", subject);
+    let lines = match spool.read().await {
+        Ok(lines) => lines,
+        Err(err) => {
+            failures.fail(&spool_subject, err);
+            return false;
+        }
+    };
+
+    let mut delivered = 0;
+    for chunk in lines.chunks(limit) {
+        let items = chunk.iter().filter_map(|line| S::Item::from_spool(line)).collect::<Vec<_>>();
+        // Like a line cut short by a crash.
+        failures.drop_items(subject, chunk.len() - items.len());
+        match sink.deliver(&items).await {
+            Ok(()) => failures.recover(subject),
+            Err(err) => {
+                let rejected = err.is::<Rejected>();
+                failures.fail(subject, err);
+                if !rejected {
+                    break;
+                }
+                failures.drop_items(subject, items.len());
+            }
+        }
+        delivered += chunk.len();
+    }
+
+    match spool.keep(&lines[delivered..]).await {
+        Ok(()) => failures.recover(&spool_subject),
+        Err(err) => failures.fail(&spool_subject, err),
+    }
+    delivered == lines.len()
+}
+
+// Add the batch to the spool; the items which don't fit are dropped.
+async fn save<T: Spooled>(spool: &mut Spool, batch: &[T], subject: &str, failures: &Failures) {
+    let spool_subject = format!("Spool of {}", subject);
+    let lines = batch.iter().filter_map(Spooled::to_spool).collect::<Vec<_>>();
+    let dropped = match spool.append(&lines).await {
+        Ok(dropped) => {
+            failures.recover(&spool_subject);
+            dropped + batch.len() - lines.len()
+        }
+        Err(err) => {
+            failures.fail(&spool_subject, err);
+            batch.len()
+        }
+    };
+    failures.drop_items(subject, dropped);
+}
+
+// The delay shifted by a random amount of up to a half of it either way.
+fn jitter(delay: Duration) -> Duration {
+    // RandomState is seeded from the OS randomness source, good enough to spread the retries.
+    let random = RandomState::new().build_hasher().finish() as f64 / u64::MAX as f64;
+    delay.mul_f64(0.5 + random)
+}
@@ -0,0 +1,141 @@
+use std::{
+    collections::BTreeMap,
+    fs,
+    path::{Path, PathBuf},
+    time::{Duration, Instant},
+};
+
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, Local};
+use qnode_logproc_core::{Clock, SloCount, SloStatus};
+use serde::{Deserialize, Serialize};
+
+use crate::{config::SloSpec, types::Level};
+
+/// Windows the burn rate is measured in, with their alert metrics and lengths in minutes. They are the pairs of the
+/// multiwindow, multi-burn-rate alerts of the SRE workbook.
+pub(crate) const BURN_WINDOWS: [(&str, &str, i64); 7] = [
+    ("5m", "burn_rate_5m", 5),
+    ("30m", "burn_rate_30m", 30),
+    ("1h", "burn_rate_1h", 60),
+    ("2h", "burn_rate_2h", 120),
+    ("6h", "burn_rate_6h", 360),
+    ("1d", "burn_rate_1d", 1440),
+    ("3d", "burn_rate_3d", 4320),
+];
+
+const MINUTE_MS: i64 = 60_000;
+/// How often the history is written to `--slo-state` while running.
+const SAVE_INTERVAL: Duration = Duration::from_secs(60);
+
+/// What `--slo-state` keeps.
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct SloState {
+    /// Minutes since the epoch with their entries and errors
+    minutes: Vec<(i64, u64, u64)>,
+}
+
+/// Counts the entries and errors per minute over the period of `--slo`, seeded from the `--slo-state` file.
+#[derive(Debug)]
+pub(crate) struct SloTracker {
+    spec:       Option<SloSpec>,
+    /// Whether the minutes are measured by log time rather than receive time
+    clock:      Clock,
+    /// Not set if the state is not kept or could not be read, so that it is not overwritten
+    state_path: Option<PathBuf>,
+    minutes:    BTreeMap<i64, SloCount>,
+    saved:      Instant,
+}
+
+impl SloTracker {
+    pub(crate) fn new(spec: Option<SloSpec>, clock: Clock) -> Self {
+        Self {
+            spec,
+            clock,
+            state_path: None,
+            minutes: BTreeMap::new(),
+            saved: Instant::now(),
+        }
+    }
+
+    /// Seed the history from the state file; a missing one is started afresh.
+    pub(crate) fn load(&mut self, path: &Path) -> Result<()> {
+        if path.exists() {
+            let content = fs::read_to_string(path).map_err(|e| anyhow!("Can't read SLO state {:?}: {}", path, e))?;
+            let state = serde_json::from_str::<SloState>(&content)
+                .map_err(|e| anyhow!("Bad SLO state in {:?}: {}", path, e))?;
+            for (minute, entries, errors) in state.minutes {
+                let count = self.minutes.entry(minute).or_default();
+                count.entries += entries;
+                count.errors += errors;
+            }
+        }
+        self.state_path = Some(path.to_path_buf());
+        Ok(())
+    }
+
+    pub(crate) fn is_enabled(&self) -> bool {
+        self.spec.is_some()
+    }
+
+    pub(crate) fn push(&mut self, level: Level, logged_millis: i64, received_millis: i64) {
+        let now = match self.clock {
+            Clock::Receive => received_millis,
+            Clock::Log => logged_millis,
+        };
+        let count = self.minutes.entry(now.div_euclid(MINUTE_MS)).or_default();
+        count.entries += 1;
+        if level == Level::ERROR {
+            count.errors += 1;
+        }
+    }
+
+    /// The budget as of `now`; the minutes before the period are forgotten.
+    pub(crate) fn status(&mut self, now: DateTime<Local>) -> Option<SloStatus> {
+        let spec = self.spec.as_ref()?;
+        let minute = now.timestamp_millis().div_euclid(MINUTE_MS);
+        self.minutes = self.minutes.split_off(&(minute - spec.period_secs / 60 + 1));
+
+        let sum = |from| {
+            self.minutes.range(from..).fold(SloCount::default(), |sum, (_, count)| SloCount {
+                entries: sum.entries + count.entries,
+                errors:  sum.errors + count.errors,
+            })
+        };
+        Some(SloStatus {
+            objective:   spec.objective,
+            period_secs: spec.period_secs,
+            total:       sum(i64::MIN),
+            windows:     BURN_WINDOWS
+                .iter()
+                .map(|(name, _, minutes)| (name.to_string(), sum(minute - minutes + 1)))
+                .collect(),
+        })
+    }
+
+    pub(crate) fn is_save_due(&self) -> bool {
+        self.state_path.is_some() && self.saved.elapsed() >= SAVE_INTERVAL
+    }
+
+    /// Write the history into the state file, if there is one.
+    pub(crate) fn save(&mut self) -> Result<()> {
+        let Some(ref path) = self.state_path
+        else {
+            return Ok(());
+        };
+        self.saved = Instant::now();
+        let state = SloState {
+            minutes: self
+                .minutes
+                .iter()
+                .map(|(minute, count)| (*minute, count.entries, count.errors))
+                .collect(),
+        };
+        // Written aside first, so that a crash doesn't leave the history truncated.
+        let mut tmp_path = path.as_os_str().to_owned();
+        tmp_path.push(".tmp");
+        fs::write(&tmp_path, serde_json::to_string(&state)?)
+            .and_then(|()| fs::rename(&tmp_path, path))
+            .map_err(|e| anyhow!("Can't write SLO state {:?}: {}", path, e))
+    }
+}
@@ -0,0 +1,133 @@
+use std::{
+    io::ErrorKind,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use anyhow::{anyhow, Result};
+use qnode_logproc_core::{ParsedLine, SnapshotView};
+use tokio::{fs, io::AsyncWriteExt};
+
+/// Items of a sink which can be kept in its spool file, a line each.
+pub(crate) trait Spooled: Sized {
+    fn to_spool(&self) -> Option<String>;
+
+    fn from_spool(line: &str) -> Option<Self>;
+}
+
+impl Spooled for Arc<SnapshotView> {
+    fn to_spool(&self) -> Option<String> {
+        serde_json::to_string(&**self).ok()
+    }
+
+    fn from_spool(line: &str) -> Option<Self> {
+        serde_json::from_str(line).ok().map(Arc::new)
+    }
+}
+
+impl Spooled for Arc<ParsedLine> {
+    fn to_spool(&self) -> Option<String> {
+        let fields = (self.logged_millis, format!("{:?}", self.level), &self.ip, &self.message, self.priority);
+        serde_json::to_string(&fields).ok()
+    }
+
+    fn from_spool(line: &str) -> Option<Self> {
+        let (logged_millis, level, ip, message, priority) =
+            serde_json::from_str::<(i64, String, String, String, Option<u8>)>(line).ok()?;
+        Some(Arc::new(ParsedLine {
+            logged_millis,
+            level: level.parse().ok()?,
+            ip,
+            message,
+            priority,
+        }))
+    }
+}
+
+/// Batches a sink could not deliver, kept in a file of `--spool-dir` to be delivered in order once the sink works
+/// again, the next run included. The file doesn't grow past the limit; items which don't fit are dropped.
+pub(crate) struct Spool {
+    path:  PathBuf,
+    limit: u64,
+    size:  u64,
+}
+
+impl Spool {
+    /// The spool of the sink in the directory, named after the sink.
+    pub(crate) fn new(dir: &Path, sink: &str, limit: u64) -> Self {
+        let name = sink
+            .split(|c: char| !c.is_ascii_alphanumeric())
+            .filter(|word| !word.is_empty())
+            .collect::<Vec<_>>()
+            .join("-")
+            .to_ascii_lowercase();
+        let path = dir.join(format!("{}.spool", name));
+        let size = std::fs::metadata(&path).map_or(0, |meta| meta.len());
+        Self { path, limit, size }
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.size == 0
+    }
+
+    /// Append the lines which fit under the limit; returns how many did not.
+    pub(crate) async fn append(&mut self, lines: &[String]) -> Result<usize> {
+        let mut out = String::new();
+        let mut fitting = 0;
+        for line in lines {
+            if self.size + (out.len() + line.len() + 1) as u64 > self.limit {
+                break;
+            }
+            out.push_str(line);
+            out.push('\n');
+            fitting += 1;
+        }
+        if !out.is_empty() {
+            if let Some(dir) = self.path.parent() {
+                fs::create_dir_all(dir).await?;
+            }
+            let mut file = fs::OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(&self.path)
+                .await
+                .map_err(|e| anyhow!("Can't open spool {:?}: {}", self.path, e))?;
+            file.write_all(out.as_bytes()).await?;
+            self.size += out.len() as u64;
+        }
+        Ok(lines.len() - fitting)
+    }
+
+    /// The lines spooled, the oldest first.
+    pub(crate) async fn read(&self) -> Result<Vec<String>> {
+        match fs::read_to_string(&self.path).await {
+            Ok(content) => Ok(content.lines().map(str::to_string).collect()),
+            Err(err) if err.kind() == ErrorKind::NotFound => Ok(Vec::new()),
+            Err(err) => Err(anyhow!("Can't read spool {:?}: {}", self.path, err)),
+        }
+    }
+
+    /// Replace the spooled lines with those still to be delivered.
+    pub(crate) async fn keep(&mut self, lines: &[String]) -> Result<()> {
+        if lines.is_empty() {
+            if let Err(err) = fs::remove_file(&self.path).await {
+                if err.kind() != ErrorKind::NotFound {
+                    return Err(anyhow!("Can't remove spool {:?}: {}", self.path, err));
+                }
+            }
+            self.size = 0;
+            return Ok(());
+        }
+        let content = lines.iter().map(|line| format!("{}\n", line)).collect::<String>();
+        // Written aside first, so that a crash doesn't leave the spool truncated.
+        let mut tmp_path = self.path.as_os_str().to_owned();
+        tmp_path.push(".tmp");
+        let written = match fs::write(&tmp_path, &content).await {
+            Ok(()) => fs::rename(&tmp_path, &self.path).await,
+            Err(err) => Err(err),
+        };
+        written.map_err(|e| anyhow!("Can't write spool {:?}: {}", self.path, e))?;
+        self.size = content.len() as u64;
+        Ok(())
+    }
+}
@@ -1,491 +1,840 @@
-use core::f64;
 use std::{
-    cmp::Ordering,
-    collections::{HashMap, VecDeque},
+    hash::{DefaultHasher, Hash, Hasher},
+    sync::{mpsc, Arc},
+    time::{Duration, Instant},
 };
 
-use crate::{app::App, types::*};
-use anyhow::Result;
-use chrono::{DateTime, Local, Utc};
-use fieldx::fxstruct;
+use crate::{
+    app::App, attacks::AttackDetector, baseline::Baseline, config::{Config, Rules}, context::ContextCapture,
+    escalate::Escalations, filter::Filter, heartbeat::Heartbeats, output::Publisher, remote_write::RemoteWrite,
+    render::Pane, seconds::Seconds, slo::SloTracker, summary::Summaries, suspicious::SuspiciousIps, systemd,
+    types::*,
+};
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, Local};
 use fieldx_plus::fx_plus;
-use tokio::sync::mpsc::UnboundedSender;
-
-// In seconds
-const MSG_ERROR_WINDOW: i64 = 15000;
-// Window bounds in seconds
-const MIN_WINDOW: usize = 30;
-const MAX_WINDOW: usize = 120;
-
-#[derive(Clone, Copy)]
-enum Act {
-    Inc = 1,
-    Dec = -1,
-}
+use qnode_logproc_core::{Clock, Extracted, Extractor, SnapshotView, StatsWindow};
+use regex::Regex;
+use tokio::sync::{mpsc::UnboundedSender, watch};
+
+#[fx_plus(
+    agent(App, unwrap(error(anyhow::Error, App::app_is_gone()))),
+    sync,
+    rc,
+    fallible(off, error(anyhow::Error))
+)]
+pub(crate) struct Stats {
+    #[fieldx(lazy, lock, private, get, get_mut)]
+    window: StatsWindow,
+
+    /// Windows of the comparison panes with the filters selecting their records.
+    #[fieldx(lazy, lock, private, get(off), get_mut)]
+    panes: Vec<(Filter, StatsWindow)>,
+
+    /// Patterns of `--mute`, with the ones of the `--rules` file.
+    #[fieldx(lazy, lock, private, get(off), get_mut)]
+    mutes: Vec<Regex>,
+
+    /// Frequency of the records matching `--escalate` rules.
+    #[fieldx(lazy, lock, private, get, get_mut)]
+    escalations: Escalations,
+
+    /// Last times of the messages expected with `--heartbeat`.
+    #[fieldx(lazy, lock, private, get, get_mut)]
+    heartbeats: Heartbeats,
+
+    /// Per-IP error baselines of `--suspicious-ips`.
+    #[fieldx(lazy, lock, private, get, get_mut)]
+    suspicious_ips: SuspiciousIps,
+
+    /// Failed authentications of `--auth-failure`.
+    #[fieldx(lazy, lock, private, get, get_mut)]
+    attacks: AttackDetector,
+
+    /// Entries and errors per minute over the `--slo` period.
+    #[fieldx(lazy, lock, private, get, get_mut)]
+    slo: SloTracker,
 
-#[fxstruct(no_new, default)]
-struct StatsSnapshot {
-    entries:            i64,
-    // In milliseconds
-    collected_interval: i64,
-    rate:               f64,
-    peak_rate:          f64,
-    error_rate:         f32,
+    /// Entries and errors per hour of `--baseline`.
+    #[fieldx(lazy, lock, private, get, get_mut)]
+    baseline: Baseline,
 
-    errors:    i64,
-    infos:     i64,
-    debugs:    i64,
-    malformed: i64,
+    /// Lines around the occurrences of the messages selected with `--context`.
+    #[fieldx(lazy, lock, private, get, get_mut)]
+    context: ContextCapture,
 
-    /// Map a message ID to the number of times it has been seen
-    error_msg_counts: HashMap<u64, i64>,
+    /// Records per second sent to `--remote-write`.
+    #[fieldx(lazy, lock, private, get, get_mut)]
+    remote_write: crate::remote_write::RemoteWrite,
 
-    /// For each second, map a message ID to the number of times it has been seen in that second
-    error_msg_per_sec: HashMap<i64, HashMap<u64, i64>>,
+    /// Records received per second, sent to `--redis-output`.
+    #[fieldx(lazy, lock, private, get, get_mut)]
+    seconds: Seconds,
 
-    /// Map a message ID to its weight
-    error_msg_rates: HashMap<u64, f64>,
+    /// Records of the current period of `--summary-schedule`.
+    #[fieldx(lazy, lock, private, get, get_mut)]
+    summaries: Summaries,
 
-    /// Measure in milliseconds
-    #[fieldx(default(60000))]
-    window: usize,
+    /// Sinks of every record counted.
+    #[fieldx(lazy, lock, private, get, get_mut)]
+    event_sinks: Vec<Publisher<Arc<ParsedLine>>>,
 
-    #[fieldx(default(60000))]
-    previous_window: usize,
+    /// When the previous snapshot was taken, with the lines dropped by then.
+    #[fieldx(lock, private, get_mut, default(None))]
+    drops: Option<(Instant, u64)>,
 
-    /// List of timestamps in milliseconds of messages received in the last second
-    last_second_received: VecDeque<i64>,
+    #[fieldx(lazy, fallible, clearer, private, get)]
+    tx: UnboundedSender<StatRecord>,
+
+    /// Channels of the shard workers with `--stats-shards` above one. They count the records into partials of the
+    /// window and the panes and pass them on to `tx` to be merged, with the records for the rest of the statistics.
+    #[fieldx(lazy, fallible, private, get)]
+    shards: Vec<mpsc::Sender<StatRecord>>,
+
+    /// Publishes every refreshed snapshot to the subscribers.
+    #[fieldx(private, get, default(watch::channel(SnapshotView::default()).0))]
+    snapshots: watch::Sender<SnapshotView>,
 }
 
-impl StatsSnapshot {
-    fn count_inner_rec(&mut self, rec: InnerRecord, act: Act) -> InnerRecord {
-        match &rec {
-            InnerRecord::OK(ok) => match ok.level {
-                Level::ERROR => {
-                    self.errors += act as i64;
-                    self.error_msg_counts
-                        .entry(ok.msg_id)
-                        .and_modify(|count| *count += act as i64)
-                        .or_insert(act as i64);
-
-                    let log_time = rec.log_timestamp();
-                    let cnt = *self
-                        .error_msg_per_sec
-                        .entry(log_time)
-                        .or_insert_with(HashMap::new)
-                        .entry(ok.msg_id)
-                        .and_modify(|count| *count += act as i64)
-                        .or_insert(act as i64);
-                    // Clean up empty entries.
-                    // Since log times are not guaranteed to be monotonically increasing and can fall out
-                    // of the current time window, we need to check if the count is zero here for better performance.
-                    // Otherwise, it would be necessary to iterate over all entries in the cleanup_and_adjust body.
-                    if cnt == 0 {
-                        self.error_msg_per_sec.get_mut(&log_time).unwrap().remove(&ok.msg_id);
-                        if self.error_msg_per_sec[&log_time].is_empty() {
-                            self.error_msg_per_sec.remove(&log_time);
-                        }
-                    }
+/// The most records a batch is applied to the window with.
+const BATCH_LEN: usize = 1024;
+/// How long a shard worker counts records before passing its partials on.
+const SHARD_TICK: Duration = Duration::from_millis(100);
+/// The longest refresh interval backing off under overload leads to, unless `--refresh` is longer.
+const MAX_REFRESH_BACKOFF: Duration = Duration::from_secs(10);
+/// How long to wait on shutdown for the records per second of a backfill to be remote-written.
+const REMOTE_WRITE_CLOSE_TIMEOUT: Duration = Duration::from_secs(60);
+/// How long to wait on shutdown for the event sinks to deliver the records counted.
+const EVENT_SINKS_CLOSE_TIMEOUT: Duration = Duration::from_secs(5);
+
+impl Stats {
+    pub(crate) async fn start(&self) -> Result<()> {
+        let app = self.app()?;
+
+        // In `--once` mode only the final report is rendered.
+        let live = !app.config().once;
+        let output = app.output()?;
+        // The watchdog is pinged from this loop since it stalls when the statistics are not updated.
+        let watchdog_interval = systemd::watchdog_interval();
+        let mut last_watchdog = Instant::now();
+
+        // Refreshing at wall-clock moments would make a simulation nondeterministic; only its checks look at the
+        // window.
+        #[cfg(feature = "simulation")]
+        if app.config().simulate.is_some() {
+            return Ok(());
+        }
+
+        // The sending tasks are spawned on the runtime, not on the thread the records are processed in.
+        let _ = self.remote_write();
+        let _ = self.seconds();
+        let _ = self.summaries();
+        let _ = self.event_sinks();
+
+        let refresh = Duration::from_secs_f64(app.config().refresh);
+        let mut interval = refresh;
+        let mut next_refresh = Instant::now();
+
+        loop {
+            let now = Local::now();
+
+            if watchdog_interval.is_some_and(|interval| last_watchdog.elapsed() >= interval) {
+                self.notify_systemd("WATCHDOG=1");
+                last_watchdog = Instant::now();
+            }
+
+            // A summary is sent for a quiet period as well, and so are quiet seconds.
+            self.summaries_mut().send_due(now, &app.run_info().host);
+            self.seconds_mut().flush(false);
+            if self.slo().is_save_due() {
+                match self.save_slo() {
+                    Ok(()) => app.failures().recover("SLO state"),
+                    Err(err) => app.failures().fail("SLO state", err),
                 }
-                Level::INFO => {
-                    self.infos += act as i64;
+            }
+            if self.baseline().is_save_due() {
+                match self.save_baseline() {
+                    Ok(()) => app.failures().recover("Baseline"),
+                    Err(err) => app.failures().fail("Baseline", err),
                 }
-                Level::DEBUG => {
-                    self.debugs += act as i64;
+            }
+
+            if self.window().is_empty() {
+                if live {
+                    output.idle(now)?;
                 }
-            },
-            InnerRecord::Err(err) => match err.err_type {
-                StatErrType::Malformed => {
-                    self.malformed += act as i64;
+                tokio::time::sleep(Duration::from_millis(100)).await;
+                continue;
+            }
+
+            if Instant::now() >= next_refresh {
+                // Use lexical scope to localize the window lock.
+                {
+                    let mut window = self.window_mut();
+                    window.cleanup_and_adjust();
+                    let view = self.snapshot_view(now, &window)?;
+                    if live {
+                        output.render(&view, &window, &self.pane_views(now))?;
+                    }
+                    self.notify_systemd(&format!(
+                        "STATUS={} entries in window, {:.2} entries/sec, {:.2}% errors",
+                        view.entries,
+                        view.rate,
+                        view.error_rate * 100.0
+                    ));
+                    self.write_outputs(view)?;
                 }
-            },
-        }
+                self.remote_write_mut().flush(false);
 
-        rec
-    }
-}
+                // Refreshing competes with ingestion for the window, so it is done less often while the parser is
+                // overloaded.
+                interval = if *app.parser()?.overloaded() {
+                    (interval * 2).min(refresh.max(MAX_REFRESH_BACKOFF))
+                }
+                else {
+                    refresh
+                };
+                next_refresh = Instant::now() + interval;
+            }
 
-enum InnerRecord {
-    OK(InnerOKRecord),
-    Err(InnerErrRecord),
-}
+            // Wake up in time for the watchdog too.
+            let mut wake_up = next_refresh;
+            if let Some(watchdog_interval) = watchdog_interval {
+                wake_up = wake_up.min(last_watchdog + watchdog_interval);
+            }
+            tokio::time::sleep_until(wake_up.into()).await;
+        }
+    }
 
-impl InnerRecord {
-    fn recv_timestamp_millis(&self) -> i64 {
-        match self {
-            Self::OK(ok) => ok.received_millis,
-            Self::Err(err) => err.received_millis,
+    /// Render the report and write the outputs once all input is processed.
+    pub(crate) fn final_report(&self) -> Result<()> {
+        let mut window = self.window_mut();
+        window.cleanup_and_adjust();
+        let now = Local::now();
+        let view = self.snapshot_view(now, &window)?;
+        self.app()?.output()?.render(&view, &window, &self.pane_views(now))?;
+        #[cfg(feature = "sql")]
+        if !self.app()?.config().queries.is_empty() {
+            crate::query::run(&window, &self.app()?.config().queries)?;
         }
+        self.write_outputs(view)
     }
 
-    #[allow(dead_code)]
-    fn recv_timestamp(&self) -> i64 {
-        self.recv_timestamp_millis() / 1000
+    /// Run a query typed at the prompt of the terminal report over the records of the window.
+    #[cfg(feature = "sql")]
+    pub(crate) fn query(&self, query: &str) -> Vec<String> {
+        crate::query::prompt(&self.window(), query)
     }
 
-    fn log_timestamp_millis(&self) -> i64 {
-        match self {
-            Self::OK(ok) => ok.logged_millis,
-            Self::Err(err) => err.received_millis,
+    /// Send the records left to `--remote-write` and wait for their delivery.
+    pub(crate) async fn close_remote_write(&self) {
+        let sink = self.remote_write_mut().close();
+        if let Some(sink) = sink {
+            sink.close(REMOTE_WRITE_CLOSE_TIMEOUT).await;
         }
     }
 
-    fn log_timestamp(&self) -> i64 {
-        self.log_timestamp_millis() / 1000
+    /// Send the seconds left to `--redis-output` and wait for their delivery.
+    pub(crate) async fn close_seconds(&self) {
+        let sinks = self.seconds_mut().close();
+        for sink in sinks {
+            sink.close(EVENT_SINKS_CLOSE_TIMEOUT).await;
+        }
     }
-}
-
-struct InnerOKRecord {
-    received_millis: i64,
-    logged_millis:   i64,
-    level:           Level,
-    msg_id:          u64,
-}
-
-struct InnerErrRecord {
-    received_millis: i64,
-    err_type:        StatErrType,
-}
 
-impl StatsSnapshot {
-    fn refresh_last_second(&mut self, ts: Option<i64>) {
-        if let Some(ts) = ts {
-            self.last_second_received.push_front(ts);
-        }
-        let now = Utc::now().timestamp_millis();
-        while let Some(last) = self.last_second_received.back() {
-            if now - last > 1000 {
-                self.last_second_received.pop_back();
-            }
-            else {
-                break;
-            }
+    /// Let the event sinks deliver the records already counted.
+    pub(crate) async fn close_event_sinks(&self) {
+        let sinks = std::mem::take(&mut *self.event_sinks_mut());
+        for sink in sinks {
+            sink.close(EVENT_SINKS_CLOSE_TIMEOUT).await;
         }
     }
-}
-
-#[fx_plus(
-    agent(App, unwrap(error(anyhow::Error, App::app_is_gone()))),
-    sync,
-    rc,
-    fallible(off, error(anyhow::Error))
-)]
-pub(crate) struct Stats {
-    #[fieldx(lock, private, get, get_mut, default(VecDeque::new()))]
-    records: VecDeque<InnerRecord>,
 
-    /// List of all distinct log messages encountered
-    #[fieldx(private, lock, get, get_mut, default(Vec::new()))]
-    msgs: Vec<String>,
+    /// Wait for the summaries already due to be delivered.
+    pub(crate) async fn close_summaries(&self) {
+        let mut summaries = std::mem::replace(&mut *self.summaries_mut(), Summaries::disabled());
+        summaries.close(REMOTE_WRITE_CLOSE_TIMEOUT).await;
+    }
 
-    /// Map a log message to its index in `msgs`.
-    #[fieldx(private, lock, get_mut, default(HashMap::new()))]
-    msg_idx: HashMap<String, u64>,
+    /// Write the current state to the outputs immediately, out of the regular refresh schedule.
+    pub(crate) fn dump_snapshot(&self) -> Result<()> {
+        let mut window = self.window_mut();
+        window.cleanup_and_adjust();
+        self.write_outputs(self.snapshot_view(Local::now(), &window)?)
+    }
 
-    #[fieldx(lock, private, get_mut, builder(off))]
-    stat: StatsSnapshot,
+    /// Write the history of the `--slo` period into the `--slo-state` file, if there is one.
+    pub(crate) fn save_slo(&self) -> Result<()> {
+        self.slo_mut().save()
+    }
 
-    #[fieldx(lazy, fallible, clearer, private, get)]
-    tx: UnboundedSender<StatRecord>,
-}
+    /// Write the hours of `--baseline` into its file, if there is one.
+    pub(crate) fn save_baseline(&self) -> Result<()> {
+        self.baseline_mut().save()
+    }
 
-impl Stats {
-    pub(crate) async fn start(&self) -> Result<()> {
+    /// Write the message dictionary into the `--dictionary` file, if there is one.
+    pub(crate) fn dump_dictionary(&self) -> Result<()> {
         let app = self.app()?;
-
-        loop {
-            let term = app.term();
-            term.clear_screen()?;
-            term.move_cursor_to(0, 0)?;
-
-            let now = Local::now();
-
-            if self.records().is_empty() {
-                term.write_line(&format!("{} No records yet.", now.format("%Y-%m-%d %H:%M:%S%.3f")))?;
-                tokio::time::sleep(tokio::time::Duration::from_millis(100)).await;
-                continue;
-            }
-
-            // Use lexical scope to localize stat_snapshot lock.
-            {
-                let mut stat_snapshot = self.stat_mut();
-                self.cleanup_and_adjust(&mut *stat_snapshot);
-                self.print_report(now, &stat_snapshot)?;
-            }
-            tokio::time::sleep(tokio::time::Duration::from_millis(1000)).await;
+        let Some(ref path) = app.config().dictionary
+        else {
+            return Ok(());
+        };
+        let entries = self.window().dictionary();
+        let content = if path.extension().is_some_and(|ext| ext == "json") {
+            serde_json::to_string_pretty(&entries)?
         }
+        else {
+            let mut csv = String::from("id,message,count,first_seen,last_seen\n");
+            for entry in entries {
+                csv.push_str(&format!(
+                    "{},\"{}\",{},{},{}\n",
+                    entry.id,
+                    entry.message.replace('"', "\"\""),
+                    entry.count,
+                    entry.first_seen.to_rfc3339(),
+                    entry.last_seen.to_rfc3339()
+                ));
+            }
+            csv
+        };
+        std::fs::write(path, content).map_err(|e| anyhow!("Can't write dictionary {:?}: {}", path, e))
     }
 
     pub fn shutdown(&self) {
         self.clear_tx();
     }
 
-    fn print_report(&self, now: DateTime<Local>, stat_snapshot: &StatsSnapshot) -> Result<()> {
-        let app = self.app()?;
-        let term = app.term();
-        term.clear_screen()?;
-        term.move_cursor_to(0, 0)?;
-        term.write_line(&format!("Stats as of {}", now.format("%Y-%m-%d %H:%M:%S%.3f")))?;
-        term.write_line(&std::iter::repeat('-').take(80).collect::<String>())?;
-        term.write_line(&format!(
-            "Entries: {} per {:.2} seconds (window: {}sec)",
-            stat_snapshot.entries,
-            stat_snapshot.collected_interval as f64 / 1000.0,
-            stat_snapshot.window / 1000
-        ))?;
-        term.write_line(&format!(
-            "Current rate: {:.2} entries/sec",
-            stat_snapshot.last_second_received.len()
-        ))?;
-        term.write_line(&format!("Rate        : {:.2} entries/sec", stat_snapshot.rate))?;
-        term.write_line(&format!("Peak rate   : {:.2} entries/sec", stat_snapshot.peak_rate))?;
-        term.write_line("")?;
-        term.write_line(&format!(
-            "Errors: {:.2}% ({} entries); rate: {:.2} errors/sec",
-            stat_snapshot.errors as f32 / stat_snapshot.entries as f32 * 100.0,
-            stat_snapshot.errors,
-            stat_snapshot.error_rate
-        ))?;
-        term.write_line(&format!(
-            "Infos: {:.2}% ({} entries)",
-            stat_snapshot.infos as f32 / stat_snapshot.entries as f32 * 100.0,
-            stat_snapshot.infos
-        ))?;
-        term.write_line(&format!(
-            "Debugs: {:.2}% ({} entries)",
-            stat_snapshot.debugs as f32 / stat_snapshot.entries as f32 * 100.0,
-            stat_snapshot.debugs
-        ))?;
-        term.write_line(&format!("Malformed: {}", stat_snapshot.malformed))?;
-        term.write_line("")?;
-        term.write_line("Top error messages:")?;
-
-        let mut msgs = stat_snapshot.error_msg_counts.iter().collect::<Vec<_>>();
-        msgs.sort_by(|a, b| b.1.cmp(a.1));
-
-        for (pos, msg) in msgs.iter().take(3).enumerate() {
-            term.write_line(&format!(
-                "  {}. \"{}\" ({} entries)",
-                pos + 1,
-                self.msg_by_id(*msg.0),
-                msg.1
-            ))?;
-        }
-
-        term.write_line("")?;
-        term.write_line("Trending messages:")?;
-
-        let mut rates = stat_snapshot.error_msg_rates.iter().collect::<Vec<_>>();
-        rates.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap_or(Ordering::Equal));
-        for rate in rates.iter().take(3) {
-            term.write_line(&format!("  \"{}\" (rate: {:.2})", self.msg_by_id(*rate.0), rate.1))?;
-        }
-
-        term.write_line("")?;
-        term.write_line("Insights:")?;
-        term.write_line(&format!(
-            "Error messages per second table size: {}",
-            stat_snapshot.error_msg_per_sec.len()
-        ))?;
-
-        term.write_line(&std::iter::repeat('-').take(80).collect::<String>())?;
-        term.write_line("Ctrl-C to stop.")?;
-
-        term.flush()?;
+    /// The snapshot of the latest refresh, without the window being locked.
+    pub(crate) fn latest_snapshot(&self) -> SnapshotView {
+        self.snapshots().borrow().clone()
+    }
 
-        Ok(())
+    /// Receive a snapshot on every refresh.
+    pub(crate) fn subscribe(&self) -> watch::Receiver<SnapshotView> {
+        self.snapshots().subscribe()
     }
 
-    pub fn msg_id(&self, msg: &str) -> u64 {
-        let mut msg_idx = self.msg_idx_mut();
-        if let Some(id) = msg_idx.get(msg) {
-            return *id;
+    /// Resolve the window into a self-contained view, with the counters kept by the parser and the reader added.
+    fn snapshot_view(&self, now: DateTime<Local>, window: &StatsWindow) -> Result<SnapshotView> {
+        let app = self.app()?;
+        // When replaying old logs the report is as of the log time.
+        let mut view = window.view(window.log_time().unwrap_or(now));
+        view.sampled_out = *app.parser()?.sampled_out();
+        view.below_min_level = *app.parser()?.below_min_level();
+        view.throttled = app.reader()?.throttled().clone();
+        view.truncated_datagrams = app.reader()?.truncated_datagrams().clone();
+        view.backlog = *app.parser()?.backlog() as u64;
+        view.drop_rate = self.drop_rate(view.sampled_out + view.throttled.values().sum::<u64>());
+        view.input_errors = app.reader()?.input_errors().clone();
+        view.failures = app.failures().list();
+        view.output_drops = app.failures().dropped();
+        view.files = app.reader()?.file_progress();
+        view.contexts = self.context().captures();
+        view.escalated = self.escalations().escalated().clone();
+        view.heartbeats = self.heartbeats().heartbeats(view.timestamp);
+        if self.attacks().is_enabled() {
+            view.attacks = self.attacks_mut().attacks(view.timestamp);
+        }
+        if self.suspicious_ips().is_enabled() {
+            view.suspicious = self.suspicious_ips_mut().suspicious(view.timestamp);
+        }
+        view.slo = self.slo_mut().status(view.timestamp);
+        if self.baseline().is_enabled() {
+            view.hourly = self.baseline_mut().hourly(view.timestamp);
         }
+        view.maintenance = app.config().maintenance.iter().any(|window| window.is_active(view.timestamp));
+        view.run = app.run_info().clone();
+        Ok(view)
+    }
 
-        let mut msgs = self.msgs_mut();
-        let new_id = msgs.len() as u64;
-        msgs.push(msg.to_owned());
-        msg_idx.insert(msg.to_owned(), new_id);
+    // Lines dropped per second since the previous snapshot, out of the total dropped since the start.
+    fn drop_rate(&self, dropped: u64) -> f64 {
+        let now = Instant::now();
+        match self.drops_mut().replace((now, dropped)) {
+            Some((then, before)) if now > then => {
+                dropped.saturating_sub(before) as f64 / now.duration_since(then).as_secs_f64()
+            }
+            _ => 0.0,
+        }
+    }
 
-        new_id
+    // Views of the comparison panes; they only count records, the counters of the parser and the reader are left out.
+    fn pane_views(&self, now: DateTime<Local>) -> Vec<Pane> {
+        self.panes_mut()
+            .iter_mut()
+            .map(|(filter, window)| {
+                window.cleanup_and_adjust();
+                Pane {
+                    label: filter.to_string(),
+                    view:  window.view(window.log_time().unwrap_or(now)),
+                }
+            })
+            .collect()
     }
 
-    pub fn msg_by_id(&self, id: u64) -> String {
-        self.msgs()
-            .get(id as usize)
-            .map_or("N/A".to_string(), |msg| msg.clone())
+    // A notification which fails must not stop the statistics either, systemd is told again with the next refresh.
+    fn notify_systemd(&self, state: &str) {
+        let Ok(app) = self.app()
+        else {
+            return;
+        };
+        match systemd::notify(state) {
+            Ok(()) => app.failures().recover("systemd notifications"),
+            Err(err) => app.failures().fail("systemd notifications", err),
+        }
     }
 
-    pub(crate) fn push_record<S: Into<StatRecord>>(&self, rec: S) -> Result<()> {
-        self.tx()?.send(rec.into())?;
+    fn write_outputs(&self, view: SnapshotView) -> Result<()> {
+        let app = self.app()?;
+        let output = app.output()?;
+        // A failing output must not stop the statistics, it is retried with the next snapshot.
+        if output.has_outputs()? {
+            match output.write(&view) {
+                Ok(()) => app.failures().recover("Snapshot outputs"),
+                Err(err) => app.failures().fail("Snapshot outputs", err),
+            }
+        }
+        // The latest snapshot is kept for the subscribers and for the panic hook.
+        self.snapshots().send_replace(view);
+
         Ok(())
     }
 
-    pub fn stop(&self) -> Result<()> {
-        self.tx()?.send(StatRecord::Stop).unwrap();
+    pub(crate) fn push_record<S: Into<StatRecord>>(&self, rec: S) -> Result<()> {
+        let rec = rec.into();
+        let shards = self.shards()?;
+        let source = match rec {
+            StatRecord::OK(ref ok) => ok.source(),
+            StatRecord::Err(ref err) => err.source(),
+            _ => {
+                self.tx()?.send(rec)?;
+                return Ok(());
+            }
+        };
+        if shards.is_empty() {
+            self.tx()?.send(rec)?;
+        }
+        else {
+            // Records of a source stay in one shard, so they are counted in the order they came in.
+            let mut hasher = DefaultHasher::new();
+            source.hash(&mut hasher);
+            shards[hasher.finish() as usize % shards.len()].send(rec)?;
+        }
         Ok(())
     }
 
-    fn recalc_weights(&self, stat_snapshot: &mut StatsSnapshot, now: i64) {
-        if self.records().len() == 0 {
-            return;
+    pub fn stop(&self) -> Result<()> {
+        let shards = self.shards()?;
+        if shards.is_empty() {
+            self.tx()?.send(StatRecord::Stop).unwrap();
         }
-        let window_size = (self.records().front().unwrap().log_timestamp_millis()
-            - self.records().back().unwrap().log_timestamp_millis())
-        .max(MSG_ERROR_WINDOW);
-
-        // We need at least 2 seconds of data to calculate the weights
-        if window_size < 2000 {
-            return;
+        for shard in shards.iter() {
+            shard.send(StatRecord::Stop)?;
         }
+        Ok(())
+    }
 
-        let mut seconds = stat_snapshot.error_msg_per_sec.keys().copied().collect::<Vec<_>>();
-        seconds.sort_by(|a, b| b.cmp(a));
-
-        // Group by MSG_ERROR_WINDOW seconds from now. We need the last two groups only.
-        // index 0 is for newer, 1 is for older
-        let mut grouped = vec![HashMap::new(); 2];
-        let base_time_millis = vec![(now - window_size / 2), now];
-
-        for sec in seconds.iter().copied() {
-            let msec = sec * 1000;
-
-            let group_idx = ((now - msec) * 2 / window_size) as usize;
-
-            // Too old messages are not interesting
-            if group_idx > 1 {
-                break;
+    // Apply the records under a single acquisition of the locks. The records of a shard worker come counted in the
+    // partials of the window and the panes, which are merged instead.
+    fn process_batch(
+        &self,
+        records: impl Iterator<Item = StatRecord>,
+        partials: Option<(PartialStats, Vec<PartialStats>)>,
+    ) {
+        let mut window = self.window_mut();
+        let mut panes = self.panes_mut();
+        let mut escalations = self.escalations_mut();
+        let mut heartbeats = self.heartbeats_mut();
+        let mut context = self.context_mut();
+        let mut remote_write = self.remote_write_mut();
+        let mut seconds = self.seconds_mut();
+        let mut summaries = self.summaries_mut();
+        let mut suspicious_ips = self.suspicious_ips_mut();
+        let mut attacks = self.attacks_mut();
+        let mut slo = self.slo_mut();
+        let mut baseline = self.baseline_mut();
+        let event_sinks = self.event_sinks();
+        let counted = partials.is_some();
+        if let Some((partial, pane_partials)) = partials {
+            window.merge(partial);
+            for ((_, pane), partial) in panes.iter_mut().zip(pane_partials) {
+                pane.merge(partial);
             }
+        }
+        for rec in records {
+            match rec {
+                StatRecord::OK(mut ok) => {
+                    if heartbeats.is_enabled() {
+                        heartbeats.push(ok.record(), ok.source(), ok.received_millis());
+                    }
+                    if attacks.is_enabled() {
+                        attacks.push(ok.record(), ok.received_millis());
+                    }
+                    if !counted {
+                        Self::escalate(&mut ok, &mut escalations);
+                        let extracted = window.extractor().extract(&ok.record().message);
+                        Self::count_ok(&ok, extracted, &mut *window, panes.as_mut_slice());
+                    }
+                    let (logged_millis, received_millis) = (ok.record().logged_millis, ok.received_millis());
+                    let ip = suspicious_ips.is_enabled().then(|| ok.record().ip.clone());
+                    let level = Self::process_ok(&ok, &mut context, &mut summaries);
+                    if remote_write.is_enabled() {
+                        remote_write.push(logged_millis, level);
+                    }
+                    if seconds.is_enabled() {
+                        seconds.push_ok(received_millis, level);
+                    }
+                    if slo.is_enabled() {
+                        slo.push(level, logged_millis, received_millis);
+                    }
+                    if baseline.is_enabled() {
+                        baseline.push(level, logged_millis, received_millis);
+                    }
+                    if let Some(ip) = ip.filter(|_| level == Level::ERROR) {
+                        suspicious_ips.push(&ip, logged_millis, received_millis);
+                    }
+                    if !event_sinks.is_empty() {
+                        let event = Arc::new(ParsedLine {
+                            level,
+                            ..ok.into_record()
+                        });
+                        for sink in event_sinks.iter() {
+                            sink.send(event.clone());
+                        }
+                    }
+                }
+                StatRecord::Err(err) => {
+                    if summaries.is_enabled() {
+                        summaries.push_malformed(err.received_millis());
+                    }
+                    if seconds.is_enabled() {
+                        seconds.push_err(err.received_millis());
+                    }
+                    if !counted {
+                        Self::count_err(&err, &mut *window, panes.as_mut_slice());
+                    }
+                    Self::process_err(err, &mut context)
+                }
+                _ => (),
+            }
+        }
+    }
 
-            let counts = stat_snapshot.error_msg_per_sec.get(&sec).unwrap();
+    // Raise the level of the record by the `--escalate` rules.
+    fn escalate(rec: &mut StatOKRecord, escalations: &mut Escalations) {
+        if escalations.is_enabled() {
+            let level = escalations.level_of(rec.record(), rec.received_millis());
+            rec.record_mut().level = level;
+        }
+    }
 
-            for (msg_id, count) in counts.iter() {
-                let weight = grouped[group_idx].entry(*msg_id).or_insert(0.0);
-                *weight += f64::consts::E.powf((base_time_millis[group_idx] - msec) as f64 / window_size as f64)
-                    * (*count as f64);
+    // Count the record in the window and in the panes it matches. The panes are built with the same patterns as the
+    // window, so the request details are taken from the message once for all of them.
+    fn count_ok<C: Counter>(rec: &StatOKRecord, extracted: Extracted, window: &mut C, panes: &mut [(Filter, C)]) {
+        for (filter, pane) in panes.iter_mut() {
+            if filter.matches(Some(rec.record()), rec.source()) {
+                pane.count_ok(rec, extracted.clone(), true);
             }
         }
+        window.count_ok(rec, extracted, !rec.uncounted());
+    }
 
-        for msg_id in stat_snapshot.error_msg_counts.keys() {
-            stat_snapshot.error_msg_rates.insert(
-                *msg_id,
-                if let Some(older) = grouped[1].get(msg_id) {
-                    grouped[0].get(msg_id).unwrap_or(&0.0) / *older
-                }
-                else {
-                    0.0
-                },
-            );
+    fn count_err<C: Counter>(rec: &StatErrRecord, window: &mut C, panes: &mut [(Filter, C)]) {
+        window.count_err(rec);
+        for (filter, pane) in panes.iter_mut() {
+            if filter.matches(None, rec.source()) {
+                pane.count_err(rec);
+            }
         }
     }
 
-    fn cleanup_and_adjust(&self, stat_snapshot: &mut StatsSnapshot) {
-        let now = Utc::now().timestamp_millis();
-        let oldest = now - stat_snapshot.window as i64;
-
-        let mut recalc = true;
+    fn process_ok(rec: &StatOKRecord, context: &mut ContextCapture, summaries: &mut Summaries) -> Level {
+        let record = rec.record();
+        if context.is_enabled() {
+            context.push(rec.source(), record.to_line(), Some(&record.message), received(rec.received_millis()));
+        }
+        if summaries.is_enabled() {
+            summaries.push(record.level, &record.ip, &record.message, rec.received_millis());
+        }
 
-        while recalc {
-            recalc = false;
+        record.level
+    }
 
-            // records write lock scope
-            {
-                let mut records = self.records_mut();
-                stat_snapshot.entries = records.len() as i64;
+    fn process_err(rec: StatErrRecord, context: &mut ContextCapture) {
+        if context.is_enabled() {
+            let line = rec.line().clone().unwrap_or_default();
+            context.push(rec.source(), line, None, received(rec.received_millis()));
+        }
+    }
 
-                while let Some(rec) = records.back() {
-                    if rec.recv_timestamp_millis() < oldest {
-                        stat_snapshot.count_inner_rec(records.pop_back().unwrap(), Act::Dec);
+    fn process_incoming(&self, mut rx: tokio::sync::mpsc::UnboundedReceiver<StatRecord>) {
+        // Every shard worker stops on its own.
+        let mut producers = self.app().map_or(1, |app| app.config().stats_shards);
+        let mut batch = Vec::with_capacity(BATCH_LEN);
+        let mut pending = None;
+        while let Some(rec) = pending.take().or_else(|| rx.blocking_recv()) {
+            match rec {
+                StatRecord::OK(_) | StatRecord::Err(_) => {
+                    // Take whatever else is waiting along.
+                    batch.push(rec);
+                    while batch.len() < BATCH_LEN {
+                        match rx.try_recv() {
+                            Ok(rec @ (StatRecord::OK(_) | StatRecord::Err(_))) => batch.push(rec),
+                            Ok(rec) => {
+                                pending = Some(rec);
+                                break;
+                            }
+                            Err(_) => break,
+                        }
                     }
-                    else {
+                    self.process_batch(batch.drain(..), None);
+                }
+                StatRecord::Partial(partial) => {
+                    let ShardPartial { window, panes, records } = *partial;
+                    self.process_batch(records.into_iter(), Some((window, panes)));
+                }
+                #[cfg(feature = "simulation")]
+                StatRecord::Check(checkpoint) => {
+                    self.check(&checkpoint);
+                }
+                StatRecord::Stop => {
+                    producers -= 1;
+                    if producers == 0 {
                         break;
                     }
                 }
+            }
+        }
+        eprintln!("Done processing incoming...");
+        if let Ok(app) = self.app() {
+            app.stats_done().notify_one();
+        }
+    }
 
-                stat_snapshot.collected_interval = records.front().map_or(0, |r| r.recv_timestamp_millis())
-                    - records.back().map_or(0, |r| r.recv_timestamp_millis());
+    // Count the records of the shard into its partials and pass them on to be merged, every `SHARD_TICK` or once
+    // `BATCH_LEN` records are collected.
+    fn process_shard(&self, rx: mpsc::Receiver<StatRecord>, mut shard: Shard) -> Result<()> {
+        let tx = self.tx()?.clone();
+        let mut records = Vec::with_capacity(BATCH_LEN);
+        let mut flushed = Instant::now();
+        loop {
+            let stop = match rx.recv_timeout(SHARD_TICK.saturating_sub(flushed.elapsed())) {
+                Ok(StatRecord::Stop) | Err(mpsc::RecvTimeoutError::Disconnected) => true,
+                Ok(rec) => {
+                    records.push(rec);
+                    false
+                }
+                Err(mpsc::RecvTimeoutError::Timeout) => false,
+            };
+            if stop || records.len() >= BATCH_LEN || flushed.elapsed() >= SHARD_TICK {
+                if !records.is_empty() {
+                    let records = std::mem::replace(&mut records, Vec::with_capacity(BATCH_LEN));
+                    tx.send(StatRecord::Partial(Box::new(self.count_shard(&mut shard, records))))?;
+                }
+                flushed = Instant::now();
             }
-            // We need per second, not per millisecond
-            if stat_snapshot.collected_interval > 100 {
-                stat_snapshot.rate = stat_snapshot.entries as f64 / (stat_snapshot.collected_interval as f64 / 1000.0);
+            if stop {
+                tx.send(StatRecord::Stop)?;
+                return Ok(());
             }
-            if stat_snapshot.collected_interval >= 1000 {
-                // Let the stats stabilize first
-                stat_snapshot.peak_rate = stat_snapshot.rate.max(stat_snapshot.peak_rate);
+        }
+    }
+
+    // The escalations are shared by the shards; they are applied under a single acquisition of the lock, before the
+    // records are counted.
+    fn count_shard(&self, shard: &mut Shard, mut records: Vec<StatRecord>) -> ShardPartial {
+        {
+            let mut escalations = self.escalations_mut();
+            for rec in records.iter_mut() {
+                if let StatRecord::OK(ok) = rec {
+                    Self::escalate(ok, &mut escalations);
+                }
             }
-            stat_snapshot.error_rate = stat_snapshot.errors as f32 / stat_snapshot.entries as f32;
-
-            self.recalc_weights(&mut *stat_snapshot, now);
-
-            // Adjust window if necessary. The technical spec requires, say, 30 secs window for 2,500 entries/sec.
-            // Let's make it weighted dynamic decision. So, 2500*30 = 75,000 entries per window. Rust can do much better,
-            // let's round it to 100k and try keeping the records queue size around that.
-            if stat_snapshot.rate > 0.0 {
-                // Calculate expected buffer size
-                let expected_buffer_size = stat_snapshot.rate * (stat_snapshot.window as f64 / 1000.0);
-                if expected_buffer_size > 100_000.0 || expected_buffer_size < 75_000.0 {
-                    let new_window = ((100_000.0 / stat_snapshot.rate) as usize)
-                        .min(MIN_WINDOW)
-                        .max(MAX_WINDOW)
-                        * 1000;
-                    if new_window != stat_snapshot.window {
-                        stat_snapshot.previous_window = stat_snapshot.window;
-                        stat_snapshot.window = new_window;
-                        recalc = true;
-                        eprintln!("NEED RECALC");
-                    }
+        }
+        for rec in records.iter() {
+            match rec {
+                StatRecord::OK(ok) => {
+                    let extracted = shard.extractor.extract(&ok.record().message);
+                    Self::count_ok(ok, extracted, &mut shard.window, &mut shard.panes);
                 }
+                StatRecord::Err(err) => Self::count_err(err, &mut shard.window, &mut shard.panes),
+                _ => (),
             }
         }
+        ShardPartial {
+            window: shard.window.take(),
+            panes: shard.panes.iter_mut().map(|(_, pane)| pane.take()).collect(),
+            records,
+        }
     }
 
-    fn process_ok(&self, rec: StatOKRecord) {
-        let mut stat_snapshot = self.stat_mut();
+    // Checks see the window as it is when all the lines before them are processed.
+    #[cfg(feature = "simulation")]
+    fn check(&self, checkpoint: &crate::types::Checkpoint) {
+        let mut window = self.window_mut();
+        window.cleanup_and_adjust();
+        match self.snapshot_view(Local::now(), &window) {
+            Ok(view) => crate::simulation::check(checkpoint, &view),
+            Err(err) => eprintln!("Failed to take a snapshot for a check: {:?}", err),
+        }
+    }
 
-        // Refresh the last second list so we know the current rate
-        stat_snapshot.refresh_last_second(Some(rec.received_millis()));
+    fn build_escalations(&self) -> Escalations {
+        self.app().map_or_else(
+            |_| Escalations::new(Vec::new(), Clock::Receive),
+            |app| Escalations::new(app.config().escalations.clone(), app.config().window_clock()),
+        )
+    }
 
-        let msg_id = self.msg_id(&rec.message());
-        let inner_rec = InnerOKRecord {
-            received_millis: rec.received_millis(),
-            logged_millis: rec.logged_millis(),
-            level: rec.level(),
-            msg_id,
-        };
+    fn build_heartbeats(&self) -> Heartbeats {
+        self.app().map_or_else(
+            |_| Heartbeats::new(Vec::new(), Clock::Receive),
+            |app| Heartbeats::new(app.config().heartbeats.clone(), app.config().window_clock()),
+        )
+    }
 
-        self.records_mut()
-            .push_front(stat_snapshot.count_inner_rec(InnerRecord::OK(inner_rec), Act::Inc));
+    fn build_attacks(&self) -> AttackDetector {
+        self.app().map_or_else(
+            |_| AttackDetector::new(None, 0, 0, 0, Clock::Receive),
+            |app| {
+                let config = app.config();
+                AttackDetector::new(
+                    config.auth_failure.clone(),
+                    config.attack_interval.as_millis() as i64,
+                    config.brute_force_messages,
+                    config.scan_ips,
+                    config.window_clock(),
+                )
+            },
+        )
+    }
 
-        self.cleanup_and_adjust(&mut *stat_snapshot);
+    fn build_slo(&self) -> SloTracker {
+        self.app().map_or_else(
+            |_| SloTracker::new(None, Clock::Receive),
+            |app| {
+                let config = app.config();
+                let mut slo = SloTracker::new(config.slo.clone(), config.window_clock());
+                // Without the history the file is left alone, rather than overwritten with a fresh one.
+                if let Some(ref path) = config.slo_state {
+                    if let Err(err) = slo.load(path) {
+                        app.failures().fail("SLO state", err);
+                    }
+                }
+                slo
+            },
+        )
     }
 
-    fn process_err(&self, rec: StatErrRecord) {
-        let mut stat_snapshot = self.stat_mut();
+    fn build_baseline(&self) -> Baseline {
+        self.app().map_or_else(
+            |_| Baseline::new(0, Clock::Receive),
+            |app| {
+                let config = app.config();
+                let mut baseline = Baseline::new(config.baseline_days, config.window_clock());
+                // A file which can't be read is left alone, rather than overwritten with a fresh one.
+                if let Some(ref path) = config.baseline {
+                    if let Err(err) = baseline.load(path) {
+                        app.failures().fail("Baseline", err);
+                    }
+                }
+                baseline
+            },
+        )
+    }
 
-        let inner_err = InnerErrRecord {
-            received_millis: rec.received_millis(),
-            err_type:        rec.error_type(),
-        };
+    fn build_suspicious_ips(&self) -> SuspiciousIps {
+        self.app().map_or_else(
+            |_| SuspiciousIps::new(false, 0, 0.0, Clock::Receive),
+            |app| {
+                let config = app.config();
+                SuspiciousIps::new(
+                    config.suspicious_ips,
+                    config.suspicious_min_errors,
+                    config.suspicious_factor,
+                    config.window_clock(),
+                )
+            },
+        )
+    }
 
-        self.records_mut()
-            .push_front(stat_snapshot.count_inner_rec(InnerRecord::Err(inner_err), Act::Inc));
+    fn build_remote_write(&self) -> RemoteWrite {
+        self.app().map_or_else(
+            |_| RemoteWrite::disabled(),
+            |app| RemoteWrite::new(&app.config(), app.failures()),
+        )
     }
 
-    fn process_incoming(&self, mut rx: tokio::sync::mpsc::UnboundedReceiver<StatRecord>) {
-        while let Some(rec) = rx.blocking_recv() {
-            match rec {
-                StatRecord::OK(ok) => {
-                    self.process_ok(ok);
-                }
-                StatRecord::Err(err) => {
-                    self.process_err(err);
-                }
-                StatRecord::Stop => {
-                    break;
-                }
+    fn build_seconds(&self) -> Seconds {
+        self.app().map_or_else(|_| Seconds::disabled(), |app| Seconds::new(&app.config(), app.failures()))
+    }
+
+    fn build_event_sinks(&self) -> Vec<Publisher<Arc<ParsedLine>>> {
+        self.app()
+            .map_or_else(|_| Vec::new(), |app| crate::sink::event_sinks(&app.config(), &app.failures()))
+    }
+
+    fn build_summaries(&self) -> Summaries {
+        self.app().map_or_else(|_| Summaries::disabled(), |app| Summaries::new(&app.config(), app.failures()))
+    }
+
+    fn build_context(&self) -> ContextCapture {
+        self.app().map_or_else(
+            |_| ContextCapture::new(Vec::new(), 0),
+            |app| ContextCapture::new(app.config().context.clone(), app.config().context_lines),
+        )
+    }
+
+    /// Replace the mutes and the escalation rules with the reloaded ones. Messages muted from the keyboard stay muted.
+    pub(crate) fn set_rules(&self, rules: &Rules) {
+        let mut window = self.window_mut();
+        let mut panes = self.panes_mut();
+        let mut mutes = self.mutes_mut();
+        for window in std::iter::once(&mut *window).chain(panes.iter_mut().map(|(_, window)| window)) {
+            for pattern in mutes.iter() {
+                window.unmute(pattern);
+            }
+            for pattern in rules.mute.iter() {
+                window.mute(pattern.clone());
             }
         }
-        println!("Done processing incoming...");
+        *mutes = rules.mute.clone();
+        self.escalations_mut().set_rules(rules.escalations.clone());
+    }
+
+    /// Acknowledge the exact message, in the comparison panes too.
+    pub(crate) fn mute_message(&self, message: &str) {
+        self.window_mut().mute_message(message);
+        for (_, window) in self.panes_mut().iter_mut() {
+            window.mute_message(message);
+        }
+    }
+
+    fn build_mutes(&self) -> Vec<Regex> {
+        self.app().map(|app| app.config().mute.clone()).unwrap_or_default()
+    }
+
+    fn build_window(&self) -> StatsWindow {
+        let Ok(app) = self.app()
+        else {
+            return StatsWindow::default();
+        };
+        let config = app.config();
+        new_window(&config)
+    }
+
+    fn build_panes(&self) -> Vec<(Filter, StatsWindow)> {
+        let Ok(app) = self.app()
+        else {
+            return Vec::new();
+        };
+        let config = app.config();
+        config
+            .compare_filters()
+            .into_iter()
+            .map(|filter| (filter, new_window(&config)))
+            .collect()
     }
 
     fn build_tx(&self) -> Result<UnboundedSender<StatRecord>> {
@@ -498,4 +847,116 @@ impl Stats {
 
         Ok(tx)
     }
+
+    fn build_shards(&self) -> Result<Vec<mpsc::Sender<StatRecord>>> {
+        let app = self.app()?;
+        let count = app.config().stats_shards;
+        if count == 1 {
+            return Ok(Vec::new());
+        }
+        let mut shards = Vec::new();
+        for _ in 0..count {
+            let (tx, rx) = mpsc::channel();
+            let myself = self.myself().unwrap();
+            let shard = {
+                let window = self.window();
+                Shard {
+                    extractor: window.extractor().clone(),
+                    window:    window.partial(),
+                    panes:     self
+                        .panes_mut()
+                        .iter()
+                        .map(|(filter, pane)| (filter.clone(), pane.partial()))
+                        .collect(),
+                }
+            };
+            let failures = app.failures();
+            app.task_set_mut().spawn_blocking(move || {
+                if let Err(err) = myself.process_shard(rx, shard) {
+                    failures.fail("Stats shard", err);
+                }
+            });
+            shards.push(tx);
+        }
+        Ok(shards)
+    }
+}
+
+/// What a shard worker counts its records in.
+struct Shard {
+    extractor: Extractor,
+    window:    PartialStats,
+    panes:     Vec<(Filter, PartialStats)>,
+}
+
+/// Where the records are counted: the window and the panes, or their partials in a shard worker.
+trait Counter {
+    fn count_ok(&mut self, rec: &StatOKRecord, extracted: Extracted, rated: bool);
+    fn count_err(&mut self, rec: &StatErrRecord);
+}
+
+impl Counter for StatsWindow {
+    fn count_ok(&mut self, rec: &StatOKRecord, extracted: Extracted, rated: bool) {
+        let (received_millis, record, source, provenance) =
+            (rec.received_millis(), rec.record(), rec.source(), rec.provenance().clone());
+        if rated {
+            self.push_ok_extracted(received_millis, record, source, extracted, provenance);
+        }
+        else {
+            self.push_ok_uncounted_extracted(received_millis, record, source, extracted, provenance);
+        }
+    }
+
+    fn count_err(&mut self, rec: &StatErrRecord) {
+        self.push_error(rec.received_millis(), rec.error_type(), rec.source());
+    }
+}
+
+impl Counter for PartialStats {
+    fn count_ok(&mut self, rec: &StatOKRecord, extracted: Extracted, rated: bool) {
+        let (received_millis, record, source, provenance) =
+            (rec.received_millis(), rec.record(), rec.source(), rec.provenance().clone());
+        if rated {
+            self.push_ok_extracted(received_millis, record, source, extracted, provenance);
+        }
+        else {
+            self.push_ok_uncounted_extracted(received_millis, record, source, extracted, provenance);
+        }
+    }
+
+    fn count_err(&mut self, rec: &StatErrRecord) {
+        self.push_error(rec.received_millis(), rec.error_type(), rec.source());
+    }
+}
+
+pub(crate) fn new_window(config: &Config) -> StatsWindow {
+    let mut window = StatsWindow::with_clock(config.window_clock())
+        .bucket_millis(config.bucket_ms)
+        .keep_examples(config.examples);
+    if !config.endpoint.as_str().is_empty() {
+        window = window.extract_endpoints(config.endpoint.clone());
+    }
+    if !config.status.as_str().is_empty() {
+        window = window.extract_statuses(config.status.clone());
+    }
+    if !config.trace_id.as_str().is_empty() {
+        window = window.extract_trace_ids(config.trace_id.clone());
+    }
+    if let Some(ref pattern) = config.user_agent {
+        window = window.extract_user_agents(pattern.clone());
+    }
+    if let Some(ref pattern) = config.sequence {
+        window = window.extract_sequences(pattern.clone());
+    }
+    if !config.group_by.is_empty() {
+        window = window.group_by(config.group_by.clone());
+    }
+    for pattern in config.mute.iter() {
+        window.mute(pattern.clone());
+    }
+    window
+}
+
+fn received(millis: i64) -> DateTime<Local> {
+    DateTime::from_timestamp_millis(millis).unwrap_or_default().with_timezone(&Local)
 }
@@ -0,0 +1,360 @@
+use std::{
+    collections::{HashMap, HashSet},
+    hash::{DefaultHasher, Hash, Hasher},
+    str::FromStr,
+    sync::Arc,
+};
+
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, Datelike, Duration, Local, NaiveTime, TimeZone};
+use qnode_logproc_core::MessageCount;
+use serde::{Deserialize, Serialize};
+
+#[cfg(feature = "summary")]
+use crate::{
+    sink::{Delivery, Sink},
+    tls::HttpClient,
+};
+use crate::{
+    config::Config,
+    failures::Failures,
+    maintenance::{parse_days, parse_duration, parse_time},
+    output::Publisher,
+    spool::Spooled,
+    types::Level,
+};
+
+// The most error messages listed in a summary.
+const TOP_ERRORS: usize = 5;
+// Summaries kept while a target is slow to take them.
+#[cfg(feature = "summary")]
+const QUEUE_LEN: usize = 10;
+// Attempts to send a summary, with the delay doubling after each failure; a summary is due for a while.
+#[cfg(feature = "summary")]
+const ATTEMPTS: u32 = 6;
+#[cfg(feature = "summary")]
+const FIRST_RETRY_DELAY: tokio::time::Duration = tokio::time::Duration::from_secs(5);
+
+/// When the summaries are sent.
+#[derive(Debug, Clone)]
+pub(crate) enum SummarySchedule {
+    /// Every period, counted from the start of the program
+    Every(Duration),
+    /// At the time of every listed day, indexed from Monday
+    Daily { days: [bool; 7], at: NaiveTime },
+}
+
+impl SummarySchedule {
+    /// The first time after `after` a summary is due at.
+    fn next(&self, after: DateTime<Local>) -> DateTime<Local> {
+        match *self {
+            Self::Every(period) => after + period,
+            Self::Daily { days, at } => {
+                // A week ahead is enough to meet any of the days; a time skipped by a DST change is passed over.
+                (0..=7)
+                    .filter_map(|offset| {
+                        let date = after.date_naive() + Duration::days(offset);
+                        let time = Local.from_local_datetime(&date.and_time(at)).earliest()?;
+                        (days[date.weekday().num_days_from_monday() as usize] && time > after).then_some(time)
+                    })
+                    .next()
+                    .unwrap_or(after + Duration::days(1))
+            }
+        }
+    }
+}
+
+impl FromStr for SummarySchedule {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let spec = s.trim();
+        if !spec.contains(':') {
+            let period = parse_duration(spec)?;
+            if period <= Duration::zero() {
+                return Err(anyhow!("The period of the summaries must be positive, got '{}'", s));
+            }
+            return Ok(Self::Every(period));
+        }
+        let (days, at) = match spec.rsplit_once(' ') {
+            Some((days, at)) => (parse_days(days.trim())?, at),
+            None => ([true; 7], spec),
+        };
+        Ok(Self::Daily {
+            days,
+            at: parse_time(at)?,
+        })
+    }
+}
+
+/// What the records of a period come to.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(crate) struct Summary {
+    pub(crate) host:         String,
+    pub(crate) from:         DateTime<Local>,
+    pub(crate) to:           DateTime<Local>,
+    pub(crate) entries:      u64,
+    pub(crate) errors:       u64,
+    pub(crate) infos:        u64,
+    pub(crate) debugs:       u64,
+    pub(crate) malformed:    u64,
+    /// Average entries per second over the period
+    pub(crate) rate:         f64,
+    /// Most lines received within a second
+    pub(crate) peak_rate:    u64,
+    pub(crate) error_rate:   f64,
+    pub(crate) distinct_ips: usize,
+    pub(crate) top_errors:   Vec<MessageCount>,
+}
+
+impl Summary {
+    /// The summary as a Slack message.
+    #[cfg_attr(not(feature = "summary"), allow(dead_code))]
+    pub(crate) fn text(&self) -> String {
+        let mut text = format!(
+            "*Log summary of {}* for {} – {}\n{} entries: {} errors, {} infos, {} debugs, {} malformed\n",
+            self.host,
+            self.from.format("%Y-%m-%d %H:%M"),
+            self.to.format("%Y-%m-%d %H:%M"),
+            self.entries,
+            self.errors,
+            self.infos,
+            self.debugs,
+            self.malformed
+        );
+        text.push_str(&format!(
+            "{:.2} entries/sec on average, {} at peak; {:.2}% errors; {} distinct IPs\n",
+            self.rate,
+            self.peak_rate,
+            self.error_rate * 100.0,
+            self.distinct_ips
+        ));
+        if !self.top_errors.is_empty() {
+            text.push_str("Top errors:\n");
+            for top in self.top_errors.iter() {
+                text.push_str(&format!("• {} × `{}`\n", top.count, top.message.replace('`', "'")));
+            }
+        }
+        text
+    }
+}
+
+impl Spooled for Arc<Summary> {
+    fn to_spool(&self) -> Option<String> {
+        serde_json::to_string(&**self).ok()
+    }
+
+    fn from_spool(line: &str) -> Option<Self> {
+        serde_json::from_str(line).ok().map(Arc::new)
+    }
+}
+
+/// Counts the records of the current period of `--summary-schedule` and sends its summary to the `--summary-to`
+/// targets once it is over. Without the `summary` feature it is always disabled.
+pub(crate) struct Summaries {
+    schedule:  SummarySchedule,
+    from:      DateTime<Local>,
+    due:       DateTime<Local>,
+    /// ERROR, INFO, DEBUG
+    levels:    [u64; 3],
+    malformed: u64,
+    errors:    HashMap<String, u64>,
+    ips:       HashSet<u64>,
+    /// The second of the latest record received, with the number of records in it
+    second:    (i64, u64),
+    peak_rate: u64,
+    sinks:     Vec<Publisher<Arc<Summary>>>,
+}
+
+impl Summaries {
+    #[cfg_attr(not(feature = "summary"), allow(unused_variables))]
+    pub(crate) fn new(config: &Config, failures: Arc<Failures>) -> Self {
+        #[cfg(feature = "summary")]
+        if !config.summary_to.is_empty() {
+            let from = Local::now();
+            return Self {
+                schedule: config.summary_schedule.clone(),
+                from,
+                due: config.summary_schedule.next(from),
+                sinks: crate::sink::summary_sinks(config, &failures),
+                ..Self::disabled()
+            };
+        }
+        Self::disabled()
+    }
+
+    pub(crate) fn disabled() -> Self {
+        let from = Local::now();
+        Self {
+            schedule:  SummarySchedule::Every(Duration::hours(1)),
+            from,
+            due:       from,
+            levels:    [0; 3],
+            malformed: 0,
+            errors:    HashMap::new(),
+            ips:       HashSet::new(),
+            second:    (0, 0),
+            peak_rate: 0,
+            sinks:     Vec::new(),
+        }
+    }
+
+    pub(crate) fn is_enabled(&self) -> bool {
+        !self.sinks.is_empty()
+    }
+
+    pub(crate) fn push(&mut self, level: Level, ip: &str, message: &str, received_millis: i64) {
+        let column = match level {
+            Level::ERROR => 0,
+            Level::INFO => 1,
+            Level::DEBUG => 2,
+        };
+        self.levels[column] += 1;
+        if level == Level::ERROR {
+            *self.errors.entry(message.to_string()).or_default() += 1;
+        }
+        if !ip.is_empty() {
+            let mut hasher = DefaultHasher::new();
+            ip.hash(&mut hasher);
+            self.ips.insert(hasher.finish());
+        }
+        self.count_second(received_millis);
+    }
+
+    pub(crate) fn push_malformed(&mut self, received_millis: i64) {
+        self.malformed += 1;
+        self.count_second(received_millis);
+    }
+
+    /// Send the summary of the period if it is over, and start the next one.
+    pub(crate) fn send_due(&mut self, now: DateTime<Local>, host: &str) {
+        if now < self.due {
+            return;
+        }
+        if self.sinks.is_empty() {
+            return;
+        }
+        let entries = self.levels.iter().sum::<u64>();
+        let secs = (now - self.from).num_milliseconds() as f64 / 1000.0;
+        let mut top_errors = self
+            .errors
+            .drain()
+            .map(|(message, count)| MessageCount {
+                message,
+                count: count as i64,
+            })
+            .collect::<Vec<_>>();
+        top_errors.sort_by(|a, b| b.count.cmp(&a.count).then_with(|| a.message.cmp(&b.message)));
+        top_errors.truncate(TOP_ERRORS);
+        let summary = Arc::new(Summary {
+            host: host.to_string(),
+            from: self.from,
+            to: now,
+            entries,
+            errors: self.levels[0],
+            infos: self.levels[1],
+            debugs: self.levels[2],
+            malformed: self.malformed,
+            rate: if secs > 0.0 { entries as f64 / secs } else { 0.0 },
+            peak_rate: self.peak_rate.max(self.second.1),
+            error_rate: if entries > 0 { self.levels[0] as f64 / entries as f64 } else { 0.0 },
+            distinct_ips: self.ips.len(),
+            top_errors,
+        });
+        for sink in self.sinks.iter() {
+            sink.send(summary.clone());
+        }
+
+        self.from = now;
+        self.due = self.schedule.next(now);
+        self.levels = [0; 3];
+        self.malformed = 0;
+        self.ips.clear();
+        self.second = (0, 0);
+        self.peak_rate = 0;
+    }
+
+    /// Stop the sending tasks, with what is queued delivered within `timeout`.
+    pub(crate) async fn close(&mut self, timeout: tokio::time::Duration) {
+        for sink in std::mem::take(&mut self.sinks) {
+            sink.close(timeout).await;
+        }
+    }
+
+    fn count_second(&mut self, received_millis: i64) {
+        let second = received_millis.div_euclid(1000);
+        if second == self.second.0 {
+            self.second.1 += 1;
+        }
+        else {
+            self.peak_rate = self.peak_rate.max(self.second.1);
+            self.second = (second, 1);
+        }
+    }
+}
+
+/// Sends the summaries to a `--summary-to` target: as they are to a webhook, or as a message to a `slack:` one.
+#[cfg(feature = "summary")]
+pub(crate) struct SummaryTargetSink {
+    target: String,
+    client: HttpClient,
+}
+
+#[cfg(feature = "summary")]
+impl SummaryTargetSink {
+    pub(crate) fn new(target: &str, config: &Config) -> Self {
+        Self {
+            target: target.to_string(),
+            client: HttpClient::new(config),
+        }
+    }
+}
+
+#[cfg(feature = "summary")]
+impl Sink for SummaryTargetSink {
+    type Item = Arc<Summary>;
+
+    fn name(&self) -> String {
+        match self.target.strip_prefix("slack:") {
+            Some(url) => format!("Summaries to Slack {}", url),
+            None => format!("Summaries to {}", self.target),
+        }
+    }
+
+    fn delivery(&self) -> Delivery {
+        Delivery {
+            buffer: QUEUE_LEN,
+            attempts: ATTEMPTS,
+            backoff: FIRST_RETRY_DELAY,
+            ..Delivery::default()
+        }
+    }
+
+    async fn deliver(&mut self, batch: &[Arc<Summary>]) -> Result<()> {
+        for summary in batch {
+            let (url, body) = match self.target.strip_prefix("slack:") {
+                Some(url) => (url, serde_json::json!({ "text": summary.text() })),
+                None => (self.target.as_str(), serde_json::json!(**summary)),
+            };
+            post(&mut self.client, url, body).await?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(feature = "summary")]
+async fn post(client: &mut HttpClient, url: &str, body: serde_json::Value) -> Result<()> {
+    let response = client
+        .get()?
+        .post(url)
+        .header("Content-Type", "application/json")
+        .body(body.to_string())
+        .send()
+        .await?;
+    let status = response.status();
+    if !status.is_success() {
+        let text = response.text().await.unwrap_or_default();
+        return Err(anyhow!("Summary request failed with {}: {}", status, text.trim_end()));
+    }
+    Ok(())
+}
@@ -0,0 +1,134 @@
+use std::collections::HashMap;
+
+use chrono::{DateTime, Local};
+use qnode_logproc_core::{Clock, SuspiciousIp};
+
+/// Length of the periods the errors of every IP are counted in.
+const PERIOD_MS: i64 = 60_000;
+/// Weight of the latest period in the baseline, which makes it follow about the last ten minutes.
+const BASELINE_WEIGHT: f64 = 0.2;
+/// An IP whose baseline decays below this many errors per minute is forgotten.
+const FORGOTTEN: f64 = 0.01;
+
+#[derive(Debug, Default)]
+struct IpErrors {
+    /// The period `errors` are counted in
+    period:   i64,
+    errors:   u64,
+    /// Errors of the period before
+    last:     u64,
+    baseline: f64,
+    /// Whether a period of its errors is over
+    known:    bool,
+    /// When it was flagged, with whether it was new then
+    flagged:  Option<(i64, bool)>,
+}
+
+/// Flags the IPs whose errors per minute jump to more than `--suspicious-factor` times their own baseline. The
+/// baseline only learns from the periods which are not suspicious, so an IP stays flagged for as long as it keeps
+/// erroring that much.
+#[derive(Debug)]
+pub(crate) struct SuspiciousIps {
+    enabled:    bool,
+    min_errors: u64,
+    factor:     f64,
+    /// Whether the periods are measured by log time rather than receive time
+    clock:      Clock,
+    ips:        HashMap<String, IpErrors>,
+}
+
+impl SuspiciousIps {
+    pub(crate) fn new(enabled: bool, min_errors: u64, factor: f64, clock: Clock) -> Self {
+        Self {
+            enabled,
+            min_errors,
+            factor,
+            clock,
+            ips: HashMap::new(),
+        }
+    }
+
+    pub(crate) fn is_enabled(&self) -> bool {
+        self.enabled
+    }
+
+    /// Count an ERROR record of the IP.
+    pub(crate) fn push(&mut self, ip: &str, logged_millis: i64, received_millis: i64) {
+        if ip.is_empty() {
+            return;
+        }
+        let now = match self.clock {
+            Clock::Receive => received_millis,
+            Clock::Log => logged_millis,
+        };
+        let period = now.div_euclid(PERIOD_MS);
+        let (min_errors, factor) = (self.min_errors, self.factor);
+        let state = self.ips.entry(ip.to_string()).or_insert_with(|| IpErrors {
+            period,
+            ..IpErrors::default()
+        });
+        state.roll(period, min_errors, factor);
+        state.errors += 1;
+        if state.flagged.is_none() && is_suspicious(state.errors, state.baseline, min_errors, factor) {
+            state.flagged = Some((now, !state.known));
+        }
+    }
+
+    /// The IPs flagged as of `now`, the most erroring first. IPs which stopped erroring are forgotten.
+    pub(crate) fn suspicious(&mut self, now: DateTime<Local>) -> Vec<SuspiciousIp> {
+        let period = now.timestamp_millis().div_euclid(PERIOD_MS);
+        let (min_errors, factor) = (self.min_errors, self.factor);
+        self.ips.retain(|_, state| {
+            state.roll(period, min_errors, factor);
+            state.flagged.is_some() || state.errors > 0 || state.baseline >= FORGOTTEN
+        });
+
+        let time = |millis| DateTime::from_timestamp_millis(millis).unwrap_or_default().with_timezone(&Local);
+        let mut suspicious = self
+            .ips
+            .iter()
+            .filter_map(|(ip, state)| {
+                let (since, new) = state.flagged?;
+                Some(SuspiciousIp {
+                    ip: ip.clone(),
+                    errors: state.errors.max(state.last),
+                    baseline: state.baseline,
+                    new,
+                    since: time(since),
+                })
+            })
+            .collect::<Vec<_>>();
+        suspicious.sort_by(|a, b| b.errors.cmp(&a.errors).then_with(|| a.ip.cmp(&b.ip)));
+        suspicious
+    }
+}
+
+impl IpErrors {
+    // Close the periods before `period`. Records of periods already closed, arriving late by log time, are counted in
+    // the current one.
+    fn roll(&mut self, period: i64, min_errors: u64, factor: f64) {
+        if period <= self.period {
+            return;
+        }
+        if is_suspicious(self.errors, self.baseline, min_errors, factor) {
+            // An idle period after it clears the flag.
+            if period > self.period + 1 {
+                self.flagged = None;
+            }
+        }
+        else {
+            self.flagged = None;
+            self.baseline += BASELINE_WEIGHT * (self.errors as f64 - self.baseline);
+        }
+        let idle = (period - self.period - 1).min(i32::MAX as i64) as i32;
+        self.baseline *= (1.0 - BASELINE_WEIGHT).powi(idle);
+        self.last = if idle > 0 { 0 } else { self.errors };
+        self.errors = 0;
+        self.period = period;
+        self.known = true;
+    }
+}
+
+fn is_suspicious(errors: u64, baseline: f64, min_errors: u64, factor: f64) -> bool {
+    errors >= min_errors && errors as f64 > factor * baseline
+}
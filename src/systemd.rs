@@ -0,0 +1,103 @@
+use std::{
+    env,
+    os::{
+        fd::{AsRawFd, FromRawFd, OwnedFd, RawFd},
+        linux::net::SocketAddrExt,
+        unix::net::{SocketAddr, UnixDatagram},
+    },
+    sync::OnceLock,
+    time::Duration,
+};
+
+use anyhow::{anyhow, Result};
+
+// The first descriptor passed by socket activation, `SD_LISTEN_FDS_START`.
+const LISTEN_FDS_START: RawFd = 3;
+
+// The activated socket once taken from the environment. It is kept for the lifetime of the process, so that a
+// listener started over again gets the same socket.
+static ACTIVATED_SOCKET: OnceLock<Option<OwnedFd>> = OnceLock::new();
+
+/// Send a state update to the service manager as described in `sd_notify(3)`. Does nothing if the process was not
+/// started by systemd with `Type=notify`.
+pub(crate) fn notify(state: &str) -> Result<()> {
+    let Some(path) = env::var_os("NOTIFY_SOCKET")
+    else {
+        return Ok(());
+    };
+
+    let path = path.to_string_lossy();
+    let addr = match path.strip_prefix('@') {
+        Some(name) => SocketAddr::from_abstract_name(name.as_bytes())?,
+        None => SocketAddr::from_pathname(path.as_ref())?,
+    };
+
+    UnixDatagram::unbound()?.send_to_addr(state.as_bytes(), &addr)?;
+    Ok(())
+}
+
+/// How often the watchdog must be pinged, if it is enabled for this process. Half of `WATCHDOG_USEC` is used as
+/// recommended by `sd_watchdog_enabled(3)`.
+pub(crate) fn watchdog_interval() -> Option<Duration> {
+    if let Some(pid) = env::var("WATCHDOG_PID").ok().and_then(|pid| pid.parse::<u32>().ok()) {
+        if pid != std::process::id() {
+            return None;
+        }
+    }
+
+    env::var("WATCHDOG_USEC")
+        .ok()
+        .and_then(|usec| usec.parse::<u64>().ok())
+        .filter(|usec| *usec > 0)
+        .map(|usec| Duration::from_micros(usec / 2))
+}
+
+/// The socket passed by systemd socket activation as described in `sd_listen_fds(3)`, if the process was started so.
+/// Only the first of several sockets is taken. Every call returns a duplicate of it, so it stays open when a listener
+/// is dropped. `kind` is the expected socket type, `SOCK_STREAM` or `SOCK_DGRAM`; a socket of another type is an
+/// error.
+pub(crate) fn activated_socket(kind: libc::c_int) -> Result<Option<OwnedFd>> {
+    let Some(fd) = ACTIVATED_SOCKET.get_or_init(take_activated_socket)
+    else {
+        return Ok(None);
+    };
+
+    let mut socket_type: libc::c_int = 0;
+    let mut len = std::mem::size_of::<libc::c_int>() as libc::socklen_t;
+    let got = unsafe {
+        libc::getsockopt(
+            fd.as_raw_fd(),
+            libc::SOL_SOCKET,
+            libc::SO_TYPE,
+            (&mut socket_type as *mut libc::c_int).cast(),
+            &mut len,
+        )
+    };
+    if got != 0 {
+        return Err(anyhow!("Socket passed by systemd is unusable: {}", std::io::Error::last_os_error()));
+    }
+    if socket_type != kind {
+        return Err(anyhow!("Socket passed by systemd is not of the type --listen asks for"));
+    }
+    // The duplicate is close-on-exec like the sockets the process opens.
+    Ok(Some(fd.try_clone()?))
+}
+
+// Take the passed socket and unset the variables describing it, so that the commands run don't take them for their
+// own.
+fn take_activated_socket() -> Option<OwnedFd> {
+    let pid = env::var("LISTEN_PID").ok().and_then(|pid| pid.parse::<u32>().ok());
+    let count = env::var("LISTEN_FDS").ok().and_then(|count| count.parse::<u32>().ok());
+    for name in ["LISTEN_PID", "LISTEN_FDS", "LISTEN_FDNAMES"] {
+        env::remove_var(name);
+    }
+    if pid != Some(std::process::id()) || count.unwrap_or(0) == 0 {
+        return None;
+    }
+
+    // SAFETY: the descriptor is passed open to the process and nothing else owns it.
+    let fd = unsafe { OwnedFd::from_raw_fd(LISTEN_FDS_START) };
+    // Unlike the sockets the process opens, it would be inherited by the commands run.
+    unsafe { libc::fcntl(fd.as_raw_fd(), libc::F_SETFD, libc::FD_CLOEXEC) };
+    Some(fd)
+}
@@ -0,0 +1,143 @@
+use std::{
+    io::{ErrorKind, SeekFrom},
+    os::unix::fs::MetadataExt,
+    path::PathBuf,
+};
+
+use anyhow::Result;
+use tokio::{
+    fs::{self, File},
+    io::{AsyncBufReadExt, AsyncSeekExt, BufReader},
+};
+
+use crate::encoding::Encoding;
+
+// How long to wait for new data when the end of file is reached.
+const POLL_INTERVAL: tokio::time::Duration = tokio::time::Duration::from_millis(250);
+
+/// Follows a file like `tail -F` does: waits for it to appear, keeps reading as it grows, starts over when it is
+/// truncated and re-opens it when it is replaced by a new file with the same name (i.e. rotated).
+pub(crate) struct Tail {
+    path:       PathBuf,
+    reader:     Option<BufReader<File>>,
+    /// Inode of the currently opened file, to detect rotation
+    inode:      u64,
+    /// Offset in the currently opened file, to detect truncation
+    pos:        u64,
+    /// Bytes of a line which is not terminated yet
+    partial:    Vec<u8>,
+    /// Offset of the last line returned
+    line_start: u64,
+    /// Encoding of the currently opened file, told by its first bytes
+    encoding:   Encoding,
+    /// Whether to start reading the first opened file from the beginning. Files which appear after rotation are always
+    /// read from the beginning.
+    from_start: bool,
+}
+
+impl Tail {
+    pub(crate) fn new<P: Into<PathBuf>>(path: P, from_start: bool) -> Self {
+        Self {
+            path: path.into(),
+            reader: None,
+            inode: 0,
+            pos: 0,
+            partial: Vec::new(),
+            line_start: 0,
+            encoding: Encoding::default(),
+            from_start,
+        }
+    }
+
+    /// Offset in the currently opened file, including a line which is not terminated yet.
+    pub(crate) fn position(&self) -> u64 {
+        self.pos
+    }
+
+    /// Offset of the line last returned by `next_line` in its file.
+    pub(crate) fn line_start(&self) -> u64 {
+        self.line_start
+    }
+
+    /// Wait for the next complete line. Line terminators are stripped.
+    pub(crate) async fn next_line(&mut self) -> Result<String> {
+        loop {
+            if self.reader.is_none() && !self.open().await? {
+                tokio::time::sleep(POLL_INTERVAL).await;
+                continue;
+            }
+
+            let reader = self.reader.as_mut().unwrap();
+            let (read, terminated) = self.encoding.read_line(reader, &mut self.partial).await?;
+            self.pos += read as u64;
+
+            if terminated {
+                return Ok(self.take_line());
+            }
+
+            // End of file is reached, see if it is still the same file.
+            match fs::metadata(&self.path).await {
+                Ok(meta) if meta.ino() != self.inode => {
+                    // Rotated. Lines written to the old file since its end was reached are read before switching over;
+                    // whatever is left unterminated then is still a line.
+                    let (read, terminated) = self.encoding.read_line(reader, &mut self.partial).await?;
+                    self.pos += read as u64;
+                    if terminated {
+                        return Ok(self.take_line());
+                    }
+                    self.reader = None;
+                    if !self.partial.is_empty() {
+                        return Ok(self.take_line());
+                    }
+                    continue;
+                }
+                Ok(meta) if meta.len() < self.pos => {
+                    // Truncated. What is written next may come with a byte order mark of its own.
+                    self.reader = None;
+                    self.partial.clear();
+                    continue;
+                }
+                // Either nothing has changed or the file is removed but may re-appear.
+                Ok(_) => (),
+                Err(err) if err.kind() == ErrorKind::NotFound => (),
+                Err(err) => return Err(err.into()),
+            }
+
+            tokio::time::sleep(POLL_INTERVAL).await;
+        }
+    }
+
+    // Returns false if the file doesn't exist yet.
+    async fn open(&mut self) -> Result<bool> {
+        let file = match File::open(&self.path).await {
+            Ok(file) => file,
+            Err(err) if err.kind() == ErrorKind::NotFound => return Ok(false),
+            Err(err) => return Err(err.into()),
+        };
+
+        let meta = file.metadata().await?;
+        self.inode = meta.ino();
+        let mut reader = BufReader::new(file);
+        let (encoding, bom) = Encoding::detect(reader.fill_buf().await?);
+        self.encoding = encoding;
+        self.pos = if self.from_start {
+            reader.consume(bom);
+            bom as u64
+        }
+        else {
+            reader.seek(SeekFrom::End(0)).await?
+        };
+        // Any file opened after the first one is a new one and must be read completely.
+        self.from_start = true;
+        self.reader = Some(reader);
+
+        Ok(true)
+    }
+
+    fn take_line(&mut self) -> String {
+        self.line_start = self.pos - self.partial.len() as u64;
+        let line = self.encoding.decode(&self.partial);
+        self.partial.clear();
+        line
+    }
+}
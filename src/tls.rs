@@ -0,0 +1,158 @@
+use std::{path::Path, sync::Arc};
+
+use anyhow::{anyhow, Result};
+use rustls::{
+    crypto::{ring, CryptoProvider},
+    pki_types::{pem::PemObject, CertificateDer, PrivateKeyDer},
+    server::WebPkiClientVerifier,
+    RootCertStore, ServerConfig,
+};
+use tokio_rustls::TlsAcceptor;
+
+use crate::config::Config;
+
+/// TLS of the connections the analyzer makes, like to Redis, NATS, an MQTT broker or HTTP endpoints: the CA of
+/// `--tls-ca` is trusted instead of the system ones and the certificate of `--tls-cert` is presented to servers which
+/// ask for one. The server name is sent and verified as it is given in the address.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct ClientTls {
+    pub(crate) ca:   Option<std::path::PathBuf>,
+    pub(crate) cert: Option<std::path::PathBuf>,
+    pub(crate) key:  Option<std::path::PathBuf>,
+}
+
+impl ClientTls {
+    pub(crate) fn from_config(config: &Config) -> Self {
+        Self {
+            ca:   config.tls_ca.clone(),
+            cert: config.tls_cert.clone(),
+            key:  config.tls_key.clone(),
+        }
+    }
+
+    pub(crate) fn client_config(&self) -> Result<rustls::ClientConfig> {
+        let roots = match self.ca {
+            Some(ref ca) => load_roots(ca)?,
+            None => {
+                let mut roots = RootCertStore::empty();
+                roots.add_parsable_certificates(rustls_native_certs::load_native_certs().certs);
+                roots
+            }
+        };
+        let builder = rustls::ClientConfig::builder_with_provider(provider())
+            .with_safe_default_protocol_versions()?
+            .with_root_certificates(roots);
+        Ok(match (&self.cert, &self.key) {
+            (Some(cert), Some(key)) => builder.with_client_auth_cert(load_certs(cert)?, load_key(key)?)?,
+            _ => builder.with_no_client_auth(),
+        })
+    }
+}
+
+/// An HTTP client with the TLS of the options, made on first use so that a failure to read the certificates is
+/// reported like a failure to send.
+#[cfg(any(
+    feature = "influx",
+    feature = "paging",
+    feature = "pubsub",
+    feature = "remote-write",
+    feature = "share",
+    feature = "summary"
+))]
+#[derive(Debug, Clone)]
+pub(crate) struct HttpClient {
+    tls:    ClientTls,
+    client: Option<reqwest::Client>,
+}
+
+#[cfg(any(
+    feature = "influx",
+    feature = "paging",
+    feature = "pubsub",
+    feature = "remote-write",
+    feature = "share",
+    feature = "summary"
+))]
+impl HttpClient {
+    pub(crate) fn new(config: &Config) -> Self {
+        Self {
+            tls:    ClientTls::from_config(config),
+            client: None,
+        }
+    }
+
+    pub(crate) fn get(&mut self) -> Result<&reqwest::Client> {
+        if self.client.is_none() {
+            let client = reqwest::Client::builder()
+                .use_preconfigured_tls(self.tls.client_config()?)
+                .build()
+                .map_err(|e| anyhow!("Can't create HTTP client: {}", e))?;
+            self.client = Some(client);
+        }
+        Ok(self.client.as_ref().unwrap())
+    }
+}
+
+/// The acceptor of `--listen` connections if `--listen-tls-cert` is given. With `--listen-client-ca` clients must
+/// present a certificate issued by one of its CAs.
+pub(crate) fn acceptor(config: &Config) -> Result<Option<TlsAcceptor>> {
+    let (Some(cert), Some(key)) = (&config.listen_tls_cert, &config.listen_tls_key)
+    else {
+        return Ok(None);
+    };
+    server_acceptor(cert, key, config.listen_client_ca.as_deref()).map(Some)
+}
+
+/// The acceptor of `--http` connections if `--http-tls-cert` is given.
+pub(crate) fn http_acceptor(config: &Config) -> Result<Option<TlsAcceptor>> {
+    let (Some(cert), Some(key)) = (&config.http_tls_cert, &config.http_tls_key)
+    else {
+        return Ok(None);
+    };
+    server_acceptor(cert, key, None).map(Some)
+}
+
+fn server_acceptor(cert: &Path, key: &Path, client_ca: Option<&Path>) -> Result<TlsAcceptor> {
+    let builder = ServerConfig::builder_with_provider(provider()).with_safe_default_protocol_versions()?;
+    let builder = match client_ca {
+        Some(ca) => builder.with_client_cert_verifier(
+            WebPkiClientVerifier::builder_with_provider(Arc::new(load_roots(ca)?), provider()).build()?,
+        ),
+        None => builder.with_no_client_auth(),
+    };
+    let server_config = builder.with_single_cert(load_certs(cert)?, load_key(key)?)?;
+    Ok(TlsAcceptor::from(Arc::new(server_config)))
+}
+
+/// Make ring the provider of the clients which build their TLS configuration themselves, like the Redis one.
+#[cfg(feature = "redis")]
+pub(crate) fn install_provider() {
+    // It fails only if a provider is installed already.
+    let _ = ring::default_provider().install_default();
+}
+
+fn provider() -> Arc<CryptoProvider> {
+    Arc::new(ring::default_provider())
+}
+
+fn load_certs(path: &Path) -> Result<Vec<CertificateDer<'static>>> {
+    let certs = CertificateDer::pem_file_iter(path)
+        .and_then(|certs| certs.collect::<Result<Vec<_>, _>>())
+        .map_err(|e| anyhow!("Can't read certificates from {:?}: {}", path, e))?;
+    if certs.is_empty() {
+        return Err(anyhow!("No certificates in {:?}", path));
+    }
+    Ok(certs)
+}
+
+fn load_key(path: &Path) -> Result<PrivateKeyDer<'static>> {
+    PrivateKeyDer::from_pem_file(path).map_err(|e| anyhow!("Can't read private key from {:?}: {}", path, e))
+}
+
+fn load_roots(path: &Path) -> Result<RootCertStore> {
+    let mut roots = RootCertStore::empty();
+    for cert in load_certs(path)? {
+        roots.add(cert)?;
+    }
+    Ok(roots)
+}
@@ -1,18 +1,25 @@
 #![allow(dead_code)]
+use std::sync::{
+    atomic::{AtomicU64, Ordering},
+    Arc,
+};
+
 use fieldx::fxstruct;
-use strum_macros::EnumString;
 
-#[derive(Debug, Clone, Copy, EnumString)]
-pub(crate) enum Level {
-    INFO,
-    ERROR,
-    DEBUG,
-}
+use crate::config::AlertSpec;
+pub(crate) use qnode_logproc_core::{ErrorType, Level, ParsedLine, PartialStats, Provenance};
 
-#[derive(Debug, Clone)]
+/// Number of the last line received, by all inputs together.
+static LINE_SEQ: AtomicU64 = AtomicU64::new(0);
+
+#[derive(Debug)]
 pub(crate) enum StatRecord {
     OK(StatOKRecord),
     Err(StatErrRecord),
+    /// Records counted by a shard worker
+    Partial(Box<ShardPartial>),
+    #[cfg(feature = "simulation")]
+    Check(Checkpoint),
     Stop,
 }
 
@@ -21,6 +28,9 @@ impl StatRecord {
         match self {
             Self::OK(ok) => ok.received_millis(),
             Self::Err(err) => err.received_millis(),
+            Self::Partial(_) => -1,
+            #[cfg(feature = "simulation")]
+            Self::Check(_) => -1,
             Self::Stop => -1,
         }
     }
@@ -30,6 +40,16 @@ impl StatRecord {
     }
 }
 
+/// Records a shard worker has counted since its previous tick, with the partials of the window and of the panes they
+/// make, to be merged.
+#[derive(Debug)]
+pub(crate) struct ShardPartial {
+    pub(crate) window:  PartialStats,
+    /// Partials of the panes, in the order of the panes
+    pub(crate) panes:   Vec<PartialStats>,
+    pub(crate) records: Vec<StatRecord>,
+}
+
 impl From<StatOKRecord> for StatRecord {
     fn from(ok: StatOKRecord) -> Self {
         Self::OK(ok)
@@ -42,23 +62,27 @@ impl From<StatErrRecord> for StatRecord {
     }
 }
 
-#[derive(Debug, Clone, Copy)]
-pub(crate) enum StatErrType {
-    Malformed,
-}
-
 #[derive(Debug, Clone)]
 #[fxstruct(sync, no_new, builder, get)]
 pub(crate) struct StatOKRecord {
     /// When the incoming message was received by the reader, in milliseconds
     #[fieldx(get(copy))]
     received_millis: i64,
-    /// When the message was generated, in milliseconds
+    /// Parsed fields, with the IP possibly hashed and the message redacted
+    #[fieldx(get_mut)]
+    record:          ParsedLine,
+    /// Name of the input source the record came from
+    source:          Arc<str>,
+    provenance:      Provenance,
+    /// Not to be counted in the current rate, like a backfilled line
     #[fieldx(get(copy))]
-    logged_millis:   i64,
-    #[fieldx(get(copy))]
-    level:           Level,
-    message:         String,
+    uncounted:       bool,
+}
+
+impl StatOKRecord {
+    pub(crate) fn into_record(self) -> ParsedLine {
+        self.record
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -68,10 +92,12 @@ pub(crate) struct StatErrRecord {
     #[fieldx(get(copy))]
     received_millis: i64,
     #[fieldx(get(copy))]
-    error_type:      StatErrType,
+    error_type:      ErrorType,
     /// If there is a line associated with the error, it is stored here
     #[fieldx(optional)]
     line:            String,
+    /// Name of the input source the record came from
+    source:          Arc<str>,
 }
 
 #[fxstruct(get, no_new)]
@@ -79,11 +105,78 @@ pub(crate) struct LineMessage {
     line:             String,
     #[fieldx(get(copy))]
     recv_time_millis: i64,
+    /// Name of the input source the line was read from
+    source:           Arc<str>,
+    /// Set for records which arrive structured and need no parsing; `line` is empty then.
+    record:           Option<ParsedLine>,
+    /// The line is a part of a file history being backfilled
+    #[fieldx(get(copy))]
+    backfill:         bool,
+    /// The input couldn't be decoded; `line` describes why
+    #[fieldx(get(copy))]
+    undecoded:        bool,
+    /// Set for simulation checks, which carry no line
+    checkpoint:       Option<Checkpoint>,
+    provenance:       Provenance,
+}
+
+/// A check of a simulation scenario, made when the pipeline reaches it.
+#[derive(Debug, Clone)]
+pub(crate) struct Checkpoint {
+    /// Seconds from the start of the scenario
+    pub(crate) at:   f64,
+    pub(crate) rule: AlertSpec,
 }
 
 impl LineMessage {
-    pub(crate) fn new(line: String, recv_time_millis: i64) -> Self {
-        Self { line, recv_time_millis }
+    pub(crate) fn new(line: String, recv_time_millis: i64, source: Arc<str>) -> Self {
+        Self {
+            line,
+            recv_time_millis,
+            source,
+            record: None,
+            backfill: false,
+            undecoded: false,
+            checkpoint: None,
+            provenance: Provenance {
+                origin: None,
+                seq:    LINE_SEQ.fetch_add(1, Ordering::Relaxed) + 1,
+            },
+        }
+    }
+
+    pub(crate) fn from_checkpoint(checkpoint: Checkpoint) -> Self {
+        Self {
+            checkpoint: Some(checkpoint),
+            ..Self::new(String::new(), 0, Arc::from(""))
+        }
+    }
+
+    /// Mark the line as a part of a file history being backfilled.
+    pub(crate) fn backfilled(self) -> Self {
+        Self { backfill: true, ..self }
+    }
+
+    /// Tell where in the source the line is, like `PATH:OFFSET`.
+    pub(crate) fn at(mut self, origin: String) -> Self {
+        self.provenance.origin = Some(origin);
+        self
+    }
+
+    /// Mark the line as a description of an input which couldn't be decoded.
+    pub(crate) fn failed_decoding(self) -> Self {
+        Self { undecoded: true, ..self }
+    }
+
+    pub(crate) fn from_record(record: ParsedLine, recv_time_millis: i64, source: Arc<str>) -> Self {
+        Self {
+            record: Some(record),
+            ..Self::new(String::new(), recv_time_millis, source)
+        }
+    }
+
+    pub(crate) fn level(&self) -> Option<Level> {
+        self.record.as_ref().map(|record| record.level)
     }
 
     pub(crate) fn recv_time(&self) -> i64 {